@@ -0,0 +1,61 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Benchmarks for handing a turn's items to a language binding, one allocation per item versus a single [`ItemArena`].
+//!
+//! Both benchmarks include the copy the language binding makes into its own memory, as the Go binding does.
+//! Criterion reports the time and throughput of each handoff of a 10k-item turn. The benchmarks don't count allocations.
+//! Run with `cargo bench --package azure_data_cosmos_engine --bench item_arena`.
+
+use azure_data_cosmos_engine::query::ItemArena;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use serde_json::value::RawValue;
+
+const ITEM_COUNT: usize = 10_000;
+
+/// Builds the items of a turn, as the pipeline returns them in a `PipelineResponse`.
+fn create_items() -> Vec<Box<RawValue>> {
+    (0..ITEM_COUNT)
+        .map(|i| {
+            RawValue::from_string(format!(
+                r#"{{"id":"item{i}","value":{i},"name":"name{i:08}","tags":["a","b","c"]}}"#
+            ))
+            .expect("item should be valid JSON")
+        })
+        .collect()
+}
+
+/// Hands over each item as its own string, as `cosmoscx_v0_query_pipeline_run` does, then copies each one out.
+fn per_item(items: Vec<Box<RawValue>>) -> Vec<String> {
+    items
+        .into_iter()
+        .map(Box::<str>::from)
+        .collect::<Vec<_>>()
+        .iter()
+        .map(|item| item.to_string())
+        .collect()
+}
+
+/// Hands over the items in a single arena, as `cosmoscx_v0_query_pipeline_run_batch` does, then copies the arena out.
+fn arena(items: Vec<Box<RawValue>>) -> (Vec<u8>, usize) {
+    let arena = ItemArena::new(&items);
+    drop(items);
+    let len = arena.len();
+    let (data, _) = arena.into_parts();
+    (data.as_bytes().to_vec(), len)
+}
+
+fn item_handoff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("item_handoff");
+    group.throughput(Throughput::Elements(ITEM_COUNT as u64));
+    group.bench_function("per_item_10k_items", |b| {
+        b.iter_batched(create_items, per_item, BatchSize::LargeInput)
+    });
+    group.bench_function("arena_10k_items", |b| {
+        b.iter_batched(create_items, arena, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, item_handoff);
+criterion_main!(benches);
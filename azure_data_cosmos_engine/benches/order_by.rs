@@ -0,0 +1,130 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Benchmarks for merging `ORDER BY` results from multiple partitions.
+//!
+//! Run with `cargo bench --package azure_data_cosmos_engine --bench order_by`.
+
+use azure_data_cosmos_engine::query::{
+    PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan, SortOrder,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+const PARTITION_COUNT: usize = 4;
+const ITEM_COUNT: usize = 100_000;
+const PAGE_SIZE: usize = 1_000;
+
+/// A page of results for a single partition, along with the continuation to provide with it.
+struct Page {
+    pkrange_id: String,
+    data: Vec<u8>,
+    continuation: Option<String>,
+}
+
+/// Builds the pages returned by each partition, in the order they're provided to the pipeline.
+///
+/// Each partition holds every `PARTITION_COUNT`th value, so the merge has to alternate between partitions on every item.
+fn create_pages() -> Vec<Vec<Page>> {
+    let items_per_partition = ITEM_COUNT / PARTITION_COUNT;
+    (0..PARTITION_COUNT)
+        .map(|partition| {
+            let values = (0..items_per_partition)
+                .map(|i| i * PARTITION_COUNT + partition)
+                .collect::<Vec<_>>();
+            let page_count = values.chunks(PAGE_SIZE).len();
+            values
+                .chunks(PAGE_SIZE)
+                .enumerate()
+                .map(|(page, chunk)| {
+                    let documents = chunk
+                        .iter()
+                        .map(|v| {
+                            format!(
+                                r#"{{"orderByItems":[{{"item":{v}}},{{"item":"name{v:08}"}}],"payload":{{"id":"item{v}","value":{v}}}}}"#
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    Page {
+                        pkrange_id: format!("partition{partition}"),
+                        data: format!(r#"{{"Documents":[{documents}]}}"#).into_bytes(),
+                        continuation: (page + 1 < page_count).then(|| format!("page{}", page + 1)),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn create_pipeline() -> QueryPipeline {
+    QueryPipeline::new(
+        "SELECT * FROM c ORDER BY c.value, c.name",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending, SortOrder::Descending],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        (0..PARTITION_COUNT).map(|i| {
+            PartitionKeyRange::new(
+                format!("partition{i}"),
+                format!("{i:02X}"),
+                format!("{:02X}", i + 1),
+            )
+        }),
+    )
+    .expect("pipeline should be created")
+}
+
+/// Drives the pipeline to completion, providing the next page for every request it makes.
+fn run_to_completion(mut pipeline: QueryPipeline, pages: &[Vec<Page>]) -> usize {
+    let mut next_page = vec![0; pages.len()];
+    let mut produced = 0;
+    loop {
+        let response = pipeline.run().expect("pipeline should run");
+        produced += response.items.len();
+        if response.terminated {
+            return produced;
+        }
+        for request in response.requests {
+            let partition = pages
+                .iter()
+                .position(|p| p[0].pkrange_id == request.pkrange_id)
+                .expect("request should be for a known partition");
+            let page = &pages[partition][next_page[partition]];
+            next_page[partition] += 1;
+            pipeline
+                .provide_data(
+                    &page.pkrange_id,
+                    request.id,
+                    &page.data,
+                    page.continuation.clone(),
+                    None,
+                )
+                .expect("data should be accepted");
+        }
+    }
+}
+
+fn streaming_order_by(c: &mut Criterion) {
+    let pages = create_pages();
+    let mut group = c.benchmark_group("order_by");
+    group.throughput(Throughput::Elements(ITEM_COUNT as u64));
+    group.sample_size(10);
+    group.bench_function("streaming_4_partitions_100k_items", |b| {
+        b.iter_batched(
+            create_pipeline,
+            |pipeline| {
+                let produced = run_to_completion(pipeline, &pages);
+                assert_eq!(ITEM_COUNT, produced);
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, streaming_order_by);
+criterion_main!(benches);
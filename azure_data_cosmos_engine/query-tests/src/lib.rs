@@ -43,3 +43,13 @@ baseline_tests! {
         offset_limit_rrf_ft_with_vector,
     },
 }
+
+// The vector queries ask for the non-streaming producer, but the results must be the same if they're streamed.
+#[cfg(test)]
+streaming_order_by_baseline_tests! {
+    vector_streaming_order_by: vector {
+        quantized_cosine,
+        flat_euclidean,
+        diskann_dotproduct,
+    },
+}
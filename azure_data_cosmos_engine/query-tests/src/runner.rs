@@ -235,10 +235,66 @@ async fn create_test_container(
 
 static TRACING_SUBSCRIBER_INIT: std::sync::Once = std::sync::Once::new();
 
+/// A query engine that marks every non-streaming `ORDER BY` as streaming-safe, so the baselines can be checked with the streaming producer as well.
+struct StreamingOrderByEngine(azure_data_cosmos_engine::query::QueryEngine);
+
+impl azure_data_cosmos::query::QueryEngine for StreamingOrderByEngine {
+    fn create_pipeline(
+        &self,
+        query: &str,
+        plan: &[u8],
+        pkranges: &[u8],
+    ) -> azure_core::Result<Box<dyn azure_data_cosmos::query::QueryPipeline + Send>> {
+        let mut plan: serde_json::Value = serde_json::from_slice(plan)?;
+        if let Some(query_info) = plan
+            .get_mut("queryInfo")
+            .and_then(serde_json::Value::as_object_mut)
+        {
+            query_info.insert("hasStreamingSafeOrderBy".to_string(), true.into());
+        }
+        azure_data_cosmos::query::QueryEngine::create_pipeline(
+            &self.0,
+            query,
+            &serde_json::to_vec(&plan)?,
+            pkranges,
+        )
+    }
+
+    fn supported_features(&self) -> azure_core::Result<&str> {
+        azure_data_cosmos::query::QueryEngine::supported_features(&self.0)
+    }
+}
+
 const BASELINE_QUERIES_DIR: &str = "baselines/queries";
 pub async fn run_baseline_test(
     suite_name: &str,
     test_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_baseline_test_with_engine(
+        suite_name,
+        test_name,
+        azure_data_cosmos_engine::query::QueryEngine,
+    )
+    .await
+}
+
+/// Runs a baseline test with the streaming `ORDER BY` producer, even if the query plan asks for the non-streaming one.
+pub async fn run_streaming_order_by_baseline_test(
+    suite_name: &str,
+    test_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_baseline_test_with_engine(
+        suite_name,
+        test_name,
+        StreamingOrderByEngine(azure_data_cosmos_engine::query::QueryEngine),
+    )
+    .await
+}
+
+async fn run_baseline_test_with_engine(
+    suite_name: &str,
+    test_name: &str,
+    query_engine: impl azure_data_cosmos::query::QueryEngine + Send + Sync + 'static,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Enable tracing
     TRACING_SUBSCRIBER_INIT.call_once(|| {
@@ -334,7 +390,7 @@ pub async fn run_baseline_test(
         tracing::info!("running query");
 
         let options = QueryOptions {
-            query_engine: Some(Arc::new(azure_data_cosmos_engine::query::QueryEngine)),
+            query_engine: Some(Arc::new(query_engine)),
             ..Default::default()
         };
         let mut query = Query::from(test_query.query);
@@ -518,7 +574,13 @@ fn extract_partition_key(
     match values.len() {
         0 => Err("partition key must have at least one path".into()),
         1 => Ok(PartitionKey::from(values[0].clone())),
-        _ => Err("partition key must have exactly one path".into()), // TODO: We can support HPK once the bug above is fixed.
+        2 => Ok(PartitionKey::from((values[0].clone(), values[1].clone()))),
+        3 => Ok(PartitionKey::from((
+            values[0].clone(),
+            values[1].clone(),
+            values[2].clone(),
+        ))),
+        _ => Err("partition key must have at most three paths".into()),
     }
 }
 
@@ -576,3 +638,29 @@ macro_rules! baseline_tests {
         )*
     };
 }
+
+/// Like `baseline_tests!`, but runs each test with [`run_streaming_order_by_baseline_test`], in a module with the given name.
+macro_rules! streaming_order_by_baseline_tests {
+    (
+        $(
+            $module:ident: $testsuite:ident {
+                $(
+                    $test:ident,
+                )*
+            },
+        )*
+    ) => {
+        $(
+            mod $module {
+                $(
+                    #[tokio::test]
+                    async fn $test() -> Result<(), Box<dyn std::error::Error>> {
+                        let suite_name = stringify!($testsuite);
+                        let test_name = stringify!($test);
+                        $crate::runner::run_streaming_order_by_baseline_test(suite_name, test_name).await
+                    }
+                )*
+            }
+        )*
+    };
+}
@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::{borrow::Cow, fmt::Display};
+use std::{any::Any, borrow::Cow, fmt::Display};
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
@@ -52,8 +52,44 @@ pub enum ErrorKind {
     /// Indicates that the query cannot be executed by this pipeline.
     InvalidQuery,
 
+    /// Indicates that accepting the data provided to [`QueryPipeline::provide_data`](crate::query::QueryPipeline::provide_data) would exceed the pipeline's memory budget.
+    ///
+    /// The error is recoverable. The data was not accepted, and can be provided again after the pipeline has yielded enough buffered items.
+    /// See [`QueryPipeline::with_memory_budget`](crate::query::QueryPipeline::with_memory_budget).
+    MemoryBudgetExceeded,
+
+    /// Indicates that the state of the query pipeline cannot be serialized at this time.
+    ///
+    /// Some queries, like those with a non-streaming `ORDER BY`, aggregates, or hybrid search, hold state that can't be captured in a continuation.
+    /// See [`QueryPipeline::serialize_state`](crate::query::QueryPipeline::serialize_state).
+    StateNotSerializable,
+
     /// Indicates that a Python error occurred. The source of the error will be the original Python error.
     PythonError,
+
+    /// Indicates that the query would buffer more items than the pipeline allows.
+    ///
+    /// This error is not recoverable. The query can only be executed with a higher limit, or with fewer results.
+    /// See [`PipelineOptions::max_buffered_items`](crate::query::PipelineOptions::max_buffered_items).
+    BufferLimitExceeded,
+
+    /// Indicates that buffered results could not be written to, or read back from, a temporary file.
+    ///
+    /// The details of the I/O failure should be available in [`Error::source`](std::error::Error::source).
+    /// See [`QueryPipeline::with_spill`](crate::query::QueryPipeline::with_spill).
+    SpillError,
+
+    /// Indicates that the client engine panicked, and the panic was caught before it could unwind into the language binding.
+    ///
+    /// This error is not recoverable, and indicates a bug in the client engine. The pipeline may be left in an inconsistent state, and should be discarded.
+    /// See [`Error::from_panic`].
+    InternalPanic,
+
+    /// Indicates that the query was cancelled, through a [`CancellationToken`](crate::query::CancellationToken), while the pipeline was in use.
+    ///
+    /// The query is cancelled, and the pipeline reports that it has completed from then on.
+    /// See [`QueryPipeline::cancellation_token`](crate::query::QueryPipeline::cancellation_token).
+    Cancelled,
 }
 
 impl Display for ErrorKind {
@@ -69,12 +105,110 @@ impl Display for ErrorKind {
             ErrorKind::ArithmeticOverflow => write!(f, "arithmetic overflow occurred"),
             ErrorKind::InvalidRequestId => write!(f, "invalid request ID provided"),
             ErrorKind::InvalidQuery => write!(f, "invalid query"),
+            ErrorKind::MemoryBudgetExceeded => write!(f, "memory budget exceeded"),
+            ErrorKind::StateNotSerializable => write!(f, "pipeline state cannot be serialized"),
             ErrorKind::PythonError => write!(f, "python error"),
+            ErrorKind::BufferLimitExceeded => write!(f, "buffered item limit exceeded"),
+            ErrorKind::SpillError => write!(f, "failed to spill buffered results"),
+            ErrorKind::InternalPanic => write!(f, "client engine panicked"),
+            ErrorKind::Cancelled => write!(f, "query was cancelled"),
         }
     }
 }
 
 impl ErrorKind {
+    /// Every kind of error, in the order they are declared.
+    pub const ALL: &'static [ErrorKind] = &[
+        ErrorKind::InvalidGatewayResponse,
+        ErrorKind::DeserializationError,
+        ErrorKind::UnknownPartitionKeyRange,
+        ErrorKind::InternalError,
+        ErrorKind::UnsupportedQueryPlan,
+        ErrorKind::InvalidUtf8String,
+        ErrorKind::ArgumentNull,
+        ErrorKind::ArithmeticOverflow,
+        ErrorKind::InvalidRequestId,
+        ErrorKind::InvalidQuery,
+        ErrorKind::MemoryBudgetExceeded,
+        ErrorKind::StateNotSerializable,
+        ErrorKind::PythonError,
+        ErrorKind::BufferLimitExceeded,
+        ErrorKind::SpillError,
+        ErrorKind::InternalPanic,
+        ErrorKind::Cancelled,
+    ];
+
+    /// Gets a stable numeric code for this kind of error, which language bindings can use to identify it.
+    ///
+    /// Codes are never reused or changed once assigned. The C API reports each error as the negation of its code.
+    pub fn code(self) -> u32 {
+        match self {
+            ErrorKind::InvalidGatewayResponse => 2,
+            ErrorKind::DeserializationError => 3,
+            ErrorKind::UnknownPartitionKeyRange => 4,
+            ErrorKind::InternalError => 5,
+            ErrorKind::UnsupportedQueryPlan => 6,
+            ErrorKind::InvalidUtf8String => 7,
+            ErrorKind::ArgumentNull => 8,
+            ErrorKind::ArithmeticOverflow => 9,
+            ErrorKind::InvalidRequestId => 10,
+            ErrorKind::InvalidQuery => 11,
+            ErrorKind::MemoryBudgetExceeded => 12,
+            ErrorKind::StateNotSerializable => 13,
+            ErrorKind::PythonError => 14,
+            ErrorKind::BufferLimitExceeded => 15,
+            ErrorKind::SpillError => 16,
+            ErrorKind::InternalPanic => 17,
+            ErrorKind::Cancelled => 18,
+        }
+    }
+
+    /// Gets the HTTP status code that best describes this kind of error, for language bindings that report engine errors like service errors.
+    ///
+    /// User errors are reported as client errors (4xx), and bugs in the engine, the language binding or the gateway as server errors (5xx).
+    pub fn http_status(self) -> u16 {
+        match self {
+            ErrorKind::UnsupportedQueryPlan
+            | ErrorKind::ArithmeticOverflow
+            | ErrorKind::InvalidQuery
+            | ErrorKind::StateNotSerializable
+            | ErrorKind::BufferLimitExceeded => 400,
+            // The data can be provided again once the pipeline has yielded enough items, like a throttled request can be retried.
+            ErrorKind::MemoryBudgetExceeded => 429,
+            // The status used for a request the client cancelled, as in gRPC's mapping of CANCELLED to HTTP.
+            ErrorKind::Cancelled => 499,
+            ErrorKind::UnknownPartitionKeyRange
+            | ErrorKind::InternalError
+            | ErrorKind::InvalidUtf8String
+            | ErrorKind::ArgumentNull
+            | ErrorKind::InvalidRequestId
+            | ErrorKind::PythonError
+            | ErrorKind::SpillError
+            | ErrorKind::InternalPanic => 500,
+            ErrorKind::InvalidGatewayResponse | ErrorKind::DeserializationError => 502,
+        }
+    }
+
+    /// Indicates if this kind of error is caused by the query, its data, or how the pipeline was configured,
+    /// and should be reported to the user rather than treated as a bug.
+    pub fn is_user_error(self) -> bool {
+        matches!(
+            self,
+            ErrorKind::ArithmeticOverflow
+                | ErrorKind::InvalidQuery
+                | ErrorKind::MemoryBudgetExceeded
+                | ErrorKind::StateNotSerializable
+                | ErrorKind::BufferLimitExceeded
+        )
+    }
+
+    /// Indicates if this kind of error is caused by a bug in the client engine.
+    ///
+    /// Errors that are neither internal nor user errors indicate a bug in the language binding, or invalid data from the gateway.
+    pub fn is_internal(self) -> bool {
+        matches!(self, ErrorKind::InternalError | ErrorKind::InternalPanic)
+    }
+
     pub fn with_source(self, source: impl std::error::Error + Send + Sync + 'static) -> Error {
         Error::with_source(self, source)
     }
@@ -136,10 +270,52 @@ impl Error {
         err
     }
 
+    /// Creates an [`ErrorKind::InternalPanic`] error from the payload of a panic caught by [`std::panic::catch_unwind`].
+    ///
+    /// Language bindings use this to report a panic as an error, since a panic must not unwind across an FFI boundary.
+    pub fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => format!("client engine panicked: {message}"),
+            Err(payload) => match payload.downcast_ref::<&'static str>() {
+                Some(message) => format!("client engine panicked: {message}"),
+                None => "client engine panicked".to_string(),
+            },
+        };
+        Self::with_message(ErrorKind::InternalPanic, message)
+    }
+
     pub fn kind(&self) -> ErrorKind {
         self.kind
     }
 
+    /// Gets the stable numeric code of this error's kind, see [`ErrorKind::code`].
+    pub fn code(&self) -> u32 {
+        self.kind.code()
+    }
+
+    /// Gets the HTTP status code that best describes this error, see [`ErrorKind::http_status`].
+    pub fn http_status(&self) -> u16 {
+        self.kind.http_status()
+    }
+
+    /// Indicates if this error should be reported to the user, see [`ErrorKind::is_user_error`].
+    pub fn is_user_error(&self) -> bool {
+        self.kind.is_user_error()
+    }
+
+    /// Indicates if this error is caused by a bug in the client engine, see [`ErrorKind::is_internal`].
+    pub fn is_internal(&self) -> bool {
+        self.kind.is_internal()
+    }
+
+    /// Prefixes the message of this error with some context, keeping its kind and source.
+    pub fn with_context(self, context: impl Display) -> Self {
+        Self {
+            message: Some(format!("{context}: {self}").into()),
+            ..self
+        }
+    }
+
     pub fn into_source(self) -> Option<Box<dyn std::error::Error + Send + Sync>> {
         self.source
     }
@@ -168,6 +344,55 @@ impl From<pyo3::PyErr> for Error {
     }
 }
 
+/// The Python exceptions raised for engine errors.
+///
+/// Every exception derives from [`QueryEngineError`](python_exceptions::QueryEngineError), which derives from `RuntimeError`.
+#[cfg(feature = "python_conversions")]
+pub mod python_exceptions {
+    pyo3::create_exception!(
+        azure_cosmoscx,
+        QueryEngineError,
+        pyo3::exceptions::PyRuntimeError,
+        "Raised when the query engine fails."
+    );
+    pyo3::create_exception!(
+        azure_cosmoscx,
+        UnsupportedQueryError,
+        QueryEngineError,
+        "Raised when the query can't be executed by the query engine."
+    );
+    pyo3::create_exception!(
+        azure_cosmoscx,
+        DeserializationError,
+        QueryEngineError,
+        "Raised when data from the gateway or backend can't be read."
+    );
+    pyo3::create_exception!(
+        azure_cosmoscx,
+        InvalidRequestError,
+        QueryEngineError,
+        "Raised when data is provided for an unknown partition or request."
+    );
+    pyo3::create_exception!(
+        azure_cosmoscx,
+        MemoryBudgetExceededError,
+        QueryEngineError,
+        "Raised when provided data would exceed the pipeline's memory budget."
+    );
+    pyo3::create_exception!(
+        azure_cosmoscx,
+        InternalError,
+        QueryEngineError,
+        "Raised when the query engine encounters a bug."
+    );
+    pyo3::create_exception!(
+        azure_cosmoscx,
+        CancelledError,
+        QueryEngineError,
+        "Raised when the query is cancelled while the pipeline is in use."
+    );
+}
+
 #[cfg(feature = "python_conversions")]
 impl From<Error> for pyo3::PyErr {
     fn from(err: Error) -> Self {
@@ -180,9 +405,35 @@ impl From<Error> for pyo3::PyErr {
             let err = err
                 .downcast::<pyo3::PyErr>()
                 .expect("PythonError's source must be a PyErr");
-            *err
-        } else {
-            pyo3::PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(err.to_string())
+            return *err;
+        }
+
+        let message = err.to_string();
+        match err.kind() {
+            ErrorKind::UnsupportedQueryPlan | ErrorKind::InvalidQuery => {
+                python_exceptions::UnsupportedQueryError::new_err(message)
+            }
+            ErrorKind::InvalidGatewayResponse
+            | ErrorKind::DeserializationError
+            | ErrorKind::InvalidUtf8String => {
+                python_exceptions::DeserializationError::new_err(message)
+            }
+            ErrorKind::UnknownPartitionKeyRange | ErrorKind::InvalidRequestId => {
+                python_exceptions::InvalidRequestError::new_err(message)
+            }
+            ErrorKind::MemoryBudgetExceeded => {
+                python_exceptions::MemoryBudgetExceededError::new_err(message)
+            }
+            ErrorKind::InternalError | ErrorKind::InternalPanic => {
+                python_exceptions::InternalError::new_err(message)
+            }
+            ErrorKind::Cancelled => python_exceptions::CancelledError::new_err(message),
+            ErrorKind::ArgumentNull
+            | ErrorKind::ArithmeticOverflow
+            | ErrorKind::StateNotSerializable
+            | ErrorKind::BufferLimitExceeded
+            | ErrorKind::SpillError
+            | ErrorKind::PythonError => python_exceptions::QueryEngineError::new_err(message),
         }
     }
 }
@@ -199,3 +450,89 @@ fn panic_if_internal_error(err: &Error) {
         panic!("internal error: {}", err);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    pub fn every_kind_has_a_unique_code_and_classification() {
+        let mut codes = HashSet::new();
+        for &kind in ErrorKind::ALL {
+            // This match has no wildcard, so adding a variant fails to compile until it's also added to `ErrorKind::ALL`.
+            match kind {
+                ErrorKind::InvalidGatewayResponse
+                | ErrorKind::DeserializationError
+                | ErrorKind::UnknownPartitionKeyRange
+                | ErrorKind::InternalError
+                | ErrorKind::UnsupportedQueryPlan
+                | ErrorKind::InvalidUtf8String
+                | ErrorKind::ArgumentNull
+                | ErrorKind::ArithmeticOverflow
+                | ErrorKind::InvalidRequestId
+                | ErrorKind::InvalidQuery
+                | ErrorKind::MemoryBudgetExceeded
+                | ErrorKind::StateNotSerializable
+                | ErrorKind::PythonError
+                | ErrorKind::BufferLimitExceeded
+                | ErrorKind::SpillError
+                | ErrorKind::InternalPanic
+                | ErrorKind::Cancelled => {}
+            }
+
+            assert_ne!(0, kind.code(), "{kind:?} must not use the success code");
+            assert!(codes.insert(kind.code()), "{kind:?} reuses a code");
+            assert!(
+                !(kind.is_user_error() && kind.is_internal()),
+                "{kind:?} can't be both a user error and an internal error"
+            );
+        }
+        assert_eq!(17, codes.len());
+    }
+
+    #[test]
+    pub fn every_kind_has_an_http_status() {
+        for &kind in ErrorKind::ALL {
+            let status = kind.http_status();
+            assert!((400..600).contains(&status), "{kind:?} has no error status");
+            if kind.is_user_error() {
+                assert!(
+                    status < 500,
+                    "{kind:?} is a user error, but not a client error"
+                );
+            }
+            if kind.is_internal() {
+                assert_eq!(500, status, "{kind:?} is an internal error");
+            }
+        }
+        assert_eq!(400, ErrorKind::InvalidQuery.http_status());
+        assert_eq!(429, ErrorKind::MemoryBudgetExceeded.http_status());
+        assert_eq!(499, ErrorKind::Cancelled.http_status());
+        assert_eq!(502, ErrorKind::InvalidGatewayResponse.http_status());
+    }
+
+    #[test]
+    pub fn error_reports_kind_classification() {
+        let err = ErrorKind::InvalidQuery.with_message("bad query");
+        assert_eq!(11, err.code());
+        assert_eq!(400, err.http_status());
+        assert!(err.is_user_error());
+        assert!(!err.is_internal());
+    }
+
+    #[test]
+    pub fn panic_payload_becomes_message() {
+        let err = Error::from_panic(Box::new("static message"));
+        assert_eq!(ErrorKind::InternalPanic, err.kind());
+        assert_eq!("client engine panicked: static message", err.to_string());
+        assert!(err.is_internal());
+
+        let err = Error::from_panic(Box::new(format!("formatted {}", 42)));
+        assert_eq!("client engine panicked: formatted 42", err.to_string());
+
+        let err = Error::from_panic(Box::new(42));
+        assert_eq!("client engine panicked", err.to_string());
+    }
+}
@@ -3,6 +3,8 @@
 
 use std::fmt::Write;
 
+use serde::Deserialize;
+
 use crate::murmur_hash::{murmurhash3_128, murmurhash3_32};
 
 const MAX_STRING_BYTES_TO_APPEND: usize = 100;
@@ -30,13 +32,91 @@ pub enum PartitionKeyValue {
     Undefined,
 }
 
-#[derive(PartialEq, Eq)]
+impl TryFrom<&serde_json::Value> for PartitionKeyValue {
+    type Error = crate::Error;
+
+    /// Converts a JSON partition key value, as it appears in an item, into a [`PartitionKeyValue`] suitable for hashing.
+    ///
+    /// JSON numbers are hashed as 64-bit floats, matching the other Cosmos SDKs.
+    /// Arrays and objects are not valid partition key values.
+    fn try_from(value: &serde_json::Value) -> crate::Result<Self> {
+        match value {
+            serde_json::Value::Null => Ok(PartitionKeyValue::Null),
+            serde_json::Value::Bool(b) => Ok(PartitionKeyValue::Bool(*b)),
+            serde_json::Value::Number(n) => {
+                n.as_f64().map(PartitionKeyValue::Number).ok_or_else(|| {
+                    crate::ErrorKind::DeserializationError
+                        .with_message(format!("partition key value {} is not a valid number", n))
+                })
+            }
+            serde_json::Value::String(s) => Ok(PartitionKeyValue::String(s.clone())),
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+                Err(crate::ErrorKind::DeserializationError.with_message(
+                    "partition key values must be strings, numbers, booleans or null",
+                ))
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PartitionKeyValue {
+    /// Deserializes a partition key value from its JSON representation, see [`PartitionKeyValue::try_from`].
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        PartitionKeyValue::try_from(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
 pub enum PartitionKeyKind {
     Hash,
     MultiHash,
+    #[serde(other)]
     Other,
 }
 
+/// Describes the partition key of a container.
+///
+/// This deserializes from the `partitionKey` property of a container resource, for example
+/// `{"paths": ["/tenantId", "/userId"], "kind": "MultiHash", "version": 2}`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct PartitionKeyDefinition {
+    /// The paths of the partition key properties, in order. Containers with hierarchical partition keys have more than one path.
+    pub paths: Vec<String>,
+
+    /// The kind of partitioning used by the container.
+    pub kind: PartitionKeyKind,
+
+    /// The version of the hashing scheme, which is 1 for containers that don't specify a version.
+    #[serde(default = "PartitionKeyDefinition::default_version")]
+    pub version: u8,
+}
+
+impl PartitionKeyDefinition {
+    pub fn new(
+        paths: impl IntoIterator<Item = impl Into<String>>,
+        kind: PartitionKeyKind,
+        version: u8,
+    ) -> Self {
+        Self {
+            paths: paths.into_iter().map(Into::into).collect(),
+            kind,
+            version,
+        }
+    }
+
+    fn default_version() -> u8 {
+        1
+    }
+
+    /// Computes the effective partition key string for the given partition key values, using this definition's kind and version.
+    ///
+    /// For hierarchical partition keys, fewer values than paths may be provided to compute the effective partition key of a prefix.
+    pub fn effective_partition_key(&self, pk_value: &[PartitionKeyValue]) -> String {
+        get_hashed_partition_key_string(pk_value, self.kind, self.version)
+    }
+}
+
 impl PartitionKeyValue {
     /// Common hashing writer core: writes type marker + payload (string suffix used by V2).
     fn write_for_hashing_core(&self, string_suffix: u8, writer: &mut Vec<u8>) {
@@ -199,11 +279,10 @@ pub fn get_hashed_partition_key_string(
         },
         // hpk only supports V2
         PartitionKeyKind::MultiHash => {
-            panic!("MultiHash currently not supported. Pending additional testing.");
-            // if version != 2 {
-            //     panic!("MultiHash partitioning only supports version 2");
-            // }
-            // get_effective_partition_key_for_multi_hash_partitioning_v2(pk_value)
+            if version != 2 {
+                panic!("MultiHash partitioning only supports version 2");
+            }
+            get_effective_partition_key_for_multi_hash_partitioning_v2(pk_value)
         }
         _ => to_hex_encoded_binary_string(pk_value),
     }
@@ -224,21 +303,25 @@ fn get_effective_partition_key_for_hash_partitioning_v2(pk_value: &[PartitionKey
 }
 
 /// Multi-hash V2: compute per-component hash similarly and concatenate uppercase hex segments.
-// fn get_effective_partition_key_for_multi_hash_partitioning_v2(
-//     pk_value: &[PartitionKeyValue],
-// ) -> String {
-//     let mut pieces: Vec<String> = Vec::new();
-//     for comp in pk_value {
-//         let mut ms: Vec<u8> = Vec::new();
-//         write_for_hashing_v2(comp, &mut ms);
-//         let hash_128 = murmurhash3_128(&ms, 0);
-//         let mut hash_bytes = hash_128.to_le_bytes();
-//         hash_bytes.reverse();
-//         hash_bytes[0] &= 0x3F;
-//         pieces.push(bytes_to_hex_upper(&hash_bytes));
-//     }
-//     pieces.join("").to_uppercase()
-// }
+///
+/// A prefix of the partition key paths (e.g. just the first path of a two-path key) hashes to a prefix of the full effective partition key,
+/// which is what allows prefix queries to target a subset of partitions.
+fn get_effective_partition_key_for_multi_hash_partitioning_v2(
+    pk_value: &[PartitionKeyValue],
+) -> String {
+    let mut pieces = String::with_capacity(pk_value.len() * 32);
+    for comp in pk_value {
+        let mut ms: Vec<u8> = Vec::new();
+        comp.write_for_hashing_v2(&mut ms);
+        let hash_128 = murmurhash3_128(&ms, 0);
+        let mut hash_bytes = hash_128.to_le_bytes();
+        hash_bytes.reverse();
+        // Reset 2 most significant bits of first byte
+        hash_bytes[0] &= 0x3F;
+        pieces.push_str(&bytes_to_hex_upper(&hash_bytes));
+    }
+    pieces
+}
 
 /// V1: compute 32-bit murmur hash over concatenated component encodings (suffix 0x00 for strings),
 /// convert hash (u32) to f64 (possible precision loss is intentional to mirror other sdks), then binary-encode
@@ -314,6 +397,29 @@ fn bytes_to_hex_lower(bytes: &[u8]) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_partition_key_definition_from_json() {
+        let definition: PartitionKeyDefinition =
+            serde_json::from_str(r#"{"paths": ["/pk"], "kind": "Hash"}"#).unwrap();
+        assert_eq!(
+            PartitionKeyDefinition::new(["/pk"], PartitionKeyKind::Hash, 1),
+            definition
+        );
+
+        let definition: PartitionKeyDefinition = serde_json::from_str(
+            r#"{"paths": ["/tenantId", "/userId"], "kind": "MultiHash", "version": 2}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            PartitionKeyDefinition::new(["/tenantId", "/userId"], PartitionKeyKind::MultiHash, 2),
+            definition
+        );
+
+        let definition: PartitionKeyDefinition =
+            serde_json::from_str(r#"{"paths": ["/pk"], "kind": "Range"}"#).unwrap();
+        assert_eq!(PartitionKeyKind::Other, definition.kind);
+    }
+
     #[test]
     fn test_empty_pk() {
         let result = get_hashed_partition_key_string(&[], PartitionKeyKind::Hash, 0);
@@ -427,48 +533,54 @@ mod tests {
         }
     }
 
-    // #[test]
-    // fn test_effective_partition_key_hpk() {
-    //     // expected results come from python sdk
-    //     let cases = vec![
-    //         (
-    //             vec![
-    //                 PartitionKeyValue::String(String::from(
-    //                     "title_player_account!9E711EFBD3BBB492",
-    //                 )),
-    //                 PartitionKeyValue::String(String::from("Title-B60C1")),
-    //             ],
-    //             "2306FDF78C35ED4FD1C5835B075FC0B0248E1F58635558D12708326234F93A21",
-    //         ),
-    //         (
-    //             vec![PartitionKeyValue::String(String::from(
-    //                 "title_player_account!9E711EFBD3BBB499",
-    //             ))],
-    //             "378CCD42FC556DDDE688B05DC178BB92",
-    //         ),
-    //         (
-    //             vec![PartitionKeyValue::Bool(false), PartitionKeyValue::Null],
-    //             "2FE1BE91E90A3439635E0E9E37361EF2378867E4430E67857ACE5C908374FE16",
-    //         ),
-    //         // debugging currently
-    //         // (
-    //         //     vec![
-    //         //         PartitionKeyValue::Number(1234 as f64),
-    //         //         PartitionKeyValue::Undefined,
-    //         //     ],
-    //         //     "266B73B33A7065810B7D2A2938F85E80378867E4430E67857ACE5C908374FE16",
-    //         // ),
-    //     ];
-
-    //     for (components, expected) in cases {
-    //         let actual = get_hashed_partition_key_string(
-    //             &components,
-    //             Some(PartitionKeyKind::MultiHash),
-    //             Some(2),
-    //         );
-    //         assert_eq!(actual, expected, "Mismatch for multi-hash composite key");
-    //     }
-    // }
+    #[test]
+    fn test_effective_partition_key_hpk() {
+        // expected results come from python sdk
+        let cases = vec![
+            (
+                vec![
+                    PartitionKeyValue::String(String::from(
+                        "title_player_account!9E711EFBD3BBB492",
+                    )),
+                    PartitionKeyValue::String(String::from("Title-B60C1")),
+                ],
+                "2306FDF78C35ED4FD1C5835B075FC0B0248E1F58635558D12708326234F93A21",
+            ),
+            (
+                vec![PartitionKeyValue::String(String::from(
+                    "title_player_account!9E711EFBD3BBB499",
+                ))],
+                "378CCD42FC556DDDE688B05DC178BB92",
+            ),
+            (
+                vec![PartitionKeyValue::Bool(false), PartitionKeyValue::Null],
+                "2FE1BE91E90A3439635E0E9E37361EF2378867E4430E67857ACE5C908374FE16",
+            ),
+        ];
+
+        for (components, expected) in cases {
+            let actual =
+                get_hashed_partition_key_string(&components, PartitionKeyKind::MultiHash, 2);
+            assert_eq!(actual, expected, "Mismatch for multi-hash composite key");
+        }
+    }
+
+    #[test]
+    fn test_effective_partition_key_hpk_prefix() {
+        let full = vec![
+            PartitionKeyValue::String(String::from("tenant1")),
+            PartitionKeyValue::String(String::from("user1")),
+            PartitionKeyValue::Number(42.0),
+        ];
+
+        let full_epk = get_hashed_partition_key_string(&full, PartitionKeyKind::MultiHash, 2);
+        assert_eq!(full_epk.len(), 96);
+        for len in 1..full.len() {
+            let prefix_epk =
+                get_hashed_partition_key_string(&full[..len], PartitionKeyKind::MultiHash, 2);
+            assert_eq!(&full_epk[..len * 32], prefix_epk);
+        }
+    }
 
     #[test]
     fn test_effective_partition_key_hash_v2_multiple_keys() {
@@ -484,6 +596,70 @@ mod tests {
         assert_eq!(actual, expected, "Mismatch for component hash");
     }
 
+    #[test]
+    fn test_partition_key_value_from_json() -> crate::Result<()> {
+        // A batch mixing numeric and string keys should hash each key according to its JSON type.
+        let cases = vec![
+            (serde_json::json!(5), "19C08621B135968252FB34B4CF66F811"),
+            (serde_json::json!(5.0), "19C08621B135968252FB34B4CF66F811"),
+            (serde_json::json!(-128), "01DAEDABF913540367FE219B2AD06148"),
+            (
+                serde_json::json!("redmond"),
+                "22E342F38A486A088463DFF7838A5963",
+            ),
+            (serde_json::json!(true), "0E711127C5B5A8E4726AC6DD306A3E59"),
+            (serde_json::json!(null), "378867E4430E67857ACE5C908374FE16"),
+        ];
+
+        for (value, expected) in cases {
+            let component = PartitionKeyValue::try_from(&value)?;
+            let actual = get_hashed_partition_key_string(&[component], PartitionKeyKind::Hash, 2);
+            assert_eq!(actual, expected, "Mismatch for JSON value {}", value);
+        }
+
+        // The number 5 and the string "5" are different partition keys.
+        assert_ne!(
+            PartitionKeyValue::try_from(&serde_json::json!(5))?,
+            PartitionKeyValue::try_from(&serde_json::json!("5"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_key_value_from_json_rejects_containers() {
+        assert!(PartitionKeyValue::try_from(&serde_json::json!([1])).is_err());
+        assert!(PartitionKeyValue::try_from(&serde_json::json!({"a": 1})).is_err());
+    }
+
+    #[test]
+    fn test_partition_key_value_deserialize() {
+        let values: Vec<PartitionKeyValue> =
+            serde_json::from_str(r#"["redmond", 5, -128, true, false, null]"#).unwrap();
+        assert_eq!(
+            vec![
+                PartitionKeyValue::String("redmond".to_string()),
+                PartitionKeyValue::Number(5.0),
+                PartitionKeyValue::Number(-128.0),
+                PartitionKeyValue::Bool(true),
+                PartitionKeyValue::Bool(false),
+                PartitionKeyValue::Null,
+            ],
+            values
+        );
+
+        // Deserialized values hash the same as the values they represent, for both hashing versions.
+        assert_eq!(
+            "05C1D7C5A903D803",
+            get_hashed_partition_key_string(&values[3..4], PartitionKeyKind::Hash, 1)
+        );
+        assert_eq!(
+            "01DAEDABF913540367FE219B2AD06148",
+            get_hashed_partition_key_string(&values[2..3], PartitionKeyKind::Hash, 2)
+        );
+
+        assert!(serde_json::from_str::<PartitionKeyValue>(r#"{"a": 1}"#).is_err());
+    }
+
     #[test]
     fn test_effective_partition_key_hash_v1() {
         // Expected strings are the direct V1 effective partition key representations (uppercase hex).
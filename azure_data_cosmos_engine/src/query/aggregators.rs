@@ -2,6 +2,7 @@
 // Licensed under the MIT License.
 
 use std::{
+    collections::HashSet,
     ops::{Add, AddAssign},
     str::FromStr,
 };
@@ -76,11 +77,37 @@ impl TryFrom<Sum> for serde_json::Number {
 
 #[derive(Debug)]
 pub enum Aggregator {
-    Count { count: u64 },
-    Sum { sum: Sum },
-    Average { sum: f64, count: u64 },
-    Min { min: Option<QueryClauseItem> },
-    Max { max: Option<QueryClauseItem> },
+    Count {
+        count: u64,
+    },
+    CountIf {
+        count: u64,
+    },
+    Sum {
+        sum: Sum,
+    },
+    Average {
+        sum: f64,
+        count: u64,
+    },
+    Min {
+        min: Option<QueryClauseItem>,
+    },
+    Max {
+        max: Option<QueryClauseItem>,
+    },
+    MakeList {
+        items: Vec<serde_json::Value>,
+    },
+    /// Collects distinct values in the order they're first seen, de-duplicated by their canonical JSON form (see [`canonical_key`]).
+    MakeSet {
+        items: Vec<serde_json::Value>,
+        seen: HashSet<String>,
+    },
+    /// Counts distinct values, keyed by the canonical JSON form of each value (see [`canonical_key`]).
+    DCount {
+        values: HashSet<String>,
+    },
 }
 
 impl FromStr for Aggregator {
@@ -91,6 +118,8 @@ impl FromStr for Aggregator {
         // This allows us to do the comparison in a case-insensitive way without having to allocate a new string.
         if s.eq_ignore_ascii_case("count") {
             Ok(Aggregator::Count { count: 0 })
+        } else if s.eq_ignore_ascii_case("countif") {
+            Ok(Aggregator::CountIf { count: 0 })
         } else if s.eq_ignore_ascii_case("sum") {
             Ok(Aggregator::Sum { sum: Sum::Empty })
         } else if s.eq_ignore_ascii_case("average") {
@@ -99,6 +128,17 @@ impl FromStr for Aggregator {
             Ok(Aggregator::Min { min: None })
         } else if s.eq_ignore_ascii_case("max") {
             Ok(Aggregator::Max { max: None })
+        } else if s.eq_ignore_ascii_case("makelist") {
+            Ok(Aggregator::MakeList { items: Vec::new() })
+        } else if s.eq_ignore_ascii_case("makeset") {
+            Ok(Aggregator::MakeSet {
+                items: Vec::new(),
+                seen: HashSet::new(),
+            })
+        } else if s.eq_ignore_ascii_case("dcount") {
+            Ok(Aggregator::DCount {
+                values: HashSet::new(),
+            })
         } else {
             Err(ErrorKind::UnsupportedQueryPlan.with_message(format!("unknown aggregator: {}", s)))
         }
@@ -108,7 +148,9 @@ impl FromStr for Aggregator {
 impl Aggregator {
     pub fn into_value(self) -> crate::Result<Option<serde_json::Value>> {
         let value = match self {
-            Aggregator::Count { count } => Some(serde_json::Value::Number(count.into())),
+            Aggregator::Count { count } | Aggregator::CountIf { count } => {
+                Some(serde_json::Value::Number(count.into()))
+            }
             Aggregator::Sum { sum } => Some(serde_json::Value::Number(sum.try_into()?)),
             Aggregator::Average { sum, count } => {
                 if count == 0 {
@@ -125,6 +167,10 @@ impl Aggregator {
             }
             Aggregator::Min { min, .. } => min.and_then(|c| c.item),
             Aggregator::Max { max, .. } => max.and_then(|c| c.item),
+            Aggregator::MakeList { items } | Aggregator::MakeSet { items, .. } => {
+                Some(serde_json::Value::Array(items))
+            }
+            Aggregator::DCount { values } => Some(serde_json::Value::Number(values.len().into())),
         };
         Ok(value)
     }
@@ -133,12 +179,15 @@ impl Aggregator {
     pub fn aggregate(&mut self, clause_item: &QueryClauseItem) -> crate::Result<()> {
         match self {
             Aggregator::Count { count } => {
-                let value = require_non_null_value(clause_item, "count")?;
-                let int_value = value.as_u64().ok_or_else(|| {
-                    crate::ErrorKind::InvalidGatewayResponse
-                        .with_message("count aggregator expects an integer value")
-                })?;
-                *count += int_value;
+                *count += require_count_value(clause_item, "count")?;
+            }
+            Aggregator::CountIf { count } => {
+                // The gateway rewrites COUNTIF so that each partition returns a partial count of matching items.
+                // A partition may instead return the boolean result of the condition, in which case only `true` is counted.
+                match require_non_null_value(clause_item, "countif")? {
+                    serde_json::Value::Bool(matched) => *count += u64::from(*matched),
+                    _ => *count += require_count_value(clause_item, "countif")?,
+                }
             }
             Aggregator::Sum { sum } => {
                 let value = require_non_null_value(clause_item, "sum")?;
@@ -174,6 +223,28 @@ impl Aggregator {
                     *max = Some(new);
                 }
             }
+            Aggregator::MakeList { items } => {
+                let partial = require_array_value(clause_item, "makelist")?;
+                items.extend(partial.iter().cloned());
+            }
+            Aggregator::MakeSet { items, seen } => {
+                // Each partition has already de-duplicated its own partial set, but the same value may appear in several partitions.
+                // Values from different partitions may also differ in representation only (`1` vs `1.0`, or object property order).
+                let partial = require_array_value(clause_item, "makeset")?;
+                for value in partial {
+                    if seen.insert(canonical_key(value)) {
+                        items.push(value.clone());
+                    }
+                }
+            }
+            Aggregator::DCount { values } => {
+                // Each partition returns the distinct values it contains, and a value may appear in several partitions.
+                // Unlike MakeSet, we only need the number of values, so we keep a canonical key for each one rather than the values themselves.
+                let partial = require_array_value(clause_item, "dcount")?;
+                for value in partial {
+                    values.insert(canonical_key(value));
+                }
+            }
         }
         Ok(())
     }
@@ -192,6 +263,78 @@ fn require_non_null_value<'a>(
     })
 }
 
+/// Helper function to extract a partial count from a QueryClauseItem.
+fn require_count_value(clause_item: &QueryClauseItem, aggregator_name: &str) -> crate::Result<u64> {
+    require_non_null_value(clause_item, aggregator_name)?
+        .as_u64()
+        .ok_or_else(|| {
+            crate::ErrorKind::InvalidGatewayResponse.with_message(format!(
+                "{} aggregator expects an integer value",
+                aggregator_name
+            ))
+        })
+}
+
+/// Helper function to extract an array value (a partial list or set) from a QueryClauseItem.
+fn require_array_value<'a>(
+    clause_item: &'a QueryClauseItem,
+    aggregator_name: &str,
+) -> crate::Result<&'a Vec<serde_json::Value>> {
+    require_non_null_value(clause_item, aggregator_name)?
+        .as_array()
+        .ok_or_else(|| {
+            crate::ErrorKind::InvalidGatewayResponse.with_message(format!(
+                "{} aggregator expects an array value",
+                aggregator_name
+            ))
+        })
+}
+
+/// Produces a canonical string form of a JSON value, such that two values have the same key if and only if they are equal in Cosmos DB.
+///
+/// Object properties are sorted by name, and floating-point numbers with integral values are written as integers, so `1` and `1.0` are the same value.
+fn canonical_key(value: &serde_json::Value) -> String {
+    fn write(value: &serde_json::Value, out: &mut String) {
+        match value {
+            serde_json::Value::Number(n) => match n.as_f64() {
+                Some(f) if n.is_f64() && f.fract() == 0.0 && f.abs() < (1u64 << 53) as f64 => {
+                    out.push_str(&(f as i64).to_string())
+                }
+                _ => out.push_str(&n.to_string()),
+            },
+            serde_json::Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write(item, out);
+                }
+                out.push(']');
+            }
+            serde_json::Value::Object(map) => {
+                let mut entries = map.iter().collect::<Vec<_>>();
+                entries.sort_by_key(|(key, _)| *key);
+                out.push('{');
+                for (i, (key, item)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    out.push_str(&serde_json::Value::String(key.clone()).to_string());
+                    out.push(':');
+                    write(item, out);
+                }
+                out.push('}');
+            }
+            _ => out.push_str(&value.to_string()),
+        }
+    }
+
+    let mut out = String::new();
+    write(value, &mut out);
+    out
+}
+
 fn better_minmax_candidate(
     current: &Option<QueryClauseItem>,
     candidate: &QueryClauseItem,
@@ -276,6 +419,70 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn count_if() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "CountIf".parse()?;
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(4)))?;
+        // Partitions with no matching items return a zero count
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(0)))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(2)))?;
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!(6)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn count_if_booleans() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "countif".parse()?;
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(true)))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(false)))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(3)))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(true)))?;
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!(5)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn count_if_all_false() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "countif".parse()?;
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(false)))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(false)))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(0)))?;
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!(0)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn count_if_rejects_non_count_values() {
+        let mut aggregator = Aggregator::CountIf { count: 0 };
+
+        let err = aggregator
+            .aggregate(&QueryClauseItem::from_value(json!("yes")))
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
+    }
+
+    #[test]
+    fn count_if_empty() -> crate::Result<()> {
+        let aggregator = Aggregator::CountIf { count: 0 };
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!(0)));
+
+        Ok(())
+    }
+
     #[test]
     fn sum() -> crate::Result<()> {
         let mut aggregator = Aggregator::Sum { sum: Sum::Empty };
@@ -512,4 +719,146 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn make_list() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "MakeList".parse()?;
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([1, "a"])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([1, {"b": true}])))?;
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!([1, "a", 1, {"b": true}])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn make_list_empty() -> crate::Result<()> {
+        let mut aggregator = Aggregator::MakeList { items: Vec::new() };
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([])))?;
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!([])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn make_set() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "makeset".parse()?;
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([1, "a", {"b": 1}])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(["a", 2, {"b": 1}])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([{"b": 2}, 1])))?;
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!([1, "a", {"b": 1}, 2, {"b": 2}])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn make_set_empty() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "MakeSet".parse()?;
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([])))?;
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!([])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn make_set_canonical_duplicates() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "MakeSet".parse()?;
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([1, {"a": 1, "b": 2}])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(
+            json!([1.0, {"b": 2, "a": 1}, "1"]),
+        ))?;
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!([1, {"a": 1, "b": 2}, "1"])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn make_list_rejects_non_array() {
+        let mut aggregator = Aggregator::MakeList { items: Vec::new() };
+
+        let err = aggregator
+            .aggregate(&QueryClauseItem::from_value(json!(42)))
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
+    }
+
+    #[test]
+    fn dcount() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "dcount".parse()?;
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(["US", "CA", 1])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!(["CA", "MX", 2])))?;
+
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!(5)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn dcount_mixed_types() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "DCount".parse()?;
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([
+            1,
+            "1",
+            true,
+            null,
+            [1, 2],
+            {"a": 1, "b": 2}
+        ])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([
+            1.0,
+            "1",
+            false,
+            null,
+            [2, 1],
+            {"b": 2, "a": 1},
+            1.5
+        ])))?;
+
+        // 1 and 1.0 are the same value, as are objects with the same properties in a different order.
+        // Arrays are ordered, so [1, 2] and [2, 1] are different values.
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!(9)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn dcount_empty() -> crate::Result<()> {
+        let mut aggregator: Aggregator = "dcount".parse()?;
+
+        let result = Aggregator::DCount {
+            values: HashSet::new(),
+        }
+        .into_value()?;
+        assert_eq!(result, Some(json!(0)));
+
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([])))?;
+        aggregator.aggregate(&QueryClauseItem::from_value(json!([])))?;
+        let result = aggregator.into_value()?;
+        assert_eq!(result, Some(json!(0)));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,33 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// A flag used to cancel a query from another thread, while the pipeline is busy with a turn.
+///
+/// Clones share the same flag, so the language binding can keep a clone, and cancel it while another thread runs the pipeline.
+/// The pipeline checks the token between the items it produces, and between the pages it deserializes, and fails with
+/// [`ErrorKind::Cancelled`](crate::ErrorKind::Cancelled) once it sees that the token was cancelled.
+/// See [`QueryPipeline::run_with_cancel`](crate::query::QueryPipeline::run_with_cancel) and [`QueryPipeline::cancellation_token`](crate::query::QueryPipeline::cancellation_token).
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new token, which hasn't been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token, and every clone of it. Cancelling a token more than once has no further effect.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Indicates if the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
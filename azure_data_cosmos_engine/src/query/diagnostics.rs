@@ -0,0 +1,127 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Optional timing instrumentation for the query pipeline, enabled by the `diagnostics` feature.
+
+use std::time::{Duration, Instant};
+
+/// A source of monotonic time, used to time the work the pipeline does.
+///
+/// The pipeline uses [`SystemClock`] by default. Tests can provide their own clock to control the durations that are recorded.
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    /// Gets the time elapsed since an arbitrary, fixed, starting point.
+    fn now(&self) -> Duration;
+}
+
+/// A [`Clock`] that uses the system's monotonic clock.
+#[derive(Debug)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Running totals describing how long the pipeline has spent parsing the pages provided to it.
+///
+/// Parsing includes deserializing the page and adding its items to the partition buffers, but not merging or yielding them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseTimings {
+    /// The number of pages parsed.
+    pub pages: u64,
+
+    /// The total size, in bytes, of the pages parsed.
+    pub bytes: u64,
+
+    /// The total time spent parsing pages.
+    pub total: Duration,
+
+    /// The longest time spent parsing a single page.
+    pub max: Duration,
+}
+
+impl ParseTimings {
+    /// Records the time spent parsing a single page.
+    pub(crate) fn record(&mut self, bytes: usize, duration: Duration) {
+        self.pages += 1;
+        self.bytes += bytes as u64;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+
+    /// Gets the average time spent parsing a page, or `None` if no pages have been parsed.
+    pub fn average(&self) -> Option<Duration> {
+        u32::try_from(self.pages)
+            .ok()
+            .filter(|&pages| pages > 0)
+            .map(|pages| self.total / pages)
+    }
+}
+
+/// Timing instrumentation collected by the pipeline.
+#[derive(Debug)]
+pub(crate) struct Diagnostics {
+    clock: Box<dyn Clock>,
+    pub parse: ParseTimings,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self {
+            clock: Box::new(SystemClock::default()),
+            parse: ParseTimings::default(),
+        }
+    }
+}
+
+impl Diagnostics {
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Runs `f`, recording the time it takes as the time spent parsing a page of `bytes` bytes.
+    ///
+    /// The time is recorded even if `f` fails, since the parsing work was still done.
+    pub fn time_parse<T>(&mut self, bytes: usize, f: impl FnOnce() -> T) -> T {
+        let start = self.clock.now();
+        let result = f();
+        let elapsed = self.clock.now().saturating_sub(start);
+        self.parse.record(bytes, elapsed);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_parse_time() {
+        let mut timings = ParseTimings::default();
+        assert_eq!(None, timings.average());
+
+        timings.record(100, Duration::from_millis(2));
+        timings.record(300, Duration::from_millis(6));
+        assert_eq!(
+            ParseTimings {
+                pages: 2,
+                bytes: 400,
+                total: Duration::from_millis(8),
+                max: Duration::from_millis(6),
+            },
+            timings
+        );
+        assert_eq!(Some(Duration::from_millis(4)), timings.average());
+    }
+}
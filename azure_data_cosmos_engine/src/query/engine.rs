@@ -2,6 +2,21 @@
 // Licensed under the MIT License.
 
 //! Provides an implementation of the Azure Data Cosmos SDK query engine API.
+//!
+//! This is available with the `query_engine` feature, which is enabled by default.
+//! It lets the Rust SDK execute cross-partition queries with this engine, by passing a [`QueryEngine`] in the query options:
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use azure_data_cosmos::QueryOptions;
+//! use azure_data_cosmos_engine::query::QueryEngine;
+//!
+//! let options = QueryOptions {
+//!     query_engine: Some(Arc::new(QueryEngine)),
+//!     ..Default::default()
+//! };
+//! ```
 
 use core::str;
 
@@ -9,6 +24,12 @@ use serde::Deserialize;
 
 use crate::query::{PartitionKeyRange, QueryPipeline};
 
+/// A query engine for the Azure Data Cosmos SDK, which creates a [`QueryPipeline`] for each query.
+///
+/// Every query the pipeline supports can be executed this way, including aggregates and hybrid search.
+/// The engine reports [`SUPPORTED_FEATURES`](crate::query::SUPPORTED_FEATURES) to the SDK, so the gateway rejects queries it can't execute.
+/// Errors from the pipeline are converted to [`azure_core::Error`]s, keeping the original [`Error`](crate::Error) as their source.
+#[derive(Clone, Copy, Debug, Default)]
 pub struct QueryEngine;
 
 impl azure_data_cosmos::query::QueryEngine for QueryEngine {
@@ -38,12 +59,12 @@ impl azure_data_cosmos::query::QueryEngine for QueryEngine {
 impl From<crate::Error> for azure_core::Error {
     fn from(err: crate::Error) -> Self {
         let kind = match err.kind() {
-            crate::ErrorKind::DeserializationError => azure_core::error::ErrorKind::DataConversion,
-            crate::ErrorKind::UnknownPartitionKeyRange => {
-                azure_core::error::ErrorKind::DataConversion
-            }
-            crate::ErrorKind::UnsupportedQueryPlan => azure_core::error::ErrorKind::DataConversion,
-            crate::ErrorKind::InvalidUtf8String => azure_core::error::ErrorKind::DataConversion,
+            crate::ErrorKind::InvalidGatewayResponse
+            | crate::ErrorKind::DeserializationError
+            | crate::ErrorKind::UnknownPartitionKeyRange
+            | crate::ErrorKind::UnsupportedQueryPlan
+            | crate::ErrorKind::InvalidUtf8String => azure_core::error::ErrorKind::DataConversion,
+            crate::ErrorKind::SpillError => azure_core::error::ErrorKind::Io,
             _ => azure_core::error::ErrorKind::Other,
         };
         let message = format!("{}", &err);
@@ -51,8 +72,20 @@ impl From<crate::Error> for azure_core::Error {
     }
 }
 
+/// Adapts a [`QueryPipeline`] to the Azure Data Cosmos SDK's pipeline API, see [`QueryEngine`].
+///
+/// Each request is forwarded with its own ID, continuation and query text, since hybrid search queries a partition with several different queries.
+/// The SDK returns the ID with the response, which is how the pipeline matches a response to the request it answers.
+#[derive(Debug)]
 pub struct QueryPipelineAdapter(crate::query::QueryPipeline);
 
+impl QueryPipelineAdapter {
+    /// Gets the query pipeline being adapted.
+    pub fn pipeline(&self) -> &QueryPipeline {
+        &self.0
+    }
+}
+
 impl azure_data_cosmos::query::QueryPipeline for QueryPipelineAdapter {
     fn query(&self) -> Option<&str> {
         self.0.query()
@@ -93,8 +126,62 @@ impl azure_data_cosmos::query::QueryPipeline for QueryPipelineAdapter {
                 data.request_id,
                 data.result,
                 data.next_continuation,
+                None,
             )?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use azure_data_cosmos::query::{QueryEngine as _, QueryPipeline as _};
+
+    use super::*;
+
+    const PKRANGES: &[u8] = br#"{"PartitionKeyRanges":[
+        {"id":"partition0","minInclusive":"","maxExclusive":"80"},
+        {"id":"partition1","minInclusive":"80","maxExclusive":"FF"}
+    ]}"#;
+
+    #[test]
+    fn requests_are_forwarded_with_their_ids() {
+        let plan = br#"{"partitionedQueryExecutionInfoVersion":1,"queryInfo":{"orderBy":["Ascending"],"rewrittenQuery":"SELECT c._rid FROM c ORDER BY c.x"},"queryRanges":[]}"#;
+        let mut pipeline = QueryEngine
+            .create_pipeline("SELECT * FROM c ORDER BY c.x", plan, PKRANGES)
+            .unwrap();
+
+        let result = pipeline.run().unwrap();
+        assert!(!result.is_completed);
+        let requests = result
+            .requests
+            .iter()
+            .map(|r| (r.id, r.partition_key_range_id.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(0, "partition0"), (0, "partition1")], requests);
+    }
+
+    #[test]
+    fn errors_keep_their_kind_and_source() {
+        let err = azure_core::Error::from(
+            crate::ErrorKind::UnsupportedQueryPlan.with_message("unsupported clause"),
+        );
+        assert_eq!(&azure_core::error::ErrorKind::DataConversion, err.kind());
+        assert!(err.to_string().contains("unsupported clause"));
+
+        let err = azure_core::Error::from(crate::ErrorKind::SpillError.with_message("disk full"));
+        assert_eq!(&azure_core::error::ErrorKind::Io, err.kind());
+        let source = std::error::Error::source(&err)
+            .and_then(|s| s.downcast_ref::<crate::Error>())
+            .unwrap();
+        assert_eq!(crate::ErrorKind::SpillError, source.kind());
+    }
+
+    #[test]
+    fn supported_features_match_pipeline() {
+        assert_eq!(
+            crate::query::SUPPORTED_FEATURES.as_str(),
+            QueryEngine.supported_features().unwrap()
+        );
+    }
+}
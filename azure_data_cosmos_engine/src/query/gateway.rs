@@ -0,0 +1,127 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    query::{DataRequest, PartitionKeyRange},
+    ErrorKind,
+};
+
+/// A page of results, in the format the gateway uses when it returns query results to a client SDK.
+///
+/// This is intended for proxy scenarios, where the engine sits between a client SDK and Cosmos DB and must re-emit
+/// results in the exact envelope that the client SDK expects from the gateway.
+/// See [`QueryPipeline::run_as_gateway_page`](crate::query::QueryPipeline::run_as_gateway_page).
+#[derive(Clone, Debug)]
+pub struct GatewayPage {
+    /// The items in this page.
+    pub documents: Vec<Box<serde_json::value::RawValue>>,
+
+    /// The continuation token the client should send back to retrieve the next page, or `None` if the query has completed.
+    ///
+    /// The token is a JSON array of [`CompositeContinuationToken`]s, one for each partition that still has data to return.
+    pub continuation: Option<String>,
+
+    /// Requests for additional data that must be fulfilled before the next page can be produced.
+    ///
+    /// These are NOT part of the gateway envelope, they are for the proxy hosting the pipeline.
+    pub requests: Vec<DataRequest>,
+}
+
+impl GatewayPage {
+    /// Gets the number of items in this page, which is the value of the `_count` property in the envelope.
+    pub fn count(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Serializes the page to the gateway envelope, `{"Documents":[...],"_count":N}`.
+    pub fn to_json(&self) -> crate::Result<String> {
+        #[derive(Serialize)]
+        struct Envelope<'a> {
+            #[serde(rename = "Documents")]
+            documents: &'a [Box<serde_json::value::RawValue>],
+            #[serde(rename = "_count")]
+            count: usize,
+        }
+
+        serde_json::to_string(&Envelope {
+            documents: &self.documents,
+            count: self.count(),
+        })
+        .map_err(|e| ErrorKind::InternalError.with_source(e))
+    }
+}
+
+/// The continuation state of a single partition, in the composite format used by the gateway.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompositeContinuationToken {
+    /// The backend continuation token for the partition, or `None` if the partition has not been queried yet.
+    pub token: Option<String>,
+
+    /// The range of effective partition keys covered by the partition.
+    pub range: ContinuationRange,
+}
+
+/// The range of effective partition keys covered by a [`CompositeContinuationToken`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContinuationRange {
+    pub min: String,
+    pub max: String,
+}
+
+impl CompositeContinuationToken {
+    pub(crate) fn new(pkrange: &PartitionKeyRange, token: Option<String>) -> Self {
+        Self {
+            token,
+            range: ContinuationRange {
+                min: pkrange.min_inclusive.clone(),
+                max: pkrange.max_exclusive.clone(),
+            },
+        }
+    }
+
+    /// Parses a continuation token produced by [`GatewayPage`] back into the individual partition tokens.
+    pub fn parse_list(continuation: &str) -> crate::Result<Vec<Self>> {
+        serde_json::from_str(continuation)
+            .map_err(|e| ErrorKind::DeserializationError.with_source(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn envelope_shape() -> crate::Result<()> {
+        let page = GatewayPage {
+            documents: vec![
+                serde_json::value::to_raw_value(&json!({"id": "a"})).unwrap(),
+                serde_json::value::to_raw_value(&json!(42)).unwrap(),
+            ],
+            continuation: None,
+            requests: Vec::new(),
+        };
+
+        let envelope: serde_json::Value = serde_json::from_str(&page.to_json()?).unwrap();
+        assert_eq!(
+            json!({"Documents": [{"id": "a"}, 42], "_count": 2}),
+            envelope
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn empty_envelope() -> crate::Result<()> {
+        let page = GatewayPage {
+            documents: Vec::new(),
+            continuation: None,
+            requests: Vec::new(),
+        };
+
+        assert_eq!(r#"{"Documents":[],"_count":0}"#, page.to_json()?);
+        Ok(())
+    }
+}
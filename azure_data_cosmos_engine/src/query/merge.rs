@@ -0,0 +1,161 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::HashMap;
+
+use crate::{
+    query::{
+        producer::ItemProducer, query_result::FeedResponse, DataRequest, PartitionKeyRange,
+        PipelineResponse, SortOrder,
+    },
+    ErrorKind,
+};
+
+/// Merges several already-ordered result streams into a single ordered stream.
+///
+/// This enables hierarchical query execution, where separate engine instances each handle a subset of the partitions,
+/// and a coordinator merges their partially-merged results.
+/// Each stream must yield items in the `ORDER BY` result shape (`{"orderByItems": [...], "payload": ...}`), already sorted according to the same [`SortOrder`]s given to the merge pipeline.
+/// The merge uses the same comparison logic as a streaming `ORDER BY` query, and breaks ties by the order the streams were provided in.
+///
+/// Like [`QueryPipeline`](super::QueryPipeline), the merge pipeline operates in turns.
+/// Each call to [`MergePipeline::run`] yields the items that can be produced so far, and a [`DataRequest`] for each stream that hasn't terminated yet.
+/// The [`DataRequest::pkrange_id`] of those requests holds the ID of the stream, and the caller responds with [`MergePipeline::provide_response`].
+///
+/// The [`PipelineResponse::cumulative_request_charge`] of the merge is the sum of the latest cumulative charge reported by each stream,
+/// and its [`PipelineResponse::activity_ids`] are those reported by the streams since the previous turn.
+#[derive(Debug)]
+pub struct MergePipeline {
+    producer: ItemProducer,
+    preserve_order_by_items: bool,
+    request_charges: HashMap<String, f64>,
+    activity_ids: Vec<String>,
+
+    /// The number of pages provided by each stream, which is also the ID of the stream's next request.
+    pages_provided: HashMap<String, u64>,
+    terminated: bool,
+}
+
+impl MergePipeline {
+    /// Creates a new merge pipeline over the streams with the provided IDs.
+    ///
+    /// When items from different streams are equal, the item from the stream that appears first in `stream_ids` is produced first.
+    pub fn new(
+        stream_ids: impl IntoIterator<Item = impl Into<String>>,
+        order_by: Vec<SortOrder>,
+    ) -> Self {
+        // The producer orders partitions by their minimum EPK, so give each stream a range that sorts in the order provided.
+        let streams = stream_ids
+            .into_iter()
+            .enumerate()
+            .map(|(i, id)| {
+                PartitionKeyRange::new(id, format!("{:016X}", i), format!("{:016X}", i + 1))
+            })
+            .collect::<Vec<_>>();
+        Self {
+            producer: ItemProducer::streaming(streams, order_by),
+            preserve_order_by_items: false,
+            request_charges: HashMap::new(),
+            activity_ids: Vec::new(),
+            pages_provided: HashMap::new(),
+            terminated: false,
+        }
+    }
+
+    /// Configures the pipeline to yield items in the `ORDER BY` result shape, instead of just their payloads.
+    ///
+    /// This allows the output of this pipeline to be merged again by another [`MergePipeline`].
+    pub fn preserving_order_by_items(mut self) -> Self {
+        self.preserve_order_by_items = true;
+        self
+    }
+
+    /// Provides the next page of items from a stream.
+    ///
+    /// If `response.terminated` is `true`, this is the last page from that stream.
+    /// Any requests in the response are ignored, they are the concern of the engine instance that produced it.
+    pub fn provide_response(
+        &mut self,
+        stream_id: &str,
+        response: PipelineResponse,
+    ) -> crate::Result<()> {
+        let PipelineResponse {
+            items,
+            terminated,
+            cumulative_request_charge,
+            activity_ids,
+            ..
+        } = response;
+        let page = serde_json::to_vec(&FeedResponse { documents: items })
+            .map_err(|e| ErrorKind::InternalError.with_source(e))?;
+
+        // The producer tracks whether more data is expected using the continuation.
+        // Streams don't have continuations, so any value will do.
+        let continuation = (!terminated).then(String::new);
+        let request_id = self.pages_provided.get(stream_id).copied().unwrap_or(0);
+        self.producer
+            .provide_data(stream_id, request_id, &page, continuation)?;
+        self.pages_provided
+            .insert(stream_id.to_string(), request_id + 1);
+
+        // Each response carries the stream's running total, so it replaces the previous one.
+        self.request_charges
+            .insert(stream_id.to_string(), cumulative_request_charge);
+        self.activity_ids.extend(activity_ids);
+        Ok(())
+    }
+
+    /// Advances the pipeline, yielding every item that can be produced from the data provided so far.
+    pub fn run(&mut self) -> crate::Result<PipelineResponse> {
+        let cumulative_request_charge = self.request_charges.values().sum();
+        let activity_ids = std::mem::take(&mut self.activity_ids);
+        if self.terminated {
+            return Ok(PipelineResponse {
+                cumulative_request_charge,
+                activity_ids,
+                ..PipelineResponse::TERMINATED
+            });
+        }
+
+        let mut items = Vec::new();
+        loop {
+            let result = self.producer.produce_item()?;
+            if result.terminated {
+                tracing::trace!("all merged streams terminated");
+                self.terminated = true;
+            }
+
+            let Some(item) = result.value else {
+                break;
+            };
+            let item = if self.preserve_order_by_items {
+                serde_json::value::to_raw_value(&item)
+                    .map_err(|e| ErrorKind::InternalError.with_source(e))?
+            } else {
+                item.into_payload().ok_or_else(|| {
+                    ErrorKind::InternalError
+                        .with_message("items yielded by the merge must have a payload")
+                })?
+            };
+            items.push(item);
+        }
+
+        let requests = if self.terminated {
+            Vec::new()
+        } else {
+            self.producer
+                .data_requests()?
+                .into_iter()
+                .map(|r| DataRequest::new(r.id, r.pkrange_id, None))
+                .collect()
+        };
+
+        Ok(PipelineResponse {
+            items,
+            requests,
+            terminated: self.terminated,
+            cumulative_request_charge,
+            activity_ids,
+        })
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Parsing and aggregation of the query metrics the backend returns with each page.
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use crate::ErrorKind;
+
+/// The execution metrics the backend reports for a query, in the `x-ms-documentdb-query-metrics` response header.
+///
+/// The backend reports the metrics for each page separately. The pipeline adds up the metrics of every page provided to it,
+/// see [`QueryPipeline::query_metrics`](super::QueryPipeline::query_metrics).
+///
+/// The header is a list of `key=value` pairs, separated by semicolons, which is also how these metrics are formatted for display,
+/// matching the way the .NET SDK prints its backend metrics.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryMetrics {
+    /// The total time the backend spent executing the query, from `totalExecutionTimeInMs`.
+    pub total_execution_time: Duration,
+
+    /// The time spent compiling the query, from `queryCompileTimeInMs`.
+    pub query_compile_time: Duration,
+
+    /// The time spent building the logical plan, from `queryLogicalPlanBuildTimeInMs`.
+    pub logical_plan_build_time: Duration,
+
+    /// The time spent building the physical plan, from `queryPhysicalPlanBuildTimeInMs`.
+    pub physical_plan_build_time: Duration,
+
+    /// The time spent optimizing the query, from `queryOptimizationTimeInMs`.
+    pub query_optimization_time: Duration,
+
+    /// The time spent in the query runtime, from `VMExecutionTimeInMs`.
+    pub vm_execution_time: Duration,
+
+    /// The time spent looking up documents in the index, from `indexLookupTimeInMs`.
+    pub index_lookup_time: Duration,
+
+    /// The time spent loading documents, from `documentLoadTimeInMs`.
+    pub document_load_time: Duration,
+
+    /// The time spent executing system functions, from `systemFunctionExecuteTimeInMs`.
+    pub system_function_execution_time: Duration,
+
+    /// The time spent executing user-defined functions, from `userFunctionExecuteTimeInMs`.
+    pub user_function_execution_time: Duration,
+
+    /// The number of documents the query retrieved, from `retrievedDocumentCount`.
+    pub retrieved_document_count: u64,
+
+    /// The total size, in bytes, of the documents the query retrieved, from `retrievedDocumentSize`.
+    pub retrieved_document_size: u64,
+
+    /// The number of documents the query returned, from `outputDocumentCount`.
+    pub output_document_count: u64,
+
+    /// The total size, in bytes, of the documents the query returned, from `outputDocumentSize`.
+    pub output_document_size: u64,
+
+    /// The time spent writing the results, from `writeOutputTimeInMs`.
+    pub document_write_time: Duration,
+
+    /// The fraction of the retrieved documents that matched the filter using only the index, from `indexUtilizationRatio`.
+    pub index_hit_ratio: f64,
+}
+
+impl QueryMetrics {
+    /// Adds the metrics of another page, or partition, to these metrics.
+    ///
+    /// Times and counts are summed. The index hit ratio is averaged, weighted by the number of documents each side retrieved,
+    /// so it stays the ratio for all the retrieved documents. If neither side retrieved any documents, the ratio from `other` is kept.
+    pub fn merge(&mut self, other: &QueryMetrics) {
+        let retrieved = self.retrieved_document_count + other.retrieved_document_count;
+        self.index_hit_ratio = if retrieved == 0 {
+            other.index_hit_ratio
+        } else {
+            (self.index_hit_ratio * self.retrieved_document_count as f64
+                + other.index_hit_ratio * other.retrieved_document_count as f64)
+                / retrieved as f64
+        };
+
+        self.total_execution_time += other.total_execution_time;
+        self.query_compile_time += other.query_compile_time;
+        self.logical_plan_build_time += other.logical_plan_build_time;
+        self.physical_plan_build_time += other.physical_plan_build_time;
+        self.query_optimization_time += other.query_optimization_time;
+        self.vm_execution_time += other.vm_execution_time;
+        self.index_lookup_time += other.index_lookup_time;
+        self.document_load_time += other.document_load_time;
+        self.system_function_execution_time += other.system_function_execution_time;
+        self.user_function_execution_time += other.user_function_execution_time;
+        self.retrieved_document_count = retrieved;
+        self.retrieved_document_size += other.retrieved_document_size;
+        self.output_document_count += other.output_document_count;
+        self.output_document_size += other.output_document_size;
+        self.document_write_time += other.document_write_time;
+    }
+}
+
+/// Parses a time in milliseconds, like `33.67`, keeping it exact to the nanosecond so that sums of parsed times are exact.
+fn parse_millis(key: &str, value: &str) -> crate::Result<Duration> {
+    value
+        .parse::<f64>()
+        .ok()
+        .map(|millis| (millis * 1_000_000.0).round())
+        .filter(|nanos| nanos.is_finite() && *nanos >= 0.0 && *nanos <= u64::MAX as f64)
+        .map(|nanos| Duration::from_nanos(nanos as u64))
+        .ok_or_else(|| {
+            ErrorKind::InvalidGatewayResponse
+                .with_message(format!("invalid time '{value}' for query metric '{key}'"))
+        })
+}
+
+fn parse_count(key: &str, value: &str) -> crate::Result<u64> {
+    value.parse().map_err(|e| {
+        ErrorKind::InvalidGatewayResponse
+            .with_source(e)
+            .with_context(format!("invalid count '{value}' for query metric '{key}'"))
+    })
+}
+
+impl FromStr for QueryMetrics {
+    type Err = crate::Error;
+
+    /// Parses the value of an `x-ms-documentdb-query-metrics` header.
+    ///
+    /// Keys the pipeline doesn't know about are ignored, since the backend adds new metrics over time. Missing keys are left at zero.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut metrics = QueryMetrics::default();
+        for pair in s.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| {
+                ErrorKind::InvalidGatewayResponse
+                    .with_message(format!("query metric '{pair}' has no value"))
+            })?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "totalExecutionTimeInMs" => {
+                    metrics.total_execution_time = parse_millis(key, value)?
+                }
+                "queryCompileTimeInMs" => metrics.query_compile_time = parse_millis(key, value)?,
+                "queryLogicalPlanBuildTimeInMs" => {
+                    metrics.logical_plan_build_time = parse_millis(key, value)?
+                }
+                "queryPhysicalPlanBuildTimeInMs" => {
+                    metrics.physical_plan_build_time = parse_millis(key, value)?
+                }
+                "queryOptimizationTimeInMs" => {
+                    metrics.query_optimization_time = parse_millis(key, value)?
+                }
+                "VMExecutionTimeInMs" => metrics.vm_execution_time = parse_millis(key, value)?,
+                "indexLookupTimeInMs" => metrics.index_lookup_time = parse_millis(key, value)?,
+                "documentLoadTimeInMs" => metrics.document_load_time = parse_millis(key, value)?,
+                "systemFunctionExecuteTimeInMs" => {
+                    metrics.system_function_execution_time = parse_millis(key, value)?
+                }
+                "userFunctionExecuteTimeInMs" => {
+                    metrics.user_function_execution_time = parse_millis(key, value)?
+                }
+                "retrievedDocumentCount" => {
+                    metrics.retrieved_document_count = parse_count(key, value)?
+                }
+                "retrievedDocumentSize" => {
+                    metrics.retrieved_document_size = parse_count(key, value)?
+                }
+                "outputDocumentCount" => metrics.output_document_count = parse_count(key, value)?,
+                "outputDocumentSize" => metrics.output_document_size = parse_count(key, value)?,
+                "writeOutputTimeInMs" => metrics.document_write_time = parse_millis(key, value)?,
+                "indexUtilizationRatio" => {
+                    metrics.index_hit_ratio = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ratio| ratio.is_finite())
+                        .ok_or_else(|| {
+                            ErrorKind::InvalidGatewayResponse.with_message(format!(
+                                "invalid ratio '{value}' for query metric '{key}'"
+                            ))
+                        })?
+                }
+                _ => tracing::trace!(key, "ignoring unknown query metric"),
+            }
+        }
+        Ok(metrics)
+    }
+}
+
+impl fmt::Display for QueryMetrics {
+    /// Formats the metrics in the same `key=value;...` form as the header, with times in milliseconds to two decimal places.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn millis(duration: Duration) -> f64 {
+            duration.as_secs_f64() * 1000.0
+        }
+
+        write!(
+            f,
+            "totalExecutionTimeInMs={:.2};queryCompileTimeInMs={:.2};queryLogicalPlanBuildTimeInMs={:.2};queryPhysicalPlanBuildTimeInMs={:.2};queryOptimizationTimeInMs={:.2};",
+            millis(self.total_execution_time),
+            millis(self.query_compile_time),
+            millis(self.logical_plan_build_time),
+            millis(self.physical_plan_build_time),
+            millis(self.query_optimization_time),
+        )?;
+        write!(
+            f,
+            "VMExecutionTimeInMs={:.2};indexLookupTimeInMs={:.2};documentLoadTimeInMs={:.2};systemFunctionExecuteTimeInMs={:.2};userFunctionExecuteTimeInMs={:.2};",
+            millis(self.vm_execution_time),
+            millis(self.index_lookup_time),
+            millis(self.document_load_time),
+            millis(self.system_function_execution_time),
+            millis(self.user_function_execution_time),
+        )?;
+        write!(
+            f,
+            "retrievedDocumentCount={};retrievedDocumentSize={};outputDocumentCount={};outputDocumentSize={};writeOutputTimeInMs={:.2};indexUtilizationRatio={:.2}",
+            self.retrieved_document_count,
+            self.retrieved_document_size,
+            self.output_document_count,
+            self.output_document_size,
+            millis(self.document_write_time),
+            self.index_hit_ratio,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARTITION0: &str = "totalExecutionTimeInMs=33.67;queryCompileTimeInMs=0.06;queryLogicalPlanBuildTimeInMs=0.02;queryPhysicalPlanBuildTimeInMs=0.10;queryOptimizationTimeInMs=0.00;VMExecutionTimeInMs=32.56;indexLookupTimeInMs=0.99;documentLoadTimeInMs=9.58;systemFunctionExecuteTimeInMs=0.00;userFunctionExecuteTimeInMs=0.00;retrievedDocumentCount=2000;retrievedDocumentSize=1125600;outputDocumentCount=2000;outputDocumentSize=1125600;writeOutputTimeInMs=18.10;indexUtilizationRatio=1.00";
+    const PARTITION1: &str = "totalExecutionTimeInMs=1.25;queryCompileTimeInMs=0.04;queryLogicalPlanBuildTimeInMs=0.01;queryPhysicalPlanBuildTimeInMs=0.03;queryOptimizationTimeInMs=0.01;VMExecutionTimeInMs=0.83;indexLookupTimeInMs=0.31;documentLoadTimeInMs=0.27;systemFunctionExecuteTimeInMs=0.02;userFunctionExecuteTimeInMs=0.00;retrievedDocumentCount=500;retrievedDocumentSize=281400;outputDocumentCount=100;outputDocumentSize=56280;writeOutputTimeInMs=0.05;indexUtilizationRatio=0.20";
+    const PARTITION2: &str = "totalExecutionTimeInMs=0.18;queryCompileTimeInMs=0.03;queryLogicalPlanBuildTimeInMs=0.01;queryPhysicalPlanBuildTimeInMs=0.02;queryOptimizationTimeInMs=0.00;VMExecutionTimeInMs=0.04;indexLookupTimeInMs=0.03;documentLoadTimeInMs=0.00;systemFunctionExecuteTimeInMs=0.00;userFunctionExecuteTimeInMs=0.00;retrievedDocumentCount=0;retrievedDocumentSize=0;outputDocumentCount=0;outputDocumentSize=0;writeOutputTimeInMs=0.00;indexUtilizationRatio=0.00";
+
+    fn millis(ms: f64) -> Duration {
+        Duration::from_nanos((ms * 1_000_000.0).round() as u64)
+    }
+
+    #[test]
+    fn parse_header() {
+        let metrics: QueryMetrics = PARTITION0.parse().unwrap();
+        assert_eq!(
+            QueryMetrics {
+                total_execution_time: millis(33.67),
+                query_compile_time: millis(0.06),
+                logical_plan_build_time: millis(0.02),
+                physical_plan_build_time: millis(0.10),
+                query_optimization_time: Duration::ZERO,
+                vm_execution_time: millis(32.56),
+                index_lookup_time: millis(0.99),
+                document_load_time: millis(9.58),
+                system_function_execution_time: Duration::ZERO,
+                user_function_execution_time: Duration::ZERO,
+                retrieved_document_count: 2000,
+                retrieved_document_size: 1125600,
+                output_document_count: 2000,
+                output_document_size: 1125600,
+                document_write_time: millis(18.10),
+                index_hit_ratio: 1.0,
+            },
+            metrics
+        );
+        assert_eq!(PARTITION0, metrics.to_string());
+    }
+
+    #[test]
+    fn parse_ignores_unknown_keys_and_missing_keys() {
+        let metrics: QueryMetrics =
+            "totalExecutionTimeInMs=2.50; retrievedDocumentCount = 7;newMetricInMs=1.00;futureFlag=yes;"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            QueryMetrics {
+                total_execution_time: millis(2.5),
+                retrieved_document_count: 7,
+                ..Default::default()
+            },
+            metrics
+        );
+        assert_eq!(QueryMetrics::default(), "".parse().unwrap());
+    }
+
+    #[test]
+    fn parse_rejects_invalid_values() {
+        for header in [
+            "totalExecutionTimeInMs",
+            "totalExecutionTimeInMs=fast",
+            "documentLoadTimeInMs=-1.00",
+            "retrievedDocumentCount=1.5",
+            "indexUtilizationRatio=NaN",
+        ] {
+            let err = header.parse::<QueryMetrics>().unwrap_err();
+            assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind(), "{header}");
+        }
+    }
+
+    #[test]
+    fn merge_across_partitions() {
+        let mut metrics = QueryMetrics::default();
+        for header in [PARTITION0, PARTITION1, PARTITION2] {
+            metrics.merge(&header.parse().unwrap());
+        }
+
+        assert_eq!(millis(35.10), metrics.total_execution_time);
+        assert_eq!(millis(0.13), metrics.query_compile_time);
+        assert_eq!(millis(33.43), metrics.vm_execution_time);
+        assert_eq!(millis(9.85), metrics.document_load_time);
+        assert_eq!(2500, metrics.retrieved_document_count);
+        assert_eq!(1407000, metrics.retrieved_document_size);
+        assert_eq!(2100, metrics.output_document_count);
+        assert_eq!(1181880, metrics.output_document_size);
+
+        // Weighted by retrieved documents, so the partition that retrieved nothing doesn't lower the ratio.
+        assert_eq!(0.84, metrics.index_hit_ratio);
+        assert_eq!(
+            "totalExecutionTimeInMs=35.10;queryCompileTimeInMs=0.13;queryLogicalPlanBuildTimeInMs=0.04;queryPhysicalPlanBuildTimeInMs=0.15;queryOptimizationTimeInMs=0.01;VMExecutionTimeInMs=33.43;indexLookupTimeInMs=1.33;documentLoadTimeInMs=9.85;systemFunctionExecuteTimeInMs=0.02;userFunctionExecuteTimeInMs=0.00;retrievedDocumentCount=2500;retrievedDocumentSize=1407000;outputDocumentCount=2100;outputDocumentSize=1181880;writeOutputTimeInMs=18.15;indexUtilizationRatio=0.84",
+            metrics.to_string()
+        );
+    }
+}
@@ -1,11 +1,17 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::Range};
 
 use serde::Deserialize;
 
 mod aggregators;
+mod cancellation;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+mod gateway;
+mod merge;
+mod metrics;
 pub mod node;
 mod pipeline;
 mod plan;
@@ -18,8 +24,18 @@ mod engine;
 #[cfg(feature = "query_engine")]
 pub use engine::*;
 
-pub use pipeline::{QueryPipeline, SupportedFeatures, SUPPORTED_FEATURES};
-pub use plan::{DistinctType, QueryInfo, QueryPlan, QueryRange, SortOrder};
+pub use cancellation::CancellationToken;
+#[cfg(feature = "diagnostics")]
+pub use diagnostics::{Clock, ParseTimings, SystemClock};
+pub use gateway::{CompositeContinuationToken, ContinuationRange, GatewayPage};
+pub use merge::MergePipeline;
+pub use metrics::QueryMetrics;
+pub use pipeline::{
+    OwnedSupportedFeatures, PipelineOptions, QueryPipeline, QueryPipelineBuilder,
+    SupportedFeatures, SUPPORTED_FEATURES,
+};
+pub use plan::{DistinctType, QueryInfo, QueryPlan, QueryRange, SortOrder, UnrecognizedFields};
+pub use producer::{OrderByTieBreak, OrderByTypeMismatch};
 pub use query_result::{QueryClauseItem, QueryResult, QueryResultShape};
 
 /// Features that may be required by the Query Engine.
@@ -48,6 +64,64 @@ pub enum QueryFeature {
     HybridSearchSkipOrderByRewrite,
 }
 
+impl QueryFeature {
+    /// Every query feature, in the order they are declared.
+    pub const ALL: &'static [QueryFeature] = &[
+        QueryFeature::None,
+        QueryFeature::Aggregate,
+        QueryFeature::CompositeAggregate,
+        QueryFeature::Distinct,
+        QueryFeature::GroupBy,
+        QueryFeature::MultipleAggregates,
+        QueryFeature::MultipleOrderBy,
+        QueryFeature::OffsetAndLimit,
+        QueryFeature::OrderBy,
+        QueryFeature::Top,
+        QueryFeature::NonValueAggregate,
+        QueryFeature::DCount,
+        QueryFeature::NonStreamingOrderBy,
+        QueryFeature::ListAndSetAggregate,
+        QueryFeature::CountIf,
+        QueryFeature::HybridSearch,
+        QueryFeature::WeightedRankFusion,
+        QueryFeature::HybridSearchSkipOrderByRewrite,
+    ];
+
+    /// Gets the bit representing this feature in a mask of features, like the one accepted by `cosmoscx_v0_query_supported_features_for`.
+    ///
+    /// Bits are assigned in declaration order, and new features are only added at the end, so the bits are stable.
+    pub const fn bit(self) -> u64 {
+        1 << self as u64
+    }
+
+    /// Gets the name of the feature, as it's reported to the gateway.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Aggregate => "Aggregate",
+            Self::CompositeAggregate => "CompositeAggregate",
+            Self::Distinct => "Distinct",
+            Self::GroupBy => "GroupBy",
+            Self::MultipleAggregates => "MultipleAggregates",
+            Self::MultipleOrderBy => "MultipleOrderBy",
+            Self::OffsetAndLimit => "OffsetAndLimit",
+            Self::OrderBy => "OrderBy",
+            Self::Top => "Top",
+            Self::NonValueAggregate => "NonValueAggregate",
+            Self::DCount => "DCount",
+            Self::NonStreamingOrderBy => "NonStreamingOrderBy",
+            Self::ListAndSetAggregate => "ListAndSetAggregate",
+            Self::CountIf => "CountIf",
+            Self::HybridSearch => "HybridSearch",
+            Self::WeightedRankFusion => "WeightedRankFusion",
+            Self::HybridSearchSkipOrderByRewrite => "HybridSearchSkipOrderByRewrite",
+        }
+    }
+}
+
+/// A query provided by the user, along with its parameters.
+///
+/// A `&str` or `String` converts into a [`Query`] without parameters, so a pipeline can be created from the query text alone.
 #[derive(Debug, Clone)]
 pub struct Query {
     /// The text of the query.
@@ -57,6 +131,34 @@ pub struct Query {
     pub encoded_parameters: Option<Box<serde_json::value::RawValue>>,
 }
 
+impl Query {
+    /// Creates a query with the given parameters, which must already be encoded as the JSON `parameters` field of a Cosmos query.
+    pub fn with_parameters(
+        text: impl Into<String>,
+        encoded_parameters: Box<serde_json::value::RawValue>,
+    ) -> Self {
+        Self {
+            text: text.into(),
+            encoded_parameters: Some(encoded_parameters),
+        }
+    }
+}
+
+impl From<&str> for Query {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+impl From<String> for Query {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            encoded_parameters: None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[cfg_attr(
     feature = "python_conversions",
@@ -68,9 +170,12 @@ pub struct PartitionKeyRange {
     id: String,
     #[cfg_attr(feature = "python_conversions", pyo3(item("minInclusive")))]
     min_inclusive: String,
-    #[allow(dead_code)]
     #[cfg_attr(feature = "python_conversions", pyo3(item("maxExclusive")))]
     max_exclusive: String,
+    /// The IDs of the partition key ranges this range was split or merged from, which continuation tokens may still refer to.
+    #[serde(default)]
+    #[cfg_attr(feature = "python_conversions", pyo3(default))]
+    parents: Vec<String>,
 }
 
 impl PartitionKeyRange {
@@ -83,16 +188,32 @@ impl PartitionKeyRange {
             id: id.into(),
             min_inclusive: min_inclusive.into(),
             max_exclusive: max_exclusive.into(),
+            parents: Vec::new(),
         }
     }
+
+    /// Sets the IDs of the partition key ranges this range was split or merged from.
+    pub fn with_parents(mut self, parents: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.parents = parents.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Gets the ID of this partition key range.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Gets the IDs of the partition key ranges this range was split or merged from, which is empty if it's an original range.
+    pub fn parents(&self) -> &[String] {
+        &self.parents
+    }
 }
 
 /// Describes a request for additional data from the pipeline.
 ///
 /// This value is returned when the pipeline needs more data to continue processing.
 /// It contains the information necessary for the caller to make an HTTP request to the Cosmos APIs to fetch the next batch of data.
-#[derive(Clone, Debug, PartialEq, Eq)]
-#[cfg_attr(feature = "python_conversions", derive(pyo3::IntoPyObject))]
+#[derive(Clone, Debug)]
 pub struct DataRequest {
     /// A unique identifier for this request that can be used to match it with it's response.
     pub id: u64,
@@ -100,6 +221,15 @@ pub struct DataRequest {
     pub continuation: Option<String>,
     pub query: Option<String>,
     pub include_parameters: bool,
+    /// The parameters to send with the query, pre-encoded as the JSON `parameters` field of a Cosmos query.
+    ///
+    /// The pipeline fills this in from the [`Query`] it was created with, whenever [`DataRequest::include_parameters`] is `true`.
+    /// The language binding SHOULD send exactly these parameters, and no parameters at all when this is `None`.
+    pub encoded_parameters: Option<Box<serde_json::value::RawValue>>,
+    /// A hint for the maximum number of items to request in a single page, if the pipeline knows it needs fewer items than usual (or prefers more).
+    ///
+    /// The language binding SHOULD send this as the `x-ms-max-item-count` header when it is set, and use its own default otherwise.
+    pub max_item_count: Option<u32>,
 }
 
 impl DataRequest {
@@ -114,6 +244,8 @@ impl DataRequest {
             continuation,
             query: None,
             include_parameters: true,
+            encoded_parameters: None,
+            max_item_count: None,
         }
     }
 
@@ -130,10 +262,28 @@ impl DataRequest {
             continuation,
             query: Some(query.into()),
             include_parameters,
+            encoded_parameters: None,
+            max_item_count: None,
         }
     }
 }
 
+// `RawValue` doesn't implement `PartialEq`, so the parameters are compared by their JSON text.
+impl PartialEq for DataRequest {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.pkrange_id == other.pkrange_id
+            && self.continuation == other.continuation
+            && self.query == other.query
+            && self.include_parameters == other.include_parameters
+            && self.encoded_parameters.as_ref().map(|p| p.get())
+                == other.encoded_parameters.as_ref().map(|p| p.get())
+            && self.max_item_count == other.max_item_count
+    }
+}
+
+impl Eq for DataRequest {}
+
 #[derive(Clone, Debug)]
 pub struct PipelineResponse {
     /// The items returned by the pipeline.
@@ -148,6 +298,16 @@ pub struct PipelineResponse {
     ///
     /// If this is true, no further items will be produced, even if more data is provided.
     pub terminated: bool,
+
+    /// The total request charge, in request units, of every page provided to the pipeline so far.
+    ///
+    /// This is `0.0` if the language binding doesn't provide request charges with its pages.
+    pub cumulative_request_charge: f64,
+
+    /// The activity IDs of the pages the pipeline accepted since the previous turn, in the order they were provided.
+    ///
+    /// Every activity ID provided so far is available from [`QueryPipeline::activity_ids`].
+    pub activity_ids: Vec<String>,
 }
 
 impl PipelineResponse {
@@ -155,5 +315,98 @@ impl PipelineResponse {
         items: Vec::new(),
         requests: Vec::new(),
         terminated: true,
+        cumulative_request_charge: 0.0,
+        activity_ids: Vec::new(),
     };
 }
+
+/// The items of a [`PipelineResponse`], copied into a single contiguous buffer.
+///
+/// Each item in a [`PipelineResponse`] is a separate allocation. Language bindings that copy every item out of a response,
+/// like the C API, can use this to make a single allocation, and a single copy, per turn, rather than one per item.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ItemArena {
+    data: String,
+    spans: Vec<Range<usize>>,
+}
+
+impl ItemArena {
+    /// Copies the JSON of each item into a new arena, in order.
+    pub fn new(items: &[Box<serde_json::value::RawValue>]) -> Self {
+        // Size the buffer up front, so it's allocated exactly once.
+        let len = items.iter().map(|item| item.get().len()).sum();
+        let mut data = String::with_capacity(len);
+        let spans = items
+            .iter()
+            .map(|item| {
+                let start = data.len();
+                data.push_str(item.get());
+                start..data.len()
+            })
+            .collect();
+        Self { data, spans }
+    }
+
+    /// Gets the number of items in the arena.
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Indicates if the arena has no items.
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Gets the JSON of the item at `index`, if there is one.
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.spans.get(index).map(|span| &self.data[span.clone()])
+    }
+
+    /// Iterates over the JSON of each item, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.spans.iter().map(|span| &self.data[span.clone()])
+    }
+
+    /// Splits the arena into the buffer holding every item, and the range of the buffer that holds each item.
+    pub fn into_parts(self) -> (String, Vec<Range<usize>>) {
+        (self.data, self.spans)
+    }
+}
+
+/// The metadata the backend returns, in its response headers, with a page of results.
+///
+/// See [`QueryPipeline::provide_data_with_metadata`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResponseMetadata {
+    /// The charge for the page, in request units, from the `x-ms-request-charge` header.
+    pub request_charge: f64,
+
+    /// The ID the backend assigned to the request, from the `x-ms-activity-id` header.
+    pub activity_id: Option<String>,
+
+    /// The delay the backend asked for before the next request, in milliseconds, from the `x-ms-retry-after-ms` header.
+    ///
+    /// The pipeline doesn't issue requests itself, so this is only traced, to help diagnose throttled queries.
+    pub retry_after_ms: Option<u64>,
+
+    /// The execution metrics for the page, from the `x-ms-documentdb-query-metrics` header, exactly as the backend returned them.
+    ///
+    /// The backend only returns this header if the request set `x-ms-documentdb-populatequerymetrics` to `true`.
+    /// See [`QueryMetrics`] for the format.
+    pub query_metrics: Option<String>,
+}
+
+/// The result of pulling a single item from a pipeline, using [`QueryPipeline::next_item`].
+#[derive(Clone, Debug)]
+pub enum PipelineEvent {
+    /// The next item in the query results.
+    Item(Box<serde_json::value::RawValue>),
+
+    /// No item can be produced until the data described by these requests is provided to the pipeline.
+    ///
+    /// Pulling again without providing the data returns the same requests.
+    NeedsData(Vec<DataRequest>),
+
+    /// The query has completed, and no further items will be produced.
+    Done,
+}
@@ -5,7 +5,7 @@ use std::{collections::VecDeque, fmt::Debug, str::FromStr};
 
 use crate::{query::aggregators::Aggregator, ErrorKind};
 
-use super::{producer::ItemProducer, QueryResult};
+use super::{producer::ItemProducer, QueryResult, QueryResultShape};
 
 #[derive(Debug)]
 pub struct PipelineNodeResult {
@@ -87,6 +87,34 @@ pub trait PipelineNode: Send + Debug {
     fn name(&self) -> &'static str {
         std::any::type_name_of_val(self)
     }
+
+    /// Captures the state of this node, so that the pipeline can be resumed later by [`PipelineNode::restore_state`].
+    ///
+    /// Nodes that can't be resumed return `None`, which causes [`QueryPipeline::serialize_state`](crate::query::QueryPipeline::serialize_state) to fail.
+    fn save_state(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Restores the state captured by [`PipelineNode::save_state`].
+    fn restore_state(&mut self, _state: serde_json::Value) -> crate::Result<()> {
+        Err(ErrorKind::StateNotSerializable
+            .with_message(format!("{} cannot be resumed", self.name())))
+    }
+
+    /// Gets the most items this node still needs from the rest of the pipeline, given that the nodes before it need at most `needed` items from it.
+    ///
+    /// `None` means there's no limit. Nodes that may need every item, like aggregates, return `None`, which is the default.
+    fn items_needed(&self, _needed: Option<u64>) -> Option<u64> {
+        None
+    }
+}
+
+/// Restores the `remaining` counter of a limit or offset node.
+fn restore_remaining(state: serde_json::Value) -> crate::Result<u64> {
+    state.as_u64().ok_or_else(|| {
+        ErrorKind::DeserializationError
+            .with_message(format!("invalid state for a limit or offset node: {state}"))
+    })
 }
 
 /// A pipeline node that limits the number of items that can pass through it by a fixed number.
@@ -131,6 +159,19 @@ impl PipelineNode for LimitPipelineNode {
             x => Ok(x),
         }
     }
+
+    fn save_state(&self) -> Option<serde_json::Value> {
+        Some(self.remaining.into())
+    }
+
+    fn restore_state(&mut self, state: serde_json::Value) -> crate::Result<()> {
+        self.remaining = restore_remaining(state)?;
+        Ok(())
+    }
+
+    fn items_needed(&self, needed: Option<u64>) -> Option<u64> {
+        Some(needed.map_or(self.remaining, |n| n.min(self.remaining)))
+    }
 }
 
 /// A pipeline node that skips a fixed number of items before allowing any items to pass through it.
@@ -166,14 +207,109 @@ impl PipelineNode for OffsetPipelineNode {
         tracing::debug!("offset reached, returning item");
         rest.run()
     }
+
+    fn save_state(&self) -> Option<serde_json::Value> {
+        Some(self.remaining.into())
+    }
+
+    fn restore_state(&mut self, state: serde_json::Value) -> crate::Result<()> {
+        self.remaining = restore_remaining(state)?;
+        Ok(())
+    }
+
+    fn items_needed(&self, needed: Option<u64>) -> Option<u64> {
+        needed.map(|n| n.saturating_add(self.remaining))
+    }
+}
+
+/// A pipeline node that replaces each item's payload with the value at a property path, for `SELECT VALUE` queries.
+///
+/// Items that have no value at the path are `undefined`, and are skipped, since Cosmos DB omits `undefined` values from query results.
+#[derive(Debug)]
+pub struct ProjectPipelineNode {
+    /// The property names leading from the document to the projected value. An empty path projects the whole document.
+    path: Vec<String>,
+}
+
+impl ProjectPipelineNode {
+    pub fn new(path: Vec<String>) -> Self {
+        Self { path }
+    }
+
+    /// Projects a single payload, returning `None` if the projected value is `undefined`.
+    fn project(
+        &self,
+        payload: &serde_json::value::RawValue,
+    ) -> crate::Result<Option<Box<serde_json::value::RawValue>>> {
+        let mut value: serde_json::Value = serde_json::from_str(payload.get())
+            .map_err(|e| ErrorKind::DeserializationError.with_source(e))?;
+        for name in &self.path {
+            match value {
+                serde_json::Value::Object(mut properties) => match properties.remove(name) {
+                    Some(property) => value = property,
+                    None => return Ok(None),
+                },
+                _ => return Ok(None),
+            }
+        }
+        serde_json::value::to_raw_value(&value)
+            .map(Some)
+            .map_err(|e| ErrorKind::InternalError.with_source(e))
+    }
+}
+
+impl PipelineNode for ProjectPipelineNode {
+    fn next_item(&mut self, mut rest: PipelineSlice) -> crate::Result<PipelineNodeResult> {
+        loop {
+            match rest.run()? {
+                PipelineNodeResult {
+                    value: Some(item),
+                    terminated,
+                } => {
+                    let Some(payload) = item.into_payload() else {
+                        return Err(ErrorKind::InternalError
+                            .with_message("SELECT VALUE projection received an aggregate result"));
+                    };
+                    match self.project(&payload)? {
+                        Some(projected) => {
+                            return Ok(PipelineNodeResult::result(
+                                QueryResult::RawPayload(projected),
+                                terminated,
+                            ))
+                        }
+                        None if terminated => {
+                            return Ok(PipelineNodeResult {
+                                value: None,
+                                terminated: true,
+                            })
+                        }
+                        None => tracing::debug!("projected value is undefined, skipping item"),
+                    }
+                }
+
+                // Pass through any early terminations or no results.
+                x => return Ok(x),
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct AggregatePipelineNode {
-    aggregators: Vec<Aggregator>,
+    aggregators: Aggregators,
     results: Option<VecDeque<Box<serde_json::value::RawValue>>>,
 }
 
+/// The aggregators used by an [`AggregatePipelineNode`].
+#[derive(Debug)]
+enum Aggregators {
+    /// Aggregates from a `SELECT VALUE` query, each producing a separate result.
+    Value(Vec<Aggregator>),
+
+    /// Named aggregates, which are combined into a single result object keyed by alias.
+    Named(Vec<(String, Aggregator)>),
+}
+
 impl AggregatePipelineNode {
     pub fn from_names(names: Vec<String>) -> crate::Result<Self> {
         let mut aggregators = Vec::with_capacity(names.len());
@@ -181,10 +317,106 @@ impl AggregatePipelineNode {
             aggregators.push(Aggregator::from_str(&name)?);
         }
         Ok(Self {
-            aggregators,
+            aggregators: Aggregators::Value(aggregators),
             results: None,
         })
     }
+
+    /// Creates a node for named (non-value) aggregates, from the alias-to-aggregate map in the query plan.
+    pub fn from_aliases<'a>(
+        aliases: impl IntoIterator<Item = (&'a String, &'a String)>,
+    ) -> crate::Result<Self> {
+        let mut aggregators = Vec::new();
+        for (alias, name) in aliases {
+            aggregators.push((alias.clone(), Aggregator::from_str(name)?));
+        }
+        Ok(Self {
+            aggregators: Aggregators::Named(aggregators),
+            results: None,
+        })
+    }
+
+    fn aggregate(&mut self, item: &QueryResult) -> crate::Result<()> {
+        // When the query also has an ORDER BY, the producer yields ORDER BY rows, and the aggregates are in their payload.
+        let parsed;
+        let item = match item.as_order_by() {
+            Some((_, payload)) => {
+                let shape = match self.aggregators {
+                    Aggregators::Value(_) => QueryResultShape::ValueAggregate,
+                    Aggregators::Named(_) => QueryResultShape::Aggregate,
+                };
+                parsed = shape.aggregates_from_payload(payload)?;
+                &parsed
+            }
+            None => item,
+        };
+
+        match &mut self.aggregators {
+            Aggregators::Value(aggregators) => {
+                let aggregates = item.as_value_aggregates().ok_or_else(|| {
+                    ErrorKind::InvalidGatewayResponse
+                        .with_message("expected single-partition aggregate results")
+                })?;
+                tracing::debug!(aggregator_count = aggregators.len(), "processing item");
+                for clause_item in aggregates {
+                    for aggregator in aggregators.iter_mut() {
+                        aggregator.aggregate(clause_item)?
+                    }
+                }
+            }
+            Aggregators::Named(aggregators) => {
+                let aggregates = item.as_aggregates().ok_or_else(|| {
+                    ErrorKind::InvalidGatewayResponse
+                        .with_message("expected single-partition named aggregate results")
+                })?;
+                tracing::debug!(aggregator_count = aggregators.len(), "processing item");
+                for (alias, aggregator) in aggregators.iter_mut() {
+                    // A missing alias means the aggregate was undefined in that partition (for example, MAX over no items).
+                    if let Some(clause_item) = aggregates.get(alias) {
+                        aggregator.aggregate(clause_item)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn into_results(
+        aggregators: Aggregators,
+    ) -> crate::Result<VecDeque<Box<serde_json::value::RawValue>>> {
+        fn to_raw_value(
+            value: &serde_json::Value,
+        ) -> crate::Result<Box<serde_json::value::RawValue>> {
+            serde_json::value::to_raw_value(value).map_err(|e| {
+                ErrorKind::InternalError
+                    .with_message(format!("failed to serialize aggregate result: {}", e))
+            })
+        }
+
+        match aggregators {
+            Aggregators::Value(aggregators) => {
+                let mut results = VecDeque::with_capacity(aggregators.len());
+                for aggregator in aggregators {
+                    if let Some(value) = aggregator.into_value()? {
+                        results.push_back(to_raw_value(&value)?);
+                    }
+                }
+                Ok(results)
+            }
+            Aggregators::Named(aggregators) => {
+                // Undefined aggregates are omitted from the result object, but the object itself is always produced.
+                let mut object = serde_json::Map::with_capacity(aggregators.len());
+                for (alias, aggregator) in aggregators {
+                    if let Some(value) = aggregator.into_value()? {
+                        object.insert(alias, value);
+                    }
+                }
+                Ok(VecDeque::from([to_raw_value(&serde_json::Value::Object(
+                    object,
+                ))?]))
+            }
+        }
+    }
 }
 
 impl PipelineNode for AggregatePipelineNode {
@@ -211,31 +443,14 @@ impl PipelineNode for AggregatePipelineNode {
 
         let result = rest.run()?;
         if let Some(item) = result.value {
-            let aggregates = item.as_value_aggregates().ok_or_else(|| {
-                ErrorKind::InvalidGatewayResponse
-                    .with_message("expected single-partition aggregate results")
-            })?;
-            tracing::debug!(aggregator_count = self.aggregators.len(), "processing item");
-            for clause_item in aggregates {
-                for aggregator in &mut self.aggregators {
-                    aggregator.aggregate(&clause_item)?
-                }
-            }
+            self.aggregate(&item)?;
         }
 
         if result.terminated {
             tracing::debug!("aggregation complete, producing final result");
-            let mut results = VecDeque::with_capacity(self.aggregators.len());
-            for aggregator in self.aggregators.drain(..) {
-                let value = aggregator.into_value()?;
-                if let Some(value) = value {
-                    let raw_value = serde_json::value::to_raw_value(&value).map_err(|e| {
-                        ErrorKind::InternalError
-                            .with_message(format!("failed to serialize aggregate result: {}", e))
-                    })?;
-                    results.push_back(raw_value);
-                }
-            }
+            let aggregators =
+                std::mem::replace(&mut self.aggregators, Aggregators::Value(Vec::new()));
+            let mut results = Self::into_results(aggregators)?;
 
             let result = drain_result(&mut results);
             self.results = Some(results);
@@ -1,24 +1,54 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::ffi::CStr;
+use std::{
+    ffi::{CStr, CString},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
     query::{
         node::AggregatePipelineNode, plan::HybridSearchQueryInfo, query_result::QueryResultShape,
         QueryInfo,
     },
-    ErrorKind,
+    ErrorKind, PartitionKeyDefinition,
 };
 
 use super::{
-    node::{LimitPipelineNode, OffsetPipelineNode, PipelineNode, PipelineSlice},
+    node::{
+        LimitPipelineNode, OffsetPipelineNode, PipelineNode, PipelineSlice, ProjectPipelineNode,
+    },
     plan::{DistinctType, QueryRange},
-    producer::ItemProducer,
-    PartitionKeyRange, PipelineResponse, QueryFeature, QueryPlan,
+    producer::{ItemProducer, ProducerState, SpillConfig},
+    CancellationToken, DataRequest, GatewayPage, OrderByTieBreak, OrderByTypeMismatch,
+    PartitionKeyRange, PipelineEvent, PipelineResponse, Query, QueryFeature, QueryMetrics,
+    QueryPlan, ResponseMetadata,
 };
 
+/// The version of the format produced by [`QueryPipeline::serialize_state`].
+const PIPELINE_STATE_VERSION: u32 = 1;
+
+/// The serialized state of a [`QueryPipeline`], see [`QueryPipeline::serialize_state`].
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PipelineState {
+    version: u32,
+
+    // Missing from states captured before queries had IDs, in which case the resumed pipeline gets a new ID.
+    #[serde(default)]
+    query_id: Option<String>,
+    terminated: bool,
+    nodes: Vec<serde_json::Value>,
+    producer: ProducerState,
+}
+
 /// Holds a list of [`QueryFeature`]s and a string representation suitable for being passed to the gateway when requesting a query plan.
+///
+/// [`SUPPORTED_FEATURES`] describes every feature supported by this engine.
+/// A language binding can use [`SupportedFeatures::excluding`] to stop advertising some of them, so that the gateway rejects queries that need them.
+/// For example, excluding [`QueryFeature::NonStreamingOrderBy`] makes the gateway reject queries that would buffer every result, so the binding can fall back to another strategy.
 pub struct SupportedFeatures {
     #[allow(dead_code)]
     supported_features: &'static [QueryFeature],
@@ -43,6 +73,65 @@ impl SupportedFeatures {
     pub const fn as_cstr(&self) -> &'static CStr {
         self.supported_features_cstr
     }
+
+    /// Creates an [`OwnedSupportedFeatures`] describing these features, except for those in `excluded`.
+    pub fn excluding(&self, excluded: &[QueryFeature]) -> OwnedSupportedFeatures {
+        OwnedSupportedFeatures::new(
+            self.supported_features
+                .iter()
+                .copied()
+                .filter(|feature| !excluded.contains(feature)),
+        )
+    }
+
+    /// Gets a string representing the supported features, except for those in `excluded`, suitable for being passed to the gateway when requesting a query plan.
+    ///
+    /// This lets a language binding stop advertising a feature, so that the gateway rejects queries that need it. For example, when debugging how a query is rejected.
+    pub fn without(&self, excluded: &[QueryFeature]) -> String {
+        self.excluding(excluded).as_str().to_owned()
+    }
+}
+
+/// A list of [`QueryFeature`]s built at runtime, like [`SupportedFeatures`] but owning its list and string.
+///
+/// The string is formatted like the one of [`SUPPORTED_FEATURES`], with a comma after every feature name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedSupportedFeatures {
+    supported_features: Vec<QueryFeature>,
+    supported_features_cstr: CString,
+}
+
+impl OwnedSupportedFeatures {
+    /// Creates an [`OwnedSupportedFeatures`] describing the given features.
+    pub fn new(features: impl IntoIterator<Item = QueryFeature>) -> Self {
+        let supported_features = features.into_iter().collect::<Vec<_>>();
+        let names = supported_features
+            .iter()
+            .map(|feature| format!("{},", feature.name()))
+            .collect::<String>();
+        Self {
+            supported_features,
+            supported_features_cstr: CString::new(names)
+                .expect("feature names never contain NUL characters"),
+        }
+    }
+
+    /// Gets a slice of [`QueryFeature`] values representing the supported features.
+    pub fn as_slice(&self) -> &[QueryFeature] {
+        &self.supported_features
+    }
+
+    /// Gets a Rust string representing the supported features, suitable for being passed to the gateway when requesting a query plan.
+    pub fn as_str(&self) -> &str {
+        self.supported_features_cstr
+            .to_str()
+            .expect("feature names are always valid UTF-8")
+    }
+
+    /// Gets a C string representing the supported features, suitable for being passed to the gateway when requesting a query plan.
+    pub fn as_cstr(&self) -> &CStr {
+        &self.supported_features_cstr
+    }
 }
 
 macro_rules! supported_features {
@@ -65,8 +154,124 @@ supported_features!(
     NonStreamingOrderBy,
     Aggregate,
     HybridSearch,
+    WeightedRankFusion,
+    ListAndSetAggregate,
+    CountIf,
+    NonValueAggregate,
+    MultipleAggregates,
+    DCount,
 );
 
+/// Options that control how a [`QueryPipeline`] is created, see [`QueryPipeline::with_options`].
+///
+/// Language bindings can provide these as JSON, using the field names as-is. Missing fields use their default values.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineOptions {
+    /// Rejects query plans with unrecognized fields that describe how the query is executed.
+    ///
+    /// These are the fields in the `queryInfo` and `hybridSearchQueryInfo` sections of the plan, see [`QueryPlan::unrecognized_fields`].
+    /// Ignoring one of them may produce incorrect results, so this returns an [`ErrorKind::UnsupportedQueryPlan`] error instead.
+    /// Unrecognized top-level fields are always allowed.
+    pub reject_unrecognized_fields: bool,
+
+    /// The maximum number of items a non-streaming `ORDER BY` query may buffer before it yields any results.
+    ///
+    /// A non-streaming `ORDER BY` has to buffer every result from every partition, so a query with too many results fails
+    /// with an [`ErrorKind::BufferLimitExceeded`] error, rather than exhausting memory. This has no effect on other queries.
+    pub max_buffered_items: Option<usize>,
+
+    /// Requests data from every partition up front, rather than one partition at a time, for queries without an `ORDER BY`.
+    ///
+    /// Items are still yielded in partition order, so pages from later partitions are buffered until every earlier partition is exhausted.
+    /// This allows language bindings to fetch pages from several partitions concurrently, at the cost of buffering more data.
+    pub prefetch_all_partitions: bool,
+
+    /// Yields items from any partition that has provided them, rather than in partition order, for queries without an `ORDER BY`.
+    ///
+    /// Data is requested from every partition up front, as with [`PipelineOptions::prefetch_all_partitions`],
+    /// but items are yielded as soon as their page is provided, without waiting for earlier partitions.
+    /// Items from the same partition are still yielded in order. Clients that depend on results being ordered by partition must not enable this.
+    pub interleave_partitions: bool,
+
+    /// The maximum number of data requests returned by each turn of the pipeline, see [`PipelineResponse::requests`].
+    ///
+    /// Language bindings that perform every request concurrently can use this to limit how many requests are in flight for a query that targets many partitions.
+    /// Queries with an `ORDER BY` request each partition in turn, so every partition makes progress. Other queries request the earliest partitions first.
+    /// A limit of zero is treated as one.
+    pub max_concurrent_requests: Option<usize>,
+
+    /// Applies the projection of a `SELECT VALUE` query to each item, for language bindings whose partition results carry the whole document.
+    ///
+    /// The gateway's rewritten query already projects the payload, so this must only be enabled when the partitions return whole documents instead.
+    /// The projection must be a property path of the document, like `c.address.city` or `c["first name"]`, otherwise this returns
+    /// an [`ErrorKind::UnsupportedQueryPlan`] error. Items that have no value at the path are skipped.
+    /// Pipelines that project values can't be resumed, see [`QueryPipeline::serialize_state`].
+    pub project_select_value: bool,
+}
+
+/// Collects the configuration of a [`QueryPipeline`], see [`QueryPipeline::builder`].
+///
+/// The query and query plan are required. Everything else is optional, and defaults to the same values as [`QueryPipeline::new`].
+#[derive(Debug, Default)]
+pub struct QueryPipelineBuilder {
+    query: Option<Query>,
+    plan: Option<QueryPlan>,
+    pkranges: Vec<PartitionKeyRange>,
+    options: PipelineOptions,
+}
+
+impl QueryPipelineBuilder {
+    /// Creates an empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the ORIGINAL query specified by the user, see [`QueryPipeline::new`].
+    pub fn query(mut self, query: impl Into<Query>) -> Self {
+        self.query = Some(query.into());
+        self
+    }
+
+    /// Sets the query plan that describes how to execute the query.
+    pub fn plan(mut self, plan: QueryPlan) -> Self {
+        self.plan = Some(plan);
+        self
+    }
+
+    /// Sets the [`PartitionKeyRange`]s that the query will be executed against, replacing any that were set before.
+    pub fn pkranges(mut self, pkranges: impl IntoIterator<Item = PartitionKeyRange>) -> Self {
+        self.pkranges = pkranges.into_iter().collect();
+        self
+    }
+
+    /// Replaces all the [`PipelineOptions`], including any set by other methods of the builder.
+    pub fn options(mut self, options: PipelineOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets [`PipelineOptions::max_buffered_items`].
+    pub fn max_buffered_items(mut self, max_buffered_items: usize) -> Self {
+        self.options.max_buffered_items = Some(max_buffered_items);
+        self
+    }
+
+    /// Creates the query pipeline.
+    ///
+    /// Returns an [`ErrorKind::ArgumentNull`] error if the query or query plan wasn't set,
+    /// or any error [`QueryPipeline::with_options`] would return for the same configuration.
+    pub fn build(self) -> crate::Result<QueryPipeline> {
+        let query = self
+            .query
+            .ok_or_else(|| ErrorKind::ArgumentNull.with_message("a query is required"))?;
+        let plan = self
+            .plan
+            .ok_or_else(|| ErrorKind::ArgumentNull.with_message("a query plan is required"))?;
+        QueryPipeline::with_options(query, plan, self.pkranges, self.options)
+    }
+}
+
 /// Represents a query pipeline capable of accepting single-partition results for a query and returning a cross-partition stream of results.
 ///
 /// ## Overview
@@ -109,22 +314,51 @@ supported_features!(
 /// pipeline exposes the rewritten query through the [`QueryPipeline::query()`] method.
 /// If the query was *not* rewritten by the gateway, this method returns the unrewritten query,
 /// so language bindings should *always* use this query when making the signal-partition queries.
+///
+/// For an `ORDER BY` query with a `TOP` or `OFFSET`/`LIMIT` clause, the pipeline also adds a `TOP` clause to the rewritten query
+/// (unless it already limits its results), since no partition can contribute more than that many items to the results.
 pub struct QueryPipeline {
+    // Correlates the tracing spans of this query, see `QueryPipeline::id`.
+    id: String,
     query: Option<String>,
     pipeline: Vec<Box<dyn PipelineNode>>,
     producer: ItemProducer,
+    partition_key_definition: Option<PartitionKeyDefinition>,
+
+    // The total request charge of the pages provided to the pipeline.
+    request_charge: f64,
+
+    // The activity IDs of the pages provided to the pipeline, and how many of them have been returned in a `PipelineResponse`.
+    activity_ids: Vec<String>,
+    reported_activity_ids: usize,
+
+    // The query metrics of the pages provided to the pipeline, added up across every partition.
+    query_metrics: QueryMetrics,
 
     // Indicates if the pipeline has been terminated early.
     terminated: bool,
+
+    // Indicates if the query was cancelled by the caller, see `QueryPipeline::cancel`.
+    cancelled: bool,
+
+    // Lets the caller cancel the query from another thread, see `QueryPipeline::cancellation_token`.
+    cancellation: CancellationToken,
 }
 
 impl std::fmt::Debug for QueryPipeline {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("QueryPipeline")
+            .field("id", &self.id)
             .field("query", &self.query)
             .field("pipeline", &self.pipeline)
             .field("producer", &self.producer)
+            .field("partition_key_definition", &self.partition_key_definition)
+            .field("request_charge", &self.request_charge)
+            .field("activity_ids", &self.activity_ids)
+            .field("query_metrics", &self.query_metrics)
             .field("terminated", &self.terminated)
+            .field("cancelled", &self.cancelled)
+            .field("cancellation", &self.cancellation)
             .finish()
     }
 }
@@ -133,34 +367,194 @@ impl QueryPipeline {
     /// Creates a new query pipeline.
     ///
     /// # Parameters
-    /// * `query` - The ORIGINAL query specified by the user. If the [`QueryPlan`] has a `rewritten_query`, the pipeline will handle rewriting it.
+    /// * `query` - The ORIGINAL query specified by the user, as a [`Query`] or just its text. If the [`QueryPlan`] has a `rewritten_query`, the pipeline will handle rewriting it.
+    ///   Any parameters are attached to the [`DataRequest`](crate::query::DataRequest)s that need them, see [`DataRequest::encoded_parameters`](crate::query::DataRequest::encoded_parameters).
     /// * `plan` - The query plan that describes how to execute the query.
     /// * `pkranges` - An iterator that produces the [`PartitionKeyRange`]s that the query will be executed against.
-    #[tracing::instrument(level = "debug", skip_all, err)]
     pub fn new(
-        query: &str,
+        query: impl Into<Query>,
         plan: QueryPlan,
         pkranges: impl IntoIterator<Item = PartitionKeyRange>,
     ) -> crate::Result<Self> {
+        Self::builder()
+            .query(query)
+            .plan(plan)
+            .pkranges(pkranges)
+            .build()
+    }
+
+    /// Creates a [`QueryPipelineBuilder`], which configures a pipeline one setting at a time.
+    pub fn builder() -> QueryPipelineBuilder {
+        QueryPipelineBuilder::new()
+    }
+
+    /// Creates a pipeline for a simple query, without a query plan from the gateway.
+    ///
+    /// This uses [`QueryPlan::default_for_passthrough`], so language bindings can skip the query plan request for queries that
+    /// each partition executes completely on its own, like `SELECT * FROM c WHERE c.id = @id`. The results are returned unordered.
+    ///
+    /// The query must not contain `ORDER BY`, `GROUP BY`, `DISTINCT`, `TOP`, `OFFSET`, `LIMIT` or aggregate functions.
+    /// The query text is checked for those keywords, returning an [`ErrorKind::InvalidQuery`] error if any are found,
+    /// in which case the binding should request a query plan as usual.
+    /// The check is conservative, so the keywords are also rejected when they're used in a subquery or as an alias.
+    pub fn new_passthrough(
+        query: impl Into<Query>,
+        pkranges: impl IntoIterator<Item = PartitionKeyRange>,
+    ) -> crate::Result<Self> {
+        let query = query.into();
+        if let Some(clause) = find_query_plan_clause(&query.text) {
+            return Err(ErrorKind::InvalidQuery
+                .with_message(format!("a query containing {clause} requires a query plan")));
+        }
+        Self::new(query, QueryPlan::default_for_passthrough(), pkranges)
+    }
+
+    /// Creates a new query pipeline, using the provided [`PipelineOptions`].
+    ///
+    /// See [`QueryPipeline::new`] for a description of the other parameters.
+    #[tracing::instrument(level = "debug", skip_all, err, fields(query_id))]
+    pub fn with_options(
+        query: impl Into<Query>,
+        plan: QueryPlan,
+        pkranges: impl IntoIterator<Item = PartitionKeyRange>,
+        options: PipelineOptions,
+    ) -> crate::Result<Self> {
+        let query = query.into();
+        if options.reject_unrecognized_fields {
+            let unrecognized_fields = plan.unrecognized_execution_fields();
+            if !unrecognized_fields.is_empty() {
+                return Err(ErrorKind::UnsupportedQueryPlan.with_message(format!(
+                    "query plan contains unrecognized fields: {}",
+                    unrecognized_fields.join(", ")
+                )));
+            }
+        }
+
         let mut pkranges: Vec<PartitionKeyRange> = pkranges.into_iter().collect();
         get_overlapping_pk_ranges(&mut pkranges, &plan.query_ranges);
 
         tracing::trace!(?query, ?plan, "creating query pipeline");
 
-        let pipeline = if let Some(hybrid_search_query_info) = plan.hybrid_search_query_info {
+        Self::validate_plan(&plan)?;
+        let mut pipeline = if let Some(hybrid_search_query_info) = plan.hybrid_search_query_info {
             // This is a hybrid search query, which requires special handling.
             Self::from_hybrid_search_query_info(hybrid_search_query_info, pkranges)?
-        } else if let Some(query_info) = plan.query_info {
-            Self::from_query_info(query, query_info, pkranges)?
         } else {
+            let query_info = plan
+                .query_info
+                .expect("validate_plan rejects plans without query_info");
+            let projection = if options.project_select_value {
+                select_value_projection(&query.text, &query_info)?
+            } else {
+                None
+            };
+            Self::from_query_info(&query.text, query_info, pkranges, projection)?
+        };
+        pipeline.producer.set_parameters(query.encoded_parameters);
+        pipeline
+            .producer
+            .set_max_buffered_items(options.max_buffered_items);
+        pipeline
+            .producer
+            .set_prefetch_all_partitions(options.prefetch_all_partitions);
+        pipeline
+            .producer
+            .set_interleave_partitions(options.interleave_partitions);
+        pipeline
+            .producer
+            .set_max_concurrent_requests(options.max_concurrent_requests);
+
+        tracing::Span::current().record("query_id", pipeline.id.as_str());
+        tracing::debug!(pipeline = ?pipeline, "created query pipeline");
+
+        Ok(pipeline)
+    }
+
+    /// Determines if the query described by a query plan needs a query pipeline at all.
+    ///
+    /// A query that targets a single partition, and has no clauses other than `TOP`, `OFFSET` and `LIMIT`, is fully executed by that partition,
+    /// so language bindings can skip creating a pipeline and return the partition's results to the user as-is.
+    /// In that case, the binding MUST send the ORIGINAL query, rather than the [`QueryInfo::rewritten_query`](crate::query::QueryInfo::rewritten_query).
+    /// The gateway rewrites `OFFSET` and `LIMIT` clauses so that each partition returns enough items for a cross-partition merge,
+    /// so only the original query applies them correctly within the partition.
+    ///
+    /// Any other query, including every query that targets more than one partition, returns `true`.
+    /// The `pkranges` are filtered by the plan's query ranges in the same way as [`QueryPipeline::new`].
+    pub fn requires_query_engine(
+        plan: &QueryPlan,
+        pkranges: impl IntoIterator<Item = PartitionKeyRange>,
+    ) -> bool {
+        if plan.hybrid_search_query_info.is_some() {
+            return true;
+        }
+        let Some(query_info) = &plan.query_info else {
+            return true;
+        };
+
+        // We're deliberately conservative here. Anything other than TOP, OFFSET and LIMIT keeps the pipeline involved,
+        // even though a single partition could also satisfy some of those clauses.
+        let has_other_clauses = !query_info.order_by.is_empty()
+            || !query_info.aggregates.is_empty()
+            || !query_info.group_by_expressions.is_empty()
+            || !query_info.group_by_aliases.is_empty()
+            || !query_info.group_by_alias_to_aggregate_type.is_empty()
+            || query_info.distinct_type != DistinctType::None;
+        if has_other_clauses {
+            return true;
+        }
+
+        let mut pkranges: Vec<PartitionKeyRange> = pkranges.into_iter().collect();
+        get_overlapping_pk_ranges(&mut pkranges, &plan.query_ranges);
+        pkranges.len() != 1
+    }
+
+    /// Checks that this engine can execute a query plan, without creating a pipeline for it.
+    ///
+    /// This runs the same checks as [`QueryPipeline::new`], so language bindings can check a plan up front,
+    /// and fall back to executing the query some other way if it's not supported.
+    /// An unsupported plan returns an [`ErrorKind::UnsupportedQueryPlan`] error, whose message describes the reason.
+    pub fn validate_plan(plan: &QueryPlan) -> crate::Result<()> {
+        if plan.hybrid_search_query_info.is_some() {
+            return Ok(());
+        }
+        let Some(query_info) = &plan.query_info else {
             return Err(ErrorKind::UnsupportedQueryPlan.with_message(
                 "query plan is missing both hybrid_search_query_info and query_info sections",
             ));
         };
 
-        tracing::debug!(pipeline = ?pipeline, "created query pipeline");
+        let has_non_value_aggregates = has_non_value_aggregates(query_info);
+        if !query_info.aggregates.is_empty()
+            && !query_info.has_select_value
+            && !has_non_value_aggregates
+        {
+            return Err(ErrorKind::UnsupportedQueryPlan
+                .with_message("non-value aggregates must provide an alias for each aggregate"));
+        }
 
-        Ok(pipeline)
+        if has_non_value_aggregates {
+            AggregatePipelineNode::from_aliases(&query_info.group_by_alias_to_aggregate_type)?;
+        } else {
+            AggregatePipelineNode::from_names(query_info.aggregates.clone())?;
+        }
+
+        if !has_non_value_aggregates
+            && (!query_info.group_by_expressions.is_empty()
+                || !query_info.group_by_alias_to_aggregate_type.is_empty()
+                || !query_info.group_by_aliases.is_empty())
+        {
+            return Err(
+                ErrorKind::UnsupportedQueryPlan.with_message("GROUP BY queries are not supported")
+            );
+        }
+
+        if query_info.distinct_type != DistinctType::None {
+            return Err(
+                ErrorKind::UnsupportedQueryPlan.with_message("DISTINCT queries are not supported")
+            );
+        }
+
+        Ok(())
     }
 
     fn from_hybrid_search_query_info(
@@ -171,10 +565,18 @@ impl QueryPipeline {
 
         // A hybrid search has no pipeline nodes, so we can just leave that empty.
         Ok(Self {
+            id: new_query_id(),
             query: None, // The original query isn't relevant.
             pipeline: Vec::new(),
             producer,
+            partition_key_definition: None,
+            request_charge: 0.0,
+            activity_ids: Vec::new(),
+            reported_activity_ids: 0,
+            query_metrics: QueryMetrics::default(),
             terminated: false,
+            cancelled: false,
+            cancellation: CancellationToken::new(),
         })
     }
 
@@ -182,37 +584,57 @@ impl QueryPipeline {
         query: &str,
         query_info: QueryInfo,
         pkranges: impl IntoIterator<Item = PartitionKeyRange>,
+        projection: Option<ProjectPipelineNode>,
     ) -> crate::Result<Self> {
-        // We don't support non-value aggregates, so make sure the query doesn't have any.
-        if !query_info.aggregates.is_empty() && !query_info.has_select_value {
-            return Err(ErrorKind::UnsupportedQueryPlan
-                .with_message("non-value aggregates are not supported"));
-        }
-
-        if !query_info.aggregates.is_empty() && !query_info.order_by.is_empty() {
-            return Err(ErrorKind::UnsupportedQueryPlan
-                .with_message("queries with both ORDER BY and aggregates are not supported"));
-        }
+        // The plan has already been checked by `validate_plan`.
+        let has_non_value_aggregates = has_non_value_aggregates(&query_info);
+        let is_non_streaming = is_non_streaming_order_by(&query_info);
+
+        // A projection skips undefined values, so the items that count against OFFSET, LIMIT and TOP can't be known up front.
+        let counts_every_item = projection.is_none();
+
+        // Unordered and streaming queries never need more than OFFSET + LIMIT (or TOP) items from a single partition.
+        // Non-streaming queries have to read every partition to the end anyway, so they are better served by the binding's (usually larger) default.
+        let max_item_count = if is_non_streaming
+            || !query_info.aggregates.is_empty()
+            || has_non_value_aggregates
+            || !counts_every_item
+        {
+            None
+        } else {
+            max_item_count_hint(&query_info)
+        };
 
-        let producer = if query_info.order_by.is_empty() {
-            tracing::debug!("using unordered pipeline");
-            // Determine the shape for unordered queries
-            let result_shape = if !query_info.aggregates.is_empty() {
-                QueryResultShape::ValueAggregate
+        // Without aggregates, only the first OFFSET + LIMIT (or TOP) items in sorted order can ever be yielded.
+        let result_limit =
+            if query_info.aggregates.is_empty() && !has_non_value_aggregates && counts_every_item {
+                result_count(&query_info).map(|count| usize::try_from(count).unwrap_or(usize::MAX))
             } else {
-                QueryResultShape::RawPayload
+                None
             };
-            ItemProducer::unordered(pkranges, result_shape)
+
+        // Each partition of an ORDER BY query contributes at most that many items to the results, so it doesn't need to return any more.
+        let partition_top = if query_info.order_by.is_empty() {
+            None
+        } else {
+            result_limit
+        };
+
+        let mut producer = if query_info.order_by.is_empty() {
+            tracing::debug!("using unordered pipeline");
+            ItemProducer::unordered(pkranges, QueryResultShape::from_query_info(&query_info))
         } else {
-            if query_info.has_non_streaming_order_by {
+            if is_non_streaming {
                 tracing::debug!(?query_info.order_by, "using non-streaming ORDER BY pipeline");
                 ItemProducer::non_streaming(pkranges, query_info.order_by)
             } else {
-                // We can stream results, there's no vector or full-text search in the query.
+                // We can stream results, either there's no vector or full-text search in the query, or the plan says each partition's results are already in their final order.
                 tracing::debug!(?query_info.order_by, "using streaming ORDER BY pipeline");
                 ItemProducer::streaming(pkranges, query_info.order_by)
             }
         };
+        producer.set_max_item_count(max_item_count);
+        producer.set_result_limit(result_limit);
 
         // We are building the pipeline outside-in.
         // That means the first node we push will be the first node executed.
@@ -235,31 +657,32 @@ impl QueryPipeline {
             pipeline.push(Box::new(OffsetPipelineNode::new(offset)));
         }
 
-        if !query_info.aggregates.is_empty() {
+        if has_non_value_aggregates {
+            pipeline.push(Box::new(AggregatePipelineNode::from_aliases(
+                &query_info.group_by_alias_to_aggregate_type,
+            )?));
+        } else if !query_info.aggregates.is_empty() {
             pipeline.push(Box::new(AggregatePipelineNode::from_names(
                 query_info.aggregates.clone(),
             )?));
         }
 
-        if !query_info.group_by_expressions.is_empty()
-            || !query_info.group_by_alias_to_aggregate_type.is_empty()
-            || !query_info.group_by_aliases.is_empty()
-        {
-            return Err(
-                ErrorKind::UnsupportedQueryPlan.with_message("GROUP BY queries are not supported")
-            );
-        }
-
-        if query_info.distinct_type != DistinctType::None {
-            return Err(
-                ErrorKind::UnsupportedQueryPlan.with_message("DISTINCT queries are not supported")
+        // The projection runs before OFFSET and LIMIT, so that skipped undefined values don't count against them.
+        if let Some(projection) = projection {
+            tracing::debug!(
+                ?projection,
+                "adding SELECT VALUE projection node to pipeline"
             );
+            pipeline.push(Box::new(projection));
         }
 
         let query = if query_info.rewritten_query.is_empty() {
             Some(query.to_string())
         } else {
-            let rewritten = format_query(&query_info.rewritten_query);
+            let mut rewritten = format_query(&query_info.rewritten_query);
+            if let Some(limited) = partition_top.and_then(|count| with_top(&rewritten, count)) {
+                rewritten = limited;
+            }
             tracing::debug!(
                 original = ?query,
                 ?rewritten,
@@ -269,13 +692,213 @@ impl QueryPipeline {
         };
 
         Ok(Self {
+            id: new_query_id(),
             query,
             pipeline,
             producer,
+            partition_key_definition: None,
+            request_charge: 0.0,
+            activity_ids: Vec::new(),
+            reported_activity_ids: 0,
+            query_metrics: QueryMetrics::default(),
             terminated: false,
+            cancelled: false,
+            cancellation: CancellationToken::new(),
         })
     }
 
+    /// Limits the number of result payload bytes the pipeline may buffer across all partitions.
+    ///
+    /// Once set, [`QueryPipeline::provide_data`] returns an [`ErrorKind::MemoryBudgetExceeded`] error, instead of accepting a page
+    /// that could push the buffered results over the budget. The page is not consumed, and can be provided again after
+    /// [`QueryPipeline::run`] has yielded enough of the buffered items.
+    ///
+    /// Note that some queries, like those with a non-streaming `ORDER BY`, must buffer every result before yielding anything.
+    /// Such queries fail if their results don't fit in the budget.
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.producer.set_memory_budget(Some(bytes));
+        self
+    }
+
+    /// Lets a non-streaming `ORDER BY` write its buffered results to temporary files in `dir`, instead of holding them all in memory.
+    ///
+    /// Whenever the buffered result payloads exceed `threshold_bytes`, they're sorted and written to a new file.
+    /// Once every partition is done, the files are merged as the results are produced, so only one result per file is held in memory.
+    /// The files are deleted when the results have been produced, or the pipeline is dropped or cancelled.
+    /// Spilled results no longer count against [`PipelineOptions::max_buffered_items`] or the memory budget.
+    ///
+    /// This has no effect on other queries, or on a non-streaming `ORDER BY` with a `TOP` or `LIMIT` clause, which only buffers the results it can yield.
+    /// Failing to write or read a file produces an [`ErrorKind::SpillError`] error.
+    pub fn with_spill(mut self, threshold_bytes: usize, dir: impl Into<PathBuf>) -> Self {
+        self.producer.set_spill(Some(SpillConfig {
+            threshold_bytes,
+            dir: dir.into(),
+        }));
+        self
+    }
+
+    /// Sets the partition key definition of the container the query is executing against.
+    ///
+    /// The pipeline doesn't need the definition to execute a query, but operations that target specific partition key values,
+    /// like scoped queries, use it to compute effective partition keys. Language bindings should provide it whenever it's available.
+    pub fn with_partition_key_definition(mut self, definition: PartitionKeyDefinition) -> Self {
+        self.partition_key_definition = Some(definition);
+        self
+    }
+
+    /// Enables a diagnostic check that the `ORDER BY` values at each position have the same type in every partition.
+    ///
+    /// Values of different types are still ordered correctly, but a mix of types (for example, a number in one partition and a string in another)
+    /// often indicates documents with inconsistent schemas. Mismatches are logged as warnings, and can be retrieved using [`QueryPipeline::order_by_type_mismatches`].
+    /// This only applies to streaming `ORDER BY` queries.
+    pub fn with_order_by_type_check(mut self) -> Self {
+        self.producer.enable_order_by_type_check();
+        self
+    }
+
+    /// Sets how a streaming `ORDER BY` query orders items whose `ORDER BY` values are equal.
+    ///
+    /// By default, ties are broken by partition, see [`OrderByTieBreak`] for the alternatives.
+    /// This has no effect on other queries.
+    pub fn with_order_by_tie_break(mut self, tie_break: OrderByTieBreak) -> Self {
+        self.producer.set_order_by_tie_break(tie_break);
+        self
+    }
+
+    /// Gets the `ORDER BY` type mismatches found so far, see [`QueryPipeline::with_order_by_type_check`].
+    pub fn order_by_type_mismatches(&self) -> &[OrderByTypeMismatch] {
+        self.producer.order_by_type_mismatches()
+    }
+
+    /// Replaces the [`Clock`](crate::query::Clock) used to time the work done by the pipeline.
+    ///
+    /// The pipeline uses the [`SystemClock`](crate::query::SystemClock) by default, so this is mostly useful for testing.
+    #[cfg(feature = "diagnostics")]
+    pub fn with_clock(mut self, clock: impl crate::query::Clock + 'static) -> Self {
+        self.producer.set_clock(Box::new(clock));
+        self
+    }
+
+    /// Gets the time spent parsing the pages provided to [`QueryPipeline::provide_data`] so far.
+    ///
+    /// Comparing this with the total time spent in the pipeline shows whether JSON parsing, rather than merging and yielding items, dominates query processing.
+    #[cfg(feature = "diagnostics")]
+    pub fn parse_timings(&self) -> &crate::query::ParseTimings {
+        self.producer.parse_timings()
+    }
+
+    /// Retrieves the partition key definition of the container the query is executing against, if one was provided.
+    pub fn partition_key_definition(&self) -> Option<&PartitionKeyDefinition> {
+        self.partition_key_definition.as_ref()
+    }
+
+    /// Re-creates a query pipeline from the state captured by [`QueryPipeline::serialize_state`].
+    ///
+    /// The `query`, `plan` and `pkranges` must be the same as the ones used to create the original pipeline.
+    /// The resumed pipeline continues exactly where the original pipeline left off, including any items that were buffered but not yet yielded.
+    #[tracing::instrument(level = "debug", skip_all, err, fields(query_id))]
+    pub fn resume(
+        query: impl Into<Query>,
+        plan: QueryPlan,
+        pkranges: impl IntoIterator<Item = PartitionKeyRange>,
+        state: &str,
+    ) -> crate::Result<Self> {
+        let state: PipelineState = serde_json::from_str(state)
+            .map_err(|e| ErrorKind::DeserializationError.with_source(e))?;
+        if state.version != PIPELINE_STATE_VERSION {
+            return Err(ErrorKind::DeserializationError.with_message(format!(
+                "unsupported pipeline state version: {}",
+                state.version
+            )));
+        }
+
+        let mut pipeline = Self::new(query, plan, pkranges)?;
+        if state.nodes.len() != pipeline.pipeline.len() {
+            return Err(ErrorKind::DeserializationError
+                .with_message("pipeline state does not match the query plan"));
+        }
+        for (node, node_state) in pipeline.pipeline.iter_mut().zip(state.nodes) {
+            node.restore_state(node_state)?;
+        }
+        pipeline.producer.restore_state(state.producer)?;
+        pipeline.terminated = state.terminated;
+        if let Some(query_id) = state.query_id {
+            pipeline.id = query_id;
+        }
+        tracing::Span::current().record("query_id", pipeline.id.as_str());
+
+        // The page size hint was computed from the full OFFSET, LIMIT and TOP, but the resumed pipeline may need fewer items.
+        if let Some(max_item_count) = pipeline.producer.max_item_count() {
+            let needed = pipeline
+                .pipeline
+                .iter()
+                .fold(None, |needed, node| node.items_needed(needed));
+            if let Some(needed) = needed {
+                let needed = u32::try_from(needed).unwrap_or(u32::MAX).max(1);
+                pipeline
+                    .producer
+                    .set_max_item_count(Some(max_item_count.min(needed)));
+            }
+        }
+        Ok(pipeline)
+    }
+
+    /// Captures the state of the pipeline, so that the query can be resumed later, possibly in another process, by [`QueryPipeline::resume`].
+    ///
+    /// The state includes the continuation of each partition, any items that have been received but not yet yielded,
+    /// and the progress of OFFSET, LIMIT and TOP clauses. It should be treated as an opaque string.
+    ///
+    /// Only unordered queries and streaming `ORDER BY` queries can be resumed.
+    /// Other queries return an [`ErrorKind::StateNotSerializable`] error.
+    pub fn serialize_state(&self) -> crate::Result<String> {
+        let nodes = self
+            .pipeline
+            .iter()
+            .map(|node| {
+                node.save_state().ok_or_else(|| {
+                    ErrorKind::StateNotSerializable
+                        .with_message(format!("{} cannot be resumed", node.name()))
+                })
+            })
+            .collect::<crate::Result<_>>()?;
+        let state = PipelineState {
+            version: PIPELINE_STATE_VERSION,
+            query_id: Some(self.id.clone()),
+            terminated: self.terminated,
+            nodes,
+            producer: self.producer.save_state()?,
+        };
+        serde_json::to_string(&state).map_err(|e| ErrorKind::InternalError.with_source(e))
+    }
+
+    /// Gets the ID of this query, which is unique to each pipeline, and is kept by a pipeline resumed with [`QueryPipeline::resume`].
+    ///
+    /// Every tracing span the pipeline records has the ID in its `query_id` field, so that the spans of concurrent queries can be told apart.
+    /// Language bindings can include it in their own logs to correlate them with the pipeline's.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Gets the IDs of the partition key ranges this pipeline queries, in partition key order.
+    ///
+    /// These are the partition key ranges that overlap the query plan's query ranges, or every partition key range if the plan has none.
+    /// A query that targets a single logical partition should only query one partition key range, which this can be used to confirm.
+    /// Partitions that were split are replaced by the partitions they were split into, see [`QueryPipeline::handle_pkrange_gone`].
+    pub fn targeted_partitions(&self) -> Vec<String> {
+        self.producer.pkrange_ids()
+    }
+
+    /// Finds the partition key range this pipeline queries that covers the partition key range with ID `old_id`.
+    ///
+    /// If `old_id` is still one of the [`QueryPipeline::targeted_partitions`], this returns that range.
+    /// Otherwise, `old_id` may be the ID of a range that has since been split or merged, which continuation tokens and other saved state may still refer to.
+    /// In that case, this returns the first targeted range, in partition key order, that lists `old_id` in its parents.
+    /// A merged range is the only range with its parents, but a split range has several children, and this only returns the first of them.
+    /// Returns `None` if no targeted range is, or replaced, `old_id`.
+    pub fn resolve_pkrange(&self, old_id: &str) -> Option<&PartitionKeyRange> {
+        self.producer.resolve_pkrange(old_id)
+    }
+
     /// Retrieves the, possibly rewritten, query that this pipeline is executing.
     ///
     /// The pipeline has both the original query, AND the query plan that may have rewritten it.
@@ -289,17 +912,209 @@ impl QueryPipeline {
         self.terminated
     }
 
+    /// Cancels the query, releasing all the items the pipeline has buffered.
+    ///
+    /// Once cancelled, the pipeline is terminated, so [`QueryPipeline::run`] returns no items and no requests.
+    /// The language binding should cancel any outstanding requests. If their data is provided anyway, it's discarded, but its request charge is still counted.
+    ///
+    /// To cancel the query while another thread is using the pipeline, use its [`QueryPipeline::cancellation_token`] instead.
+    pub fn cancel(&mut self) {
+        tracing::debug!(query_id = %self.id, "cancelling query");
+        self.cancellation.cancel();
+        self.terminated = true;
+        self.cancelled = true;
+        self.producer.clear();
+    }
+
+    /// Gets a token that cancels the query, and can be used from any thread, even while the pipeline is in use.
+    ///
+    /// The pipeline checks the token between the items it produces, and between the pages provided to [`QueryPipeline::provide_data_batch`].
+    /// The first of those calls to see that the token was cancelled stops, fails with an [`ErrorKind::Cancelled`] error, and cancels the query as if by [`QueryPipeline::cancel`].
+    /// Items it had already produced in that turn are discarded. Afterwards, the pipeline behaves as any cancelled pipeline does,
+    /// so it reports that it has completed, and data provided to it is discarded.
+    ///
+    /// Data provided through [`QueryPipeline::provide_data`] is discarded once the token has been cancelled, without failing.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Checks the pipeline's [`QueryPipeline::cancellation_token`], failing with an [`ErrorKind::Cancelled`] error if it was cancelled since it was last checked.
+    ///
+    /// The pipeline already checks the token in each of its own calls. Language bindings that provide several pages in a loop of their own
+    /// can call this between pages, so that a cancelled query stops as promptly as it does in [`QueryPipeline::provide_data_batch`].
+    pub fn check_cancelled(&mut self) -> crate::Result<()> {
+        self.stop_if_cancelled(None)
+    }
+
+    /// Cancels the query, and fails with an [`ErrorKind::Cancelled`] error, if the pipeline's token, or `cancel`, was cancelled since the query was last checked.
+    ///
+    /// A pipeline that has already terminated has no work left to stop, so it isn't affected.
+    fn stop_if_cancelled(&mut self, cancel: Option<&CancellationToken>) -> crate::Result<()> {
+        if self.terminated
+            || !(self.cancellation.is_cancelled() || cancel.is_some_and(|c| c.is_cancelled()))
+        {
+            return Ok(());
+        }
+
+        self.cancel();
+        Err(ErrorKind::Cancelled
+            .with_message("the query was cancelled while the pipeline was in use"))
+    }
+
     /// Provides more data for the specified partition key range.
-    #[tracing::instrument(level = "debug", skip_all, err, fields(request_id, pkrange_id, data_len = data.len(), continuation = continuation.as_deref()))]
+    ///
+    /// The `request_id` must be the [`DataRequest::id`](super::DataRequest::id) of the request for that partition that the pipeline is waiting on.
+    /// Responses for a request that was never made, or that was already satisfied, fail with [`ErrorKind::InvalidRequestId`].
+    ///
+    /// The `request_charge` is the charge, in request units, reported by the backend for the page, if the language binding has it.
+    /// It isn't used to execute the query, it's only summed up and reported in [`PipelineResponse::cumulative_request_charge`].
+    ///
+    /// This is equivalent to [`QueryPipeline::provide_data_with_metadata`], with only the request charge set.
     pub fn provide_data(
         &mut self,
         pkrange_id: &str,
         request_id: u64,
         data: &[u8],
         continuation: Option<String>,
+        request_charge: Option<f64>,
     ) -> crate::Result<()> {
+        self.provide_data_with_metadata(
+            pkrange_id,
+            request_id,
+            data,
+            continuation,
+            ResponseMetadata {
+                request_charge: request_charge.unwrap_or(0.0),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Provides more data for the specified partition key range, along with the metadata from the backend's response headers.
+    ///
+    /// The metadata isn't used to execute the query. The request charges are summed up, and reported in [`PipelineResponse::cumulative_request_charge`],
+    /// and the activity IDs are collected, and reported in [`PipelineResponse::activity_ids`] and [`QueryPipeline::activity_ids`].
+    /// The query metrics are added up, and reported in [`QueryPipeline::query_metrics`].
+    /// See [`QueryPipeline::provide_data`] for more information.
+    #[tracing::instrument(level = "debug", skip_all, err, fields(query_id = %self.id, request_id, pkrange_id, data_len = data.len(), continuation = continuation.as_deref(), request_charge = metadata.request_charge, activity_id = metadata.activity_id.as_deref(), retry_after_ms = metadata.retry_after_ms))]
+    pub fn provide_data_with_metadata(
+        &mut self,
+        pkrange_id: &str,
+        request_id: u64,
+        data: &[u8],
+        continuation: Option<String>,
+        metadata: ResponseMetadata,
+    ) -> crate::Result<()> {
+        if self.cancellation.is_cancelled() && !self.cancelled {
+            self.cancel();
+        }
+        if self.cancelled {
+            // The backend has already charged for the page, even though it's discarded.
+            tracing::debug!("query was cancelled, discarding data");
+            self.record_metadata(metadata);
+            return Ok(());
+        }
+
         self.producer
-            .provide_data(pkrange_id, request_id, data, continuation)
+            .provide_data(pkrange_id, request_id, data, continuation)?;
+
+        // Only record the metadata once the page is accepted, since a rejected page may be provided again.
+        self.record_metadata(metadata);
+        Ok(())
+    }
+
+    fn record_metadata(&mut self, metadata: ResponseMetadata) {
+        self.request_charge += metadata.request_charge;
+        if let Some(activity_id) = metadata.activity_id {
+            self.activity_ids.push(activity_id);
+        }
+        if let Some(header) = metadata.query_metrics {
+            // The metrics are only diagnostic, so a malformed header shouldn't fail a page the pipeline has already accepted.
+            match header.parse::<QueryMetrics>() {
+                Ok(metrics) => self.query_metrics.merge(&metrics),
+                Err(e) => tracing::warn!(error = %e, header, "ignoring invalid query metrics"),
+            }
+        }
+    }
+
+    /// Replaces a partition that the backend reported as gone with the partitions it was split into.
+    ///
+    /// When a partition splits mid-query, the backend fails the outstanding request for it with HTTP 410 and sub-status 1002 (partition key range gone).
+    /// The language binding should then refresh the partition key ranges, and call this with the ranges that replaced `old_pkrange_id`,
+    /// rather than providing data for the failed request. The next [`QueryPipeline::run`] requests data from each replacement,
+    /// starting from the last continuation token of the gone partition, as the backend accepts it for every child of a split partition.
+    ///
+    /// The replacements must exactly cover the key range of the gone partition, otherwise this returns an [`ErrorKind::InvalidGatewayResponse`] error.
+    /// Merges, where a single range replaces several gone ones, aren't supported, since requests can't yet be limited to part of a partition.
+    ///
+    /// Unordered and streaming `ORDER BY` queries handle splits at any point.
+    /// Non-streaming `ORDER BY` queries only handle them before any results have been buffered, and hybrid search queries don't handle them at all.
+    /// In those cases, this returns an [`ErrorKind::UnsupportedQueryPlan`] error, and the query must be restarted.
+    pub fn handle_pkrange_gone(
+        &mut self,
+        old_pkrange_id: &str,
+        replacements: Vec<PartitionKeyRange>,
+    ) -> crate::Result<()> {
+        if self.cancelled {
+            tracing::debug!("query was cancelled, ignoring partition split");
+            return Ok(());
+        }
+
+        tracing::debug!(old_pkrange_id, ?replacements, "replacing gone partition");
+        self.producer.split_partition(old_pkrange_id, replacements)
+    }
+
+    /// Provides several pages at once, each as a tuple of the partition key range ID, request ID, data and continuation.
+    ///
+    /// This is equivalent to calling [`QueryPipeline::provide_data`] for each page, in order, and allows language bindings
+    /// to hand over all the responses they have ready in a single call.
+    /// If a page fails, the pages before it have already been applied, and the pages after it are not provided.
+    /// The error identifies the failed page by its index in `responses` and its partition key range ID.
+    ///
+    /// The pipeline's [`QueryPipeline::cancellation_token`] is checked before each page, so a cancelled batch fails with an [`ErrorKind::Cancelled`] error.
+    pub fn provide_data_batch(
+        &mut self,
+        responses: Vec<(String, u64, Vec<u8>, Option<String>)>,
+    ) -> crate::Result<()> {
+        for (index, (pkrange_id, request_id, data, continuation)) in
+            responses.into_iter().enumerate()
+        {
+            self.check_cancelled()
+                .and_then(|()| {
+                    self.provide_data(&pkrange_id, request_id, &data, continuation, None)
+                })
+                .map_err(|e| {
+                    e.with_context(format!(
+                        "failed to provide page {index} (partition key range ID {pkrange_id})"
+                    ))
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Gets the total request charge of all the pages provided to [`QueryPipeline::provide_data`] so far.
+    pub fn cumulative_request_charge(&self) -> f64 {
+        self.request_charge
+    }
+
+    /// Gets the activity IDs of all the pages provided to [`QueryPipeline::provide_data_with_metadata`] so far, in the order they were provided.
+    pub fn activity_ids(&self) -> &[String] {
+        &self.activity_ids
+    }
+
+    /// Gets the query metrics of all the pages provided to [`QueryPipeline::provide_data_with_metadata`] so far, added up across every partition.
+    ///
+    /// Pages provided without metrics, or with metrics that couldn't be parsed, don't contribute to the total.
+    /// The [`Display`](std::fmt::Display) form of the result is the merged metrics in the same format as the `x-ms-documentdb-query-metrics` header.
+    pub fn query_metrics(&self) -> &QueryMetrics {
+        &self.query_metrics
+    }
+
+    /// Takes the activity IDs provided since the previous turn, see [`PipelineResponse::activity_ids`].
+    fn take_new_activity_ids(&mut self) -> Vec<String> {
+        let new = self.activity_ids[self.reported_activity_ids..].to_vec();
+        self.reported_activity_ids = self.activity_ids.len();
+        new
     }
 
     /// Advances the pipeline to the next batch of results.
@@ -316,53 +1131,353 @@ impl QueryPipeline {
     /// The language binding MUST perform ALL the provided requests before the pipeline will be able to yield additional results.
     /// The language binding MAY execute additional turns without having satisfied all the requests, and the pipeline will continue
     /// to return any requests that still need to be made.
+    /// An unsatisfied request is returned again with the same ID and continuation, so a binding can retry a throttled or failed
+    /// request by leaving it unsatisfied and running another turn.
     ///
     /// If the pipeline returns no items and no requests, then the query has completed and there are no further results to return.
-    #[tracing::instrument(level = "debug", skip(self), err)]
+    ///
+    /// A terminated pipeline never returns requests. For example, once a `TOP` or `LIMIT` clause has yielded all its items,
+    /// the language binding should cancel any outstanding requests, since their results will never be used.
+    ///
+    /// A turn is equivalent to calling [`QueryPipeline::next_item`] until it returns something other than an item.
+    ///
+    /// The turn checks the pipeline's [`QueryPipeline::cancellation_token`] before producing each item, see [`QueryPipeline::run_with_cancel`].
     pub fn run(&mut self) -> crate::Result<PipelineResponse> {
-        if self.terminated {
-            return Ok(PipelineResponse::TERMINATED);
-        }
+        self.run_turn(None)
+    }
 
-        let mut slice = PipelineSlice::new(&mut self.pipeline, &mut self.producer);
+    /// Advances the pipeline to the next batch of results, unless `cancel` is cancelled first.
+    ///
+    /// This is the same as [`QueryPipeline::run`], but the turn also checks `cancel` before producing each item, so another thread can
+    /// stop a long turn, like the merge of a large non-streaming `ORDER BY`. A cancelled turn fails with an [`ErrorKind::Cancelled`] error,
+    /// discarding the items it had produced, and cancels the query, as described in [`QueryPipeline::cancellation_token`].
+    pub fn run_with_cancel(
+        &mut self,
+        cancel: &CancellationToken,
+    ) -> crate::Result<PipelineResponse> {
+        self.run_turn(Some(cancel))
+    }
 
+    /// Runs the whole query, fetching each page it needs with `fetch`, and returns every item it produces.
+    ///
+    /// This is a convenience for small queries, where the caller doesn't need to drive the turns of [`QueryPipeline::run`] itself.
+    /// The requests of each turn are passed to `fetch`, in order, which must execute the request and return the page's body and continuation token,
+    /// like the arguments to [`QueryPipeline::provide_data`]. Every item is buffered until the query completes, so use the turns directly
+    /// for queries that may return many items, or when items should reach the user as soon as they're available.
+    ///
+    /// An error from `fetch` is returned as-is, and the items produced before it are discarded.
+    /// If a turn neither completes the query nor requests any data, the query can't make progress, so this returns an [`ErrorKind::InternalError`] error.
+    ///
+    /// ```
+    /// use azure_data_cosmos_engine::query::{PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan};
+    ///
+    /// let mut pipeline = QueryPipeline::new(
+    ///     "SELECT * FROM c",
+    ///     QueryPlan {
+    ///         partitioned_query_execution_info_version: 1,
+    ///         query_info: Some(QueryInfo::default()),
+    ///         ..Default::default()
+    ///     },
+    ///     vec![PartitionKeyRange::new("0", "", "FF")],
+    /// )?;
+    ///
+    /// let items = pipeline.drain(|request| {
+    ///     // A real caller sends the query to the request's partition, starting from its continuation.
+    ///     assert_eq!("0", request.pkrange_id);
+    ///     Ok((br#"{"Documents": [{"id": "a"}, {"id": "b"}]}"#.to_vec(), None))
+    /// })?;
+    /// assert_eq!(
+    ///     vec![r#"{"id": "a"}"#, r#"{"id": "b"}"#],
+    ///     items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    /// );
+    /// # Ok::<(), azure_data_cosmos_engine::Error>(())
+    /// ```
+    pub fn drain(
+        &mut self,
+        mut fetch: impl FnMut(&DataRequest) -> crate::Result<(Vec<u8>, Option<String>)>,
+    ) -> crate::Result<Vec<Box<serde_json::value::RawValue>>> {
         let mut items = Vec::new();
-        while !self.terminated {
-            let result = slice.run()?;
+        loop {
+            let response = self.run()?;
+            items.extend(response.items);
+            if response.terminated {
+                return Ok(items);
+            }
+            if response.requests.is_empty() {
+                return Err(ErrorKind::InternalError
+                    .with_message("the pipeline was not terminated, but requested no data"));
+            }
 
-            // Termination MUST come from the pipeline, to ensure aggregates (which can only be emitted after all data is processed) work correctly.
-            if result.terminated {
-                tracing::trace!("pipeline node terminated the pipeline");
-                self.terminated = true;
+            for request in response.requests {
+                let (data, continuation) = fetch(&request)?;
+                self.provide_data(&request.pkrange_id, request.id, &data, continuation, None)?;
             }
+        }
+    }
 
-            if let Some(item) = result.value {
-                let payload = item.into_payload().ok_or_else(|| {
-                    ErrorKind::InternalError
-                        .with_message("items yielded by the pipeline must have a payload")
-                })?;
-                items.push(payload);
-            } else {
-                // The pipeline has finished for now, but we're not terminated yet.
-                break;
+    #[tracing::instrument(name = "run", level = "debug", skip_all, err, fields(query_id = %self.id))]
+    fn run_turn(&mut self, cancel: Option<&CancellationToken>) -> crate::Result<PipelineResponse> {
+        let mut items = Vec::new();
+        loop {
+            match self.next_event(cancel)? {
+                PipelineEvent::Item(item) => items.push(item),
+                PipelineEvent::NeedsData(requests) => {
+                    return Ok(PipelineResponse {
+                        items,
+                        requests,
+                        terminated: false,
+                        cumulative_request_charge: self.request_charge,
+                        activity_ids: self.take_new_activity_ids(),
+                    })
+                }
+                PipelineEvent::Done => {
+                    return Ok(PipelineResponse {
+                        items,
+                        requests: Vec::new(),
+                        terminated: true,
+                        cumulative_request_charge: self.request_charge,
+                        activity_ids: self.take_new_activity_ids(),
+                    })
+                }
             }
         }
+    }
 
-        let requests = self.producer.data_requests()?;
+    /// Pulls a single item from the pipeline.
+    ///
+    /// This allows language bindings to control exactly how many items they take from the pipeline, for example to respect a page size requested by the user.
+    /// The pipeline keeps any items it has buffered, so the binding can stop pulling at any point and resume later.
+    ///
+    /// If no item can be produced yet, this returns [`PipelineEvent::NeedsData`] with the requests that must be fulfilled first.
+    /// Pulling again without providing any data returns the same requests.
+    /// Once the query has completed, this returns [`PipelineEvent::Done`], and continues to do so if called again.
+    ///
+    /// If the pipeline's [`QueryPipeline::cancellation_token`] was cancelled, this fails with an [`ErrorKind::Cancelled`] error instead.
+    pub fn next_item(&mut self) -> crate::Result<PipelineEvent> {
+        self.next_event(None)
+    }
 
-        Ok(PipelineResponse {
-            items,
-            requests,
-            terminated: self.terminated,
+    fn next_event(&mut self, cancel: Option<&CancellationToken>) -> crate::Result<PipelineEvent> {
+        self.stop_if_cancelled(cancel)?;
+        if self.terminated {
+            return Ok(PipelineEvent::Done);
+        }
+
+        let result = PipelineSlice::new(&mut self.pipeline, &mut self.producer).run()?;
+
+        // Termination MUST come from the pipeline, to ensure aggregates (which can only be emitted after all data is processed) work correctly.
+        if result.terminated {
+            tracing::trace!("pipeline node terminated the pipeline");
+            self.terminated = true;
+        }
+
+        if let Some(item) = result.value {
+            let payload = item.into_payload().ok_or_else(|| {
+                ErrorKind::InternalError
+                    .with_message("items yielded by the pipeline must have a payload")
+            })?;
+            return Ok(PipelineEvent::Item(payload));
+        }
+
+        // Once a TOP or LIMIT is satisfied, the pipeline terminates even though partitions may still have data.
+        // Nothing more will be yielded, so there's no point in asking for that data.
+        if self.terminated {
+            Ok(PipelineEvent::Done)
+        } else {
+            Ok(PipelineEvent::NeedsData(self.producer.data_requests()?))
+        }
+    }
+
+    /// Advances the pipeline to the next batch of results, and wraps them in a [`GatewayPage`].
+    ///
+    /// This is intended for proxy scenarios, where the pipeline's results must be re-emitted to a client SDK in the gateway's own format.
+    /// The turn behaves exactly like [`QueryPipeline::run`], but the page also carries a continuation token describing the remaining partitions.
+    /// That continuation is only meaningful for unordered queries without OFFSET, LIMIT, TOP or aggregates,
+    /// since the state of those operations can't be described by the partition continuations alone.
+    /// For any other query, this returns an [`ErrorKind::UnsupportedQueryPlan`] error.
+    pub fn run_as_gateway_page(&mut self) -> crate::Result<GatewayPage> {
+        if !self.pipeline.is_empty() {
+            return Err(ErrorKind::UnsupportedQueryPlan.with_message(
+                "gateway pages are not supported for queries with OFFSET, LIMIT, TOP or aggregates",
+            ));
+        }
+
+        let response = self.run()?;
+        let continuation = if response.terminated {
+            None
+        } else {
+            let tokens = self.producer.continuation_tokens()?;
+            Some(
+                serde_json::to_string(&tokens)
+                    .map_err(|e| ErrorKind::InternalError.with_source(e))?,
+            )
+        };
+
+        Ok(GatewayPage {
+            documents: response.items,
+            continuation,
+            requests: response.requests,
         })
     }
 }
 
+/// Non-value aggregates (like `SELECT COUNT(1) AS total FROM c`) are planned by the gateway as a GROUP BY with no grouping expressions.
+/// The aggregates are described by the alias-to-aggregate map, rather than the aggregates list.
+pub(crate) fn has_non_value_aggregates(query_info: &QueryInfo) -> bool {
+    !query_info.has_select_value
+        && query_info.group_by_expressions.is_empty()
+        && !query_info.group_by_alias_to_aggregate_type.is_empty()
+}
+
+/// Indicates if the query needs the non-streaming `ORDER BY` producer, which buffers every partition's results before yielding any.
+fn is_non_streaming_order_by(query_info: &QueryInfo) -> bool {
+    query_info.has_non_streaming_order_by && !query_info.has_streaming_safe_order_by
+}
+
+/// Computes how many items, in total, a query with a TOP or LIMIT clause reads before its OFFSET is applied.
+fn result_count(query_info: &QueryInfo) -> Option<u64> {
+    match (query_info.top, query_info.limit) {
+        (Some(top), _) => Some(top),
+        (None, Some(limit)) => Some(limit.saturating_add(query_info.offset.unwrap_or(0))),
+        (None, None) => None,
+    }
+}
+
+/// Computes the page size hint for a query with a TOP or LIMIT clause.
+fn max_item_count_hint(query_info: &QueryInfo) -> Option<u32> {
+    // A page size of zero isn't meaningful to the backend, so always ask for at least one item.
+    result_count(query_info).map(|count| u32::try_from(count).unwrap_or(u32::MAX).max(1))
+}
+
+/// Generates a new, random, ID for a query, see [`QueryPipeline::id`].
+fn new_query_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
 /// Rewrites the incoming query by replacing tokens within it.
 fn format_query(original: &str) -> String {
     original.replace("{documentdb-formattableorderbyquery-filter}", "true")
 }
 
+/// Adds a `TOP` clause to a rewritten query, so each partition returns at most `count` items.
+///
+/// Returns `None` if the query doesn't start with `SELECT`, or already limits its results some other way, anywhere in the query.
+fn with_top(query: &str, count: usize) -> Option<String> {
+    const LIMITING_KEYWORDS: &[&str] = &["TOP", "OFFSET", "LIMIT", "DISTINCT"];
+
+    let start = query.len() - query.trim_start().len();
+    let rest = query[start..].get(.."SELECT".len())?;
+    let after = query[start + rest.len()..].chars().next()?;
+    if !rest.eq_ignore_ascii_case("SELECT") || !after.is_whitespace() {
+        return None;
+    }
+    if find_keyword(query, |word, _| {
+        LIMITING_KEYWORDS
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(word))
+    })
+    .is_some()
+    {
+        return None;
+    }
+
+    let end = start + rest.len();
+    Some(format!("{} TOP {count}{}", &query[..end], &query[end..]))
+}
+
+/// Creates the node that applies the projection of a `SELECT VALUE` query, see [`PipelineOptions::project_select_value`].
+///
+/// Returns `None` if the query doesn't need a projection, because it isn't a `SELECT VALUE` query, it projects aggregates, or it projects the whole document.
+fn select_value_projection(
+    query: &str,
+    query_info: &QueryInfo,
+) -> crate::Result<Option<ProjectPipelineNode>> {
+    if !query_info.has_select_value
+        || !query_info.aggregates.is_empty()
+        || has_non_value_aggregates(query_info)
+    {
+        return Ok(None);
+    }
+    let path = select_value_path(query).ok_or_else(|| {
+        ErrorKind::UnsupportedQueryPlan
+            .with_message("SELECT VALUE projection is not a property path of the document")
+    })?;
+    Ok((!path.is_empty()).then(|| ProjectPipelineNode::new(path)))
+}
+
+/// Finds the property path projected by a `SELECT VALUE` query, like `["address", "city"]` for `SELECT VALUE c.address.city FROM c`.
+///
+/// Properties can be accessed with a `.`, or with a quoted name in brackets. The root of the path must be the alias of the `FROM` clause.
+/// Returns `None` if the query projects anything else, or joins other values to the document.
+fn select_value_path(query: &str) -> Option<Vec<String>> {
+    const CLAUSE_KEYWORDS: &[&str] = &["WHERE", "ORDER", "GROUP", "OFFSET", "LIMIT", "JOIN"];
+
+    let mut rest = strip_keyword(query.trim_start(), "SELECT")?;
+    if let Some(after_top) = strip_keyword(rest, "TOP") {
+        // The count is a number or a parameter, neither of which contains whitespace.
+        let end = after_top.find(char::is_whitespace)?;
+        rest = after_top[end..].trim_start();
+    }
+    let (root, mut rest) = split_identifier(strip_keyword(rest, "VALUE")?)?;
+
+    let mut path = Vec::new();
+    loop {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let (name, after_name) = split_identifier(after_dot)?;
+            path.push(name.to_string());
+            rest = after_name;
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            let quote = after_bracket
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'')?;
+            let quoted = &after_bracket[1..];
+            let end = quoted.find(quote)?;
+            path.push(quoted[..end].to_string());
+            rest = quoted[end + 1..].strip_prefix(']')?;
+        } else {
+            break;
+        }
+    }
+
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let (container, rest) = split_identifier(strip_keyword(rest.trim_start(), "FROM")?)?;
+    let rest = rest.trim_start();
+    let is_clause = |word: &str| CLAUSE_KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(word));
+    let (alias, rest) = match strip_keyword(rest, "AS") {
+        Some(after_as) => split_identifier(after_as)?,
+        None => match split_identifier(rest) {
+            Some((alias, after_alias)) if !is_clause(alias) => (alias, after_alias),
+            _ => (container, rest),
+        },
+    };
+    if root != alias || strip_keyword(rest.trim_start(), "JOIN").is_some() {
+        return None;
+    }
+    Some(path)
+}
+
+/// Strips a keyword, and the whitespace that must follow it, from the start of `text`.
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let word = text.get(..keyword.len())?;
+    let rest = &text[keyword.len()..];
+    (word.eq_ignore_ascii_case(keyword) && rest.starts_with(char::is_whitespace))
+        .then(|| rest.trim_start())
+}
+
+/// Splits an identifier, like a property name or alias, from the start of `text`.
+fn split_identifier(text: &str) -> Option<(&str, &str)> {
+    if !text.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        return None;
+    }
+    let end = text
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(text.len());
+    Some(text.split_at(end))
+}
+
 /// Filters the partition key ranges to include only those that overlap with the query ranges.
 /// If no query ranges are provided, all partition key ranges are retained.
 fn get_overlapping_pk_ranges(pkranges: &mut Vec<PartitionKeyRange>, query_ranges: &[QueryRange]) {
@@ -419,6 +1534,13 @@ fn get_overlapping_pk_ranges(pkranges: &mut Vec<PartitionKeyRange>, query_ranges
                 pkranges.swap(write_idx, read_idx);
             }
             write_idx += 1;
+        } else {
+            tracing::debug!(
+                pkrange_id = %pkrange.id,
+                min_inclusive = %pkrange.min_inclusive,
+                max_exclusive = %pkrange.max_exclusive,
+                "skipping partition key range, it doesn't overlap any of the query ranges"
+            );
         }
     }
 
@@ -449,12 +1571,80 @@ fn pkrange_overlaps_query_range(pkrange: &PartitionKeyRange, query_range: &Query
     true
 }
 
+/// Finds a keyword in the query text that only a query plan from the gateway can handle, see [`QueryPipeline::new_passthrough`].
+///
+/// This only tokenizes the query, skipping string literals, comments and property names (anything following a `.`), rather than parsing it.
+/// Aggregate functions are only matched when they're called.
+fn find_query_plan_clause(query: &str) -> Option<&str> {
+    const KEYWORDS: &[&str] = &["ORDER", "GROUP", "DISTINCT", "TOP", "OFFSET", "LIMIT"];
+    const AGGREGATES: &[&str] = &[
+        "COUNT", "SUM", "AVG", "MIN", "MAX", "COUNTIF", "MAKELIST", "MAKESET",
+    ];
+
+    find_keyword(query, |word, called| {
+        KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(word))
+            || (called && AGGREGATES.iter().any(|a| a.eq_ignore_ascii_case(word)))
+    })
+}
+
+/// Finds the first word in the query text that `matches` accepts, given the word and whether it's followed by a `(`.
+///
+/// String literals, comments and property names (anything following a `.`) are skipped.
+fn find_keyword(query: &str, mut matches: impl FnMut(&str, bool) -> bool) -> Option<&str> {
+    let mut chars = query.char_indices().peekable();
+    let mut after_dot = false;
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                while let Some((_, next)) = chars.next() {
+                    if next == '\\' {
+                        chars.next();
+                    } else if next == c {
+                        break;
+                    }
+                }
+                after_dot = false;
+            }
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                for (_, next) in chars.by_ref() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            }
+            '.' => after_dot = true,
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, next)) = chars.next_if(|(_, n)| n.is_alphanumeric() || *n == '_')
+                {
+                    end = i + next.len_utf8();
+                }
+                let word = &query[start..end];
+                if !after_dot {
+                    let called = chars
+                        .clone()
+                        .find(|(_, n)| !n.is_whitespace())
+                        .is_some_and(|(_, n)| n == '(');
+                    if matches(word, called) {
+                        return Some(word);
+                    }
+                }
+                after_dot = false;
+            }
+            c if c.is_whitespace() => {}
+            _ => after_dot = false,
+        }
+    }
+    None
+}
+
 // The tests for the pipeline are found in integration tests (in the `tests`) directory, since we want to test an end-to-end experience that matches what the user will see.
 // Individual components of the pipeline are tested in the other modules.
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::query::node::PipelineNodeResult;
 
     fn create_pkrange(id: &str, min: &str, max: &str) -> PartitionKeyRange {
         PartitionKeyRange::new(id, min, max)
@@ -729,4 +1919,394 @@ mod tests {
 
         assert_eq!(pkranges.len(), 0);
     }
+
+    #[test]
+    fn test_multi_hash_prefix_query_range() {
+        use crate::{get_hashed_partition_key_string, PartitionKeyKind, PartitionKeyValue};
+
+        // A query filtering on the first path of a hierarchical partition key covers every EPK starting with the prefix's hash.
+        let prefix = get_hashed_partition_key_string(
+            &[PartitionKeyValue::String("tenant1".to_string())],
+            PartitionKeyKind::MultiHash,
+            2,
+        );
+        let split = format!("{}20000000000000000000000000000000", prefix);
+        let mut pkranges = vec![
+            create_pkrange("pk1", "", &prefix),
+            create_pkrange("pk2", &prefix, &split),
+            create_pkrange("pk3", &split, "FF"),
+        ];
+        pkranges.sort_by(|a, b| a.min_inclusive.cmp(&b.min_inclusive));
+        let query_ranges = vec![create_query_range(
+            &prefix,
+            &format!("{}FF", prefix),
+            true,
+            false,
+        )];
+
+        get_overlapping_pk_ranges(&mut pkranges, &query_ranges);
+
+        assert_eq!(pkranges.len(), 2);
+        assert_eq!(pkranges[0].id, "pk2");
+        assert_eq!(pkranges[1].id, "pk3");
+    }
+
+    #[test]
+    fn supported_features_without() {
+        let all = SUPPORTED_FEATURES
+            .as_str()
+            .split_terminator(',')
+            .collect::<Vec<_>>();
+        assert_eq!(SUPPORTED_FEATURES.as_str(), SUPPORTED_FEATURES.without(&[]));
+
+        let without = SUPPORTED_FEATURES.without(&[
+            QueryFeature::HybridSearch,
+            QueryFeature::OffsetAndLimit,
+            QueryFeature::DCount,
+            QueryFeature::Distinct,
+        ]);
+        let expected = all
+            .iter()
+            .copied()
+            .filter(|f| !["HybridSearch", "OffsetAndLimit", "DCount"].contains(f))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, without.split_terminator(',').collect::<Vec<_>>());
+        assert!(without.ends_with(',') && !without.contains(",,"));
+
+        assert_eq!(
+            "",
+            SUPPORTED_FEATURES.without(SUPPORTED_FEATURES.as_slice())
+        );
+    }
+
+    /// Builds a plan for a query that needs `feature`, or returns `None` if the engine has no code path for that feature.
+    fn plan_requiring(feature: QueryFeature) -> Option<QueryPlan> {
+        let hybrid = |weights: serde_json::Value| {
+            serde_json::json!({
+                "globalStatisticsQuery": "SELECT COUNT(1) AS documentCount, [] AS fullTextStatistics FROM c",
+                "componentQueryInfos": [
+                    {"rewrittenQuery": "SELECT * FROM c ORDER BY c.score0 DESC", "orderBy": ["Descending"]},
+                    {"rewrittenQuery": "SELECT * FROM c ORDER BY c.score1 DESC", "orderBy": ["Descending"]}
+                ],
+                "componentWeights": weights,
+                "take": 10,
+                "requiresGlobalStatistics": false
+            })
+        };
+
+        // This match has no wildcard, so adding a feature fails to compile until it's decided whether the engine supports it.
+        let plan = match feature {
+            QueryFeature::None
+            | QueryFeature::CompositeAggregate
+            | QueryFeature::HybridSearchSkipOrderByRewrite => return None,
+            QueryFeature::HybridSearch => {
+                serde_json::json!({"hybridSearchQueryInfo": hybrid(serde_json::json!([]))})
+            }
+            QueryFeature::WeightedRankFusion => {
+                serde_json::json!({"hybridSearchQueryInfo": hybrid(serde_json::json!([2.0, 0.5]))})
+            }
+            QueryFeature::Aggregate
+            | QueryFeature::CountIf
+            | QueryFeature::DCount
+            | QueryFeature::ListAndSetAggregate => {
+                let aggregate = match feature {
+                    QueryFeature::CountIf => "CountIf",
+                    QueryFeature::DCount => "DCount",
+                    QueryFeature::ListAndSetAggregate => "MakeList",
+                    _ => "Count",
+                };
+                serde_json::json!({"queryInfo": {"aggregates": [aggregate], "hasSelectValue": true}})
+            }
+            QueryFeature::NonValueAggregate => serde_json::json!({"queryInfo": {
+                "groupByAliases": ["total"],
+                "groupByAliasToAggregateType": {"total": "Count"}
+            }}),
+            QueryFeature::MultipleAggregates => serde_json::json!({"queryInfo": {
+                "groupByAliases": ["total", "sum"],
+                "groupByAliasToAggregateType": {"total": "Count", "sum": "Sum"}
+            }}),
+            QueryFeature::GroupBy => serde_json::json!({"queryInfo": {
+                "groupByExpressions": ["c.category"],
+                "groupByAliases": ["category"]
+            }}),
+            QueryFeature::Distinct => serde_json::json!({"queryInfo": {"distinctType": "Ordered"}}),
+            QueryFeature::OrderBy => serde_json::json!({"queryInfo": {"orderBy": ["Ascending"]}}),
+            QueryFeature::MultipleOrderBy => {
+                serde_json::json!({"queryInfo": {"orderBy": ["Ascending", "Descending"]}})
+            }
+            QueryFeature::NonStreamingOrderBy => serde_json::json!({"queryInfo": {
+                "orderBy": ["Descending"],
+                "hasNonStreamingOrderBy": true
+            }}),
+            QueryFeature::OffsetAndLimit => {
+                serde_json::json!({"queryInfo": {"offset": 1, "limit": 2}})
+            }
+            QueryFeature::Top => serde_json::json!({"queryInfo": {"top": 1}}),
+        };
+        let mut plan = plan;
+        plan["partitionedQueryExecutionInfoVersion"] = 1.into();
+        plan["queryRanges"] = serde_json::json!([]);
+        Some(serde_json::from_value(plan).unwrap())
+    }
+
+    #[test]
+    fn supported_features_match_implemented_features() {
+        for &feature in QueryFeature::ALL {
+            let implemented = plan_requiring(feature).is_some_and(|plan| {
+                QueryPipeline::new(
+                    "SELECT * FROM c",
+                    plan,
+                    [create_pkrange("partition0", "", "FF")],
+                )
+                .is_ok()
+            });
+            assert_eq!(
+                implemented,
+                SUPPORTED_FEATURES.as_slice().contains(&feature),
+                "{feature:?} is {}implemented, but is {}advertised",
+                if implemented { "" } else { "not " },
+                if implemented { "not " } else { "" },
+            );
+        }
+    }
+
+    #[test]
+    fn supported_features_are_const() {
+        // Downstream crates use the built-in features in constants, like the value of a header.
+        const HEADER: &str = SUPPORTED_FEATURES.as_str();
+        const FEATURES: &[QueryFeature] = SUPPORTED_FEATURES.as_slice();
+        assert_eq!(SUPPORTED_FEATURES.as_cstr().to_str().unwrap(), HEADER);
+        assert_eq!(FEATURES.len(), HEADER.split_terminator(',').count());
+    }
+
+    #[test]
+    fn supported_features_excluding() {
+        let excluded = SUPPORTED_FEATURES.excluding(&[QueryFeature::NonStreamingOrderBy]);
+        assert_eq!(
+            SUPPORTED_FEATURES
+                .as_slice()
+                .iter()
+                .copied()
+                .filter(|f| *f != QueryFeature::NonStreamingOrderBy)
+                .collect::<Vec<_>>(),
+            excluded.as_slice()
+        );
+        assert_eq!(
+            SUPPORTED_FEATURES.without(&[QueryFeature::NonStreamingOrderBy]),
+            excluded.as_str()
+        );
+        assert_eq!(excluded.as_str(), excluded.as_cstr().to_str().unwrap());
+        assert!(!excluded
+            .as_str()
+            .split(',')
+            .any(|f| f == "NonStreamingOrderBy"));
+
+        assert_eq!(
+            "OrderBy,Top,",
+            OwnedSupportedFeatures::new([QueryFeature::OrderBy, QueryFeature::Top]).as_str()
+        );
+        assert_eq!(
+            SUPPORTED_FEATURES.as_str(),
+            OwnedSupportedFeatures::new(SUPPORTED_FEATURES.as_slice().iter().copied()).as_str()
+        );
+    }
+
+    #[test]
+    fn test_find_query_plan_clause() {
+        for (query, expected) in [
+            ("SELECT * FROM c WHERE c.id = @id", None),
+            ("SELECT * FROM c ORDER BY c.id", Some("ORDER")),
+            ("select distinct c.name from c", Some("distinct")),
+            ("SELECT TOP 10 * FROM c", Some("TOP")),
+            ("SELECT * FROM c OFFSET 1 LIMIT 2", Some("OFFSET")),
+            ("SELECT VALUE COUNT (1) FROM c", Some("COUNT")),
+            ("SELECT c.count, c.order, c.top FROM c", None),
+            (
+                "SELECT * FROM c WHERE c.title = 'ORDER BY it\\'s TOP'",
+                None,
+            ),
+            ("SELECT * FROM c -- ORDER BY c.id\nWHERE c.x > 1.5", None),
+            (
+                "SELECT * FROM c WHERE c['group'] = \"x\" GROUP BY c.x",
+                Some("GROUP"),
+            ),
+        ] {
+            assert_eq!(expected, find_query_plan_clause(query), "{query}");
+        }
+    }
+
+    #[test]
+    fn test_with_top() {
+        for (query, expected) in [
+            (
+                "SELECT c._rid, c AS payload FROM c ORDER BY c.x",
+                Some("SELECT TOP 15 c._rid, c AS payload FROM c ORDER BY c.x"),
+            ),
+            (
+                "  select\nVALUE c FROM c ORDER BY c.x",
+                Some("  select TOP 15\nVALUE c FROM c ORDER BY c.x"),
+            ),
+            ("SELECT TOP 10 c._rid FROM c ORDER BY c.x", None),
+            ("SELECT c._rid FROM c ORDER BY c.x OFFSET 5 LIMIT 10", None),
+            ("SELECT DISTINCT c.x FROM c ORDER BY c.x", None),
+            (
+                "SELECT c._rid FROM c WHERE EXISTS (SELECT TOP 1 t FROM t IN c.tags) ORDER BY c.x",
+                None,
+            ),
+            (
+                "SELECT c.top, c.offset FROM c WHERE c.title = 'TOP' ORDER BY c.x",
+                Some("SELECT TOP 15 c.top, c.offset FROM c WHERE c.title = 'TOP' ORDER BY c.x"),
+            ),
+            ("SELECTED", None),
+            ("SELECT", None),
+        ] {
+            assert_eq!(expected, with_top(query, 15).as_deref(), "{query}");
+        }
+    }
+
+    #[test]
+    fn test_select_value_path() {
+        for (query, expected) in [
+            (
+                "SELECT VALUE c.name FROM c ORDER BY c.name",
+                Some(vec!["name"]),
+            ),
+            (
+                "select value c.address.city from c",
+                Some(vec!["address", "city"]),
+            ),
+            (
+                "SELECT TOP 5 VALUE r[\"first name\"]['x'] FROM root r WHERE r.y > 1",
+                Some(vec!["first name", "x"]),
+            ),
+            ("SELECT VALUE d.name FROM c AS d", Some(vec!["name"])),
+            ("SELECT VALUE c FROM c", Some(vec![])),
+            (
+                "SELECT VALUE c.name FROM c WHERE c.x = 1",
+                Some(vec!["name"]),
+            ),
+            ("SELECT VALUE c.name FROM d", None),
+            ("SELECT VALUE t.name FROM c JOIN t IN c.tags", None),
+            ("SELECT VALUE c.name FROM c JOIN t IN c.tags", None),
+            ("SELECT VALUE c.x + 1 FROM c", None),
+            ("SELECT VALUE {\"n\": c.name} FROM c", None),
+            ("SELECT VALUE c.tags[0] FROM c", None),
+            ("SELECT c.name FROM c", None),
+        ] {
+            let expected = expected.map(|path| path.into_iter().map(String::from).collect());
+            assert_eq!(expected, select_value_path(query), "{query}");
+        }
+    }
+
+    #[test]
+    fn drain_runs_query_with_in_memory_fetcher() {
+        let mut pipeline = QueryPipeline::new(
+            "SELECT * FROM c ORDER BY c.x",
+            QueryPlan {
+                partitioned_query_execution_info_version: 1,
+                query_info: Some(QueryInfo {
+                    order_by: vec![crate::query::SortOrder::Ascending],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            vec![
+                create_pkrange("partition0", "", "80"),
+                create_pkrange("partition1", "80", "FF"),
+            ],
+        )
+        .unwrap();
+
+        // Each partition's pages, keyed by the continuation they're requested with.
+        let pages: std::collections::HashMap<_, _> = [
+            (("partition0", None), ("[1, 4]", Some("p0"))),
+            (("partition0", Some("p0")), ("[5]", None)),
+            (("partition1", None), ("[2]", Some("p1"))),
+            (("partition1", Some("p1")), ("[3, 6]", None)),
+        ]
+        .into_iter()
+        .collect();
+        let mut fetched = Vec::new();
+        let items = pipeline
+            .drain(|request| {
+                let key = (request.pkrange_id.as_ref(), request.continuation.as_deref());
+                fetched.push(format!("{}/{:?}", key.0, key.1));
+                let (values, continuation) = pages[&key];
+                let documents = serde_json::from_str::<Vec<u32>>(values)
+                    .unwrap()
+                    .into_iter()
+                    .map(|x| format!(r#"{{"orderByItems":[{{"item":{x}}}],"payload":{x}}}"#))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                Ok((
+                    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes(),
+                    continuation.map(String::from),
+                ))
+            })
+            .unwrap();
+
+        assert_eq!(
+            vec!["1", "2", "3", "4", "5", "6"],
+            items.iter().map(|i| i.get()).collect::<Vec<_>>()
+        );
+        assert_eq!(4, fetched.len());
+        assert!(pipeline.complete());
+    }
+
+    #[test]
+    fn drain_returns_fetch_errors() {
+        let mut pipeline = QueryPipeline::new(
+            "SELECT * FROM c",
+            QueryPlan {
+                partitioned_query_execution_info_version: 1,
+                query_info: Some(QueryInfo::default()),
+                ..Default::default()
+            },
+            vec![create_pkrange("partition0", "", "FF")],
+        )
+        .unwrap();
+        let err = pipeline
+            .drain(|_| Err(ErrorKind::InvalidGatewayResponse.with_message("service unavailable")))
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
+        assert!(!pipeline.complete());
+    }
+
+    /// Discards every item, and never terminates, so the pipeline stalls once its partitions are exhausted.
+    #[derive(Debug)]
+    struct StallingPipelineNode;
+
+    impl PipelineNode for StallingPipelineNode {
+        fn next_item(&mut self, mut rest: PipelineSlice) -> crate::Result<PipelineNodeResult> {
+            while rest.run()?.value.is_some() {}
+            Ok(PipelineNodeResult::NO_RESULT)
+        }
+    }
+
+    #[test]
+    fn drain_fails_when_the_pipeline_stalls() {
+        let mut pipeline = QueryPipeline::new(
+            "SELECT * FROM c",
+            QueryPlan {
+                partitioned_query_execution_info_version: 1,
+                query_info: Some(QueryInfo::default()),
+                ..Default::default()
+            },
+            vec![create_pkrange("partition0", "", "FF")],
+        )
+        .unwrap();
+        pipeline.pipeline.push(Box::new(StallingPipelineNode));
+
+        // Internal errors panic in debug builds, so the error is only returned in release builds.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pipeline.drain(|_| Ok((br#"{"Documents": [1, 2]}"#.to_vec(), None)))
+        }));
+        if cfg!(debug_assertions) {
+            let message = *result.unwrap_err().downcast::<String>().unwrap();
+            assert!(message.contains("requested no data"), "{message}");
+        } else {
+            let err = result.unwrap().unwrap_err();
+            assert_eq!(ErrorKind::InternalError, err.kind());
+        }
+    }
 }
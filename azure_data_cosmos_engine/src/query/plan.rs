@@ -3,7 +3,9 @@
 
 use std::collections::HashMap;
 
-use serde::Deserialize;
+use serde::{de::IgnoredAny, Deserialize};
+
+use crate::ErrorKind;
 
 /// Models the response returned by the Gateway when making a query plan request.
 #[derive(Debug, Default, Deserialize)]
@@ -33,7 +35,128 @@ pub struct QueryPlan {
     pub query_ranges: Vec<QueryRange>,
 
     /// Information about hybrid search queries, if applicable.
+    #[cfg_attr(
+        feature = "python_conversions",
+        pyo3(item("hybridSearchQueryInfo"), default)
+    )]
     pub hybrid_search_query_info: Option<HybridSearchQueryInfo>,
+
+    /// The top-level fields of the plan that the engine doesn't recognize.
+    #[cfg_attr(feature = "python_conversions", pyo3(default))]
+    #[serde(flatten)]
+    pub unrecognized: UnrecognizedFields,
+}
+
+impl QueryPlan {
+    /// Creates the plan for a query that the partitions can execute without any cross-partition processing.
+    ///
+    /// The plan has an empty [`QueryInfo`] and no query ranges, so the query runs against every partition and the results are returned unordered.
+    /// It must only be used for queries without `ORDER BY`, `GROUP BY`, `DISTINCT`, `TOP`, `OFFSET`, `LIMIT` or aggregates,
+    /// which need a plan from the gateway to be executed correctly. See [`QueryPipeline::new_passthrough`](crate::query::QueryPipeline::new_passthrough).
+    pub fn default_for_passthrough() -> Self {
+        Self {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo::default()),
+            ..Default::default()
+        }
+    }
+
+    /// Parses a query plan, logging a warning if it contains fields the engine doesn't recognize.
+    ///
+    /// The gateway may add new fields to query plans before the engine knows how to handle them.
+    /// The plan can still be executed, but the warning helps diagnose incorrect results caused by ignoring them.
+    /// See [`QueryPlan::unrecognized_fields`] for the fields that were found.
+    pub fn from_slice_strict(data: &[u8]) -> crate::Result<Self> {
+        let plan: QueryPlan = serde_json::from_slice(data)
+            .map_err(|e| ErrorKind::InvalidGatewayResponse.with_source(e))?;
+        let unrecognized_fields = plan.unrecognized_fields();
+        if !unrecognized_fields.is_empty() {
+            tracing::warn!(
+                ?unrecognized_fields,
+                "query plan contains fields the engine doesn't recognize"
+            );
+        }
+        Ok(plan)
+    }
+
+    /// Gets the names of the fields in this plan that the engine doesn't recognize.
+    ///
+    /// Fields within a section of the plan are prefixed by the name of that section, for example `queryInfo.newClause`,
+    /// or `hybridSearchQueryInfo.componentQueryInfos[1].newClause`.
+    /// Fields are only recorded when the plan is deserialized from JSON.
+    pub fn unrecognized_fields(&self) -> Vec<String> {
+        let mut fields = self.unrecognized.0.clone();
+        fields.extend(self.unrecognized_execution_fields());
+        fields
+    }
+
+    /// Gets the names of the unrecognized fields in the sections of the plan that describe how the query is executed.
+    ///
+    /// Ignoring one of these fields may change the results of the query.
+    pub(crate) fn unrecognized_execution_fields(&self) -> Vec<String> {
+        let mut fields = Vec::new();
+        if let Some(query_info) = &self.query_info {
+            fields.extend(query_info.unrecognized.prefixed("queryInfo"));
+        }
+        if let Some(hybrid) = &self.hybrid_search_query_info {
+            fields.extend(hybrid.unrecognized.prefixed("hybridSearchQueryInfo"));
+            for (index, query_info) in hybrid.component_query_infos.iter().enumerate() {
+                fields.extend(query_info.unrecognized.prefixed(&format!(
+                    "hybridSearchQueryInfo.componentQueryInfos[{index}]"
+                )));
+            }
+        }
+        fields
+    }
+}
+
+/// The names of the fields in a section of a query plan that the engine doesn't recognize.
+///
+/// Serde ignores unknown fields, so this is flattened into each section to collect the names of any that remain.
+/// The values of those fields are discarded.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UnrecognizedFields(Vec<String>);
+
+impl UnrecognizedFields {
+    fn prefixed<'a>(&'a self, section: &'a str) -> impl Iterator<Item = String> + 'a {
+        self.0.iter().map(move |field| format!("{section}.{field}"))
+    }
+}
+
+impl<'de> Deserialize<'de> for UnrecognizedFields {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = UnrecognizedFields;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a map")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_key::<String>()? {
+                    map.next_value::<IgnoredAny>()?;
+                    fields.push(field);
+                }
+                Ok(UnrecognizedFields(fields))
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+#[cfg(feature = "python_conversions")]
+impl<'a> pyo3::FromPyObject<'a> for UnrecognizedFields {
+    /// Plans provided by Python are converted field by field, so unrecognized fields are never collected from them.
+    fn extract_bound(_ob: &pyo3::Bound<'a, pyo3::PyAny>) -> pyo3::PyResult<Self> {
+        Ok(Self::default())
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,25 +168,46 @@ pub struct QueryPlan {
 #[serde(rename_all = "camelCase")]
 pub struct HybridSearchQueryInfo {
     /// Provides the query to be used for global statistics gathering.
+    #[cfg_attr(feature = "python_conversions", pyo3(item("globalStatisticsQuery")))]
     pub global_statistics_query: String,
 
     /// Provides the individual component queries that make up the hybrid search query.
+    #[cfg_attr(feature = "python_conversions", pyo3(item("componentQueryInfos")))]
     pub component_query_infos: Vec<QueryInfo>,
 
     /// The weights assigned to each component query, if any.
+    #[cfg_attr(
+        feature = "python_conversions",
+        pyo3(item("componentWeights"), default)
+    )]
     #[serde(default)]
     pub component_weights: Vec<f64>,
 
     /// The number of results to skip.
+    #[cfg_attr(feature = "python_conversions", pyo3(default))]
     pub skip: Option<u64>,
 
     /// The number of results to take.
     ///
     /// This should always be present, because hybrid search queries require a TOP clause.
+    #[cfg_attr(feature = "python_conversions", pyo3(default))]
     pub take: Option<u64>,
 
     /// Indicates if global statistics are required for this query.
+    #[cfg_attr(feature = "python_conversions", pyo3(item("requiresGlobalStatistics")))]
     pub requires_global_statistics: bool,
+
+    /// The constant `k` used by reciprocal rank fusion, which scores each result as the sum of `weight / (k + rank)` across the component queries.
+    ///
+    /// Smaller values give more weight to the top ranked results of each component query. If not provided, `60` is used.
+    #[cfg_attr(feature = "python_conversions", pyo3(item("rrfConstant"), default))]
+    #[serde(default)]
+    pub rrf_constant: Option<f64>,
+
+    /// The fields of this section that the engine doesn't recognize.
+    #[cfg_attr(feature = "python_conversions", pyo3(default))]
+    #[serde(flatten)]
+    pub unrecognized: UnrecognizedFields,
 }
 
 /// The kind of DISTINCT tracking required by the query.
@@ -182,6 +326,21 @@ pub struct QueryInfo {
         pyo3(item("hasNonStreamingOrderBy"), default)
     )]
     pub has_non_streaming_order_by: bool,
+
+    /// Indicates if a non-streaming `ORDER BY`, like a vector search ordered by `VectorDistance`, can still be streamed.
+    ///
+    /// This is set when every partition already returns its results in their final order, for example with a flat vector index.
+    /// It only has an effect when [`QueryInfo::has_non_streaming_order_by`] is set.
+    #[cfg_attr(
+        feature = "python_conversions",
+        pyo3(item("hasStreamingSafeOrderBy"), default)
+    )]
+    pub has_streaming_safe_order_by: bool,
+
+    /// The fields of this section that the engine doesn't recognize.
+    #[cfg_attr(feature = "python_conversions", pyo3(default))]
+    #[serde(flatten)]
+    pub unrecognized: UnrecognizedFields,
 }
 
 /// The sort order used by a particular `ORDER BY` expression.
@@ -1,7 +1,9 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::collections::{BTreeSet, VecDeque};
+use std::collections::{BTreeMap, VecDeque};
+
+use serde_json::value::RawValue;
 
 use crate::{
     query::{
@@ -22,7 +24,7 @@ pub enum QueryResultCollector {
 
     /// Collects results from multiple component queries.
     /// Results must be de-duplicated based on their RID.
-    Multiple(BTreeSet<ComponentQueryResult>),
+    Multiple(CandidateSet),
 }
 
 impl QueryResultCollector {
@@ -31,17 +33,25 @@ impl QueryResultCollector {
     }
 
     pub fn multiple() -> Self {
-        QueryResultCollector::Multiple(BTreeSet::new())
+        QueryResultCollector::Multiple(CandidateSet::default())
     }
 
     pub fn len(&self) -> usize {
         match self {
             QueryResultCollector::Singleton(v) => v.len(),
-            QueryResultCollector::Multiple(s) => s.len(),
+            QueryResultCollector::Multiple(s) => s.results.len(),
         }
     }
 
-    pub fn provide_data(&mut self, data: &[u8]) -> crate::Result<()> {
+    /// Collects a page of results from a component query.
+    ///
+    /// Results that can no longer appear in the final page are discarded as pages arrive, so that we don't have to hold every payload until all component queries complete.
+    pub fn provide_data(
+        &mut self,
+        data: &[u8],
+        pagination: PaginationParameters,
+        component_queries: &[ComponentQueryState],
+    ) -> crate::Result<()> {
         let result: FeedResponse<ComponentQueryResult> =
             serde_json::from_slice(data).map_err(|e| {
                 ErrorKind::DeserializationError.with_message(format!(
@@ -51,25 +61,17 @@ impl QueryResultCollector {
             })?;
 
         match self {
-            QueryResultCollector::Singleton(v) => v.extend(result.documents),
+            QueryResultCollector::Singleton(v) => {
+                // Results are paginated in the order they arrive, so anything after the final page will never be used.
+                let remaining = pagination.limit().saturating_sub(v.len());
+                v.extend(result.documents.into_iter().take(remaining));
+            }
             QueryResultCollector::Multiple(s) => {
+                let sort_orders = component_sort_orders(component_queries)?;
                 for item in result.documents {
-                    #[cfg(debug_assertions)]
-                    let scores = item.payload.component_scores.clone();
-
-                    #[allow(unused_variables, reason = "used in debug assertions")]
-                    if let Some(old) = s.replace(item) {
-                        // Check that all the component scores are the same for duplicate items
-                        // We do have to include `#[cfg(debug_assertions)]` here, even though `debug_assert_eq!` exists.
-                        // `debug_assert_eq!` works using 'if cfg!(debug_assertions)', which means the code is still type-checked in release builds.
-                        // And it would error out because it can't find `scores`, which is only defined in debug builds.
-                        #[cfg(debug_assertions)]
-                        assert_eq!(
-                            old.payload.component_scores, scores,
-                            "mismatched component scores for duplicate hybrid search result"
-                        );
-                    }
+                    s.insert(item, &sort_orders);
                 }
+                s.prune(pagination, component_queries);
             }
         }
         Ok(())
@@ -79,19 +81,187 @@ impl QueryResultCollector {
         self,
         pagination: PaginationParameters,
         component_queries: &[ComponentQueryState],
+        rrf_constant: f64,
     ) -> crate::Result<VecDeque<QueryResult>> {
         match self {
             QueryResultCollector::Singleton(results) => Ok(pagination.paginate(results)),
-            QueryResultCollector::Multiple(results) => {
-                let scores = get_scores(component_queries, &results)?;
+            QueryResultCollector::Multiple(candidates) => {
+                let results = candidates.results.into_values().collect::<Vec<_>>();
+                let scores = get_scores(
+                    component_queries,
+                    results.iter().map(|c| c.component_scores.as_slice()),
+                )?;
                 let ranks = scores.into_ranks();
-                let fused = ranks.into_fused_results(component_queries, results);
-                Ok(pagination.paginate(fused))
+                let fused = ranks.into_fused_results(component_queries, results, rrf_constant);
+                fused
+                    .into_iter()
+                    .skip(pagination.skip as usize)
+                    .take(pagination.take as usize)
+                    .map(|c| {
+                        c.payload.map(QueryResult::RawPayload).ok_or_else(|| {
+                            ErrorKind::InternalError.with_message(format!(
+                                "hybrid search result {} was discarded, but is in the final page",
+                                c.rid
+                            ))
+                        })
+                    })
+                    .collect()
             }
         }
     }
 }
 
+/// A hybrid search result collected from one or more component queries.
+#[derive(Debug)]
+pub struct Candidate {
+    rid: String,
+    component_scores: Vec<f64>,
+
+    /// The user payload, or `None` if the result was discarded because it can't appear in the final page.
+    payload: Option<Box<RawValue>>,
+}
+
+/// The results collected from multiple component queries, de-duplicated by RID, see [`CandidateSet::insert`].
+///
+/// Every result's scores are kept until the end, because the ranks of all the other results depend on them.
+/// But a result's payload is dropped as soon as we know it can't be in the final page.
+/// A result can't be in the final page if at least `skip + take` other results "dominate" it.
+/// Result A dominates result D when A's score is at least as good as D's in every component with a positive weight, and A's RID sorts before D's.
+/// That means A's rank is never worse than D's in any component, so A's fused score is at least D's, and ties are broken by RID, so A sorts before D.
+/// Since that only depends on the scores of A and D, it remains true no matter what other results arrive later.
+#[derive(Debug, Default)]
+pub struct CandidateSet {
+    results: BTreeMap<String, Candidate>,
+
+    /// The number of results that still have a payload.
+    retained: usize,
+
+    /// The number of retained results that triggers the next pruning pass.
+    next_prune: usize,
+}
+
+impl CandidateSet {
+    /// Adds a result, merging it with any existing result with the same RID.
+    ///
+    /// The same document can be returned by several component queries, and each may report different scores for it.
+    /// For each component, we keep the best score reported so far: the highest for descending components, and the lowest for ascending ones.
+    ///
+    /// Since scores only ever improve, a result that dominates another keeps dominating it, so earlier pruning stays valid.
+    /// A discarded result whose scores improve may no longer be dominated, so it takes the payload of the new copy.
+    fn insert(&mut self, item: ComponentQueryResult, sort_orders: &[SortOrder]) {
+        match self.results.get_mut(&item.rid) {
+            Some(existing) => {
+                let mut improved = false;
+                for ((existing_score, &score), order) in existing
+                    .component_scores
+                    .iter_mut()
+                    .zip(&item.payload.component_scores)
+                    .zip(sort_orders)
+                {
+                    let better = match order {
+                        SortOrder::Ascending => score < *existing_score,
+                        SortOrder::Descending => score > *existing_score,
+                    };
+                    if better {
+                        *existing_score = score;
+                        improved = true;
+                    }
+                }
+
+                if existing.payload.is_none() && improved {
+                    self.retained += 1;
+                }
+                if existing.payload.is_some() || improved {
+                    existing.payload = Some(item.payload.user_payload);
+                }
+            }
+            None => {
+                self.retained += 1;
+                self.results.insert(
+                    item.rid.clone(),
+                    Candidate {
+                        rid: item.rid,
+                        component_scores: item.payload.component_scores,
+                        payload: Some(item.payload.user_payload),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Drops the payloads of results that can't appear in the final page.
+    ///
+    /// Checking a result compares it against every other result, so we only do a pass when the number of retained payloads has doubled since the last one.
+    fn prune(
+        &mut self,
+        pagination: PaginationParameters,
+        component_queries: &[ComponentQueryState],
+    ) {
+        let limit = pagination.limit();
+        if self.retained <= limit.max(self.next_prune) {
+            return;
+        }
+
+        // Negative weights reward worse ranks, so dominance doesn't imply a better fused score.
+        if component_queries.iter().any(|c| c.weight < 0.0) {
+            return;
+        }
+        // An invalid plan is reported when the final results are computed, don't prune anything until then.
+        let Ok(sort_orders) = component_sort_orders(component_queries) else {
+            return;
+        };
+        let weighted = component_queries
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.weight > 0.0)
+            .map(|(i, _)| (i, sort_orders[i]))
+            .collect::<Vec<_>>();
+
+        let dominates = |a: &Candidate, d: &Candidate| {
+            weighted.iter().all(|&(i, order)| {
+                match (a.component_scores.get(i), d.component_scores.get(i)) {
+                    (Some(a), Some(d)) => match order {
+                        SortOrder::Ascending => a <= d,
+                        SortOrder::Descending => a >= d,
+                    },
+                    // Mismatched scores are reported when the final results are computed, don't prune anything until then.
+                    _ => false,
+                }
+            })
+        };
+
+        let mut discarded = Vec::new();
+        for (rid, candidate) in &self.results {
+            if candidate.payload.is_none() {
+                continue;
+            }
+            // The results are sorted by RID, so only the results before this one can dominate it.
+            let dominators = self
+                .results
+                .range::<String, _>(..rid)
+                .filter(|(_, other)| dominates(other, candidate))
+                .take(limit)
+                .count();
+            if dominators >= limit {
+                discarded.push(rid.clone());
+            }
+        }
+
+        tracing::trace!(
+            retained = self.retained,
+            discarded = discarded.len(),
+            "pruned hybrid search candidates"
+        );
+        for rid in discarded {
+            if let Some(candidate) = self.results.get_mut(&rid) {
+                candidate.payload = None;
+                self.retained -= 1;
+            }
+        }
+        self.next_prune = self.retained * 2;
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ScoreTuple {
     pub score: f64,
@@ -187,13 +357,15 @@ impl ScoreList {
 pub struct RankList(Vec<Vec<usize>>);
 
 impl RankList {
-    const RRF_CONSTANT: f64 = 60.0;
+    /// The RRF constant used when the query plan doesn't provide one.
+    pub const DEFAULT_RRF_CONSTANT: f64 = 60.0;
 
-    pub fn into_fused_results(
+    pub fn into_fused_results<T>(
         self,
         components: &[ComponentQueryState],
-        results: impl IntoIterator<Item = ComponentQueryResult>,
-    ) -> Vec<ComponentQueryResult> {
+        results: impl IntoIterator<Item = T>,
+        rrf_constant: f64,
+    ) -> Vec<T> {
         debug_assert_eq!(self.0.len(), components.len());
         let mut fused_results = Vec::new();
 
@@ -202,7 +374,7 @@ impl RankList {
             for (component_index, rank_list) in self.0.iter().enumerate() {
                 let rank = rank_list[index] as f64;
                 let weight = components[component_index].weight;
-                fused_score += weight / (Self::RRF_CONSTANT + rank);
+                fused_score += weight / (rrf_constant + rank);
             }
             fused_results.push(RankFusionResult {
                 fused_score,
@@ -221,26 +393,26 @@ impl RankList {
 }
 
 #[derive(Debug)]
-struct RankFusionResult {
+struct RankFusionResult<T> {
     fused_score: f64,
-    result: ComponentQueryResult,
+    result: T,
 }
 
-impl PartialEq for RankFusionResult {
+impl<T> PartialEq for RankFusionResult<T> {
     fn eq(&self, other: &Self) -> bool {
         self.fused_score == other.fused_score
     }
 }
 
-impl Eq for RankFusionResult {}
+impl<T> Eq for RankFusionResult<T> {}
 
-impl PartialOrd for RankFusionResult {
+impl<T> PartialOrd for RankFusionResult<T> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for RankFusionResult {
+impl<T> Ord for RankFusionResult<T> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         // Only compare by fused score - stable sort will handle ties
         other
@@ -250,33 +422,42 @@ impl Ord for RankFusionResult {
     }
 }
 
-fn get_scores(
+/// Gets the sort order of each component query's scores.
+///
+/// Most scoring functions rank higher scores first, but some (like distance metrics) rank lower scores first.
+/// The gateway always provides the order for each component query, so a component without one indicates an invalid query plan.
+fn component_sort_orders(
     component_queries: &[ComponentQueryState],
-    results: &BTreeSet<ComponentQueryResult>,
-) -> crate::Result<ScoreList> {
-    let sort_orders = component_queries
+) -> crate::Result<Vec<SortOrder>> {
+    component_queries
         .iter()
-        .map(|cq| {
-            cq.query_info
-                .order_by
-                .get(0)
-                .copied()
-                .unwrap_or(SortOrder::Descending)
+        .enumerate()
+        .map(|(index, cq)| {
+            cq.query_info.order_by.first().copied().ok_or_else(|| {
+                ErrorKind::InvalidQuery.with_message(format!(
+                    "hybrid search component query {index} has no ORDER BY"
+                ))
+            })
         })
-        .collect();
+        .collect()
+}
 
-    let mut score_list = ScoreListBuilder::new(sort_orders, results.len());
-    for (index, result) in results.iter().enumerate() {
-        if result.payload.component_scores.len() != component_queries.len() {
+fn get_scores<'a>(
+    component_queries: &[ComponentQueryState],
+    results: impl ExactSizeIterator<Item = &'a [f64]>,
+) -> crate::Result<ScoreList> {
+    let mut score_list =
+        ScoreListBuilder::new(component_sort_orders(component_queries)?, results.len());
+    for (index, component_scores) in results.enumerate() {
+        if component_scores.len() != component_queries.len() {
             return Err(ErrorKind::InternalError.with_message(format!(
                 "mismatched number of component scores in hybrid search result: expected {}, got {}",
                 component_queries.len(),
-                result.payload.component_scores.len()
+                component_scores.len()
             )));
         }
 
-        for (component_index, score) in result.payload.component_scores.iter().copied().enumerate()
-        {
+        for (component_index, score) in component_scores.iter().copied().enumerate() {
             score_list.push_score(component_index, score, index)?;
         }
     }
@@ -309,7 +490,10 @@ mod tests {
     fn create_mock_component_state(weight: f64) -> ComponentQueryState {
         // Create a minimal component state for testing
         // We only need the weight field for RRF calculations
-        let query_info = crate::query::QueryInfo::default();
+        let query_info = crate::query::QueryInfo {
+            order_by: vec![SortOrder::Descending],
+            ..Default::default()
+        };
 
         ComponentQueryState::new(0, query_info, weight, &["partition1".to_string()])
     }
@@ -387,7 +571,8 @@ mod tests {
             create_test_result("doc5", vec![50.0, 90.0, 90.0, 50.0]),
         ];
 
-        let fused = rank_list.into_fused_results(&components, results);
+        let fused =
+            rank_list.into_fused_results(&components, results, RankList::DEFAULT_RRF_CONSTANT);
 
         // Validate the ordering of fused results based on expected fused scores
         assert_eq!(
@@ -396,6 +581,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rrf_constant_changes_fused_order() {
+        let components = vec![
+            create_mock_component_state(1.0),
+            create_mock_component_state(1.0),
+        ];
+        let fuse = |rrf_constant| {
+            // "top" is ranked first by one component, but poorly by the other. "even" is ranked fairly well by both.
+            let rank_list = RankList(vec![vec![1, 4], vec![9, 4]]);
+            let results = vec![
+                create_test_result("top", vec![100.0, 10.0]),
+                create_test_result("even", vec![70.0, 70.0]),
+            ];
+            rank_list
+                .into_fused_results(&components, results, rrf_constant)
+                .into_iter()
+                .map(|r| r.rid)
+                .collect::<Vec<_>>()
+        };
+
+        // A smaller constant rewards the top ranks of each component more.
+        assert_eq!(vec!["top", "even"], fuse(10.0));
+        assert_eq!(vec!["even", "top"], fuse(60.0));
+    }
+
     #[test]
     fn test_score_tuple_ordering() {
         // Create a score list with out-of-order scores and validate the final list is ordered by score
@@ -434,4 +644,314 @@ mod tests {
             score_list.scores
         )
     }
+
+    /// A tiny deterministic PRNG, so the randomized tests are reproducible without extra dependencies.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, max: u64) -> u64 {
+            self.next() % max
+        }
+    }
+
+    fn component_state(weight: f64, order: SortOrder) -> ComponentQueryState {
+        let query_info = crate::query::QueryInfo {
+            order_by: vec![order],
+            ..Default::default()
+        };
+        ComponentQueryState::new(0, query_info, weight, &["partition1".to_string()])
+    }
+
+    fn random_pages(rng: &mut XorShift, components: usize) -> Vec<Vec<u8>> {
+        let document_count = 20 + rng.below(200);
+
+        // Use a small range of scores so there are plenty of ties.
+        let scores = (0..document_count)
+            .map(|_| {
+                (0..components)
+                    .map(|_| (rng.below(20) as f64) / 2.0)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Pick documents with replacement, so the same document is returned several times, as it would be by different component queries.
+        let pages = (0..document_count)
+            .map(|_| {
+                let document = rng.below(document_count) as usize;
+
+                // Different component queries may report slightly different scores for the same document.
+                let component_scores = scores[document]
+                    .iter()
+                    .map(|score| score + (rng.below(3) as f64) / 4.0)
+                    .collect::<Vec<_>>();
+                serde_json::json!({
+                    "_rid": format!("rid{:04}", document),
+                    "payload": {"componentScores": component_scores, "payload": {"index": document}},
+                })
+            })
+            .collect::<Vec<_>>();
+        pages
+            .chunks(1 + rng.below(25) as usize)
+            .map(|documents| {
+                serde_json::to_vec(&serde_json::json!({"Documents": documents})).unwrap()
+            })
+            .collect()
+    }
+
+    fn collect_all(pages: &[Vec<u8>], components: &[ComponentQueryState]) -> QueryResultCollector {
+        let sort_orders = component_sort_orders(components).unwrap();
+        let mut candidates = CandidateSet::default();
+        for page in pages {
+            let page: FeedResponse<ComponentQueryResult> = serde_json::from_slice(page).unwrap();
+            for item in page.documents {
+                candidates.insert(item, &sort_orders);
+            }
+        }
+        QueryResultCollector::Multiple(candidates)
+    }
+
+    fn payloads(results: VecDeque<QueryResult>) -> Vec<String> {
+        results
+            .into_iter()
+            .map(|r| match r {
+                QueryResult::RawPayload(p) => p.get().to_string(),
+                _ => panic!("expected a raw payload"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_incremental_collection_matches_full_collection() {
+        let mut rng = XorShift(0x5EED_1234_ABCD_0001);
+        let mut pruned_any = false;
+        for _ in 0..200 {
+            let orders = [SortOrder::Descending, SortOrder::Ascending];
+            let components = (0..2 + rng.below(2))
+                .map(|_| {
+                    component_state(
+                        [0.0, 0.5, 1.0, 2.0][rng.below(4) as usize],
+                        orders[rng.below(2) as usize],
+                    )
+                })
+                .collect::<Vec<_>>();
+            let pagination = PaginationParameters {
+                skip: rng.below(5),
+                take: 1 + rng.below(10),
+            };
+            let pages = random_pages(&mut rng, components.len());
+
+            let expected = collect_all(&pages, &components)
+                .compute_final_results(pagination, &components, RankList::DEFAULT_RRF_CONSTANT)
+                .unwrap();
+
+            let mut collector = QueryResultCollector::multiple();
+            for page in &pages {
+                collector
+                    .provide_data(page, pagination, &components)
+                    .unwrap();
+            }
+            if let QueryResultCollector::Multiple(candidates) = &collector {
+                pruned_any |= candidates.retained < candidates.results.len();
+            }
+            let actual = collector
+                .compute_final_results(pagination, &components, RankList::DEFAULT_RRF_CONSTANT)
+                .unwrap();
+
+            assert_eq!(payloads(expected), payloads(actual));
+        }
+        assert!(pruned_any, "no candidates were ever pruned");
+    }
+
+    #[test]
+    fn test_incremental_collection_skips_pruning_with_negative_weights() {
+        let mut rng = XorShift(0x5EED_1234_ABCD_0002);
+        let components = vec![
+            component_state(1.0, SortOrder::Descending),
+            component_state(-1.0, SortOrder::Descending),
+        ];
+        let pagination = PaginationParameters { skip: 0, take: 2 };
+        let pages = random_pages(&mut rng, components.len());
+
+        let mut collector = QueryResultCollector::multiple();
+        for page in &pages {
+            collector
+                .provide_data(page, pagination, &components)
+                .unwrap();
+        }
+        let QueryResultCollector::Multiple(candidates) = &collector else {
+            panic!("expected a multiple collector");
+        };
+        assert_eq!(candidates.results.len(), candidates.retained);
+
+        let expected = collect_all(&pages, &components)
+            .compute_final_results(pagination, &components, RankList::DEFAULT_RRF_CONSTANT)
+            .unwrap();
+        let actual = collector
+            .compute_final_results(pagination, &components, RankList::DEFAULT_RRF_CONSTANT)
+            .unwrap();
+        assert_eq!(payloads(expected), payloads(actual));
+    }
+
+    #[test]
+    fn test_singleton_collection_keeps_only_final_page() {
+        let components = vec![create_mock_component_state(1.0)];
+        let pagination = PaginationParameters { skip: 1, take: 2 };
+        let page = br#"{"Documents":[
+            {"_rid":"a","payload":{"componentScores":[4],"payload":"a"}},
+            {"_rid":"b","payload":{"componentScores":[3],"payload":"b"}},
+            {"_rid":"c","payload":{"componentScores":[2],"payload":"c"}},
+            {"_rid":"d","payload":{"componentScores":[1],"payload":"d"}}
+        ]}"#;
+
+        let mut collector = QueryResultCollector::singleton();
+        collector
+            .provide_data(page, pagination, &components)
+            .unwrap();
+        assert_eq!(3, collector.len());
+
+        let results = collector
+            .compute_final_results(pagination, &components, RankList::DEFAULT_RRF_CONSTANT)
+            .unwrap();
+        assert_eq!(vec![r#""b""#, r#""c""#], payloads(results));
+    }
+
+    fn candidates(results: Vec<ComponentQueryResult>) -> QueryResultCollector {
+        let mut candidates = CandidateSet::default();
+        for result in results {
+            // The results have distinct RIDs, so no scores are merged and the sort orders aren't needed.
+            candidates.insert(result, &[]);
+        }
+        QueryResultCollector::Multiple(candidates)
+    }
+
+    #[test]
+    fn test_duplicate_results_keep_best_score_per_component() {
+        // A full-text score, where higher is better, and a vector distance, where lower is better.
+        let components = vec![
+            component_state(1.0, SortOrder::Descending),
+            component_state(1.0, SortOrder::Ascending),
+        ];
+        let pagination = PaginationParameters { skip: 0, take: 1 };
+
+        // Each component query returns "x", but only computes its own score precisely.
+        let full_text_page = br#"{"Documents":[
+            {"_rid":"x","payload":{"componentScores":[5.0, 0.9],"payload":"x"}},
+            {"_rid":"y","payload":{"componentScores":[3.0, 0.5],"payload":"y"}}
+        ]}"#;
+        let vector_page = br#"{"Documents":[
+            {"_rid":"x","payload":{"componentScores":[4.0, 0.2],"payload":"x"}},
+            {"_rid":"z","payload":{"componentScores":[1.0, 0.3],"payload":"z"}}
+        ]}"#;
+
+        let mut collector = QueryResultCollector::multiple();
+        for page in [&full_text_page[..], &vector_page[..]] {
+            collector
+                .provide_data(page, pagination, &components)
+                .unwrap();
+        }
+        let QueryResultCollector::Multiple(candidates) = &collector else {
+            panic!("expected a multiple collector");
+        };
+        assert_eq!(3, candidates.results.len());
+        assert_eq!(vec![5.0, 0.2], candidates.results["x"].component_scores);
+
+        // With the merged scores, "x" ranks first in both components.
+        let results = collector
+            .compute_final_results(pagination, &components, RankList::DEFAULT_RRF_CONSTANT)
+            .unwrap();
+        assert_eq!(vec![r#""x""#], payloads(results));
+    }
+
+    #[test]
+    fn test_fused_ranking_with_mixed_sort_orders() {
+        // A full-text score, where higher is better, and a distance, where lower is better.
+        let components = vec![
+            component_state(1.0, SortOrder::Descending),
+            component_state(2.0, SortOrder::Ascending),
+        ];
+        let results = vec![
+            create_test_result("a", vec![10.0, 0.9]),
+            create_test_result("b", vec![5.0, 0.5]),
+            create_test_result("c", vec![1.0, 0.1]),
+        ];
+
+        // Ranks are a: (1, 3), b: (2, 2), c: (3, 1), and the distance has the higher weight.
+        // If the distance were ranked in descending order, the order would be reversed.
+        let fused = get_scores(
+            &components,
+            results
+                .iter()
+                .map(|r| r.payload.component_scores.as_slice()),
+        )
+        .unwrap()
+        .into_ranks()
+        .into_fused_results(&components, results, RankList::DEFAULT_RRF_CONSTANT);
+        assert_eq!(
+            vec!["c", "b", "a"],
+            fused.iter().map(|r| r.rid.as_str()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_final_results_with_mixed_sort_orders() {
+        let components = vec![
+            component_state(1.0, SortOrder::Ascending),
+            component_state(1.0, SortOrder::Descending),
+        ];
+        let collector = candidates(
+            [("a", 0.1, 10.0), ("b", 0.5, 20.0), ("c", 0.2, 30.0)]
+                .into_iter()
+                .map(|(rid, distance, score)| ComponentQueryResult {
+                    rid: rid.to_string(),
+                    payload: crate::query::producer::hybrid::models::ComponentQueryPayload {
+                        component_scores: vec![distance, score],
+                        user_payload: create_raw_payload(&format!(r#""{rid}""#)),
+                    },
+                })
+                .collect(),
+        );
+
+        // Ranks are a: (1, 3), b: (3, 2), c: (2, 1).
+        let results = collector
+            .compute_final_results(
+                PaginationParameters { skip: 0, take: 2 },
+                &components,
+                RankList::DEFAULT_RRF_CONSTANT,
+            )
+            .unwrap();
+        assert_eq!(vec![r#""c""#, r#""a""#], payloads(results));
+    }
+
+    #[test]
+    fn test_component_without_order_by_is_invalid() {
+        let components = vec![
+            create_mock_component_state(1.0),
+            ComponentQueryState::new(
+                1,
+                crate::query::QueryInfo::default(),
+                1.0,
+                &["partition1".to_string()],
+            ),
+        ];
+        let collector = candidates(vec![
+            create_test_result("a", vec![1.0, 2.0]),
+            create_test_result("b", vec![2.0, 1.0]),
+        ]);
+
+        let err = collector
+            .compute_final_results(
+                PaginationParameters { skip: 0, take: 10 },
+                &components,
+                RankList::DEFAULT_RRF_CONSTANT,
+            )
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidQuery, err.kind());
+    }
 }
@@ -18,14 +18,15 @@ use crate::{
 };
 
 use component_state::ComponentQueryState;
-use fusion::QueryResultCollector;
+use fusion::{QueryResultCollector, RankList};
 use models::{ComponentQueryResult, GlobalStatistics, HybridRequestId};
 
 enum HybridSearchPhase {
     IssuingGlobalStatisticsQuery,
     AwaitingGlobalStatistics {
         aggregated_global_statistics: Option<GlobalStatistics>,
-        remaining_partitions: usize,
+        /// The IDs of the partitions that haven't provided their statistics yet.
+        pending_partitions: Vec<String>,
     },
     ComponentQueries {
         remaining_component_queries: usize,
@@ -58,11 +59,11 @@ impl std::fmt::Debug for HybridSearchPhase {
             }
             HybridSearchPhase::AwaitingGlobalStatistics {
                 aggregated_global_statistics,
-                remaining_partitions,
+                pending_partitions,
             } => f
                 .debug_struct("AwaitingGlobalStatistics")
                 .field("aggregated_global_statistics", aggregated_global_statistics)
-                .field("remaining_partitions", remaining_partitions)
+                .field("pending_partitions", pending_partitions)
                 .finish(),
             HybridSearchPhase::ComponentQueries {
                 remaining_component_queries,
@@ -87,6 +88,14 @@ struct PaginationParameters {
 }
 
 impl PaginationParameters {
+    /// Gets the number of results needed to produce the final page, including the skipped results.
+    pub fn limit(self) -> usize {
+        self.skip
+            .saturating_add(self.take)
+            .try_into()
+            .unwrap_or(usize::MAX)
+    }
+
     pub fn paginate(
         self,
         results: impl IntoIterator<Item = ComponentQueryResult>,
@@ -104,9 +113,10 @@ impl PaginationParameters {
 pub struct HybridSearchStrategy {
     global_statistics_query: String,
     phase: HybridSearchPhase,
-    pkrange_ids: Vec<String>,
+    pkranges: Vec<PartitionKeyRange>,
     component_queries: Vec<ComponentQueryState>,
     pagination: PaginationParameters,
+    rrf_constant: f64,
 }
 
 impl HybridSearchStrategy {
@@ -119,7 +129,17 @@ impl HybridSearchStrategy {
         } else {
             HybridSearchPhase::for_component_queries(query_info.component_query_infos.len())
         };
-        let pkrange_ids: Vec<String> = pkranges.into_iter().map(|p| p.id).collect();
+        let pkranges: Vec<PartitionKeyRange> = pkranges.into_iter().collect();
+        let pkrange_ids: Vec<String> = pkranges.iter().map(|p| p.id.clone()).collect();
+
+        let rrf_constant = query_info
+            .rrf_constant
+            .unwrap_or(RankList::DEFAULT_RRF_CONSTANT);
+        if !rrf_constant.is_finite() || rrf_constant <= 0.0 {
+            return Err(ErrorKind::InvalidQuery.with_message(format!(
+                "hybrid search RRF constant must be a positive, finite number, but was {rrf_constant}"
+            )));
+        }
 
         let component_queries = query_info
             .component_query_infos
@@ -137,7 +157,7 @@ impl HybridSearchStrategy {
         Ok(Self {
             global_statistics_query: query_info.global_statistics_query,
             phase,
-            pkrange_ids,
+            pkranges,
             component_queries,
             pagination: PaginationParameters {
                 skip: query_info.skip.unwrap_or(0),
@@ -146,38 +166,51 @@ impl HybridSearchStrategy {
                         .with_message("hybrid search query must include take parameter")
                 })?,
             },
+            rrf_constant,
         })
     }
 
+    /// Gets the partition key ranges that every component query is executed against.
+    pub fn pkranges(&self) -> &[PartitionKeyRange] {
+        &self.pkranges
+    }
+
     pub fn requests(&mut self) -> crate::Result<Vec<DataRequest>> {
         match self.phase {
             HybridSearchPhase::IssuingGlobalStatisticsQuery => {
-                let requests = self
-                    .pkrange_ids
-                    .iter()
-                    .map(|pkrange_id| {
-                        DataRequest::with_query(
-                            HybridRequestId::GLOBAL_STATISTICS_QUERY_ID.into(),
-                            pkrange_id.clone(),
-                            None,
-                            self.global_statistics_query.clone(),
-                            true,
-                        )
-                    })
-                    .collect::<Vec<_>>();
                 self.phase = HybridSearchPhase::AwaitingGlobalStatistics {
                     aggregated_global_statistics: None,
-                    remaining_partitions: self.pkrange_ids.len(),
+                    pending_partitions: self.pkranges.iter().map(|p| p.id.clone()).collect(),
                 };
-                Ok(requests)
+                self.requests()
             }
-            HybridSearchPhase::AwaitingGlobalStatistics { .. } => Err(ErrorKind::InternalError
-                .with_message("no requests should be made in AwaitingGlobalStatistics phase")),
+            // Until every partition has provided its statistics, keep asking the partitions that haven't.
+            HybridSearchPhase::AwaitingGlobalStatistics {
+                ref pending_partitions,
+                ..
+            } => Ok(pending_partitions
+                .iter()
+                .map(|pkrange_id| {
+                    // The statistics query is generated by the gateway and never refers to the user's parameters.
+                    DataRequest::with_query(
+                        HybridRequestId::GLOBAL_STATISTICS_QUERY_ID.into(),
+                        pkrange_id.clone(),
+                        None,
+                        self.global_statistics_query.clone(),
+                        false,
+                    )
+                })
+                .collect()),
             HybridSearchPhase::ComponentQueries { .. } => {
+                // The fusion only ever returns `take` results, so that's the most useful page size for each component query.
+                let max_item_count = self.pagination.take.try_into().unwrap_or(u32::MAX);
                 let mut requests = Vec::new();
                 for query_state in &self.component_queries {
                     let query_requests = query_state.requests();
-                    requests.extend(query_requests);
+                    requests.extend(query_requests.into_iter().map(|r| DataRequest {
+                        max_item_count: Some(max_item_count),
+                        ..r
+                    }));
                 }
                 Ok(requests)
             }
@@ -186,13 +219,17 @@ impl HybridSearchStrategy {
         }
     }
 
+    /// Provides data for a global statistics or component query.
+    ///
+    /// Returns the number of bytes of component query results buffered by the collector.
+    /// Global statistics are aggregated immediately, so they are never counted as buffered.
     pub fn provide_data(
         &mut self,
         pkrange_id: &str,
         request_id: u64,
         data: &[u8],
         continuation: Option<String>,
-    ) -> Result<(), crate::Error> {
+    ) -> Result<usize, crate::Error> {
         let request_id = HybridRequestId::from(request_id);
         match self.phase {
             HybridSearchPhase::IssuingGlobalStatisticsQuery => Err(ErrorKind::InternalError
@@ -201,12 +238,21 @@ impl HybridSearchStrategy {
                 )),
             HybridSearchPhase::AwaitingGlobalStatistics {
                 ref mut aggregated_global_statistics,
-                ref mut remaining_partitions,
+                ref mut pending_partitions,
             } => {
                 if request_id != HybridRequestId::GLOBAL_STATISTICS_QUERY_ID {
                     return Err(ErrorKind::InvalidGatewayResponse
                         .with_message("expected global statistics query response"));
                 }
+                let position = pending_partitions
+                    .iter()
+                    .position(|id| id == pkrange_id)
+                    .ok_or_else(|| {
+                        ErrorKind::InvalidGatewayResponse.with_message(format!(
+                            "unexpected global statistics for partition key range ID: {}",
+                            pkrange_id
+                        ))
+                    })?;
 
                 #[derive(Deserialize)]
                 struct GlobalStatisticsResult {
@@ -222,8 +268,11 @@ impl HybridSearchStrategy {
                     })?;
 
                 if results.documents.len() != 1 {
-                    return Err(ErrorKind::InvalidGatewayResponse
-                        .with_message("global statistics query should have only one item"));
+                    return Err(ErrorKind::InvalidGatewayResponse.with_message(format!(
+                        "global statistics query should have only one item, but partition key range ID {} returned {}",
+                        pkrange_id,
+                        results.documents.len()
+                    )));
                 }
                 let stats = results
                     .documents
@@ -239,8 +288,8 @@ impl HybridSearchStrategy {
                     None => stats,
                     Some(existing_stats) => existing_stats.aggregate_with(stats)?,
                 };
-                *remaining_partitions -= 1;
-                if *remaining_partitions == 0 {
+                pending_partitions.remove(position);
+                if pending_partitions.is_empty() {
                     // We've received all the global statistics results.
                     // Rewrite component queries with aggregated global statistics
                     tracing::debug!(
@@ -253,12 +302,9 @@ impl HybridSearchStrategy {
                         global_statistics.rewrite_component_query(&mut query_state.query_info)?;
                     }
                 } else {
-                    self.phase = HybridSearchPhase::AwaitingGlobalStatistics {
-                        aggregated_global_statistics: Some(global_statistics),
-                        remaining_partitions: *remaining_partitions,
-                    };
+                    *aggregated_global_statistics = Some(global_statistics);
                 }
-                Ok(())
+                Ok(0)
             }
             HybridSearchPhase::ComponentQueries {
                 ref mut remaining_component_queries,
@@ -281,10 +327,10 @@ impl HybridSearchStrategy {
                             .with_message("invalid component query index in request ID")
                     })?;
                 component_query.update_partition_state(pkrange_id, continuation)?;
-                results.provide_data(data)?;
                 if component_query.complete() {
                     *remaining_component_queries -= 1;
                 }
+                results.provide_data(data, self.pagination, &self.component_queries)?;
                 if *remaining_component_queries == 0 {
                     tracing::debug!("all component queries complete");
 
@@ -293,17 +339,25 @@ impl HybridSearchStrategy {
                     let results = std::mem::replace(results, QueryResultCollector::singleton());
 
                     // Process the results and move to result production
-                    let results =
-                        results.compute_final_results(self.pagination, &self.component_queries)?;
+                    let results = results.compute_final_results(
+                        self.pagination,
+                        &self.component_queries,
+                        self.rrf_constant,
+                    )?;
                     self.phase = HybridSearchPhase::ResultProduction(results);
                 }
-                Ok(())
+                Ok(data.len())
             }
             HybridSearchPhase::ResultProduction(_) => Err(ErrorKind::InternalError
                 .with_message("provide_data should not be called in ResultProduction phase")),
         }
     }
 
+    /// Releases every buffered result, by moving straight to producing an empty set of results.
+    pub fn clear(&mut self) {
+        self.phase = HybridSearchPhase::ResultProduction(VecDeque::new());
+    }
+
     pub fn produce_item(&mut self) -> crate::Result<PipelineNodeResult> {
         if let HybridSearchPhase::ResultProduction(ref mut results) = self.phase {
             if let Some(item) = results.pop_front() {
@@ -329,7 +383,7 @@ impl HybridSearchStrategy {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::query::{plan::HybridSearchQueryInfo, QueryInfo};
+    use crate::query::{plan::HybridSearchQueryInfo, QueryInfo, SortOrder};
     use models::{FullTextStatistics, GlobalStatistics};
     use pretty_assertions::assert_eq;
 
@@ -340,13 +394,13 @@ mod tests {
                 (
                     Self::AwaitingGlobalStatistics {
                         aggregated_global_statistics: a1,
-                        remaining_partitions: r1,
+                        pending_partitions: p1,
                     },
                     Self::AwaitingGlobalStatistics {
                         aggregated_global_statistics: a2,
-                        remaining_partitions: r2,
+                        pending_partitions: p2,
                     },
-                ) => a1 == a2 && r1 == r2,
+                ) => a1 == a2 && p1 == p2,
                 (
                     Self::ComponentQueries {
                         remaining_component_queries: r1,
@@ -372,6 +426,7 @@ mod tests {
     fn create_test_query_info(query: &str) -> QueryInfo {
         QueryInfo {
             rewritten_query: query.to_string(),
+            order_by: vec![SortOrder::Descending],
             ..Default::default()
         }
     }
@@ -393,16 +448,14 @@ mod tests {
             skip: Some(0),
             take,
             requires_global_statistics: requires_global_stats,
+            rrf_constant: None,
+            unrecognized: Default::default(),
         }
     }
 
     fn create_test_pkranges(count: usize) -> Vec<PartitionKeyRange> {
         (0..count)
-            .map(|i| PartitionKeyRange {
-                id: format!("partition_{}", i),
-                min_inclusive: "00".to_string(),
-                max_exclusive: "FF".to_string(),
-            })
+            .map(|i| PartitionKeyRange::new(format!("partition_{}", i), "00", "FF"))
             .collect()
     }
 
@@ -427,6 +480,25 @@ mod tests {
         strategy.phase = phase;
     }
 
+    #[test]
+    fn test_rrf_constant_must_be_positive_and_finite() {
+        for rrf_constant in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let query_info = HybridSearchQueryInfo {
+                rrf_constant: Some(rrf_constant),
+                ..create_hybrid_query_info(false, 2, Some(10))
+            };
+            let err = HybridSearchStrategy::new(create_test_pkranges(1), query_info).unwrap_err();
+            assert_eq!(ErrorKind::InvalidQuery, err.kind());
+        }
+
+        let query_info = HybridSearchQueryInfo {
+            rrf_constant: Some(10.0),
+            ..create_hybrid_query_info(false, 2, Some(10))
+        };
+        let strategy = HybridSearchStrategy::new(create_test_pkranges(1), query_info).unwrap();
+        assert_eq!(10.0, strategy.rrf_constant);
+    }
+
     #[test]
     fn test_global_statistics_to_component_queries_transition() {
         let pkranges = create_test_pkranges(2);
@@ -445,14 +517,14 @@ mod tests {
                     "partition_0".to_string(),
                     None,
                     strategy.global_statistics_query.clone(),
-                    true,
+                    false,
                 ),
                 DataRequest::with_query(
                     HybridRequestId::GLOBAL_STATISTICS_QUERY_ID.into(),
                     "partition_1".to_string(),
                     None,
                     strategy.global_statistics_query.clone(),
-                    true,
+                    false,
                 ),
             ],
             requests
@@ -462,7 +534,7 @@ mod tests {
             strategy.phase,
             HybridSearchPhase::AwaitingGlobalStatistics {
                 aggregated_global_statistics: None,
-                remaining_partitions: 2
+                pending_partitions: vec!["partition_0".to_string(), "partition_1".to_string()]
             }
         );
 
@@ -475,7 +547,7 @@ mod tests {
             strategy.phase,
             HybridSearchPhase::AwaitingGlobalStatistics {
                 aggregated_global_statistics: Some(stats1.clone()),
-                remaining_partitions: 1
+                pending_partitions: vec!["partition_1".to_string()]
             }
         );
 
@@ -504,6 +576,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_empty_global_statistics_are_rejected() {
+        let pkranges = create_test_pkranges(2);
+        let query_info = create_hybrid_query_info(true, 2, Some(10));
+        let mut strategy = HybridSearchStrategy::new(pkranges, query_info).unwrap();
+        strategy.requests().unwrap();
+        let awaiting_both = HybridSearchPhase::AwaitingGlobalStatistics {
+            aggregated_global_statistics: None,
+            pending_partitions: vec!["partition_0".to_string(), "partition_1".to_string()],
+        };
+
+        let err = strategy
+            .provide_data("partition_0", 0, br#"{"Documents": []}"#, None)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
+        assert_eq!(
+            "global statistics query should have only one item, but partition key range ID partition_0 returned 0",
+            err.to_string()
+        );
+        assert_eq!(awaiting_both, strategy.phase);
+
+        let err = strategy
+            .provide_data("partition_0", 0, br#"{"Documents": [{}]}"#, None)
+            .unwrap_err();
+        assert_eq!(ErrorKind::DeserializationError, err.kind());
+        assert_eq!(awaiting_both, strategy.phase);
+    }
+
+    #[test]
+    fn test_global_statistics_without_full_text_statistics_are_rejected() {
+        let pkranges = create_test_pkranges(2);
+        let mut query_info = create_hybrid_query_info(true, 2, Some(10));
+        query_info.component_query_infos[1].rewritten_query = "SELECT * FROM c ORDER BY RANK _FullTextScore(c.text, ['swim'], {documentdb-formattablehybridsearchquery-totaldocumentcount}, {documentdb-formattablehybridsearchquery-totalwordcount-0}, {documentdb-formattablehybridsearchquery-hitcountsarray-0})".to_string();
+        let mut strategy = HybridSearchStrategy::new(pkranges, query_info).unwrap();
+        strategy.requests().unwrap();
+
+        // Every partition agrees there are no full text statistics, so they aggregate, but the component query can't be scored without them.
+        let response = br#"{"Documents": [{"documentCount": 10, "fullTextStatistics": []}]}"#;
+        strategy
+            .provide_data("partition_0", 0, response, None)
+            .unwrap();
+        let err = strategy
+            .provide_data("partition_1", 0, response, None)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
+        assert_eq!(
+            "global statistics have 0 full text statistics, which don't include the value for {documentdb-formattablehybridsearchquery-totalwordcount-0}",
+            err.to_string()
+        );
+    }
+
     #[test]
     fn test_component_queries_to_result_production_transition() {
         let pkranges = create_test_pkranges(1);
@@ -669,9 +792,9 @@ mod tests {
 
         let requests = strategy.requests().unwrap();
 
-        // Should generate 2 partitions × 2 component queries = 4 requests
+        // Should generate 2 partitions × 2 component queries = 4 requests, each using `take` as the page size hint
         assert_eq!(
-            vec![
+            [
                 DataRequest::with_query(
                     HybridRequestId::for_component_query(0, 0).into(),
                     "partition_0".to_string(),
@@ -712,7 +835,13 @@ mod tests {
                         .clone(),
                     true,
                 ),
-            ],
+            ]
+            .into_iter()
+            .map(|r| DataRequest {
+                max_item_count: Some(10),
+                ..r
+            })
+            .collect::<Vec<_>>(),
             requests
         );
     }
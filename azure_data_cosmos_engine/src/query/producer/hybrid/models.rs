@@ -62,6 +62,7 @@ pub struct GlobalStatistics {
 
 const TOTAL_DOCUMENT_COUNT: &str = "{documentdb-formattablehybridsearchquery-totaldocumentcount}";
 const FORMATTABLE_ORDER_BY: &str = "{documentdb-formattableorderbyquery-filter}";
+const HYBRID_SEARCH_PLACEHOLDER_PREFIX: &str = "{documentdb-formattablehybridsearchquery-";
 
 impl GlobalStatistics {
     pub fn aggregate_with(mut self, stats: GlobalStatistics) -> crate::Result<GlobalStatistics> {
@@ -133,6 +134,20 @@ impl GlobalStatistics {
         let final_query = input_query
             .replace(TOTAL_DOCUMENT_COUNT, &self.document_count.to_string())
             .replace(FORMATTABLE_ORDER_BY, "true");
+
+        // A placeholder that's left over refers to full text statistics no partition returned.
+        // Running the query with it would fail, or score the results with the wrong statistics, so reject the statistics instead.
+        if let Some(start) = final_query.find(HYBRID_SEARCH_PLACEHOLDER_PREFIX) {
+            let placeholder = final_query[start..]
+                .split_inclusive('}')
+                .next()
+                .unwrap_or_default();
+            return Err(ErrorKind::InvalidGatewayResponse.with_message(format!(
+                "global statistics have {} full text statistics, which don't include the value for {}",
+                self.full_text_statistics.len(),
+                placeholder
+            )));
+        }
         tracing::trace!(final_query = ?final_query, "rewrote hybrid search query to incorporate global statistics");
         Ok(final_query)
     }
@@ -362,6 +377,32 @@ mod tests {
         assert_eq!(simple_query.rewritten_query, "SELECT * FROM c");
     }
 
+    #[test]
+    fn test_global_statistics_missing_full_text_statistics_error() {
+        let stats = GlobalStatistics {
+            document_count: 1000,
+            full_text_statistics: vec![FullTextStatistics {
+                total_word_count: 500,
+                hit_counts: vec![10],
+            }],
+        };
+
+        let mut query_info = QueryInfo {
+            rewritten_query: "SELECT * FROM c ORDER BY RANK _FullTextScore(c.text, ['swim'], {documentdb-formattablehybridsearchquery-totaldocumentcount}, {documentdb-formattablehybridsearchquery-totalwordcount-0}, {documentdb-formattablehybridsearchquery-hitcountsarray-0})".to_string(),
+            order_by_expressions: vec![
+                "_FullTextScore(c.title, ['swim'], {documentdb-formattablehybridsearchquery-totaldocumentcount}, {documentdb-formattablehybridsearchquery-totalwordcount-1}, {documentdb-formattablehybridsearchquery-hitcountsarray-1})".to_string(),
+            ],
+            ..Default::default()
+        };
+
+        let err = stats.rewrite_component_query(&mut query_info).unwrap_err();
+        assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
+        assert_eq!(
+            "global statistics have 1 full text statistics, which don't include the value for {documentdb-formattablehybridsearchquery-totalwordcount-1}",
+            err.to_string()
+        );
+    }
+
     // ComponentQueryResult tests
     #[test]
     fn test_component_query_result_equality_ignores_payload() {
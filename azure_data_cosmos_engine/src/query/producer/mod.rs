@@ -1,31 +1,76 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use crate::query::{
-    node::PipelineNodeResult, plan::HybridSearchQueryInfo, query_result::QueryResultShape,
-    DataRequest, PartitionKeyRange, SortOrder,
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use crate::{
+    query::{
+        gateway::CompositeContinuationToken, node::PipelineNodeResult, plan::HybridSearchQueryInfo,
+        query_result::QueryResultShape, DataRequest, PartitionKeyRange, SortOrder,
+    },
+    ErrorKind,
 };
 
+#[cfg(feature = "diagnostics")]
+use crate::query::diagnostics::{Clock, Diagnostics, ParseTimings};
+
 mod hybrid;
 mod non_streaming;
 mod sorting;
+mod spill;
 mod state;
 mod streaming;
+mod type_check;
 mod unordered;
 
 use hybrid::HybridSearchStrategy;
 use non_streaming::NonStreamingStrategy;
-use state::PartitionState;
+pub use sorting::OrderByTieBreak;
+pub use spill::SpillConfig;
+use state::PartitionSnapshot;
 use streaming::StreamingStrategy;
+pub use type_check::OrderByTypeMismatch;
 use unordered::UnorderedStrategy;
 
 /// An item producer handles merging results from several partitions into a single stream of results.
 ///
-/// The single-partition result streams are merged according to the variant of the producer selected when the producer is initialized.
+/// The single-partition result streams are merged according to the strategy selected when the producer is initialized.
 /// The producer is only responsible for handling ordering the results, other query operations like aggregations or offset/limit
 /// are handled by the pipeline that runs after a specific item has been produced.
 /// Ordering can't really be done by the pipeline though, since it may require buffering results from some or all partitions.
 /// So, before the pipeline runs, the producer is responsible for actually organizing the initial set of results in the correct order.
+///
+/// The producer also tracks how many bytes of result payloads it is currently buffering, so that an optional memory budget can be enforced.
+#[derive(Debug)]
+pub struct ItemProducer {
+    strategy: Strategy,
+
+    /// The maximum number of payload bytes the producer may buffer, if any.
+    memory_budget: Option<usize>,
+
+    /// The number of payload bytes currently buffered by the producer.
+    buffered_bytes: usize,
+
+    /// The page size hint attached to each [`DataRequest`], if any.
+    max_item_count: Option<u32>,
+
+    /// The encoded query parameters attached to each [`DataRequest`] that includes parameters, if any.
+    encoded_parameters: Option<Box<RawValue>>,
+
+    /// The maximum number of requests returned by [`ItemProducer::data_requests`], if any.
+    max_concurrent_requests: Option<usize>,
+
+    /// The partition key range ID of the partition data was last provided for, which the next limited set of requests starts after.
+    last_provided: Option<String>,
+
+    /// Timing instrumentation for the pages provided to the producer.
+    #[cfg(feature = "diagnostics")]
+    diagnostics: Diagnostics,
+}
+
 // --
 // This uses a common Rust pattern for internal-only "dynamic dispatch" called "enum dispatch".
 // True dynamic dispatch, using `dyn` has an increased runtime cost and hides information from the optimizer leading to even more performance loss.
@@ -33,7 +78,7 @@ use unordered::UnorderedStrategy;
 // This dispatch should be no worse than a virtual function call, and is often quite a lot better.
 // See https://crates.io/crates/enum_dispatch for more on this pattern (we're not using that crate, but we're doing what it does manually).
 #[derive(Debug)]
-pub enum ItemProducer {
+enum Strategy {
     /// Results are not re-ordered by the query and should be ordered by the partition key range minimum.
     Unordered(UnorderedStrategy),
     /// Results should be merged by comparing the sort order of the `ORDER BY` items. Results can be streamed, because each partition will provide data in a global order.
@@ -44,16 +89,30 @@ pub enum ItemProducer {
     Hybrid(HybridSearchStrategy),
 }
 
-pub fn create_partition_state(
-    pkranges: impl IntoIterator<Item = PartitionKeyRange>,
-) -> Vec<PartitionState> {
-    let mut partitions = pkranges
-        .into_iter()
-        .enumerate()
-        .map(|(i, p)| PartitionState::new(i, p))
-        .collect::<Vec<_>>();
-    partitions.sort();
-    partitions
+/// The serializable state of an [`ItemProducer`], used to resume a query in a new pipeline.
+///
+/// Only the strategies whose state is fully described by partition continuations and buffered items can be captured.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "camelCase")]
+pub enum ProducerState {
+    Unordered { partitions: Vec<PartitionSnapshot> },
+    Streaming { partitions: Vec<PartitionSnapshot> },
+}
+
+impl From<Strategy> for ItemProducer {
+    fn from(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            memory_budget: None,
+            buffered_bytes: 0,
+            max_item_count: None,
+            encoded_parameters: None,
+            max_concurrent_requests: None,
+            last_provided: None,
+            #[cfg(feature = "diagnostics")]
+            diagnostics: Diagnostics::default(),
+        }
+    }
 }
 
 impl ItemProducer {
@@ -67,7 +126,7 @@ impl ItemProducer {
         pkranges: impl IntoIterator<Item = PartitionKeyRange>,
         result_shape: QueryResultShape,
     ) -> Self {
-        Self::Unordered(UnorderedStrategy::new(pkranges, result_shape))
+        Strategy::Unordered(UnorderedStrategy::new(pkranges, result_shape)).into()
     }
 
     /// Creates a producer for ORDER BY queries where each partition returns globally sorted results.
@@ -84,7 +143,7 @@ impl ItemProducer {
         pkranges: impl IntoIterator<Item = PartitionKeyRange>,
         sorting: Vec<SortOrder>,
     ) -> Self {
-        Self::Streaming(StreamingStrategy::new(pkranges, sorting))
+        Strategy::Streaming(StreamingStrategy::new(pkranges, sorting)).into()
     }
 
     /// Creates a producer for ORDER BY queries where partitions return locally sorted results.
@@ -102,7 +161,7 @@ impl ItemProducer {
         pkranges: impl IntoIterator<Item = PartitionKeyRange>,
         sorting: Vec<SortOrder>,
     ) -> Self {
-        Self::NonStreaming(NonStreamingStrategy::new(pkranges, sorting))
+        Strategy::NonStreaming(NonStreamingStrategy::new(pkranges, sorting)).into()
     }
 
     /// Creates a producer for Hybrid search queries (which include Full-Text searches, and Rank Fusion operations)
@@ -110,24 +169,184 @@ impl ItemProducer {
         pkranges: impl IntoIterator<Item = PartitionKeyRange>,
         hybrid_search_query_info: HybridSearchQueryInfo,
     ) -> crate::Result<Self> {
-        Ok(Self::Hybrid(HybridSearchStrategy::new(
+        Ok(Strategy::Hybrid(HybridSearchStrategy::new(
             pkranges,
             hybrid_search_query_info,
-        )?))
+        )?)
+        .into())
+    }
+
+    /// Limits the number of result payload bytes the producer may buffer across all partitions.
+    ///
+    /// When set, [`ItemProducer::provide_data`] rejects any page that could push the buffered payloads over the budget.
+    pub fn set_memory_budget(&mut self, bytes: Option<usize>) {
+        self.memory_budget = bytes;
+    }
+
+    /// Gets the number of result payload bytes currently buffered by the producer.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    /// Sets the page size hint attached to each [`DataRequest`] produced by the unordered, streaming and non-streaming strategies.
+    ///
+    /// Hybrid search queries compute their own hint, based on the number of results the fusion needs.
+    pub fn set_max_item_count(&mut self, max_item_count: Option<u32>) {
+        self.max_item_count = max_item_count;
+    }
+
+    /// Gets the page size hint set by [`ItemProducer::set_max_item_count`].
+    pub fn max_item_count(&self) -> Option<u32> {
+        self.max_item_count
+    }
+
+    /// Enables checking that the `ORDER BY` values at each position have the same type in every partition.
+    ///
+    /// Mismatches are logged as warnings, and can be retrieved using [`ItemProducer::order_by_type_mismatches`].
+    /// This only applies to streaming `ORDER BY` queries, it has no effect on other strategies.
+    pub fn enable_order_by_type_check(&mut self) {
+        if let Strategy::Streaming(s) = &mut self.strategy {
+            s.enable_type_check();
+        }
+    }
+
+    /// Sets how a streaming `ORDER BY` orders items with equal `ORDER BY` values, it has no effect on other strategies.
+    pub fn set_order_by_tie_break(&mut self, tie_break: OrderByTieBreak) {
+        if let Strategy::Streaming(s) = &mut self.strategy {
+            s.set_tie_break(tie_break);
+        }
+    }
+
+    /// Limits the number of items a non-streaming `ORDER BY` may buffer, it has no effect on other strategies.
+    ///
+    /// When set, [`ItemProducer::provide_data`] rejects any page that would push the buffered items over the limit with an [`ErrorKind::BufferLimitExceeded`] error.
+    pub fn set_max_buffered_items(&mut self, max_buffered_items: Option<usize>) {
+        if let Strategy::NonStreaming(s) = &mut self.strategy {
+            s.max_buffered_items = max_buffered_items;
+        }
+    }
+
+    /// Lets a non-streaming `ORDER BY` write its buffered items to disk, it has no effect on other strategies.
+    pub fn set_spill(&mut self, spill: Option<SpillConfig>) {
+        if let Strategy::NonStreaming(s) = &mut self.strategy {
+            s.spill = spill;
+        }
+    }
+
+    /// Sets the maximum number of items the query can yield, based on its `TOP` or `LIMIT` clause.
+    ///
+    /// A non-streaming `ORDER BY` uses this to retain only the items that sort first, discarding the rest as pages arrive.
+    /// This has no effect on other strategies.
+    pub fn set_result_limit(&mut self, result_limit: Option<usize>) {
+        if let Strategy::NonStreaming(s) = &mut self.strategy {
+            s.result_limit = result_limit;
+        }
+    }
+
+    /// Sets whether an unordered query requests data from every partition up front, it has no effect on other strategies.
+    pub fn set_prefetch_all_partitions(&mut self, prefetch_all_partitions: bool) {
+        if let Strategy::Unordered(s) = &mut self.strategy {
+            s.prefetch_all_partitions = prefetch_all_partitions;
+        }
+    }
+
+    /// Sets whether an unordered query produces items from any partition that has them, rather than in partition order.
+    ///
+    /// This has no effect on other strategies.
+    pub fn set_interleave_partitions(&mut self, interleave_partitions: bool) {
+        if let Strategy::Unordered(s) = &mut self.strategy {
+            s.interleave_partitions = interleave_partitions;
+        }
+    }
+
+    /// Gets the `ORDER BY` type mismatches found so far, see [`ItemProducer::enable_order_by_type_check`].
+    pub fn order_by_type_mismatches(&self) -> &[OrderByTypeMismatch] {
+        match &self.strategy {
+            Strategy::Streaming(s) => s.type_mismatches(),
+            _ => &[],
+        }
+    }
+
+    /// Sets the encoded query parameters attached to each [`DataRequest`] that includes parameters.
+    ///
+    /// Each strategy decides which requests include parameters, using [`DataRequest::include_parameters`].
+    pub fn set_parameters(&mut self, encoded_parameters: Option<Box<RawValue>>) {
+        self.encoded_parameters = encoded_parameters;
+    }
+
+    /// Limits the number of requests returned by each call to [`ItemProducer::data_requests`].
+    ///
+    /// A limit of zero is treated as one, so the query can always make progress.
+    pub fn set_max_concurrent_requests(&mut self, max_concurrent_requests: Option<usize>) {
+        self.max_concurrent_requests = max_concurrent_requests.map(|max| max.max(1));
     }
 
     /// Gets the [`DataRequest`]s that must be performed in order to add additional data to the partition buffers.
+    ///
+    /// If the number of requests is limited, see [`ItemProducer::set_max_concurrent_requests`], only some of the partitions that need data are requested.
+    /// An unordered query requests the first partitions, in partition key order, since it drains its partitions in that order.
+    /// Other queries need data from every partition to make progress, so their requests start after the partition data was last provided for,
+    /// wrapping around to the first partition, so every partition is requested in turn.
+    /// The requests only change when data is provided, so pulling again without providing data still returns the same requests.
     pub fn data_requests(&mut self) -> crate::Result<Vec<DataRequest>> {
         // The default value for Vec is an empty vec, which doesn't allocate until items are added.
-        match self {
-            ItemProducer::Unordered(s) => Ok(s.requests()),
-            ItemProducer::Streaming(s) => Ok(s.requests()),
-            ItemProducer::NonStreaming(s) => Ok(s.requests()),
-            ItemProducer::Hybrid(s) => s.requests(),
+        let mut requests = match &mut self.strategy {
+            Strategy::Unordered(s) => s.requests(),
+            Strategy::Streaming(s) => s.requests(),
+            Strategy::NonStreaming(s) => s.requests(),
+            Strategy::Hybrid(s) => s.requests()?,
+        };
+        for request in &mut requests {
+            // Hybrid search requests carry their own hint, and the pipeline never sets one for them.
+            if self.max_item_count.is_some() {
+                request.max_item_count = self.max_item_count;
+            }
+            if request.include_parameters {
+                request.encoded_parameters = self.encoded_parameters.clone();
+            }
+        }
+        match self.max_concurrent_requests {
+            Some(max) if requests.len() > max => Ok(self.limit_requests(requests, max)),
+            _ => Ok(requests),
         }
     }
 
+    /// Selects `max` of the `requests`, which are in partition key order, see [`ItemProducer::data_requests`].
+    fn limit_requests(&self, mut requests: Vec<DataRequest>, max: usize) -> Vec<DataRequest> {
+        if let (false, Some(last_provided)) = (
+            matches!(self.strategy, Strategy::Unordered(_)),
+            &self.last_provided,
+        ) {
+            let positions = self
+                .pkrange_ids()
+                .into_iter()
+                .enumerate()
+                .map(|(position, id)| (id, position))
+                .collect::<HashMap<_, _>>();
+
+            // A partition that's gone, because it was split, has no position, so the requests start from the first partition.
+            let last = positions.get(last_provided.as_str());
+            let start = requests
+                .iter()
+                .position(|r| positions.get(r.pkrange_id.as_ref()) > last)
+                .unwrap_or(0);
+            requests.rotate_left(start);
+        }
+        tracing::trace!(
+            requested = max,
+            waiting = requests.len(),
+            "limiting concurrent requests"
+        );
+        requests.truncate(max);
+        requests
+    }
+
     /// Provides additional data for the given partition.
+    ///
+    /// If a memory budget is set, and the page could push the buffered payloads over it, the page is rejected
+    /// with an [`ErrorKind::MemoryBudgetExceeded`] error and the producer is left unchanged.
+    /// The size of the raw page is used as an upper bound for the size of the payloads it contains,
+    /// so the caller can retry the same page once enough items have been drained from the producer.
     pub fn provide_data(
         &mut self,
         pkrange_id: &str,
@@ -135,23 +354,184 @@ impl ItemProducer {
         data: &[u8],
         continuation: Option<String>,
     ) -> crate::Result<()> {
-        match self {
-            ItemProducer::Unordered(s) => s.provide_data(pkrange_id, data, continuation),
-            ItemProducer::Streaming(s) => s.provide_data(pkrange_id, data, continuation),
-            ItemProducer::NonStreaming(s) => s.provide_data(pkrange_id, data, continuation),
-            ItemProducer::Hybrid(s) => s.provide_data(pkrange_id, request_id, data, continuation),
+        if let Some(budget) = self.memory_budget {
+            if self.buffered_bytes.saturating_add(data.len()) > budget {
+                return Err(ErrorKind::MemoryBudgetExceeded.with_message(format!(
+                    "accepting {} bytes for partition key range ID {pkrange_id} would exceed the memory budget of {budget} bytes ({} bytes already buffered)",
+                    data.len(),
+                    self.buffered_bytes,
+                )));
+            }
+        }
+
+        let strategy = &mut self.strategy;
+        let provide = move || match strategy {
+            Strategy::Unordered(s) => s.provide_data(pkrange_id, request_id, data, continuation),
+            Strategy::Streaming(s) => s.provide_data(pkrange_id, request_id, data, continuation),
+            Strategy::NonStreaming(s) => s.provide_data(pkrange_id, request_id, data, continuation),
+            Strategy::Hybrid(s) => s.provide_data(pkrange_id, request_id, data, continuation),
+        };
+        #[cfg(feature = "diagnostics")]
+        let added = self.diagnostics.time_parse(data.len(), provide)?;
+        #[cfg(not(feature = "diagnostics"))]
+        let added = provide()?;
+        self.buffered_bytes += added;
+        if self.max_concurrent_requests.is_some() {
+            self.last_provided = Some(pkrange_id.to_string());
+        }
+
+        // A non-streaming ORDER BY may have evicted items it retained from earlier pages, or spilled its items to disk.
+        if let Strategy::NonStreaming(s) = &mut self.strategy {
+            self.buffered_bytes = self.buffered_bytes.saturating_sub(s.take_released_size());
+        }
+        Ok(())
+    }
+
+    /// Gets the IDs of the partition key ranges the producer requests data from, in partition key order.
+    ///
+    /// Partitions that were split are replaced by the partitions they were split into, see [`ItemProducer::split_partition`].
+    pub fn pkrange_ids(&self) -> Vec<String> {
+        self.pkranges().into_iter().map(|p| p.id.clone()).collect()
+    }
+
+    /// Gets the partition key ranges the producer is querying, in the same order as [`ItemProducer::pkrange_ids`].
+    fn pkranges(&self) -> Vec<&PartitionKeyRange> {
+        let partitions = match &self.strategy {
+            Strategy::Unordered(s) => &s.partitions,
+            Strategy::Streaming(s) => &s.partitions,
+            Strategy::NonStreaming(s) => &s.partitions,
+            Strategy::Hybrid(s) => return s.pkranges().iter().collect(),
+        };
+        partitions.iter().map(|p| &p.pkrange).collect()
+    }
+
+    /// Finds the partition key range the producer is querying that replaced the partition key range with ID `pkrange_id`, see [`QueryPipeline::resolve_pkrange`](crate::query::QueryPipeline::resolve_pkrange).
+    pub fn resolve_pkrange(&self, pkrange_id: &str) -> Option<&PartitionKeyRange> {
+        let pkranges = self.pkranges();
+        pkranges
+            .iter()
+            .find(|p| p.id == pkrange_id)
+            .or_else(|| {
+                pkranges
+                    .iter()
+                    .find(|p| p.parents.iter().any(|parent| parent == pkrange_id))
+            })
+            .copied()
+    }
+
+    /// Replaces a partition that the backend reported as gone, because it was split, with the partitions it was split into.
+    ///
+    /// Each replacement continues from the partition's last continuation token, and items already received from it are kept.
+    /// Non-streaming `ORDER BY` queries only support this before any results have been buffered, and hybrid search queries don't support it at all,
+    /// since their buffered results can't be divided between the replacements. Both return an [`ErrorKind::UnsupportedQueryPlan`] error instead.
+    pub fn split_partition(
+        &mut self,
+        pkrange_id: &str,
+        replacements: Vec<PartitionKeyRange>,
+    ) -> crate::Result<()> {
+        match &mut self.strategy {
+            Strategy::Unordered(s) => s.split_partition(pkrange_id, replacements),
+            Strategy::Streaming(s) => s.split_partition(pkrange_id, replacements),
+            Strategy::NonStreaming(s) => s.split_partition(pkrange_id, replacements),
+            Strategy::Hybrid(_) => Err(ErrorKind::UnsupportedQueryPlan.with_message(format!(
+                "partition key range ID {pkrange_id} was split during a hybrid search query, the query must be restarted"
+            ))),
+        }
+    }
+
+    /// Replaces the [`Clock`] used to time the work done by the producer.
+    #[cfg(feature = "diagnostics")]
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.diagnostics.set_clock(clock);
+    }
+
+    /// Gets the time spent parsing the pages provided to the producer so far.
+    #[cfg(feature = "diagnostics")]
+    pub fn parse_timings(&self) -> &ParseTimings {
+        &self.diagnostics.parse
+    }
+
+    /// Gets the continuation state of each partition that still has data to return.
+    ///
+    /// This is only supported by the unordered strategy, since the other strategies may hold buffered items
+    /// that a partition continuation token alone can't describe.
+    pub fn continuation_tokens(&self) -> crate::Result<Vec<CompositeContinuationToken>> {
+        match &self.strategy {
+            Strategy::Unordered(s) => s.continuation_tokens(),
+            _ => Err(ErrorKind::UnsupportedQueryPlan
+                .with_message("continuation tokens are only supported for unordered queries")),
+        }
+    }
+
+    /// Captures the state of the producer, so that the query can be resumed later by [`ItemProducer::restore_state`].
+    ///
+    /// Returns an [`ErrorKind::StateNotSerializable`] error for non-streaming `ORDER BY` and hybrid search queries,
+    /// since they may hold results from every partition that can't be described by partition continuations.
+    pub fn save_state(&self) -> crate::Result<ProducerState> {
+        match &self.strategy {
+            Strategy::Unordered(s) => Ok(ProducerState::Unordered {
+                partitions: s.save_state()?,
+            }),
+            Strategy::Streaming(s) => Ok(ProducerState::Streaming {
+                partitions: s.save_state()?,
+            }),
+            Strategy::NonStreaming(_) => Err(ErrorKind::StateNotSerializable
+                .with_message("non-streaming ORDER BY queries cannot be resumed")),
+            Strategy::Hybrid(_) => Err(ErrorKind::StateNotSerializable
+                .with_message("hybrid search queries cannot be resumed")),
+        }
+    }
+
+    /// Restores the state captured by [`ItemProducer::save_state`].
+    ///
+    /// The producer must have been created with the same strategy and partitions as the one the state was captured from.
+    pub fn restore_state(&mut self, state: ProducerState) -> crate::Result<()> {
+        self.buffered_bytes = match (&mut self.strategy, state) {
+            (Strategy::Unordered(s), ProducerState::Unordered { partitions }) => {
+                s.restore_state(partitions)?
+            }
+            (Strategy::Streaming(s), ProducerState::Streaming { partitions }) => {
+                s.restore_state(partitions)?
+            }
+            _ => {
+                return Err(ErrorKind::DeserializationError
+                    .with_message("pipeline state was captured from a different kind of query"))
+            }
+        };
+        Ok(())
+    }
+
+    /// Releases every item buffered by the producer, without changing the state of any partition.
+    ///
+    /// This is used when a query is cancelled, the producer shouldn't be used to produce items afterwards.
+    pub fn clear(&mut self) {
+        match &mut self.strategy {
+            Strategy::Unordered(s) => s.clear(),
+            Strategy::Streaming(s) => s.clear(),
+            Strategy::NonStreaming(s) => s.clear(),
+            Strategy::Hybrid(s) => s.clear(),
         }
+        self.buffered_bytes = 0;
     }
 
     /// Requests the next item from the cross-partition result stream.
     #[tracing::instrument(level = "trace", skip(self))]
     pub fn produce_item(&mut self) -> crate::Result<PipelineNodeResult> {
-        match self {
-            ItemProducer::Unordered(s) => s.produce_item(),
-            ItemProducer::Streaming(s) => s.produce_item(),
-            ItemProducer::NonStreaming(s) => s.produce_item(),
-            ItemProducer::Hybrid(s) => s.produce_item(),
+        let result = match &mut self.strategy {
+            Strategy::Unordered(s) => s.produce_item(),
+            Strategy::Streaming(s) => s.produce_item(),
+            Strategy::NonStreaming(s) => s.produce_item(),
+            Strategy::Hybrid(s) => s.produce_item(),
+        }?;
+
+        if result.terminated {
+            // Nothing is buffered once the producer has terminated.
+            // This also releases anything a strategy merged or discarded without producing it.
+            self.buffered_bytes = 0;
+        } else if let Some(item) = &result.value {
+            self.buffered_bytes = self.buffered_bytes.saturating_sub(item.buffered_size());
         }
+        Ok(result)
     }
 }
 
@@ -212,6 +592,7 @@ mod tests {
             QueryResult::OrderBy {
                 order_by_items,
                 payload: serde_json::value::to_raw_value(&item).unwrap(),
+                rid: None,
             }
         }
     }
@@ -572,4 +953,244 @@ mod tests {
 
         Ok(())
     }
+
+    /// Provides a page of items to the producer, for the request it made for the given partition.
+    fn provide_page(
+        producer: &mut ItemProducer,
+        pkrange_id: &str,
+        items: &[(&str, Option<i64>)],
+        continuation: Option<&str>,
+    ) -> crate::Result<()> {
+        let requests = producer.data_requests()?;
+        let request = requests
+            .iter()
+            .find(|r| r.pkrange_id == pkrange_id)
+            .expect("the producer should request data for the partition");
+        let results = items
+            .iter()
+            .map(|(id, value)| {
+                create_item(
+                    pkrange_id,
+                    *id,
+                    value
+                        .map(|v| vec![json!({ "item": v })])
+                        .unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        producer.provide_data(
+            pkrange_id,
+            request.id,
+            &serialize_query_results(&results)?,
+            continuation.map(String::from),
+        )
+    }
+
+    /// Produces every remaining item, once all the data has been provided, returning their IDs.
+    fn produce_ids(producer: &mut ItemProducer) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut ids = Vec::new();
+        loop {
+            let result = producer.produce_item()?;
+            if let Some(value) = result.value {
+                let item: Item = serde_json::from_str(value.into_payload().unwrap().get())?;
+                ids.push(item.id);
+            }
+            if result.terminated {
+                return Ok(ids);
+            }
+        }
+    }
+
+    fn split_ranges() -> Vec<PartitionKeyRange> {
+        vec![
+            PartitionKeyRange::new("partition0b", "40", "80"),
+            PartitionKeyRange::new("partition0a", "00", "40"),
+        ]
+    }
+
+    fn request_summary(
+        producer: &mut ItemProducer,
+    ) -> crate::Result<Vec<(String, u64, Option<String>)>> {
+        Ok(producer
+            .data_requests()?
+            .into_iter()
+            .map(|r| (r.pkrange_id.to_string(), r.id, r.continuation))
+            .collect())
+    }
+
+    #[test]
+    pub fn unordered_strategy_continues_split_partition_from_its_children(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut producer = ItemProducer::unordered(
+            vec![
+                PartitionKeyRange::new("partition0", "00", "80"),
+                PartitionKeyRange::new("partition1", "80", "FF"),
+            ],
+            QueryResultShape::RawPayload,
+        );
+        provide_page(
+            &mut producer,
+            "partition0",
+            &[("a0", None), ("a1", None)],
+            Some("c1"),
+        )?;
+
+        // The partition splits between its first and second pages.
+        producer.split_partition("partition0", split_ranges())?;
+        assert_eq!(
+            vec![("partition0a".to_string(), 1, Some("c1".to_string()))],
+            request_summary(&mut producer)?
+        );
+
+        provide_page(&mut producer, "partition0a", &[("b0", None)], None)?;
+        provide_page(&mut producer, "partition0b", &[("c0", None)], None)?;
+        provide_page(&mut producer, "partition1", &[("d0", None)], None)?;
+
+        assert_eq!(
+            vec!["a0", "a1", "b0", "c0", "d0"],
+            produce_ids(&mut producer)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn streaming_strategy_merges_children_of_split_partition(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut producer = ItemProducer::streaming(
+            vec![
+                PartitionKeyRange::new("partition0", "00", "80"),
+                PartitionKeyRange::new("partition1", "80", "FF"),
+            ],
+            vec![SortOrder::Ascending],
+        );
+        provide_page(
+            &mut producer,
+            "partition0",
+            &[("a1", Some(1)), ("a4", Some(4))],
+            Some("c1"),
+        )?;
+        provide_page(
+            &mut producer,
+            "partition1",
+            &[("d2", Some(2)), ("d9", Some(9))],
+            None,
+        )?;
+
+        // The partition splits while its items are still buffered, so both children have to be queried before anything is produced.
+        producer.split_partition("partition0", split_ranges())?;
+        assert_eq!(
+            vec![
+                ("partition0a".to_string(), 1, Some("c1".to_string())),
+                ("partition0b".to_string(), 1, Some("c1".to_string())),
+            ],
+            request_summary(&mut producer)?
+        );
+        assert!(producer.produce_item()?.value.is_none());
+
+        provide_page(
+            &mut producer,
+            "partition0a",
+            &[("b5", Some(5)), ("b8", Some(8))],
+            None,
+        )?;
+        provide_page(&mut producer, "partition0b", &[("c6", Some(6))], None)?;
+
+        assert_eq!(
+            vec!["a1", "d2", "a4", "b5", "c6", "b8", "d9"],
+            produce_ids(&mut producer)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn non_streaming_strategy_only_splits_before_buffering(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let pkranges = vec![
+            PartitionKeyRange::new("partition0", "00", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ];
+
+        let mut producer =
+            ItemProducer::non_streaming(pkranges.clone(), vec![SortOrder::Ascending]);
+        producer.split_partition("partition0", split_ranges())?;
+        assert_eq!(
+            vec!["partition0a", "partition0b", "partition1"],
+            request_summary(&mut producer)?
+                .into_iter()
+                .map(|(id, _, _)| id)
+                .collect::<Vec<_>>()
+        );
+
+        let mut producer = ItemProducer::non_streaming(pkranges, vec![SortOrder::Ascending]);
+        provide_page(&mut producer, "partition1", &[("d2", Some(2))], None)?;
+        provide_page(&mut producer, "partition0", &[("a1", Some(1))], Some("c1"))?;
+        let err = producer
+            .split_partition("partition0", split_ranges())
+            .unwrap_err();
+        assert_eq!(ErrorKind::UnsupportedQueryPlan, err.kind());
+        Ok(())
+    }
+
+    #[test]
+    pub fn order_by_ties_are_broken_by_rid_across_partitions(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Rids for documents 1, 2, 3, 255, 256 and 257 of the same container.
+        // Their strings don't sort in the same order as the documents, for example document 256 has the lowest string.
+        let rid = |document: u64| match document {
+            1 => "W8YaAOxMJtUBAAAAAAAAAA==",
+            2 => "W8YaAOxMJtUCAAAAAAAAAA==",
+            3 => "W8YaAOxMJtUDAAAAAAAAAA==",
+            255 => "W8YaAOxMJtX-AAAAAAAAAA==",
+            256 => "W8YaAOxMJtUAAQAAAAAAAA==",
+            257 => "W8YaAOxMJtUBAQAAAAAAAA==",
+            _ => unreachable!(),
+        };
+
+        // Each partition returns the items with equal values in rid order, as the backend does.
+        let pages = [
+            ("partition0", [(1, 2), (1, 256), (2, 3)]),
+            ("partition1", [(1, 1), (1, 255), (2, 257)]),
+        ];
+
+        let pkranges = vec![
+            PartitionKeyRange::new("partition0", "00", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ];
+        for mut producer in [
+            ItemProducer::streaming(pkranges.clone(), vec![SortOrder::Ascending]),
+            ItemProducer::non_streaming(pkranges.clone(), vec![SortOrder::Ascending]),
+        ] {
+            for (pkrange_id, items) in &pages {
+                let results = items
+                    .iter()
+                    .map(|(value, document)| QueryResult::OrderBy {
+                        order_by_items: vec![
+                            serde_json::from_value(json!({ "item": value })).unwrap()
+                        ],
+                        payload: serde_json::value::to_raw_value(&Item::new(
+                            document.to_string(),
+                            *pkrange_id,
+                            "",
+                        ))
+                        .unwrap(),
+                        rid: Some(rid(*document).to_string()),
+                    })
+                    .collect::<Vec<_>>();
+                let request = producer.data_requests()?.remove(0);
+                producer.provide_data(
+                    pkrange_id,
+                    request.id,
+                    &serialize_query_results(&results)?,
+                    None,
+                )?;
+            }
+
+            // Items with equal values are produced in rid order, rather than in partition order.
+            assert_eq!(
+                vec!["1", "2", "255", "256", "3", "257"],
+                produce_ids(&mut producer)?
+            );
+        }
+        Ok(())
+    }
 }
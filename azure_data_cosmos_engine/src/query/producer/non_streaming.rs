@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::collections::BinaryHeap;
+use std::{cmp::Reverse, collections::BinaryHeap};
 
 use crate::{
     query::{
@@ -12,15 +12,46 @@ use crate::{
 };
 
 use super::{
-    create_partition_state,
     sorting::{SortableResult, Sorting},
-    state::PartitionState,
+    spill::{SpillConfig, SpillMerge, SpillRun},
+    state::PartitionList,
 };
 
 pub struct NonStreamingStrategy {
-    pub partitions: Vec<PartitionState>,
+    pub partitions: PartitionList,
     pub sorting: Sorting,
     pub items: BinaryHeap<SortableResult>,
+
+    /// The maximum number of items that may be buffered, if any.
+    pub max_buffered_items: Option<usize>,
+
+    /// The maximum number of items the query can yield, if it has a `TOP` or `LIMIT` clause.
+    ///
+    /// When set, only the items that sort first are retained, in `retained`, and the rest are discarded as pages arrive.
+    pub result_limit: Option<usize>,
+
+    /// The items retained while partitions are still providing data, when there's a `result_limit`.
+    ///
+    /// This is a min-heap, so the item that sorts LAST is at the top, ready to be evicted by a better item.
+    /// Once every partition is done, the items are moved to `items` to be produced in order.
+    retained: BinaryHeap<Reverse<SortableResult>>,
+
+    /// Where, and when, the buffered items are written to disk, if they may be.
+    ///
+    /// Spilling only applies to `items`, since the items in `retained` are already bounded by the `result_limit`.
+    pub spill: Option<SpillConfig>,
+
+    /// The size of the payloads in `items`, used to decide when to spill them.
+    items_size: usize,
+
+    /// The sorted runs written to disk so far.
+    runs: Vec<SpillRun>,
+
+    /// Merges the runs, once every partition is done and the runs have been written.
+    merge: Option<SpillMerge>,
+
+    /// The size of the items released from memory since the last call to [`NonStreamingStrategy::take_released_size`].
+    released_size: usize,
 }
 
 impl std::fmt::Debug for NonStreamingStrategy {
@@ -29,6 +60,12 @@ impl std::fmt::Debug for NonStreamingStrategy {
             .field("partitions", &self.partitions)
             .field("sorting", &self.sorting)
             .field("items_len", &self.items.len())
+            .field("max_buffered_items", &self.max_buffered_items)
+            .field("result_limit", &self.result_limit)
+            .field("retained_len", &self.retained.len())
+            .field("spill", &self.spill)
+            .field("runs", &self.runs)
+            .field("merge", &self.merge)
             .finish()
     }
 }
@@ -38,11 +75,19 @@ impl NonStreamingStrategy {
         pkranges: impl IntoIterator<Item = PartitionKeyRange>,
         sorting: Vec<SortOrder>,
     ) -> Self {
-        let partitions = create_partition_state(pkranges);
+        let partitions = PartitionList::new(pkranges);
         Self {
             partitions,
             sorting: Sorting::new(sorting),
             items: BinaryHeap::new(),
+            max_buffered_items: None,
+            result_limit: None,
+            retained: BinaryHeap::new(),
+            spill: None,
+            items_size: 0,
+            runs: Vec::new(),
+            merge: None,
+            released_size: 0,
         }
     }
 
@@ -56,32 +101,129 @@ impl NonStreamingStrategy {
     pub fn provide_data(
         &mut self,
         pkrange_id: &str,
+        request_id: u64,
         data: &[u8],
         continuation: Option<String>,
-    ) -> crate::Result<()> {
+    ) -> crate::Result<usize> {
+        self.partitions
+            .get_by_id_mut(pkrange_id)?
+            .validate_request_id(request_id)?;
+
         let parsed_data = QueryResultShape::OrderBy.results_from_slice(data)?;
+        if let Some(max) = self.max_buffered_items {
+            let buffered = self.items.len() + self.retained.len() + parsed_data.len();
+            let buffered = self
+                .result_limit
+                .map_or(buffered, |limit| buffered.min(limit));
+            if buffered > max {
+                return Err(ErrorKind::BufferLimitExceeded.with_message(format!(
+                    "a non-streaming ORDER BY would buffer {buffered} items, exceeding the limit of {max} items"
+                )));
+            }
+        }
+        // Validate the items before inserting any of them, so that an invalid page doesn't leave some of its items in the heap.
+        let sortable = parsed_data
+            .into_iter()
+            .map(|item| SortableResult::new(&self.sorting, item))
+            .collect::<crate::Result<Vec<_>>>()?;
 
-        // Insert the items into the heap as we go, which will keep them sorted
-        for item in parsed_data {
-            // We need to sort the items by the order by items, so we create a SortableResult.
-            self.items
-                .push(SortableResult::new(self.sorting.clone(), item));
+        let mut buffered_size = 0;
+        match self.result_limit {
+            Some(limit) => {
+                for item in sortable {
+                    buffered_size += self.retain(limit, item);
+                }
+            }
+            None => {
+                // Insert the items into the heap, which will keep them sorted
+                buffered_size = sortable.iter().map(|i| i.result().buffered_size()).sum();
+                self.items.extend(sortable);
+                self.items_size += buffered_size;
+                if self
+                    .spill
+                    .as_ref()
+                    .is_some_and(|spill| self.items_size > spill.threshold_bytes)
+                {
+                    self.spill_items()?;
+                }
+            }
         }
 
         // Update the partition state with the continuation token
-        let partition = self
-            .partitions
-            .iter_mut()
-            .find(|p| p.pkrange.id == pkrange_id)
-            .ok_or_else(|| {
-                ErrorKind::UnknownPartitionKeyRange
-                    .with_message(format!("unknown partition key range ID: {pkrange_id}"))
-            })?;
-        partition.update_state(continuation);
+        self.partitions
+            .get_by_id_mut(pkrange_id)?
+            .update_state(continuation);
 
+        Ok(buffered_size)
+    }
+
+    /// Replaces a partition that was split with the partitions it was split into, see [`PartitionList::split`].
+    ///
+    /// This is only supported before any results have been buffered.
+    pub fn split_partition(
+        &mut self,
+        pkrange_id: &str,
+        replacements: Vec<PartitionKeyRange>,
+    ) -> crate::Result<()> {
+        if !self.items.is_empty() || !self.retained.is_empty() || !self.runs.is_empty() {
+            return Err(ErrorKind::UnsupportedQueryPlan.with_message(format!(
+                "partition key range ID {pkrange_id} was split after a non-streaming ORDER BY buffered results, the query must be restarted"
+            )));
+        }
+        self.partitions.split(pkrange_id, replacements)?;
         Ok(())
     }
 
+    /// Retains the item if it's among the first `limit` items seen so far, evicting the item that sorts last if necessary.
+    ///
+    /// Returns the size of the item if it was retained, or zero if it was discarded.
+    fn retain(&mut self, limit: usize, item: SortableResult) -> usize {
+        if self.retained.len() >= limit {
+            // When the item ties with the last retained item, the one we already have is kept.
+            match self.retained.peek() {
+                Some(Reverse(last)) if item > *last => {}
+                _ => return 0,
+            }
+            if let Some(Reverse(evicted)) = self.retained.pop() {
+                self.released_size += evicted.result().buffered_size();
+            }
+        }
+        let size = item.result().buffered_size();
+        self.retained.push(Reverse(item));
+        size
+    }
+
+    /// Writes every item in `items` to a new sorted run, releasing them from memory.
+    fn spill_items(&mut self) -> crate::Result<()> {
+        let Some(spill) = &self.spill else {
+            return Ok(());
+        };
+
+        // The heap's greatest item is produced first, so the sorted items are written in reverse.
+        let items = std::mem::take(&mut self.items).into_sorted_vec();
+        let run = SpillRun::write(&spill.dir, items.into_iter().rev().map(Into::into))?;
+        self.runs.push(run);
+        self.released_size += std::mem::take(&mut self.items_size);
+        Ok(())
+    }
+
+    /// Gets the size of the items released from memory since the last call, and resets it.
+    ///
+    /// Items are released when they're evicted to make room for items that sort before them, if there's a `result_limit`,
+    /// or when they're spilled to disk.
+    pub fn take_released_size(&mut self) -> usize {
+        std::mem::take(&mut self.released_size)
+    }
+
+    /// Releases every buffered item, including those spilled to disk.
+    pub fn clear(&mut self) {
+        self.items = BinaryHeap::new();
+        self.retained = BinaryHeap::new();
+        self.items_size = 0;
+        self.runs = Vec::new();
+        self.merge = None;
+    }
+
     pub fn produce_item(&mut self) -> crate::Result<PipelineNodeResult> {
         // We can only produce items when all partitions are done.
         if self.partitions.iter().any(|p| !p.done()) {
@@ -90,6 +232,29 @@ impl NonStreamingStrategy {
             return Ok(PipelineNodeResult::NO_RESULT);
         }
 
+        // Every partition is done, so the retained items are the final results.
+        if !self.retained.is_empty() {
+            let retained = std::mem::take(&mut self.retained);
+            self.items
+                .extend(retained.into_iter().map(|Reverse(item)| item));
+        }
+
+        // If any items were spilled, the items still in memory are spilled too, and the results come from merging the runs.
+        if !self.runs.is_empty() {
+            if !self.items.is_empty() {
+                self.spill_items()?;
+            }
+            let runs = std::mem::take(&mut self.runs);
+            self.merge = Some(SpillMerge::new(self.sorting.clone(), runs)?);
+        }
+        if let Some(merge) = &mut self.merge {
+            let value = merge.next()?;
+            return Ok(PipelineNodeResult {
+                value,
+                terminated: merge.is_empty(),
+            });
+        }
+
         // We can just pop the next item from the heap, since it's already sorted.
         let value = self.items.pop().map(|r| r.into());
         Ok(PipelineNodeResult {
@@ -3,12 +3,25 @@
 
 use std::{cmp::Ordering, sync::Arc};
 
+use serde::Deserialize;
+
 use crate::{
-    query::{QueryClauseItem, QueryResult, SortOrder},
+    query::{query_result::compare_integer_to_float, QueryClauseItem, QueryResult, SortOrder},
     ErrorKind,
 };
 
-pub struct SortableResult(Sorting, QueryResult);
+/// A query result paired with its decoded first `ORDER BY` value, so that it can be sorted in a [`BinaryHeap`](std::collections::BinaryHeap).
+///
+/// The first value decides most comparisons, so it's decoded once, when the result is created, and compared without inspecting the JSON value again.
+/// The remaining values are only compared when the first values are equal.
+/// Results with equal values are ordered by their `_rid`, see [`ResourceId`], so the order doesn't depend on the order the results were received in.
+/// Like [`Sorting::compare`], results are ordered from LARGEST to SMALLEST, so the result that sorts first is the greatest.
+pub struct SortableResult {
+    sorting: Sorting,
+    first: SortValue,
+    rid: Option<ResourceId>,
+    result: QueryResult,
+}
 
 impl PartialEq for SortableResult {
     fn eq(&self, other: &Self) -> bool {
@@ -26,25 +39,273 @@ impl PartialOrd for SortableResult {
 
 impl Ord for SortableResult {
     fn cmp(&self, other: &Self) -> Ordering {
-        let (left_order_by_items, _) = self.1.as_order_by().expect("should have order by items");
-        let (right_order_by_items, _) = other.1.as_order_by().expect("should have order by items");
-
-        // Unless the gateway provides invalid data, this shouldn't fail.
-        self.0
-            .compare(Some(&left_order_by_items), Some(&right_order_by_items))
-            .expect("Sorting should not fail")
+        self.cmp_values(other).then_with(|| self.cmp_rids(other))
     }
 }
 
 impl SortableResult {
-    pub fn new(sorting: Sorting, result: QueryResult) -> Self {
-        Self(sorting, result)
+    /// Decodes the first `ORDER BY` value of the result, failing if the result can't be sorted using `sorting`.
+    ///
+    /// This fails in the same cases as [`Sorting::validate`].
+    pub fn new(sorting: &Sorting, result: QueryResult) -> crate::Result<Self> {
+        let (order_by_items, _) = result.as_order_by().ok_or_else(|| {
+            ErrorKind::InvalidGatewayResponse.with_message("expected an ORDER BY result")
+        })?;
+        sorting.validate(order_by_items)?;
+        let first = match order_by_items.first() {
+            Some(item) => SortValue::new(item)?,
+            None => SortValue::Undefined,
+        };
+        Ok(Self {
+            sorting: sorting.clone(),
+            first,
+            rid: result.rid().and_then(ResourceId::parse),
+            result,
+        })
+    }
+
+    /// Gets the result being sorted.
+    pub fn result(&self) -> &QueryResult {
+        &self.result
+    }
+
+    /// Compares the `ORDER BY` values of the results, ignoring their `_rid`s.
+    pub fn cmp_values(&self, other: &Self) -> Ordering {
+        let Some(ordering) = self.sorting.0.first() else {
+            return Ordering::Equal;
+        };
+        let order = self.first.compare(other.first, || {
+            (&self.order_by_items()[0], &other.order_by_items()[0])
+        });
+        let order = match ordering {
+            SortOrder::Ascending => order.reverse(),
+            SortOrder::Descending => order,
+        };
+
+        // Results are validated when they're created, so comparing the remaining values can't fail.
+        order.then_with(|| {
+            self.sorting
+                .compare(self.order_by_items(), other.order_by_items())
+                .expect("order by items should have been validated")
+        })
+    }
+
+    /// Compares the `_rid`s of results with equal `ORDER BY` values.
+    ///
+    /// Rids are ordered in the direction of the first `ORDER BY` item, so ascending queries produce the lowest rid first, and descending queries the highest.
+    /// Results without a valid rid sort before results with one, in an ascending query.
+    pub fn cmp_rids(&self, other: &Self) -> Ordering {
+        match self.sorting.0.first() {
+            Some(SortOrder::Descending) => self.rid.cmp(&other.rid),
+            _ => other.rid.cmp(&self.rid),
+        }
+    }
+
+    fn order_by_items(&self) -> &[QueryClauseItem] {
+        self.result
+            .as_order_by()
+            .map(|(items, _)| items)
+            .unwrap_or_default()
     }
 }
 
 impl From<SortableResult> for QueryResult {
     fn from(value: SortableResult) -> Self {
-        value.1
+        value.result
+    }
+}
+
+/// A document's `_rid`, decoded so that rids are compared the way the backend compares them.
+///
+/// A rid is the base64 encoding (using `-` in place of `/`) of the database, collection and document IDs, stored as little-endian unsigned integers.
+/// Rids are compared by those IDs, in that order, rather than by their encoded strings, which don't sort in the same order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResourceId {
+    database: u32,
+    collection: u32,
+    document: u64,
+}
+
+impl ResourceId {
+    /// Decodes a document rid, returning `None` if it isn't valid base64, or is too short to be a document rid.
+    pub fn parse(rid: &str) -> Option<Self> {
+        let bytes = decode_base64(rid)?;
+        let bytes: &[u8; 16] = bytes.get(..16)?.try_into().ok()?;
+        let (database, rest) = bytes.split_at(4);
+        let (collection, document) = rest.split_at(4);
+        Some(Self {
+            database: u32::from_le_bytes(database.try_into().ok()?),
+            collection: u32::from_le_bytes(collection.try_into().ok()?),
+            document: u64::from_le_bytes(document.try_into().ok()?),
+        })
+    }
+}
+
+/// Decodes padded, or unpadded, base64, accepting `-` as well as `/` for the 63rd digit, as rids use it.
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    fn digit(c: u8) -> Option<u32> {
+        Some(match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' | b'-' => 63,
+            _ => return None,
+        } as u32)
+    }
+
+    let input = input.trim_end_matches('=').as_bytes();
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    for chunk in input.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut bits = 0;
+        for (i, c) in chunk.iter().enumerate() {
+            bits |= digit(*c)? << (18 - 6 * i);
+        }
+        output.extend_from_slice(&bits.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(output)
+}
+
+/// An `ORDER BY` value, decoded from a [`QueryClauseItem`].
+///
+/// Strings, arrays and objects aren't copied out of the item, they're compared using the item the value was decoded from.
+#[derive(Debug, Clone, Copy)]
+enum SortValue {
+    Undefined,
+    Null,
+    Bool(bool),
+    /// Both signed and unsigned 64-bit integers, which are compared exactly.
+    Integer(i128),
+    Float(f64),
+    String,
+    Array,
+    Object,
+}
+
+impl SortValue {
+    fn new(item: &QueryClauseItem) -> crate::Result<Self> {
+        Ok(match &item.item {
+            None => Self::Undefined,
+            Some(serde_json::Value::Null) => Self::Null,
+            Some(serde_json::Value::Bool(b)) => Self::Bool(*b),
+            Some(serde_json::Value::Number(n)) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+                (Some(i), _, _) => Self::Integer(i.into()),
+                (None, Some(u), _) => Self::Integer(u.into()),
+                (None, None, Some(f)) => Self::Float(f),
+                (None, None, None) => {
+                    return Err(ErrorKind::InvalidGatewayResponse
+                        .with_message("encountered NaN or Infinity while comparing floats"))
+                }
+            },
+            Some(serde_json::Value::String(_)) => Self::String,
+            Some(serde_json::Value::Array(_)) => Self::Array,
+            Some(serde_json::Value::Object(_)) => Self::Object,
+        })
+    }
+
+    /// Gets the type ordinal of the value, which matches [`QueryClauseItem::type_ordinal`].
+    fn type_ordinal(self) -> usize {
+        match self {
+            Self::Undefined => 0,
+            Self::Null => 1,
+            Self::Bool(_) => 2,
+            Self::Integer(_) | Self::Float(_) => 4,
+            Self::String => 5,
+            Self::Array => 6,
+            Self::Object => 7,
+        }
+    }
+
+    /// Compares two values using the same rules as [`QueryClauseItem::compare`].
+    ///
+    /// Strings, arrays and objects are compared using the items returned by `items`, which must be the items the values were decoded from.
+    fn compare<'a>(
+        self,
+        other: Self,
+        items: impl FnOnce() -> (&'a QueryClauseItem, &'a QueryClauseItem),
+    ) -> Ordering {
+        match (self, other) {
+            (Self::Bool(left), Self::Bool(right)) => left.cmp(&right),
+            (Self::Integer(left), Self::Integer(right)) => left.cmp(&right),
+            (Self::String, Self::String) => {
+                let (left_item, right_item) = items();
+                let left = left_item.item.as_ref().and_then(|v| v.as_str());
+                let right = right_item.item.as_ref().and_then(|v| v.as_str());
+                left.cmp(&right)
+            }
+            (Self::Array, Self::Array) | (Self::Object, Self::Object) => {
+                // JSON can't represent NaN, so comparing the elements and properties always succeeds.
+                let (left_item, right_item) = items();
+                left_item.compare(right_item).unwrap_or(Ordering::Equal)
+            }
+
+            // Mixed integers and floats are compared by their exact values.
+            // JSON can't represent NaN, so the comparisons always succeed.
+            (Self::Float(left), Self::Float(right)) => {
+                left.partial_cmp(&right).unwrap_or(Ordering::Equal)
+            }
+            (Self::Integer(left), Self::Float(right)) => {
+                compare_integer_to_float(left, right).unwrap_or(Ordering::Equal)
+            }
+            (Self::Float(left), Self::Integer(right)) => {
+                compare_integer_to_float(right, left).map_or(Ordering::Equal, Ordering::reverse)
+            }
+            _ => self.type_ordinal().cmp(&other.type_ordinal()),
+        }
+    }
+}
+
+/// Selects how a streaming `ORDER BY` orders items whose `ORDER BY` values are equal.
+///
+/// Ties that remain after the selected tie-break are broken by the `_rid` the rewritten query selects, compared as the backend compares rids, and then by partition.
+/// A non-streaming `ORDER BY` always orders such items by `_rid`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OrderByTieBreak {
+    /// Items are ordered by their `_rid`, like the backend orders them, and items from the partition with the lowest minimum EPK are produced first when that doesn't decide.
+    #[default]
+    Partition,
+
+    /// Items are produced in chronological order, using the `_ts` property of their payloads, and then by their `_rid`.
+    ///
+    /// Items without a `_ts` are produced before items with one. Remaining ties fall back to [`OrderByTieBreak::Partition`].
+    /// This decides how items from different partitions are merged, items from the same partition stay in the order the backend returned them.
+    /// Both properties are read from the payload, so the query must select them, as `SELECT * FROM c` does.
+    Timestamp,
+}
+
+/// The values used to break ties between items with equal `ORDER BY` values, see [`OrderByTieBreak`].
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TieBreakKey {
+    ts: Option<u64>,
+    rid: Option<String>,
+}
+
+impl TieBreakKey {
+    /// Reads the tie-break key from an item's payload.
+    ///
+    /// A payload that isn't an object, or doesn't have the properties, produces an empty key rather than an error.
+    pub fn from_result(tie_break: OrderByTieBreak, item: &QueryResult) -> Self {
+        #[derive(Deserialize)]
+        struct SystemProperties {
+            _ts: Option<u64>,
+            _rid: Option<String>,
+        }
+
+        match (tie_break, item.as_order_by()) {
+            (OrderByTieBreak::Timestamp, Some((_, payload))) => {
+                match serde_json::from_str::<SystemProperties>(payload.get()) {
+                    Ok(properties) => Self {
+                        ts: properties._ts,
+                        rid: properties._rid,
+                    },
+                    Err(_) => Self::default(),
+                }
+            }
+            _ => Self::default(),
+        }
     }
 }
 
@@ -56,6 +317,22 @@ impl Sorting {
         Self(Arc::from(ordering))
     }
 
+    /// Gets the number of `ORDER BY` items being sorted on.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Checks that the provided order by items can be compared using [`Sorting::compare`].
+    ///
+    /// If this succeeds for two lists of items, comparing them is guaranteed to succeed.
+    pub fn validate(&self, items: &[QueryClauseItem]) -> crate::Result<()> {
+        if items.len() != self.0.len() {
+            return Err(ErrorKind::InvalidGatewayResponse
+                .with_message("items have inconsistent numbers of order by items"));
+        }
+        Ok(())
+    }
+
     /// Compares two items based on the sorting order defined in this `Sorting` instance.
     ///
     /// This ALWAYS returns an ordering based on sorting from LARGEST to SMALLEST, meaning that the first item in the list is greater than the second item.
@@ -63,23 +340,13 @@ impl Sorting {
     ///
     /// In other words, we return an [`Ordering`] such that a DESCENDING sort of the items will result in the user's desired sort order.
     ///
-    /// The `left` and `right` parameters are optional, allowing for comparisons where one or both items may be absent.
+    /// Both items must be present. An undefined `ORDER BY` value is just a value that sorts before `null`,
+    /// whether a partition needs more data is tracked by the strategies, and never decided by comparing items.
     pub fn compare(
         &self,
-        left: Option<&[QueryClauseItem]>,
-        right: Option<&[QueryClauseItem]>,
+        left: &[QueryClauseItem],
+        right: &[QueryClauseItem],
     ) -> crate::Result<Ordering> {
-        let (left, right) = match (left, right) {
-            (Some(left), Some(right)) => (left, right),
-
-            // "Empty" partitions sort before non-empty partitions, because they need to cause iteration to stop so we can get more data.
-            (None, Some(_)) => return Ok(Ordering::Greater),
-            (Some(_), None) => return Ok(Ordering::Less),
-            (None, None) => {
-                return Ok(Ordering::Equal);
-            }
-        };
-
         if left.len() != right.len() {
             return Err(ErrorKind::InvalidGatewayResponse
                 .with_message("items have inconsistent numbers of order by items"));
@@ -115,7 +382,10 @@ mod tests {
     use std::cmp::Ordering;
 
     use crate::{
-        query::{producer::sorting::Sorting, QueryClauseItem},
+        query::{
+            producer::sorting::{ResourceId, SortableResult, Sorting},
+            QueryClauseItem, QueryResult,
+        },
         ErrorKind,
     };
 
@@ -133,10 +403,7 @@ mod tests {
             crate::query::SortOrder::Ascending,
             crate::query::SortOrder::Descending,
         ]);
-        assert_eq!(
-            Ordering::Greater,
-            sorting.compare(Some(&left), Some(&right)).unwrap()
-        );
+        assert_eq!(Ordering::Greater, sorting.compare(&left, &right).unwrap());
     }
 
     #[test]
@@ -153,31 +420,28 @@ mod tests {
             crate::query::SortOrder::Ascending,
             crate::query::SortOrder::Descending,
         ]);
-        assert_eq!(
-            Ordering::Equal,
-            sorting.compare(Some(&left), Some(&right)).unwrap()
-        );
+        assert_eq!(Ordering::Equal, sorting.compare(&left, &right).unwrap());
     }
 
     #[test]
-    pub fn compare_with_empty() {
-        let non_empty = vec![
-            QueryClauseItem::from_value(serde_json::json!(1)),
-            QueryClauseItem::from_value(serde_json::json!("zzzz")),
-        ];
-        let sorting = Sorting::new(vec![
-            crate::query::SortOrder::Ascending,
-            crate::query::SortOrder::Descending,
-        ]);
+    pub fn compare_with_undefined() {
+        let undefined = vec![QueryClauseItem::default()];
+        let null = vec![QueryClauseItem::from_value(serde_json::Value::Null)];
+        let sorting = Sorting::new(vec![crate::query::SortOrder::Ascending]);
+
+        // Undefined sorts first, so it's the greatest.
         assert_eq!(
             Ordering::Greater,
-            sorting.compare(None, Some(&non_empty)).unwrap()
+            sorting.compare(&undefined, &null).unwrap()
         );
+        assert_eq!(Ordering::Less, sorting.compare(&null, &undefined).unwrap());
         assert_eq!(
-            Ordering::Less,
-            sorting.compare(Some(&non_empty), None).unwrap()
+            Ordering::Equal,
+            sorting.compare(&undefined, &undefined).unwrap()
         );
-        assert_eq!(Ordering::Equal, sorting.compare(None, None).unwrap());
+
+        let sorting = Sorting::new(vec![crate::query::SortOrder::Descending]);
+        assert_eq!(Ordering::Less, sorting.compare(&undefined, &null).unwrap());
     }
 
     #[test]
@@ -191,7 +455,133 @@ mod tests {
             crate::query::SortOrder::Ascending,
             crate::query::SortOrder::Descending,
         ]);
-        let err = sorting.compare(Some(&left), Some(&right)).unwrap_err();
+        let err = sorting.compare(&left, &right).unwrap_err();
         assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
     }
+
+    #[test]
+    pub fn sortable_results_match_compare() {
+        let values = [
+            None,
+            Some(serde_json::json!(null)),
+            Some(serde_json::json!(false)),
+            Some(serde_json::json!(true)),
+            Some(serde_json::json!(-1)),
+            Some(serde_json::json!(1)),
+            Some(serde_json::json!(1.0)),
+            Some(serde_json::json!(1.5)),
+            Some(serde_json::json!(i64::MAX)),
+            Some(serde_json::json!(9223372036854775807.0)),
+            Some(serde_json::json!(i64::MAX as u64 + 1)),
+            Some(serde_json::json!(u64::MAX - 1)),
+            Some(serde_json::json!(u64::MAX)),
+            Some(serde_json::json!(18446744073709551616.0)),
+            Some(serde_json::json!("")),
+            Some(serde_json::json!("a")),
+            Some(serde_json::json!("b")),
+            Some(serde_json::json!([])),
+            Some(serde_json::json!([1, "a"])),
+            Some(serde_json::json!([1, "b"])),
+            Some(serde_json::json!([1, "b", null])),
+            Some(serde_json::json!({})),
+            Some(serde_json::json!({"a": 1})),
+            Some(serde_json::json!({"a": 2})),
+            Some(serde_json::json!({"a": 1, "b": null})),
+            Some(serde_json::json!({"b": 0})),
+        ];
+        let items = values
+            .iter()
+            .flat_map(|first| {
+                values.iter().map(|second| {
+                    vec![
+                        QueryClauseItem {
+                            item: first.clone(),
+                            item2: None,
+                        },
+                        QueryClauseItem {
+                            item: second.clone(),
+                            item2: None,
+                        },
+                    ]
+                })
+            })
+            .collect::<Vec<_>>();
+        let sorting = Sorting::new(vec![
+            crate::query::SortOrder::Ascending,
+            crate::query::SortOrder::Descending,
+        ]);
+        let sortable = |items: &Vec<QueryClauseItem>| {
+            let result = QueryResult::OrderBy {
+                order_by_items: items.clone(),
+                payload: serde_json::value::to_raw_value(&serde_json::json!({})).unwrap(),
+                rid: None,
+            };
+            SortableResult::new(&sorting, result).unwrap()
+        };
+
+        for left in &items {
+            for right in &items {
+                assert_eq!(
+                    sorting.compare(left, right).unwrap(),
+                    sortable(left).cmp(&sortable(right)),
+                    "comparing {left:?} to {right:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn resource_ids_compare_by_document() {
+        // Documents 1, 2, 255, 256 and 257 of the same container, in order.
+        let rids = [
+            "W8YaAOxMJtUBAAAAAAAAAA==",
+            "W8YaAOxMJtUCAAAAAAAAAA==",
+            "W8YaAOxMJtX-AAAAAAAAAA==",
+            "W8YaAOxMJtUAAQAAAAAAAA==",
+            "W8YaAOxMJtUBAQAAAAAAAA==",
+        ];
+        let parsed = rids
+            .iter()
+            .map(|rid| ResourceId::parse(rid).unwrap())
+            .collect::<Vec<_>>();
+        assert!(parsed.windows(2).all(|w| w[0] < w[1]), "{parsed:?}");
+
+        // Standard base64, and unpadded rids, are accepted too.
+        assert_eq!(Some(parsed[2]), ResourceId::parse("W8YaAOxMJtX/AAAAAAAAAA"));
+
+        for invalid in [
+            "",
+            "W8YaAOxMJtU",
+            "W8YaAOxMJtU!AAAAAAAAAA==",
+            "W8YaAOxMJtUBAAAAAAAAAAAAA",
+        ] {
+            assert_eq!(None, ResourceId::parse(invalid), "parsing {invalid:?}");
+        }
+    }
+
+    #[test]
+    pub fn sortable_results_break_ties_by_rid() {
+        let sortable = |sorting: &Sorting, rid: Option<&str>| {
+            let result = QueryResult::OrderBy {
+                order_by_items: vec![QueryClauseItem::from_value(serde_json::json!(1))],
+                payload: serde_json::value::to_raw_value(&serde_json::json!({})).unwrap(),
+                rid: rid.map(String::from),
+            };
+            SortableResult::new(sorting, result).unwrap()
+        };
+
+        // Document 256 has a lower rid string than document 1, but a higher document ID.
+        let ascending = Sorting::new(vec![crate::query::SortOrder::Ascending]);
+        let document1 = sortable(&ascending, Some("W8YaAOxMJtUBAAAAAAAAAA=="));
+        let document256 = sortable(&ascending, Some("W8YaAOxMJtUAAQAAAAAAAA=="));
+        let missing = sortable(&ascending, None);
+        assert_eq!(Ordering::Greater, document1.cmp(&document256));
+        assert_eq!(Ordering::Greater, missing.cmp(&document1));
+        assert_eq!(Ordering::Equal, document1.cmp_values(&document256));
+
+        let descending = Sorting::new(vec![crate::query::SortOrder::Descending]);
+        let document1 = sortable(&descending, Some("W8YaAOxMJtUBAAAAAAAAAA=="));
+        let document256 = sortable(&descending, Some("W8YaAOxMJtUAAQAAAAAAAA=="));
+        assert_eq!(Ordering::Less, document1.cmp(&document256));
+    }
 }
@@ -0,0 +1,199 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Spilling of buffered non-streaming `ORDER BY` results to temporary files.
+//!
+//! Once the buffered results pass a threshold, they're written to a file as a sorted "run".
+//! When every partition is done, the runs are merged, reading one item at a time from each, so only one item per run is held in memory.
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering as AtomicOrdering},
+};
+
+use serde_json::{de::IoRead, StreamDeserializer};
+
+use crate::{
+    query::{query_result::OrderByResult, QueryResult},
+    ErrorKind,
+};
+
+use super::sorting::{SortableResult, Sorting};
+
+/// Used to give every run written by this process a unique file name.
+static NEXT_RUN_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Configures when, and where, a non-streaming `ORDER BY` writes its buffered results to disk.
+#[derive(Clone, Debug)]
+pub struct SpillConfig {
+    /// The number of payload bytes that may be buffered in memory before they're written to a run.
+    pub threshold_bytes: usize,
+
+    /// The directory the runs are written to.
+    pub dir: PathBuf,
+}
+
+/// A sorted run of results, written to a temporary file as a sequence of JSON objects.
+///
+/// The file is deleted when the run is dropped.
+pub struct SpillRun {
+    path: PathBuf,
+    len: usize,
+    reader: Option<StreamDeserializer<'static, IoRead<BufReader<File>>, OrderByResult>>,
+}
+
+impl std::fmt::Debug for SpillRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpillRun")
+            .field("path", &self.path)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl SpillRun {
+    /// Writes the results to a new file in `dir`. The results must already be in the order they'll be produced in.
+    pub fn write(
+        dir: &Path,
+        results: impl IntoIterator<Item = QueryResult>,
+    ) -> crate::Result<Self> {
+        let path = dir.join(format!(
+            "cosmoscx-spill-{}-{}.json",
+            std::process::id(),
+            NEXT_RUN_ID.fetch_add(1, AtomicOrdering::Relaxed)
+        ));
+        let file = File::create_new(&path).map_err(|e| ErrorKind::SpillError.with_source(e))?;
+
+        // Create the run before writing anything, so the file is deleted if writing fails.
+        let mut run = Self {
+            path,
+            len: 0,
+            reader: None,
+        };
+        let mut writer = BufWriter::new(file);
+        for result in results {
+            // Payloads can contain newlines, so the results are just concatenated, rather than written one per line.
+            serde_json::to_writer(&mut writer, &result)
+                .map_err(|e| ErrorKind::SpillError.with_source(e))?;
+            run.len += 1;
+        }
+        writer
+            .flush()
+            .map_err(|e| ErrorKind::SpillError.with_source(e))?;
+        tracing::debug!(path = ?run.path, len = run.len, "spilled sorted run");
+        Ok(run)
+    }
+
+    /// Reads the next result from the run, opening the file the first time it's called.
+    fn next(&mut self) -> crate::Result<Option<QueryResult>> {
+        let reader = match &mut self.reader {
+            Some(reader) => reader,
+            None => {
+                let file =
+                    File::open(&self.path).map_err(|e| ErrorKind::SpillError.with_source(e))?;
+                self.reader
+                    .insert(serde_json::Deserializer::from_reader(BufReader::new(file)).into_iter())
+            }
+        };
+        reader
+            .next()
+            .transpose()
+            .map(|result| result.map(QueryResult::from))
+            .map_err(|e| ErrorKind::SpillError.with_source(e))
+    }
+}
+
+impl Drop for SpillRun {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!(path = ?self.path, error = %e, "failed to delete spilled run");
+        }
+    }
+}
+
+/// The next result of a run, ordered so that the result that sorts first is the greatest.
+struct RunHead {
+    result: SortableResult,
+    run: usize,
+}
+
+impl PartialEq for RunHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RunHead {}
+
+impl PartialOrd for RunHead {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RunHead {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Results with equal values are produced from the earliest run first.
+        self.result
+            .cmp(&other.result)
+            .then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+/// Merges sorted runs, producing their results in order.
+pub struct SpillMerge {
+    sorting: Sorting,
+    runs: Vec<SpillRun>,
+    heads: BinaryHeap<RunHead>,
+}
+
+impl std::fmt::Debug for SpillMerge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpillMerge")
+            .field("runs", &self.runs)
+            .field("heads_len", &self.heads.len())
+            .finish()
+    }
+}
+
+impl SpillMerge {
+    pub fn new(sorting: Sorting, runs: Vec<SpillRun>) -> crate::Result<Self> {
+        let mut merge = Self {
+            sorting,
+            runs,
+            heads: BinaryHeap::new(),
+        };
+        for run in 0..merge.runs.len() {
+            merge.advance(run)?;
+        }
+        Ok(merge)
+    }
+
+    /// Reads the next result of the given run into the heads, if it has one.
+    fn advance(&mut self, run: usize) -> crate::Result<()> {
+        if let Some(result) = self.runs[run].next()? {
+            // The values are re-parsed from the run. serde_json's `float_roundtrip` feature makes that exact, so they sort as they did in memory.
+            let result = SortableResult::new(&self.sorting, result)?;
+            self.heads.push(RunHead { result, run });
+        }
+        Ok(())
+    }
+
+    /// Gets the next result in order, or `None` if every run is exhausted.
+    pub fn next(&mut self) -> crate::Result<Option<QueryResult>> {
+        let Some(RunHead { result, run }) = self.heads.pop() else {
+            return Ok(None);
+        };
+        self.advance(run)?;
+        Ok(Some(result.into()))
+    }
+
+    /// Indicates if every result has been produced.
+    pub fn is_empty(&self) -> bool {
+        self.heads.is_empty()
+    }
+}
@@ -1,23 +1,108 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+};
 
-use crate::query::{DataRequest, PartitionKeyRange};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    query::{
+        query_result::{FeedResponse, QueryResultShape},
+        DataRequest, PartitionKeyRange, QueryResult,
+    },
+    ErrorKind,
+};
 
 /// Represents the current stage of pagination for a partition.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "camelCase")]
 pub enum PaginationState {
     /// The partition is ready for the first data request. There should be no data in the queue yet.
     Initial,
 
     /// The partition has a pending continuation. When the current queue is exhausted, the continuation can be used to fetch more data.
-    Continuing { token: String, next_page_index: u32 },
+    Continuing {
+        token: String,
+        #[serde(rename = "nextPageIndex")]
+        next_page_index: u32,
+    },
 
     /// The partition has been exhausted. When the current queue is exhausted, the partition is done.
     Done,
 }
 
+/// Describes the format of a continuation token, as best as the engine can tell.
+///
+/// Continuation tokens are opaque to the engine, which only passes them back to the backend.
+/// This classification is purely diagnostic, it's logged when tokens are received so that resumption issues can be traced back to the kind of token in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContinuationTokenFormat {
+    /// A raw backend token, like `+RID:~AAAA#RT:1#TRC:10#ISV:2`.
+    ///
+    /// The `version` is the value of the `ISV` field, if present.
+    Backend { version: Option<u32> },
+
+    /// A JSON composite token (or array of them), with a `token` and a `range` property.
+    Composite,
+
+    /// A JSON ORDER BY token (or array of them), with a `compositeToken` and `orderByItems` properties.
+    OrderBy,
+
+    /// A JSON token wrapping another token for OFFSET/LIMIT or TOP, with a `sourceToken` property.
+    Wrapped,
+
+    /// A token that doesn't match any known format.
+    Unknown,
+}
+
+impl ContinuationTokenFormat {
+    /// Classifies the provided continuation token.
+    ///
+    /// This never fails, tokens that can't be identified are classified as [`ContinuationTokenFormat::Unknown`].
+    pub fn classify(token: &str) -> Self {
+        let token = token.trim();
+        if token.starts_with('{') || token.starts_with('[') {
+            return match serde_json::from_str::<serde_json::Value>(token) {
+                Ok(serde_json::Value::Array(items)) => items
+                    .first()
+                    .map(Self::classify_json)
+                    .unwrap_or(ContinuationTokenFormat::Unknown),
+                Ok(value) => Self::classify_json(&value),
+                Err(_) => ContinuationTokenFormat::Unknown,
+            };
+        }
+
+        if token.starts_with("+RID:") || token.starts_with("-RID:") {
+            let version = token
+                .split('#')
+                .find_map(|field| field.strip_prefix("ISV:"))
+                .and_then(|v| v.parse().ok());
+            return ContinuationTokenFormat::Backend { version };
+        }
+
+        ContinuationTokenFormat::Unknown
+    }
+
+    fn classify_json(value: &serde_json::Value) -> Self {
+        let Some(obj) = value.as_object() else {
+            return ContinuationTokenFormat::Unknown;
+        };
+        if obj.contains_key("compositeToken") && obj.contains_key("orderByItems") {
+            ContinuationTokenFormat::OrderBy
+        } else if obj.contains_key("token") && obj.contains_key("range") {
+            ContinuationTokenFormat::Composite
+        } else if obj.contains_key("sourceToken") {
+            ContinuationTokenFormat::Wrapped
+        } else {
+            ContinuationTokenFormat::Unknown
+        }
+    }
+}
+
 impl PaginationState {
     pub fn update(&mut self, continuation: Option<String>) {
         let next_page_index = match &self {
@@ -29,6 +114,11 @@ impl PaginationState {
         };
         match continuation {
             Some(token) => {
+                tracing::debug!(
+                    next_page_index,
+                    format = ?ContinuationTokenFormat::classify(&token),
+                    "received continuation token"
+                );
                 *self = PaginationState::Continuing {
                     token,
                     next_page_index,
@@ -97,15 +187,378 @@ impl PartitionState {
         }
     }
 
-    pub fn update_state(&mut self, continuation: Option<String>) {
-        self.stage.update(continuation);
+    /// Checks that `request_id` is the ID of the request this partition is waiting on, see [`PartitionState::request`].
+    ///
+    /// This catches responses that are provided twice, out of order, or paired with the wrong request.
+    pub fn validate_request_id(&self, request_id: u64) -> crate::Result<()> {
+        match self.request() {
+            Some(request) if request.id == request_id => Ok(()),
+            Some(request) => Err(ErrorKind::InvalidRequestId.with_message(format!(
+                "provided data for request ID {request_id} for partition key range ID {}, but the outstanding request ID is {}",
+                self.pkrange.id, request.id
+            ))),
+            None => Err(ErrorKind::InvalidRequestId.with_message(format!(
+                "provided data for request ID {request_id} for partition key range ID {}, but the partition has no outstanding request",
+                self.pkrange.id
+            ))),
+        }
     }
 
-    pub fn started(&self) -> bool {
-        !matches!(self.stage, PaginationState::Initial)
+    pub fn update_state(&mut self, continuation: Option<String>) {
+        self.stage.update(continuation);
     }
 
     pub fn done(&self) -> bool {
         matches!(self.stage, PaginationState::Done)
     }
+
+    /// Captures the pagination state of this partition, with no buffered items.
+    pub fn snapshot(&self) -> PartitionSnapshot {
+        PartitionSnapshot {
+            id: self.pkrange.id.clone(),
+            stage: self.stage.clone(),
+            buffered: Vec::new(),
+        }
+    }
+}
+
+/// The serializable state of a single partition, used to resume a query in a new pipeline.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionSnapshot {
+    pub id: String,
+    #[serde(flatten)]
+    pub stage: PaginationState,
+
+    /// Items received from the partition that have not been produced yet, in the shape the backend returned them in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buffered: Vec<serde_json::Value>,
+}
+
+impl PartitionSnapshot {
+    /// Adds the given buffered items to the snapshot.
+    pub fn with_buffered<'a>(
+        mut self,
+        items: impl IntoIterator<Item = &'a QueryResult>,
+    ) -> crate::Result<Self> {
+        self.buffered = items
+            .into_iter()
+            .map(serde_json::to_value)
+            .collect::<Result<_, _>>()
+            .map_err(|e| ErrorKind::InternalError.with_source(e))?;
+        Ok(self)
+    }
+}
+
+/// The states of the partitions targeted by a query, sorted by partition key range minimum.
+///
+/// The order of the list is the order results must be merged in, so it never changes after creation.
+/// The list also maintains an index from partition key range ID to position, so that looking up
+/// the partition for incoming data doesn't require scanning the list.
+#[derive(Debug)]
+pub struct PartitionList {
+    partitions: Vec<PartitionState>,
+    positions: HashMap<String, usize>,
+}
+
+impl PartitionList {
+    pub fn new(pkranges: impl IntoIterator<Item = PartitionKeyRange>) -> Self {
+        let mut partitions = pkranges
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| PartitionState::new(i, p))
+            .collect::<Vec<_>>();
+        partitions.sort();
+        let positions = partitions
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.pkrange.id.clone(), i))
+            .collect();
+        Self {
+            partitions,
+            positions,
+        }
+    }
+
+    /// Gets the position of the partition with the given partition key range ID.
+    pub fn position(&self, pkrange_id: &str) -> crate::Result<usize> {
+        self.positions.get(pkrange_id).copied().ok_or_else(|| {
+            ErrorKind::UnknownPartitionKeyRange
+                .with_message(format!("unknown partition key range ID: {pkrange_id}"))
+        })
+    }
+
+    /// Gets the state of the partition with the given partition key range ID.
+    pub fn get_by_id_mut(&mut self, pkrange_id: &str) -> crate::Result<&mut PartitionState> {
+        let position = self.position(pkrange_id)?;
+        Ok(&mut self.partitions[position])
+    }
+
+    /// Replaces the partition with the given ID with the partitions it was split into, returning its position.
+    ///
+    /// The replacements must exactly cover the key range of the partition they replace, and are inserted in its place, in order,
+    /// so the list stays sorted. Each replacement continues from the same continuation token as the partition it replaces,
+    /// as the backend accepts a parent partition's continuation token on each of its children.
+    pub fn split(
+        &mut self,
+        pkrange_id: &str,
+        mut replacements: Vec<PartitionKeyRange>,
+    ) -> crate::Result<usize> {
+        let position = self.position(pkrange_id)?;
+        replacements.sort_by(|a, b| a.min_inclusive.cmp(&b.min_inclusive));
+
+        let gone = &self.partitions[position];
+        if gone.done() {
+            return Err(ErrorKind::InvalidRequestId.with_message(format!(
+                "partition key range ID {pkrange_id} was split, but the partition has no outstanding request"
+            )));
+        }
+        let covers_range = replacements
+            .first()
+            .is_some_and(|first| first.min_inclusive == gone.pkrange.min_inclusive)
+            && replacements
+                .last()
+                .is_some_and(|last| last.max_exclusive == gone.pkrange.max_exclusive)
+            && replacements
+                .windows(2)
+                .all(|pair| pair[0].max_exclusive == pair[1].min_inclusive);
+        if !covers_range {
+            return Err(ErrorKind::InvalidGatewayResponse.with_message(format!(
+                "the replacements for partition key range ID {pkrange_id} must exactly cover its key range"
+            )));
+        }
+        if let Some(duplicate) = replacements
+            .iter()
+            .find(|r| r.id != pkrange_id && self.positions.contains_key(&r.id))
+        {
+            return Err(ErrorKind::InvalidGatewayResponse.with_message(format!(
+                "replacement partition key range ID {} is already targeted by the query",
+                duplicate.id
+            )));
+        }
+
+        let first_index = gone.index;
+        let stage = gone.stage.clone();
+        let next_index = self.partitions.len();
+        let children = replacements.into_iter().enumerate().map(|(i, pkrange)| {
+            let index = if i == 0 {
+                first_index
+            } else {
+                next_index + i - 1
+            };
+            PartitionState {
+                index,
+                pkrange,
+                stage: stage.clone(),
+            }
+        });
+        self.partitions.splice(position..=position, children);
+        self.positions = self
+            .partitions
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.pkrange.id.clone(), i))
+            .collect();
+        Ok(position)
+    }
+
+    /// Restores the pagination state of every partition from the given snapshots.
+    ///
+    /// The snapshots must cover exactly the partitions in this list, though they may be in any order.
+    /// Returns the buffered items of each partition, parsed using `shape`, in the order of this list.
+    pub fn restore(
+        &mut self,
+        snapshots: Vec<PartitionSnapshot>,
+        shape: QueryResultShape,
+    ) -> crate::Result<Vec<Vec<QueryResult>>> {
+        if snapshots.len() != self.partitions.len() {
+            return Err(ErrorKind::DeserializationError.with_message(format!(
+                "pipeline state has {} partitions, but the pipeline has {}",
+                snapshots.len(),
+                self.partitions.len()
+            )));
+        }
+
+        let mut buffers = Vec::new();
+        buffers.resize_with(self.partitions.len(), Vec::new);
+        for snapshot in snapshots {
+            let position = self.position(&snapshot.id)?;
+            self.partitions[position].stage = snapshot.stage;
+            if !snapshot.buffered.is_empty() {
+                // The buffered items were serialized individually, so wrap them back up in the backend's envelope and parse them with the expected shape.
+                let envelope = serde_json::to_vec(&FeedResponse {
+                    documents: snapshot.buffered,
+                })
+                .map_err(|e| ErrorKind::InternalError.with_source(e))?;
+                buffers[position] = shape.results_from_slice(&envelope)?;
+            }
+        }
+        Ok(buffers)
+    }
+}
+
+impl Deref for PartitionList {
+    type Target = [PartitionState];
+
+    fn deref(&self) -> &Self::Target {
+        &self.partitions
+    }
+}
+
+impl DerefMut for PartitionList {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.partitions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partition_list_sorts_and_indexes_partitions() -> crate::Result<()> {
+        let partitions = PartitionList::new(vec![
+            PartitionKeyRange::new("b", "80", "FF"),
+            PartitionKeyRange::new("a", "00", "80"),
+        ]);
+
+        assert_eq!(
+            vec!["a", "b"],
+            partitions
+                .iter()
+                .map(|p| p.pkrange.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(0, partitions.position("a")?);
+        assert_eq!(1, partitions.position("b")?);
+        assert_eq!(
+            ErrorKind::UnknownPartitionKeyRange,
+            partitions.position("c").unwrap_err().kind()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn split_replaces_partition_with_children() -> crate::Result<()> {
+        let mut partitions = PartitionList::new(vec![
+            PartitionKeyRange::new("a", "00", "80"),
+            PartitionKeyRange::new("b", "80", "FF"),
+        ]);
+        partitions[0].update_state(Some("token".into()));
+
+        let position = partitions.split(
+            "a",
+            vec![
+                PartitionKeyRange::new("a2", "40", "80"),
+                PartitionKeyRange::new("a1", "00", "40"),
+            ],
+        )?;
+        assert_eq!(0, position);
+        assert_eq!(
+            vec!["a1", "a2", "b"],
+            partitions
+                .iter()
+                .map(|p| p.pkrange.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, partitions.position("a2")?);
+        assert_eq!(2, partitions.position("b")?);
+        assert!(partitions.position("a").is_err());
+        for child in &partitions[..2] {
+            let request = child.request().unwrap();
+            assert_eq!(Some("token"), request.continuation.as_deref());
+            assert_eq!(1, request.id);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn split_rejects_replacements_that_do_not_cover_range() {
+        let mut partitions = PartitionList::new(vec![
+            PartitionKeyRange::new("a", "00", "80"),
+            PartitionKeyRange::new("b", "80", "FF"),
+        ]);
+        for replacements in [
+            vec![],
+            vec![PartitionKeyRange::new("a1", "00", "40")],
+            vec![
+                PartitionKeyRange::new("a1", "00", "40"),
+                PartitionKeyRange::new("a2", "50", "80"),
+            ],
+            vec![
+                PartitionKeyRange::new("a1", "00", "40"),
+                PartitionKeyRange::new("b", "40", "80"),
+            ],
+        ] {
+            assert_eq!(
+                ErrorKind::InvalidGatewayResponse,
+                partitions.split("a", replacements).unwrap_err().kind()
+            );
+        }
+        assert_eq!(
+            ErrorKind::UnknownPartitionKeyRange,
+            partitions
+                .split("c", vec![PartitionKeyRange::new("c1", "00", "80")])
+                .unwrap_err()
+                .kind()
+        );
+    }
+
+    #[test]
+    fn classify_backend_token() {
+        assert_eq!(
+            ContinuationTokenFormat::Backend { version: Some(2) },
+            ContinuationTokenFormat::classify(
+                "+RID:~QY8NAJ0dm8MBAAAAAAAAAA==#RT:1#TRC:1#ISV:2#IEO:65567#QCF:8"
+            )
+        );
+        assert_eq!(
+            ContinuationTokenFormat::Backend { version: None },
+            ContinuationTokenFormat::classify("-RID:~QY8NAJ0dm8MBAAAAAAAAAA==#RT:1")
+        );
+    }
+
+    #[test]
+    fn classify_composite_token() {
+        assert_eq!(
+            ContinuationTokenFormat::Composite,
+            ContinuationTokenFormat::classify(
+                r#"[{"token":"+RID:~abc#RT:1","range":{"min":"","max":"FF"}}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn classify_order_by_token() {
+        assert_eq!(
+            ContinuationTokenFormat::OrderBy,
+            ContinuationTokenFormat::classify(
+                r#"{"compositeToken":{"token":"+RID:~abc","range":{"min":"","max":"FF"}},"orderByItems":[{"item":42}],"rid":"abc","skipCount":0,"filter":null}"#
+            )
+        );
+    }
+
+    #[test]
+    fn classify_wrapped_token() {
+        assert_eq!(
+            ContinuationTokenFormat::Wrapped,
+            ContinuationTokenFormat::classify(r#"{"top":10,"sourceToken":"+RID:~abc"}"#)
+        );
+    }
+
+    #[test]
+    fn classify_unknown_token() {
+        assert_eq!(
+            ContinuationTokenFormat::Unknown,
+            ContinuationTokenFormat::classify("continuation_token")
+        );
+        assert_eq!(
+            ContinuationTokenFormat::Unknown,
+            ContinuationTokenFormat::classify("{not json")
+        );
+        assert_eq!(
+            ContinuationTokenFormat::Unknown,
+            ContinuationTokenFormat::classify("[]")
+        );
+    }
 }
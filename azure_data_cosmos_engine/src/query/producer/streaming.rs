@@ -1,7 +1,10 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::{cmp::Ordering, collections::VecDeque};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, VecDeque},
+};
 
 use crate::{
     query::{
@@ -11,12 +14,72 @@ use crate::{
     ErrorKind,
 };
 
-use super::{create_partition_state, sorting::Sorting, state::PartitionState};
+use super::{
+    sorting::{OrderByTieBreak, SortableResult, Sorting, TieBreakKey},
+    state::{PartitionList, PartitionSnapshot},
+    type_check::{OrderByTypeCheck, OrderByTypeMismatch},
+};
+
+/// The next item to produce from a partition, ordered so that a [`BinaryHeap`] pops the item that sorts first.
+///
+/// The first `ORDER BY` value is decoded once, when the item is queued, so most comparisons in the heap don't have to inspect the JSON values.
+/// Items with equal sort values are ordered by their [`TieBreakKey`], if one is used, then by their `_rid`, and then by partition position,
+/// so that the partition with the lowest minimum EPK wins any remaining ties.
+struct PartitionHead {
+    item: SortableResult,
+    tie_break: TieBreakKey,
+    position: usize,
+}
+
+impl PartialEq for PartitionHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PartitionHead {}
+
+impl PartialOrd for PartitionHead {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PartitionHead {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.item
+            .cmp_values(&other.item)
+            .then_with(|| other.tie_break.cmp(&self.tie_break))
+            .then_with(|| self.item.cmp_rids(&other.item))
+            .then_with(|| other.position.cmp(&self.position))
+    }
+}
+
+/// The items buffered from a single partition.
+#[derive(Default)]
+struct PartitionBuffer {
+    /// Indicates if the first item from this partition is currently in the heap.
+    queued: bool,
+
+    /// The items received from this partition, after the one in the heap.
+    items: VecDeque<QueryResult>,
+}
 
 pub struct StreamingStrategy {
-    pub partitions: Vec<PartitionState>,
+    pub partitions: PartitionList,
     pub sorting: Sorting,
-    pub buffers: Vec<(String, VecDeque<QueryResult>)>,
+    buffers: Vec<PartitionBuffer>,
+    heads: BinaryHeap<PartitionHead>,
+
+    /// The number of partitions that have no buffered items, but may still produce more.
+    ///
+    /// No items can be produced while this is non-zero, since the missing items may sort before everything we have.
+    waiting: usize,
+
+    /// Checks that the `ORDER BY` values have consistent types across partitions, if enabled.
+    type_check: Option<OrderByTypeCheck>,
+
+    tie_break: OrderByTieBreak,
 }
 
 impl std::fmt::Debug for StreamingStrategy {
@@ -29,9 +92,10 @@ impl std::fmt::Debug for StreamingStrategy {
                 &self
                     .buffers
                     .iter()
-                    .map(|(_, b)| b.len())
+                    .map(|b| b.items.len() + usize::from(b.queued))
                     .collect::<Vec<_>>(),
             )
+            .field("waiting", &self.waiting)
             .finish()
     }
 }
@@ -41,18 +105,49 @@ impl StreamingStrategy {
         pkranges: impl IntoIterator<Item = PartitionKeyRange>,
         sorting: Vec<SortOrder>,
     ) -> Self {
-        let partitions = create_partition_state(pkranges);
-        let buffers = partitions
-            .iter()
-            .map(|p| (p.pkrange.id.clone(), VecDeque::new()))
-            .collect();
+        let partitions = PartitionList::new(pkranges);
+        let mut buffers = Vec::new();
+        buffers.resize_with(partitions.len(), PartitionBuffer::default);
         Self {
+            waiting: partitions.len(),
             partitions,
             sorting: Sorting::new(sorting),
             buffers,
+            heads: BinaryHeap::new(),
+            type_check: None,
+            tie_break: OrderByTieBreak::default(),
         }
     }
 
+    /// Sets how items with equal `ORDER BY` values are ordered.
+    pub fn set_tie_break(&mut self, tie_break: OrderByTieBreak) {
+        self.tie_break = tie_break;
+
+        // Items may already be queued, for example when the strategy was restored from a saved state.
+        self.heads = std::mem::take(&mut self.heads)
+            .into_iter()
+            .map(|head| PartitionHead {
+                tie_break: TieBreakKey::from_result(tie_break, head.item.result()),
+                ..head
+            })
+            .collect();
+    }
+
+    /// Enables checking that the `ORDER BY` values in each partition have the same types, see [`OrderByTypeMismatch`].
+    pub fn enable_type_check(&mut self) {
+        if self.type_check.is_none() {
+            self.type_check = Some(OrderByTypeCheck::new(self.sorting.len()));
+        }
+    }
+
+    /// Gets the type mismatches found so far, if type checking is enabled.
+    pub fn type_mismatches(&self) -> &[OrderByTypeMismatch] {
+        self.type_check
+            .as_ref()
+            .map(OrderByTypeCheck::mismatches)
+            .unwrap_or_default()
+    }
+
     pub fn requests(&mut self) -> Vec<DataRequest> {
         self.partitions
             .iter()
@@ -63,131 +158,187 @@ impl StreamingStrategy {
     pub fn provide_data(
         &mut self,
         pkrange_id: &str,
+        request_id: u64,
         data: &[u8],
         continuation: Option<String>,
-    ) -> crate::Result<()> {
-        let partition_index = self
-            .partitions
-            .iter()
-            .position(|p| p.pkrange.id == pkrange_id)
-            .ok_or_else(|| {
-                ErrorKind::UnknownPartitionKeyRange
-                    .with_message(format!("unknown partition key range ID: {pkrange_id}"))
-            })?;
-        let (pkrange_id, buffer) = self.buffers.get_mut(partition_index).ok_or_else(|| {
-            ErrorKind::InternalError.with_message(format!(
-                "missing buffer for partition index: {}",
-                partition_index
-            ))
-        })?;
-        debug_assert_eq!(
-            pkrange_id, &self.partitions[partition_index].pkrange.id,
-            "buffer ID should match partition key range ID",
-        );
+    ) -> crate::Result<usize> {
+        let position = self.partitions.position(pkrange_id)?;
+        self.partitions[position].validate_request_id(request_id)?;
 
         // Parse the raw bytes using the result shape
         let parsed_data = QueryResultShape::OrderBy.results_from_slice(data)?;
+        self.validate(&parsed_data)?;
+        if let Some(type_check) = &mut self.type_check {
+            for item in &parsed_data {
+                let (order_by_items, _) = item.as_order_by().expect("items have been validated");
+                type_check.check(pkrange_id, order_by_items);
+            }
+        }
+        let buffered_size = parsed_data.iter().map(QueryResult::buffered_size).sum();
+
+        let was_waiting = self.is_waiting(position);
 
         // We assume the data is coming from the server pre-sorted, so we can just extend the buffer with the data.
-        buffer.extend(parsed_data);
+        self.buffers[position].items.extend(parsed_data);
+        self.partitions[position].update_state(continuation);
+        if !self.buffers[position].queued {
+            self.queue_next(position);
+        }
 
-        self.partitions[partition_index].update_state(continuation);
+        if was_waiting && !self.is_waiting(position) {
+            self.waiting -= 1;
+        }
 
-        Ok(())
+        Ok(buffered_size)
     }
 
-    pub fn produce_item(&mut self) -> crate::Result<PipelineNodeResult> {
-        // Scan through each partition to find the next item to produce.
-        // We do the scan first with an immutable borrow of the buffers, and then end up with the index of the partition that has the next item to produce.
-        // Then we can borrow the buffer mutably after the loop to pop the item out of it.
-        let mut current_match = None;
-        for (i, partition) in self.partitions.iter().enumerate() {
-            let (pkrange_id, buffer) = self.buffers.get(i).ok_or_else(|| {
-                ErrorKind::InternalError.with_message(format!(
-                    "missing buffer for partition key range ID: {}",
-                    partition.pkrange.id
-                ))
+    /// Checks that the items can be compared with each other, so that comparisons in the heap can't fail.
+    fn validate(&self, items: &[QueryResult]) -> crate::Result<()> {
+        for item in items {
+            let (order_by_items, _) = item.as_order_by().ok_or_else(|| {
+                ErrorKind::InvalidGatewayResponse.with_message("expected an ORDER BY result")
             })?;
-            debug_assert_eq!(pkrange_id, &partition.pkrange.id); // This should always be true, as the lists are initialized together.
-
-            if !partition.started() {
-                // If any partition hasn't started, we have to stop producing items.
-                // A Partition is considered "started" when we've received at least one `provide_data` call referencing it.
-                // For a streaming order by, we can't stream ANY results until we've received at least one set of results from each partition.
-                // The missing partitions may contain values that sort BEFORE items in the partitions we've received.
-                //
-                // SDKs could optimize how they call the engine to avoid this scenario (by always making requests first, for example),
-                // but we can't assume that will always be the case.
-                tracing::debug!(pkrange_id = ?partition.pkrange.id, "partition not started, stopping item production");
-                return Ok(PipelineNodeResult::NO_RESULT);
-            }
+            self.sorting.validate(order_by_items)?;
+        }
+        Ok(())
+    }
 
-            if partition.done() && buffer.is_empty() {
-                // If the partition is done and the buffer is empty, we can skip it.
-                // In fact, we NEED to skip it because we know it won't produce any more items and if we leave it in the set of partitions we consider,
-                // we might end up trying to query it for more data.
-                tracing::debug!(pkrange_id = ?partition.pkrange.id, "partition done and buffer empty, skipping");
-                continue;
-            }
+    /// Checks if the partition at `position` has no buffered items, but may still produce more.
+    fn is_waiting(&self, position: usize) -> bool {
+        !self.buffers[position].queued && !self.partitions[position].done()
+    }
 
-            match current_match {
-                None => {
-                    // If we haven't found a match yet, we set the current match to this partition so that we always pick a partition.
-                    current_match = Some((i, buffer.front()));
-                }
-                Some((current_index, current_item)) => {
-                    let current_order_by_items =
-                        current_item.and_then(|r| r.as_order_by()).map(|(i, _)| i);
-                    let new_order_by_items =
-                        buffer.front().and_then(|r| r.as_order_by()).map(|(i, _)| i);
-                    match self
-                        .sorting
-                        .compare(current_order_by_items, new_order_by_items)?
-                    {
-                        Ordering::Greater => {
-                            // The current item sorts higher than the new item, so we keep the current match.
-                            continue;
-                        }
-                        Ordering::Less => {
-                            // The new item sorts higher than the current item, so we update the current match to this partition.
-                            // Note: This might be because the partition's buffer is currently empty.
-                            // That can result in the selected partition being one with an empty buffer.
-                            // This is intentional, see below where we return the item.
-                            current_match = Some((i, buffer.front()));
-                        }
-                        Ordering::Equal => {
-                            // Compare the index of the partitions to ensure we always return the first partition with the same item.
-                            if i < current_index {
-                                // The new item is equal to the current item, but the partition index is lower,
-                                // so we update the current match to this partition.
-                                current_match = Some((i, buffer.front()));
-                            }
-                        }
-                    }
-                }
-            }
+    /// Moves the next buffered item from the partition at `position` into the heap, if there is one.
+    fn queue_next(&mut self, position: usize) {
+        let buffer = &mut self.buffers[position];
+        debug_assert!(!buffer.queued, "partition already has an item in the heap");
+        if let Some(item) = buffer.items.pop_front() {
+            buffer.queued = true;
+            self.heads.push(PartitionHead {
+                tie_break: TieBreakKey::from_result(self.tie_break, &item),
+                item: SortableResult::new(&self.sorting, item).expect("items have been validated"),
+                position,
+            });
         }
-        if let Some((i, _)) = current_match {
-            // We found a match, pop the item out of the buffer and return it.
-            debug_assert_eq!(
-                self.buffers[i].0, self.partitions[i].pkrange.id,
-                "buffer ID should match partition key range ID",
+    }
+
+    /// Replaces a partition that was split with the partitions it was split into, see [`PartitionList::split`].
+    ///
+    /// The partition's stream was sorted across its whole key range, so the items already received from it sort before anything
+    /// its replacements return. Those items stay with its first replacement, and the others start out waiting for data.
+    pub fn split_partition(
+        &mut self,
+        pkrange_id: &str,
+        replacements: Vec<PartitionKeyRange>,
+    ) -> crate::Result<()> {
+        let added = replacements.len().saturating_sub(1);
+        let position = self.partitions.split(pkrange_id, replacements)?;
+
+        self.buffers.splice(
+            position + 1..position + 1,
+            std::iter::repeat_with(PartitionBuffer::default).take(added),
+        );
+        self.heads = std::mem::take(&mut self.heads)
+            .into_iter()
+            .map(|head| PartitionHead {
+                position: if head.position > position {
+                    head.position + added
+                } else {
+                    head.position
+                },
+                ..head
+            })
+            .collect();
+        self.waiting += added;
+        Ok(())
+    }
+
+    /// Captures the state of every partition, including the items buffered from each one.
+    pub fn save_state(&self) -> crate::Result<Vec<PartitionSnapshot>> {
+        let mut heads = vec![None; self.buffers.len()];
+        for head in &self.heads {
+            heads[head.position] = Some(head.item.result());
+        }
+        self.partitions
+            .iter()
+            .zip(self.buffers.iter().zip(heads))
+            .map(|(p, (buffer, head))| {
+                p.snapshot()
+                    .with_buffered(head.into_iter().chain(&buffer.items))
+            })
+            .collect()
+    }
+
+    /// Restores the state captured by [`StreamingStrategy::save_state`], returning the size of the restored buffered items.
+    pub fn restore_state(&mut self, snapshots: Vec<PartitionSnapshot>) -> crate::Result<usize> {
+        let restored = self
+            .partitions
+            .restore(snapshots, QueryResultShape::OrderBy)?;
+        let mut buffered_size = 0;
+        self.heads.clear();
+        for (position, items) in restored.into_iter().enumerate() {
+            self.validate(&items)?;
+            buffered_size += items.iter().map(QueryResult::buffered_size).sum::<usize>();
+            self.buffers[position] = PartitionBuffer {
+                queued: false,
+                items: items.into(),
+            };
+            self.queue_next(position);
+        }
+        self.waiting = (0..self.buffers.len())
+            .filter(|&position| self.is_waiting(position))
+            .count();
+        Ok(buffered_size)
+    }
+
+    /// Releases every buffered item, including the items in the heap.
+    pub fn clear(&mut self) {
+        self.heads = BinaryHeap::new();
+        for buffer in &mut self.buffers {
+            *buffer = PartitionBuffer::default();
+        }
+        self.waiting = (0..self.buffers.len())
+            .filter(|&position| self.is_waiting(position))
+            .count();
+    }
+
+    pub fn produce_item(&mut self) -> crate::Result<PipelineNodeResult> {
+        if self.waiting > 0 {
+            // If any partition may have more items but has none buffered, we have to stop producing items.
+            // This includes partitions that haven't started yet, which are partitions that haven't received a `provide_data` call.
+            // For a streaming order by, we can't stream ANY results until we've received at least one set of results from each partition.
+            // The missing items may sort BEFORE items in the partitions we've received.
+            //
+            // SDKs could optimize how they call the engine to avoid this scenario (by always making requests first, for example),
+            // but we can't assume that will always be the case.
+            tracing::debug!(
+                waiting = self.waiting,
+                "partitions waiting for data, stopping item production"
             );
-            // If the buffer is empty, this may return `None`. That's by design!
-            // It means the partition has an empty buffer, and we may need to fetch more data for it.
-            // If it was fully exhausted, the check for `done() && buffer.is_empty()` would have excluded it.
-            // Instead, we have an empty buffer AND the possibility for more data from this partition.
-            // That means we WANT to return `None` here. We need to check this partition for more data before we can yield an item.
-            let value = self.buffers[i].1.pop_front();
-            let terminated = value.is_none() && self.partitions.iter().all(|p| p.done());
-            Ok(PipelineNodeResult { value, terminated })
-        } else {
-            // No match found, meaning all partitions are either exhausted or waiting for data.
-            let terminated = self.partitions.iter().all(|p| p.done());
-            Ok(PipelineNodeResult {
+            return Ok(PipelineNodeResult::NO_RESULT);
+        }
+
+        let Some(head) = self.heads.pop() else {
+            // No partition is waiting and nothing is buffered, so every partition is exhausted.
+            return Ok(PipelineNodeResult {
                 value: None,
-                terminated,
-            })
+                terminated: true,
+            });
+        };
+
+        // Replace the item we're producing with the next one from the same partition.
+        // If the partition's buffer is empty but it isn't done, it now has to be queried for more data before we can produce anything else.
+        let position = head.position;
+        self.buffers[position].queued = false;
+        self.queue_next(position);
+        if self.is_waiting(position) {
+            tracing::debug!(pkrange_id = ?self.partitions[position].pkrange.id, "partition buffer exhausted, waiting for more data");
+            self.waiting += 1;
         }
+
+        Ok(PipelineNodeResult {
+            value: Some(head.item.into()),
+            terminated: false,
+        })
     }
 }
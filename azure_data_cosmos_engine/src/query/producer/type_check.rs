@@ -0,0 +1,148 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use crate::query::QueryClauseItem;
+
+/// Describes an `ORDER BY` item whose values have different types in different partitions.
+///
+/// Values of different types are still ordered consistently (by type, then by value), so results are correct.
+/// However, a mix of types usually means documents were written with inconsistent schemas, which users may want to know about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderByTypeMismatch {
+    /// The index of the `ORDER BY` item, within the `ORDER BY` clause.
+    pub position: usize,
+
+    /// The partition key range ID of the partition the first type was seen in.
+    pub first_pkrange_id: String,
+
+    /// The type of the value first seen at this position.
+    pub first_type: &'static str,
+
+    /// The partition key range ID of the partition with the differing type.
+    pub pkrange_id: String,
+
+    /// The differing type.
+    pub found_type: &'static str,
+}
+
+/// Tracks the types of the `ORDER BY` values seen in each partition, recording a mismatch when partitions disagree.
+///
+/// Undefined values are ignored, since a missing property is usually intentional.
+/// Only the first mismatch at each position is recorded.
+#[derive(Debug)]
+pub struct OrderByTypeCheck {
+    /// For each position, the types seen so far and the partition each was first seen in.
+    seen: Vec<Vec<(&'static str, String)>>,
+    mismatched: Vec<bool>,
+    mismatches: Vec<OrderByTypeMismatch>,
+}
+
+impl OrderByTypeCheck {
+    pub fn new(positions: usize) -> Self {
+        Self {
+            seen: vec![Vec::new(); positions],
+            mismatched: vec![false; positions],
+            mismatches: Vec::new(),
+        }
+    }
+
+    /// Checks the `ORDER BY` values of an item from the given partition.
+    pub fn check(&mut self, pkrange_id: &str, items: &[QueryClauseItem]) {
+        for (position, item) in items.iter().enumerate() {
+            if self.mismatched[position] {
+                continue;
+            }
+            let Some(found_type) = type_name(item) else {
+                continue;
+            };
+            let seen = &mut self.seen[position];
+            if seen.iter().any(|(t, _)| *t == found_type) {
+                continue;
+            }
+
+            // A partition that mixes types itself isn't a cross-partition mismatch.
+            if let Some((first_type, first_pkrange_id)) =
+                seen.iter().find(|(_, id)| id != pkrange_id)
+            {
+                tracing::warn!(
+                    position,
+                    first_type,
+                    first_pkrange_id,
+                    found_type,
+                    pkrange_id,
+                    "ORDER BY values have different types in different partitions"
+                );
+                self.mismatches.push(OrderByTypeMismatch {
+                    position,
+                    first_pkrange_id: first_pkrange_id.clone(),
+                    first_type,
+                    pkrange_id: pkrange_id.to_string(),
+                    found_type,
+                });
+                self.mismatched[position] = true;
+            }
+            seen.push((found_type, pkrange_id.to_string()));
+        }
+    }
+
+    pub fn mismatches(&self) -> &[OrderByTypeMismatch] {
+        &self.mismatches
+    }
+}
+
+fn type_name(item: &QueryClauseItem) -> Option<&'static str> {
+    match item.type_ordinal() {
+        1 => Some("null"),
+        2 => Some("boolean"),
+        4 => Some("number"),
+        5 => Some("string"),
+        6 => Some("array"),
+        7 => Some("object"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn items(values: &[serde_json::Value]) -> Vec<QueryClauseItem> {
+        values
+            .iter()
+            .cloned()
+            .map(QueryClauseItem::from_value)
+            .collect()
+    }
+
+    #[test]
+    pub fn records_first_mismatch_per_position() {
+        let mut check = OrderByTypeCheck::new(2);
+        check.check("partition0", &items(&[json!(1), json!("a")]));
+        check.check("partition0", &items(&[json!("mixed"), json!("b")]));
+        assert!(check.mismatches().is_empty());
+
+        check.check("partition1", &items(&[json!(true), json!(2)]));
+        check.check("partition2", &items(&[json!(null), json!(false)]));
+        assert_eq!(
+            &[
+                OrderByTypeMismatch {
+                    position: 0,
+                    first_pkrange_id: "partition0".to_string(),
+                    first_type: "number",
+                    pkrange_id: "partition1".to_string(),
+                    found_type: "boolean",
+                },
+                OrderByTypeMismatch {
+                    position: 1,
+                    first_pkrange_id: "partition0".to_string(),
+                    first_type: "string",
+                    pkrange_id: "partition1".to_string(),
+                    found_type: "number",
+                },
+            ],
+            check.mismatches()
+        );
+    }
+}
@@ -5,20 +5,34 @@ use std::collections::VecDeque;
 
 use crate::{
     query::{
-        node::PipelineNodeResult, query_result::QueryResultShape, DataRequest, PartitionKeyRange,
-        QueryResult,
+        gateway::CompositeContinuationToken, node::PipelineNodeResult,
+        query_result::QueryResultShape, DataRequest, PartitionKeyRange, QueryResult,
     },
     ErrorKind,
 };
 
-use super::{create_partition_state, state::PartitionState};
+use super::state::{PaginationState, PartitionList, PartitionSnapshot};
 
 pub struct UnorderedStrategy {
-    pub partitions: Vec<PartitionState>,
+    pub partitions: PartitionList,
     pub current_partition_index: usize,
     pub current_pkrange_id: Option<String>,
     pub items: VecDeque<QueryResult>,
     pub result_shape: QueryResultShape,
+
+    /// Indicates if data is requested from every partition up front, rather than only from the current partition.
+    pub prefetch_all_partitions: bool,
+
+    /// Indicates if items are produced from any partition that has them, rather than in partition order.
+    ///
+    /// This implies requesting data from every partition up front. Items from the same partition are still produced in the order they were received.
+    pub interleave_partitions: bool,
+
+    /// The items received from each partition that haven't been moved to `items` yet.
+    ///
+    /// When producing items in partition order, these are the items prefetched from partitions after the current one.
+    /// When interleaving partitions, every partition's items are kept here until they're produced.
+    pending: Vec<VecDeque<QueryResult>>,
 }
 
 impl std::fmt::Debug for UnorderedStrategy {
@@ -28,6 +42,12 @@ impl std::fmt::Debug for UnorderedStrategy {
             .field("current_partition_index", &self.current_partition_index)
             .field("current_pkrange_id", &self.current_pkrange_id)
             .field("items_len", &self.items.len())
+            .field("prefetch_all_partitions", &self.prefetch_all_partitions)
+            .field("interleave_partitions", &self.interleave_partitions)
+            .field(
+                "pending_len",
+                &self.pending.iter().map(VecDeque::len).sum::<usize>(),
+            )
             .finish()
     }
 }
@@ -37,13 +57,30 @@ impl UnorderedStrategy {
         pkranges: impl IntoIterator<Item = PartitionKeyRange>,
         result_shape: QueryResultShape,
     ) -> Self {
-        let partitions = create_partition_state(pkranges);
+        let partitions = PartitionList::new(pkranges);
+        let mut pending = Vec::new();
+        pending.resize_with(partitions.len(), VecDeque::new);
         Self {
             current_partition_index: 0,
             current_pkrange_id: partitions.first().map(|p| p.pkrange.id.clone()),
             items: VecDeque::new(),
             partitions,
             result_shape,
+            prefetch_all_partitions: false,
+            interleave_partitions: false,
+            pending,
+        }
+    }
+
+    /// Moves on to the next partition, queueing any items prefetched from it after the items still buffered from the previous one.
+    fn next_partition(&mut self) {
+        self.current_partition_index += 1;
+        self.current_pkrange_id = self
+            .partitions
+            .get(self.current_partition_index)
+            .map(|p| p.pkrange.id.clone());
+        if let Some(pending) = self.pending.get_mut(self.current_partition_index) {
+            self.items.append(pending);
         }
     }
 
@@ -63,26 +100,35 @@ impl UnorderedStrategy {
                 }
                 None => {
                     tracing::trace!(pkrange_id = ?partition.pkrange.id, "partition exhausted, removing from list");
-                    self.current_partition_index += 1;
-                    self.current_pkrange_id = self
-                        .partitions
-                        .get(self.current_partition_index)
-                        .map(|p| p.pkrange.id.clone());
+                    self.next_partition();
                 }
             }
         }
+
+        // When prefetching or interleaving, the later partitions can be queried at the same time as the current one.
+        if self.prefetch_all_partitions || self.interleave_partitions {
+            if let Some(later) = self.partitions.get(self.current_partition_index + 1..) {
+                requests.extend(later.iter().filter_map(|p| p.request()));
+            }
+        }
         requests
     }
 
     pub fn provide_data(
         &mut self,
         pkrange_id: &str,
+        request_id: u64,
         data: &[u8],
         continuation: Option<String>,
-    ) -> crate::Result<()> {
+    ) -> crate::Result<usize> {
+        // A partition that was split is no longer known, so a late response for it is rejected as a caller error, rather than an internal one.
+        let position = self.partitions.position(pkrange_id)?;
         match &self.current_pkrange_id {
             Some(id) => {
-                if *id != pkrange_id {
+                // When prefetching or interleaving, data can be provided for any partition, but partitions before the current one are already exhausted,
+                // so providing data for them fails when validating the request ID.
+                if *id != pkrange_id && !self.prefetch_all_partitions && !self.interleave_partitions
+                {
                     // The caller provided data for a different partition key range ID before draining the current items queue.
                     return Err(ErrorKind::InternalError.with_message(format!(
                         "provided data for partition key range ID: {}, but current partition is: {}",
@@ -98,31 +144,166 @@ impl UnorderedStrategy {
             }
         }
 
+        self.partitions[position].validate_request_id(request_id)?;
+
         // Parse the raw bytes using the result shape
         let parsed_data = self.result_shape.results_from_slice(data)?;
+        let buffered_size = parsed_data.iter().map(QueryResult::buffered_size).sum();
 
         // Add the data to the items queue. There's no ordering to worry about, so we just append the items.
-        self.items.extend(parsed_data);
+        // Items from later partitions wait until the partitions before them are exhausted.
+        if position == self.current_partition_index && !self.interleave_partitions {
+            self.items.extend(parsed_data);
+        } else {
+            self.pending[position].extend(parsed_data);
+        }
 
         // Update the partition state with the continuation token
-        let partition = self
-            .partitions
-            .iter_mut()
-            .find(|p| p.pkrange.id == pkrange_id)
-            .ok_or_else(|| {
-                ErrorKind::UnknownPartitionKeyRange
-                    .with_message(format!("unknown partition key range ID: {pkrange_id}"))
-            })?;
-        partition.update_state(continuation);
+        self.partitions[position].update_state(continuation);
+
+        Ok(buffered_size)
+    }
+
+    /// Replaces a partition that was split with the partitions it was split into, see [`PartitionList::split`].
+    ///
+    /// Items already received from the partition are produced before any items from its replacements.
+    pub fn split_partition(
+        &mut self,
+        pkrange_id: &str,
+        replacements: Vec<PartitionKeyRange>,
+    ) -> crate::Result<()> {
+        let added = replacements.len().saturating_sub(1);
+        let position = self.partitions.split(pkrange_id, replacements)?;
 
+        // The items already received from the partition stay with its first replacement.
+        self.pending.splice(
+            position + 1..position + 1,
+            std::iter::repeat_with(VecDeque::new).take(added),
+        );
+        if position == self.current_partition_index {
+            self.current_pkrange_id = Some(self.partitions[position].pkrange.id.clone());
+        }
         Ok(())
     }
 
+    /// Gets the continuation state of each partition that has not yet been exhausted.
+    pub fn continuation_tokens(&self) -> crate::Result<Vec<CompositeContinuationToken>> {
+        if !self.items.is_empty() || self.pending.iter().any(|p| !p.is_empty()) {
+            return Err(ErrorKind::InternalError.with_message(
+                "cannot produce a continuation token while items are still buffered",
+            ));
+        }
+
+        Ok(
+            self.partitions[self.current_partition_index.min(self.partitions.len())..]
+                .iter()
+                .filter_map(|p| match &p.stage {
+                    PaginationState::Initial => {
+                        Some(CompositeContinuationToken::new(&p.pkrange, None))
+                    }
+                    PaginationState::Continuing { token, .. } => Some(
+                        CompositeContinuationToken::new(&p.pkrange, Some(token.clone())),
+                    ),
+                    PaginationState::Done => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Captures the state of every partition, including any items buffered from it that haven't been produced yet.
+    pub fn save_state(&self) -> crate::Result<Vec<PartitionSnapshot>> {
+        self.partitions
+            .iter()
+            .zip(&self.pending)
+            .enumerate()
+            .map(|(i, (p, pending))| {
+                let snapshot = p.snapshot();
+                if i == self.current_partition_index {
+                    snapshot.with_buffered(self.items.iter().chain(pending))
+                } else {
+                    snapshot.with_buffered(pending)
+                }
+            })
+            .collect()
+    }
+
+    /// Restores the state captured by [`UnorderedStrategy::save_state`], returning the size of the restored buffered items.
+    pub fn restore_state(&mut self, snapshots: Vec<PartitionSnapshot>) -> crate::Result<usize> {
+        let buffers = self.partitions.restore(snapshots, self.result_shape)?;
+
+        // The current partition is the first one that still has items to produce or data to request.
+        self.current_partition_index = buffers
+            .iter()
+            .zip(self.partitions.iter())
+            .position(|(buffer, p)| !buffer.is_empty() || !p.done())
+            .unwrap_or(self.partitions.len().saturating_sub(1));
+        self.current_pkrange_id = self
+            .partitions
+            .get(self.current_partition_index)
+            .map(|p| p.pkrange.id.clone());
+
+        // Partitions before the current one have no buffered items, by definition.
+        // Items buffered by later partitions were prefetched, and wait until the current partition is exhausted, unless partitions are interleaved.
+        self.items.clear();
+        let mut buffered_size = 0;
+        for (i, buffer) in buffers.into_iter().enumerate() {
+            buffered_size += buffer.iter().map(QueryResult::buffered_size).sum::<usize>();
+            if i == self.current_partition_index {
+                self.items = buffer.into();
+            } else {
+                self.pending[i] = buffer.into();
+            }
+        }
+        Ok(buffered_size)
+    }
+
+    /// Releases every buffered item, including the items prefetched from later partitions.
+    pub fn clear(&mut self) {
+        self.items = VecDeque::new();
+        for pending in &mut self.pending {
+            *pending = VecDeque::new();
+        }
+    }
+
     pub fn produce_item(&mut self) -> crate::Result<PipelineNodeResult> {
+        if self.interleave_partitions {
+            return Ok(self.produce_interleaved_item());
+        }
+
+        // When the query ranges don't overlap any partition, there's nothing to produce.
+        if self.partitions.is_empty() {
+            return Ok(PipelineNodeResult {
+                value: None,
+                terminated: true,
+            });
+        }
+
+        // Move past exhausted partitions, so that any items prefetched from the next ones can be produced.
+        while self.items.is_empty()
+            && self.current_partition_index + 1 < self.partitions.len()
+            && self.partitions[self.current_partition_index].done()
+        {
+            self.next_partition();
+        }
+
         let value = self.items.pop_front();
         let terminated = self.items.is_empty()
             && (self.current_partition_index == self.partitions.len() - 1)
             && self.partitions[self.current_partition_index].done();
         Ok(PipelineNodeResult { value, terminated })
     }
+
+    /// Produces the next item from the first partition that has one, so that partitions that haven't provided data yet don't hold up the others.
+    fn produce_interleaved_item(&mut self) -> PipelineNodeResult {
+        // Items restored from a saved state may have been moved to `items`, so those come first.
+        let value = self.items.pop_front().or_else(|| {
+            self.pending
+                .iter_mut()
+                .find_map(|pending| pending.pop_front())
+        });
+        let terminated = self.items.is_empty()
+            && self.pending.iter().all(VecDeque::is_empty)
+            && self.partitions.iter().all(|p| p.done());
+        PipelineNodeResult { value, terminated }
+    }
 }
@@ -2,9 +2,12 @@
 // Licensed under the MIT License.
 
 use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize};
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::Debug};
 
-use crate::ErrorKind;
+use crate::{
+    query::{pipeline::has_non_value_aggregates, QueryInfo, QueryPlan},
+    ErrorKind,
+};
 
 /// Holds an owned list of items retrieved from the backend
 #[derive(Serialize, Deserialize)]
@@ -16,11 +19,36 @@ pub(crate) struct FeedResponse<T> {
 /// Helper struct for ORDER BY query results
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct OrderByResult {
+pub(crate) struct OrderByResult {
+    #[serde(rename = "_rid", default, skip_serializing_if = "Option::is_none")]
+    rid: Option<String>,
     order_by_items: Vec<QueryClauseItem>,
     payload: Box<serde_json::value::RawValue>,
 }
 
+impl From<OrderByResult> for QueryResult {
+    fn from(value: OrderByResult) -> Self {
+        QueryResult::OrderBy {
+            order_by_items: value.order_by_items,
+            payload: value.payload,
+            rid: value.rid,
+        }
+    }
+}
+
+/// Helper enum for non-value aggregate query results.
+///
+/// The gateway rewrites these queries as a `GROUP BY` with no grouping expressions, so each row is usually wrapped in a `groupByItems`/`payload` object.
+/// We also accept the bare alias map, in case the rewrite doesn't include the wrapper.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AggregateResult {
+    Wrapped {
+        payload: BTreeMap<String, QueryClauseItem>,
+    },
+    Bare(BTreeMap<String, QueryClauseItem>),
+}
+
 /// Describes the expected shape of the query result.
 ///
 /// The results the gateway gives us can vary in shape depending on the type of query executed.
@@ -37,11 +65,41 @@ pub enum QueryResultShape {
     /// The result is from a `SELECT VALUE [aggregate function](...)` query.
     ValueAggregate,
 
+    /// The result is from a query with named, non-value, aggregates, like `SELECT COUNT(1) AS total, MAX(c.price) AS maxPrice FROM c`.
+    Aggregate,
+
     /// The result it is from a hybrid search component query.
     HybridComponent,
 }
 
 impl QueryResultShape {
+    /// Gets the shape of the results each partition returns for the query described by `plan`.
+    ///
+    /// This is the shape the pipeline parses pages with, so a language binding that parses responses itself can pick the same deserializer.
+    /// A plan with an `ORDER BY` produces [`OrderBy`](QueryResultShape::OrderBy) results even when it also has aggregates, since the aggregates are in the payload of each row.
+    /// A hybrid search plan produces [`HybridComponent`](QueryResultShape::HybridComponent) results,
+    /// and a plan without query info produces [`RawPayload`](QueryResultShape::RawPayload) results, like a query executed without a plan.
+    pub fn from_plan(plan: &QueryPlan) -> Self {
+        match (&plan.hybrid_search_query_info, &plan.query_info) {
+            (Some(_), _) => QueryResultShape::HybridComponent,
+            (None, Some(query_info)) => QueryResultShape::from_query_info(query_info),
+            (None, None) => QueryResultShape::RawPayload,
+        }
+    }
+
+    /// Gets the shape of the results for a query that isn't a hybrid search, see [`QueryResultShape::from_plan`].
+    pub(crate) fn from_query_info(query_info: &QueryInfo) -> Self {
+        if !query_info.order_by.is_empty() {
+            QueryResultShape::OrderBy
+        } else if has_non_value_aggregates(query_info) {
+            QueryResultShape::Aggregate
+        } else if !query_info.aggregates.is_empty() {
+            QueryResultShape::ValueAggregate
+        } else {
+            QueryResultShape::RawPayload
+        }
+    }
+
     pub fn results_from_slice(self, buffer: &[u8]) -> crate::Result<Vec<QueryResult>> {
         match self {
             QueryResultShape::RawPayload => {
@@ -60,10 +118,7 @@ impl QueryResultShape {
                 Ok(results
                     .documents
                     .into_iter()
-                    .map(|item| QueryResult::OrderBy {
-                        order_by_items: item.order_by_items,
-                        payload: item.payload,
-                    })
+                    .map(QueryResult::from)
                     .collect())
             }
             QueryResultShape::ValueAggregate => {
@@ -75,9 +130,43 @@ impl QueryResultShape {
                     .map(QueryResult::ValueAggregates)
                     .collect())
             }
+            QueryResultShape::Aggregate => {
+                let results: FeedResponse<AggregateResult> = serde_json::from_slice(buffer)
+                    .map_err(|e| ErrorKind::InvalidGatewayResponse.with_source(e))?;
+                Ok(results
+                    .documents
+                    .into_iter()
+                    .map(|item| match item {
+                        AggregateResult::Wrapped { payload } => QueryResult::Aggregates(payload),
+                        AggregateResult::Bare(aggregates) => QueryResult::Aggregates(aggregates),
+                    })
+                    .collect())
+            }
             QueryResultShape::HybridComponent => todo!(),
         }
     }
+
+    /// Parses a single aggregate row from the payload of another result, like the payload of an `ORDER BY` row when the query has both `ORDER BY` and aggregates.
+    ///
+    /// Only the [`ValueAggregate`](QueryResultShape::ValueAggregate) and [`Aggregate`](QueryResultShape::Aggregate) shapes can be parsed this way.
+    pub(crate) fn aggregates_from_payload(
+        self,
+        payload: &serde_json::value::RawValue,
+    ) -> crate::Result<QueryResult> {
+        match self {
+            QueryResultShape::ValueAggregate => serde_json::from_str(payload.get())
+                .map(QueryResult::ValueAggregates)
+                .map_err(|e| ErrorKind::InvalidGatewayResponse.with_source(e)),
+            QueryResultShape::Aggregate => serde_json::from_str(payload.get())
+                .map(|item| match item {
+                    AggregateResult::Wrapped { payload } => QueryResult::Aggregates(payload),
+                    AggregateResult::Bare(aggregates) => QueryResult::Aggregates(aggregates),
+                })
+                .map_err(|e| ErrorKind::InvalidGatewayResponse.with_source(e)),
+            _ => Err(ErrorKind::InternalError
+                .with_message(format!("{self:?} is not an aggregate result shape"))),
+        }
+    }
 }
 
 /// Represents the result of a rewritten query.
@@ -96,10 +185,18 @@ pub enum QueryResult {
 
         /// The actual payload of the query result.
         payload: Box<serde_json::value::RawValue>,
+
+        /// The `_rid` of the document the result came from, if the rewritten query selected it.
+        ///
+        /// This breaks ties between results with equal order by items, the same way the backend does.
+        rid: Option<String>,
     },
 
     /// The result is from a `SELECT VALUE [aggregate function](...)` query against a single partition.
     ValueAggregates(Vec<QueryClauseItem>),
+
+    /// The result is from a query with named aggregates against a single partition, keyed by the alias of each aggregate.
+    Aggregates(BTreeMap<String, QueryClauseItem>),
 }
 
 impl QueryResult {
@@ -117,11 +214,20 @@ impl QueryResult {
             QueryResult::OrderBy {
                 order_by_items,
                 payload,
+                ..
             } => Some((order_by_items, payload)),
             _ => None,
         }
     }
 
+    /// Gets the `_rid` of the document an `OrderBy` result came from, if it has one.
+    pub fn rid(&self) -> Option<&str> {
+        match self {
+            QueryResult::OrderBy { rid, .. } => rid.as_deref(),
+            _ => None,
+        }
+    }
+
     /// Expects the result to be of the `ValueAggregates` variant and unwraps it, returning an error if it is not.
     pub fn as_value_aggregates(&self) -> Option<&[QueryClauseItem]> {
         match self {
@@ -130,12 +236,31 @@ impl QueryResult {
         }
     }
 
+    /// Expects the result to be of the `Aggregates` variant and unwraps it, returning an error if it is not.
+    pub fn as_aggregates(&self) -> Option<&BTreeMap<String, QueryClauseItem>> {
+        match self {
+            QueryResult::Aggregates(aggregates) => Some(aggregates),
+            _ => None,
+        }
+    }
+
     /// Converts the `QueryResult` into its payload, if it has one.
     pub fn into_payload(self) -> Option<Box<serde_json::value::RawValue>> {
         match self {
             QueryResult::RawPayload(payload) => Some(payload),
             QueryResult::OrderBy { payload, .. } => Some(payload),
-            QueryResult::ValueAggregates(_) => None,
+            QueryResult::ValueAggregates(_) | QueryResult::Aggregates(_) => None,
+        }
+    }
+
+    /// Gets the number of bytes this result counts against a memory budget.
+    ///
+    /// Only the payload is counted, aggregate results are small and are not counted at all.
+    pub(crate) fn buffered_size(&self) -> usize {
+        match self {
+            QueryResult::RawPayload(payload) => payload.get().len(),
+            QueryResult::OrderBy { payload, .. } => payload.get().len(),
+            QueryResult::ValueAggregates(_) | QueryResult::Aggregates(_) => 0,
         }
     }
 }
@@ -150,13 +275,19 @@ impl Serialize for QueryResult {
             QueryResult::OrderBy {
                 order_by_items,
                 payload,
+                rid,
             } => {
-                let mut state = serializer.serialize_struct("OrderByItem", 2)?;
+                let mut state =
+                    serializer.serialize_struct("OrderByItem", 2 + usize::from(rid.is_some()))?;
+                if let Some(rid) = rid {
+                    state.serialize_field("_rid", rid)?;
+                }
                 state.serialize_field("orderByItems", order_by_items)?;
                 state.serialize_field("payload", payload)?;
                 state.end()
             }
             QueryResult::ValueAggregates(aggregates) => aggregates.serialize(serializer),
+            QueryResult::Aggregates(aggregates) => aggregates.serialize(serializer),
         }
     }
 }
@@ -165,7 +296,16 @@ impl Serialize for QueryResult {
 /// This struct represents that shape, and provides comparison logic for ordering.
 #[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
 pub struct QueryClauseItem {
-    #[serde(default, deserialize_with = "deserialize_item")]
+    /// The value of the item, or `None` if the value is undefined.
+    ///
+    /// The backend represents an undefined value by omitting the `item` property entirely (`{}`), which is distinct from an explicit `null`.
+    /// An undefined item is serialized the same way, so it remains undefined (and sorts before `null`) if it is deserialized again.
+    /// There is no string sentinel for undefined, a string like `"Undefined"` is just a string value.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_item",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub item: Option<serde_json::Value>,
 
     /// The backend sometimes returns an alternate form of the item, such as a min/max value with added metadata about the number of items in the partition.
@@ -206,66 +346,147 @@ impl QueryClauseItem {
 
     /// Compares two [`QueryClauseItem`]s based on the ordering rules defined for Cosmos DB.
     ///
+    /// Numbers are compared by their exact values. Integers, whether they fit in an `i64` or only in a `u64`, are compared exactly,
+    /// and an integer is compared to a float without rounding the integer, so distinct large integers never compare as equal.
+    ///
+    /// Values of different types are ordered by type, see [`QueryClauseItem::type_ordinal`], so arrays and objects sort after every primitive value,
+    /// and primitives compare exactly as they would if no array or object was present.
+    /// Arrays are compared element by element. Objects are compared by their properties, sorted by key, comparing each key and then its value.
+    /// In both cases, a value that's a prefix of the other sorts first.
+    ///
     /// We can't just implement [`PartialOrd`] here, because we need to be able to return an error.
     pub fn compare(&self, other: &Self) -> crate::Result<std::cmp::Ordering> {
-        let left_ordinal = self.type_ordinal()?;
-        let right_ordinal = other.type_ordinal()?;
+        compare_values(self.item.as_ref(), other.item.as_ref())
+    }
 
-        if left_ordinal != right_ordinal {
-            return Ok(left_ordinal.cmp(&right_ordinal));
-        }
+    /// Gets the "Type Ordinal" for a given item.
+    ///
+    /// The Type Ordinal is used to order items of differing types.
+    /// If the Type Ordinal is the same, the items are compared using their underlying values.
+    pub(crate) fn type_ordinal(&self) -> usize {
+        type_ordinal(self.item.as_ref())
+    }
+}
+
+/// Gets the type ordinal of a value, where `None` is undefined, see [`QueryClauseItem::type_ordinal`].
+///
+/// Values are ordered: undefined, null, booleans, numbers, strings, arrays and then objects.
+fn type_ordinal(value: Option<&serde_json::Value>) -> usize {
+    match value {
+        None => 0,
+        Some(serde_json::Value::Null) => 1,
+        Some(serde_json::Value::Bool(_)) => 2,
+        // 3 is skipped in the current implementation for both Python and JS.
+        Some(serde_json::Value::Number(_)) => 4,
+        Some(serde_json::Value::String(_)) => 5,
+        Some(serde_json::Value::Array(_)) => 6,
+        Some(serde_json::Value::Object(_)) => 7,
+    }
+}
+
+/// Compares two values, where `None` is undefined, see [`QueryClauseItem::compare`].
+fn compare_values(
+    left: Option<&serde_json::Value>,
+    right: Option<&serde_json::Value>,
+) -> crate::Result<std::cmp::Ordering> {
+    let left_ordinal = type_ordinal(left);
+    let right_ordinal = type_ordinal(right);
 
-        match (&self.item, &other.item) {
-            (None, None) => Ok(std::cmp::Ordering::Equal),
-            (Some(serde_json::Value::Null), Some(serde_json::Value::Null)) => {
-                Ok(std::cmp::Ordering::Equal)
+    if left_ordinal != right_ordinal {
+        return Ok(left_ordinal.cmp(&right_ordinal));
+    }
+
+    match (left, right) {
+        (None, None) => Ok(std::cmp::Ordering::Equal),
+        (Some(serde_json::Value::Null), Some(serde_json::Value::Null)) => {
+            Ok(std::cmp::Ordering::Equal)
+        }
+        (Some(serde_json::Value::String(left)), Some(serde_json::Value::String(right))) => {
+            Ok(left.cmp(right))
+        }
+        (Some(serde_json::Value::Bool(left)), Some(serde_json::Value::Bool(right))) => {
+            Ok(left.cmp(right))
+        }
+        (Some(serde_json::Value::Number(left)), Some(serde_json::Value::Number(right))) => {
+            let nan_error = || {
+                ErrorKind::InvalidGatewayResponse
+                    .with_message("encountered NaN or Infinity while comparing floats")
+            };
+            let integer = |n: &serde_json::Number| {
+                n.as_i64()
+                    .map(i128::from)
+                    .or_else(|| n.as_u64().map(i128::from))
+            };
+            match (integer(left), integer(right)) {
+                (Some(l_int), Some(r_int)) => Ok(l_int.cmp(&r_int)),
+                (Some(l_int), None) => {
+                    let r = right.as_f64().ok_or_else(nan_error)?;
+                    compare_integer_to_float(l_int, r).ok_or_else(nan_error)
+                }
+                (None, Some(r_int)) => {
+                    let l = left.as_f64().ok_or_else(nan_error)?;
+                    compare_integer_to_float(r_int, l)
+                        .map(std::cmp::Ordering::reverse)
+                        .ok_or_else(nan_error)
+                }
+                (None, None) => {
+                    let l = left.as_f64().ok_or_else(nan_error)?;
+                    let r = right.as_f64().ok_or_else(nan_error)?;
+                    l.partial_cmp(&r).ok_or_else(nan_error)
+                }
             }
-            (Some(serde_json::Value::String(left)), Some(serde_json::Value::String(right))) => {
-                Ok(left.cmp(right))
+        }
+
+        // Arrays are compared element by element, and an array that's a prefix of another sorts first.
+        (Some(serde_json::Value::Array(left)), Some(serde_json::Value::Array(right))) => {
+            for (l, r) in left.iter().zip(right) {
+                let order = compare_values(Some(l), Some(r))?;
+                if order.is_ne() {
+                    return Ok(order);
+                }
             }
-            (Some(serde_json::Value::Bool(left)), Some(serde_json::Value::Bool(right))) => {
-                Ok(left.cmp(right))
+            Ok(left.len().cmp(&right.len()))
+        }
+
+        // Objects are compared property by property, in key order, by key and then by value, and an object whose properties are a prefix of another's sorts first.
+        (Some(serde_json::Value::Object(left)), Some(serde_json::Value::Object(right))) => {
+            // Sort the properties explicitly, since the map keeps them in document order if serde_json's `preserve_order` feature is enabled.
+            fn sorted(
+                object: &serde_json::Map<String, serde_json::Value>,
+            ) -> Vec<(&String, &serde_json::Value)> {
+                let mut properties = object.iter().collect::<Vec<_>>();
+                properties.sort_unstable_by_key(|(key, _)| *key);
+                properties
             }
-            (Some(serde_json::Value::Number(left)), Some(serde_json::Value::Number(right))) => {
-                // Try integer comparison first. This will fail if either value is not an integer.
-                if let (Some(l_int), Some(r_int)) = (left.as_i64(), right.as_i64()) {
-                    Ok(l_int.cmp(&r_int))
-                } else {
-                    // We need to compare as floats.
-                    let l = left.as_f64().ok_or_else(|| {
-                        ErrorKind::InvalidGatewayResponse.with_message("encountered NaN or Infinity while comparing floats")
-                    })?;
-                    let r = right.as_f64().ok_or_else(|| {
-                        ErrorKind::InvalidGatewayResponse.with_message("encountered NaN or Infinity while comparing floats")
-                    })?;
-                    l.partial_cmp(&r).ok_or_else(|| {
-                        ErrorKind::InvalidGatewayResponse.with_message("encountered NaN or Infinity while comparing floats")
-                    })
+
+            for ((l_key, l), (r_key, r)) in sorted(left).into_iter().zip(sorted(right)) {
+                let order = match l_key.cmp(r_key) {
+                    std::cmp::Ordering::Equal => compare_values(Some(l), Some(r))?,
+                    order => order,
+                };
+                if order.is_ne() {
+                    return Ok(order);
                 }
             }
-
-            // Shouldn't be possible to get here, since we've already checked the type ordinal.
-            _ => unreachable!("encountered different types after comparing type ordinal, this shouldn't be possible")
+            Ok(left.len().cmp(&right.len()))
         }
+
+        // Shouldn't be possible to get here, since we've already checked the type ordinal.
+        _ => unreachable!(
+            "encountered different types after comparing type ordinal, this shouldn't be possible"
+        ),
     }
+}
 
-    /// Gets the "Type Ordinal" for a given item.
-    ///
-    /// The Type Ordinal is used to order items of differing types.
-    /// If the Type Ordinal is the same, the items are compared using their underlying values.
-    ///
-    /// Returns an error if a non-primitive value is encountered.
-    fn type_ordinal(&self) -> crate::Result<usize> {
-        match &self.item {
-            None => Ok(0),
-            Some(serde_json::Value::Null) => Ok(1),
-            Some(serde_json::Value::Bool(_)) => Ok(2),
-            // 3 is skipped in the current implementation for both Python and JS.
-            Some(serde_json::Value::Number(_)) => Ok(4),
-            Some(serde_json::Value::String(_)) => Ok(5),
-            _ => Err(ErrorKind::InvalidGatewayResponse
-                .with_message("cannot compare non-primitive values")),
-        }
+/// Compares an integer to a float by their exact values, returning `None` if the float is NaN.
+///
+/// Rounding the integer to a float would make integers beyond the precision of a float equal to the same float, but not to each other,
+/// which isn't a consistent order. Rounding preserves order though, so the rounded integer only has to be compared exactly when it equals the float.
+pub(crate) fn compare_integer_to_float(integer: i128, float: f64) -> Option<std::cmp::Ordering> {
+    match (integer as f64).partial_cmp(&float)? {
+        // The float is a whole number, within the range of the integers we compare, so it converts exactly.
+        std::cmp::Ordering::Equal => Some(integer.cmp(&(float as i128))),
+        order => Some(order),
     }
 }
 
@@ -301,6 +522,7 @@ mod tests {
             QueryResult::OrderBy {
                 order_by_items,
                 payload,
+                ..
             } => {
                 assert_eq!(
                     order_by_items,
@@ -327,6 +549,81 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn query_result_deserializes_aggregate_shape() {
+        const JSON: &str = r#"{"Documents":[{"groupByItems":[],"payload":{"total":{"item":42},"maxPrice":{"item":10,"item2":{"max":10,"count":2}}}}]}"#;
+        let result: QueryResult = json_to_query_result(QueryResultShape::Aggregate, JSON);
+        match result {
+            QueryResult::Aggregates(aggregates) => {
+                assert_eq!(
+                    aggregates,
+                    BTreeMap::from([
+                        (
+                            "maxPrice".to_string(),
+                            QueryClauseItem::from_values(
+                                serde_json::json!(10),
+                                serde_json::json!({"max": 10, "count": 2})
+                            )
+                        ),
+                        (
+                            "total".to_string(),
+                            QueryClauseItem::from_value(serde_json::json!(42))
+                        ),
+                    ])
+                );
+            }
+            _ => panic!("expected Aggregates variant"),
+        }
+    }
+
+    #[test]
+    pub fn query_result_deserializes_bare_aggregate_shape() {
+        const JSON: &str = r#"{"Documents":[{"total":{"item":42}}]}"#;
+        let result: QueryResult = json_to_query_result(QueryResultShape::Aggregate, JSON);
+        match result {
+            QueryResult::Aggregates(aggregates) => {
+                assert_eq!(
+                    aggregates,
+                    BTreeMap::from([(
+                        "total".to_string(),
+                        QueryClauseItem::from_value(serde_json::json!(42))
+                    )])
+                );
+            }
+            _ => panic!("expected Aggregates variant"),
+        }
+    }
+
+    #[test]
+    pub fn query_result_deserializes_undefined_order_by_item() {
+        const JSON: &str = r#"{"Documents":[{"orderByItems":[{},{"item":null},{"item":"Undefined"}], "payload": {"a":1}}]}"#;
+        let result: QueryResult = json_to_query_result(QueryResultShape::OrderBy, JSON);
+        let (order_by_items, _) = result.as_order_by().unwrap();
+        assert_eq!(
+            order_by_items,
+            vec![
+                QueryClauseItem::default(),
+                QueryClauseItem::from_value(serde_json::Value::Null),
+                QueryClauseItem::from_value(serde_json::json!("Undefined")),
+            ]
+        );
+        assert_eq!(0, order_by_items[0].type_ordinal());
+        assert_eq!(1, order_by_items[1].type_ordinal());
+        assert_eq!(5, order_by_items[2].type_ordinal());
+    }
+
+    #[test]
+    pub fn undefined_query_clause_item_round_trips() {
+        let items = vec![
+            QueryClauseItem::default(),
+            QueryClauseItem::from_value(serde_json::Value::Null),
+        ];
+        let serialized = serde_json::to_string(&items).unwrap();
+        assert_eq!(serialized, r#"[{},{"item":null}]"#);
+        let deserialized: Vec<QueryClauseItem> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(items, deserialized);
+    }
+
     #[test]
     pub fn query_result_serializes_raw_payload() {
         let payload = serde_json::value::RawValue::from_string(r#"{"a":1}"#.to_string()).unwrap();
@@ -341,6 +638,7 @@ mod tests {
         let result = QueryResult::OrderBy {
             order_by_items: vec![QueryClauseItem::from_value(serde_json::json!(1))],
             payload,
+            rid: None,
         };
         let serialized = serde_json::to_string(&result).unwrap();
         assert_eq!(
@@ -349,6 +647,17 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn query_result_order_by_round_trips_rid() {
+        const JSON: &str = r#"{"Documents":[{"_rid":"W8YaAOxMJtUBAAAAAAAAAA==","orderByItems":[{"item":1}],"payload":{"a":1}}]}"#;
+        let result = json_to_query_result(QueryResultShape::OrderBy, JSON);
+        assert_eq!(Some("W8YaAOxMJtUBAAAAAAAAAA=="), result.rid());
+        assert_eq!(
+            r#"{"_rid":"W8YaAOxMJtUBAAAAAAAAAA==","orderByItems":[{"item":1}],"payload":{"a":1}}"#,
+            serde_json::to_string(&result).unwrap()
+        );
+    }
+
     #[test]
     pub fn query_result_serializes_value_aggregates() {
         let result =
@@ -394,6 +703,29 @@ mod tests {
             {"item": -1}, {"item": -1} => Ok(Ordering::Equal),
             {"item": -1}, {"item": 1} => Ok(Ordering::Less),
             {"item": 1}, {"item": -1} => Ok(Ordering::Greater),
+            {"item": 1}, {"item": 1.0} => Ok(Ordering::Equal),
+            {"item": 9007199254740992_i64}, {"item": 9007199254740992.0} => Ok(Ordering::Equal),
+            {"item": 9007199254740993_i64}, {"item": 9007199254740992.0} => Ok(Ordering::Greater),
+            {"item": 9007199254740992.0}, {"item": 9007199254740993_i64} => Ok(Ordering::Less),
+            {"item": 9223372036854775807_i64}, {"item": 9223372036854775808_u64} => Ok(Ordering::Less),
+            {"item": 18446744073709551615_u64}, {"item": 18446744073709551616.0} => Ok(Ordering::Less),
+        }
+
+        compare_large_integers {
+            {"item": 9223372036854775806_i64}, {"item": 9223372036854775807_i64} => Ok(Ordering::Less),
+            {"item": 9223372036854775807_i64}, {"item": 9223372036854775807.0} => Ok(Ordering::Less),
+            {"item": 9223372036854775808_u64}, {"item": 9223372036854775807.0} => Ok(Ordering::Equal),
+            {"item": 9223372036854775809_u64}, {"item": 9223372036854775808_u64} => Ok(Ordering::Greater),
+            {"item": 9223372036854775809_u64}, {"item": 9223372036854775808.0} => Ok(Ordering::Greater),
+            {"item": 18446744073709551614_u64}, {"item": 18446744073709551615_u64} => Ok(Ordering::Less),
+            {"item": 18446744073709551615_u64}, {"item": 18446744073709551615_u64} => Ok(Ordering::Equal),
+            // The largest f64 below 2^64.
+            {"item": 18446744073709551615_u64}, {"item": 18446744073709549568.0} => Ok(Ordering::Greater),
+            {"item": 18446744073709551615_u64}, {"item": 1e20} => Ok(Ordering::Less),
+            {"item": -9223372036854775808_i64}, {"item": 9223372036854775808_u64} => Ok(Ordering::Less),
+            {"item": -9223372036854775808_i64}, {"item": -9223372036854775808.0} => Ok(Ordering::Equal),
+            {"item": -9223372036854775807_i64}, {"item": -9223372036854775808.0} => Ok(Ordering::Greater),
+            {"item": -9223372036854775808_i64}, {"item": -1e19} => Ok(Ordering::Greater),
         }
 
         compare_bools {
@@ -422,10 +754,48 @@ mod tests {
             {"item": 1}, {"item": "a"} => Ok(Ordering::Less),
         }
 
-        cannot_compare_non_primitives {
-            {"item": {"a": 1}}, {"item": {"a": 2}} => Err(_),
-            {"item": [1, 2]}, {"item": [3, 4]} => Err(_),
-            {"item": {"a": 1}}, {} => Err(_),
+        compare_arrays {
+            {"item": [1, 2]}, {"item": [1, 2]} => Ok(Ordering::Equal),
+            {"item": [1, 2]}, {"item": [1, 3]} => Ok(Ordering::Less),
+            {"item": [2]}, {"item": [1, 3]} => Ok(Ordering::Greater),
+            {"item": [1]}, {"item": [1, 0]} => Ok(Ordering::Less),
+            {"item": []}, {"item": [null]} => Ok(Ordering::Less),
+            {"item": [1, "a"]}, {"item": [1, 2]} => Ok(Ordering::Greater),
+            {"item": [[1, 2]]}, {"item": [[1, 3]]} => Ok(Ordering::Less),
+            {"item": [{"a": 1}]}, {"item": [{"a": 2}]} => Ok(Ordering::Less),
+            {"item": [{"a": 1}, 2]}, {"item": [{"a": 1}, 1]} => Ok(Ordering::Greater),
+        }
+
+        compare_objects {
+            {"item": {"a": 1}}, {"item": {"a": 1}} => Ok(Ordering::Equal),
+            {"item": {"a": 1}}, {"item": {"a": 2}} => Ok(Ordering::Less),
+            {"item": {"a": 1, "b": 2}}, {"item": {"b": 2, "a": 1}} => Ok(Ordering::Equal),
+            {"item": {"b": 1}}, {"item": {"a": 2}} => Ok(Ordering::Greater),
+            {"item": {"a": 9, "b": 1}}, {"item": {"b": 0}} => Ok(Ordering::Less),
+            {"item": {"a": "x"}}, {"item": {"a": 1}} => Ok(Ordering::Greater),
+            {"item": {"a": null}}, {"item": {"a": false}} => Ok(Ordering::Less),
+            {"item": {"a": 1, "c": 1}}, {"item": {"a": 1, "b": 5}} => Ok(Ordering::Greater),
+        }
+
+        compare_object_prefixes {
+            {"item": {}}, {"item": {"b": [1]}} => Ok(Ordering::Less),
+            {"item": {"a": 1}}, {"item": {"a": 1, "b": null}} => Ok(Ordering::Less),
+            {"item": {"a": 1, "b": null}}, {"item": {"a": 1}} => Ok(Ordering::Greater),
+        }
+
+        compare_nested_values {
+            {"item": {"a": {"x": [1, 2]}}}, {"item": {"a": {"x": [1, 3]}}} => Ok(Ordering::Less),
+            {"item": {"a": {"x": 1}}}, {"item": {"a": {"y": 0}}} => Ok(Ordering::Less),
+            {"item": {"a": [{"k": "v"}]}}, {"item": {"a": [{"k": "v"}]}} => Ok(Ordering::Equal),
+            {"item": {"a": [1]}}, {"item": {"a": {}}} => Ok(Ordering::Less),
+            {"item": {"n": 9007199254740993_u64}}, {"item": {"n": 9007199254740992.0}} => Ok(Ordering::Greater),
+        }
+
+        compare_arrays_and_objects_with_other_types {
+            {"item": {"a": 1}}, {} => Ok(Ordering::Greater),
+            {"item": "zzz"}, {"item": []} => Ok(Ordering::Less),
+            {"item": [1]}, {"item": {}} => Ok(Ordering::Less),
+            {"item": {}}, {"item": ["a"]} => Ok(Ordering::Greater),
         }
     }
 }
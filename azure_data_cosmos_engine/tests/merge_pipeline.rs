@@ -0,0 +1,114 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::query::{MergePipeline, PipelineResponse, SortOrder};
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+/// Builds a page from a stream, with each item in the `ORDER BY` result shape.
+fn page(items: &[(i64, &str)], terminated: bool) -> PipelineResponse {
+    PipelineResponse {
+        items: items
+            .iter()
+            .map(|(sort, id)| {
+                serde_json::value::to_raw_value(
+                    &json!({"orderByItems": [{"item": sort}], "payload": {"id": id}}),
+                )
+                .unwrap()
+            })
+            .collect(),
+        requests: Vec::new(),
+        terminated,
+        cumulative_request_charge: 0.0,
+        activity_ids: Vec::new(),
+    }
+}
+
+fn ids(response: &PipelineResponse) -> Vec<String> {
+    response
+        .items
+        .iter()
+        .map(|item| {
+            let value: serde_json::Value = serde_json::from_str(item.get()).unwrap();
+            value["id"].as_str().unwrap().to_string()
+        })
+        .collect()
+}
+
+fn requested_streams(response: &PipelineResponse) -> Vec<&str> {
+    response
+        .requests
+        .iter()
+        .map(|r| r.pkrange_id.as_ref())
+        .collect()
+}
+
+#[test]
+pub fn merges_two_ordered_streams() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = MergePipeline::new(["left", "right"], vec![SortOrder::Ascending]);
+
+    // Nothing can be produced until every stream has provided data.
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert_eq!(vec!["left", "right"], requested_streams(&response));
+
+    // Every stream that hasn't terminated is requested, even if the merge has items buffered from it.
+    pipeline.provide_response("left", page(&[(1, "l1"), (4, "l4")], false))?;
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert_eq!(vec!["left", "right"], requested_streams(&response));
+
+    pipeline.provide_response("right", page(&[(2, "r2"), (3, "r3"), (5, "r5")], false))?;
+    let response = pipeline.run()?;
+    assert_eq!(vec!["l1", "r2", "r3", "l4"], ids(&response));
+    assert_eq!(vec!["left", "right"], requested_streams(&response));
+    assert!(!response.terminated);
+
+    pipeline.provide_response("left", page(&[(6, "l6")], true))?;
+    let response = pipeline.run()?;
+    assert_eq!(vec!["r5"], ids(&response));
+    assert_eq!(vec!["right"], requested_streams(&response));
+
+    pipeline.provide_response("right", page(&[], true))?;
+    let response = pipeline.run()?;
+    assert_eq!(vec!["l6"], ids(&response));
+    assert!(response.terminated);
+    assert!(response.requests.is_empty());
+
+    assert!(pipeline.run()?.terminated);
+    Ok(())
+}
+
+#[test]
+pub fn ties_prefer_earlier_streams() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = MergePipeline::new(["b", "a"], vec![SortOrder::Descending]);
+    pipeline.provide_response("a", page(&[(2, "a2"), (1, "a1")], true))?;
+    pipeline.provide_response("b", page(&[(2, "b2"), (1, "b1")], true))?;
+
+    let response = pipeline.run()?;
+    assert_eq!(vec!["b2", "a2", "b1", "a1"], ids(&response));
+    assert!(response.terminated);
+    Ok(())
+}
+
+#[test]
+pub fn merges_hierarchically() -> Result<(), Box<dyn std::error::Error>> {
+    let mut first =
+        MergePipeline::new(["s0", "s1"], vec![SortOrder::Ascending]).preserving_order_by_items();
+    first.provide_response("s0", page(&[(1, "a"), (5, "e")], true))?;
+    first.provide_response("s1", page(&[(3, "c")], true))?;
+
+    let mut second =
+        MergePipeline::new(["s2", "s3"], vec![SortOrder::Ascending]).preserving_order_by_items();
+    second.provide_response("s2", page(&[(2, "b")], true))?;
+    second.provide_response("s3", page(&[(4, "d"), (6, "f")], true))?;
+
+    let mut coordinator = MergePipeline::new(["first", "second"], vec![SortOrder::Ascending]);
+    coordinator.provide_response("first", first.run()?)?;
+    coordinator.provide_response("second", second.run()?)?;
+
+    let response = coordinator.run()?;
+    assert_eq!(vec!["a", "b", "c", "d", "e", "f"], ids(&response));
+    assert!(response.terminated);
+    Ok(())
+}
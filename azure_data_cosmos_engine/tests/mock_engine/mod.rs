@@ -8,7 +8,7 @@
 //! * If testing an ORDER BY query, the data in each partition is ALREADY sorted by the ORDER BY field(s).
 //! * Partitions are "ordered" by their ID (in Cosmos DB, physical partitions are ordered by the minimum logical partition key value covered by the physical partition).
 
-use std::{collections::BTreeMap, fmt::Debug};
+use std::{collections::BTreeMap, fmt::Debug, time::Duration};
 
 use azure_data_cosmos_engine::query::{
     DataRequest, PartitionKeyRange, QueryPipeline, QueryPlan, QueryResult,
@@ -46,12 +46,71 @@ impl Engine {
         plan: QueryPlan,
         request_page_size: usize,
     ) -> Result<Self, azure_data_cosmos_engine::Error> {
-        // Divide the EPK space evenly among the partitions we have
+        let pipeline = QueryPipeline::new(query, plan, Self::partitions(&container))?;
+        Ok(Engine {
+            container,
+            pipeline,
+            request_page_size,
+        })
+    }
+
+    /// Creates a new engine for a query executed without a query plan, see [`QueryPipeline::new_passthrough`].
+    #[allow(dead_code)] // Only used by some of the test binaries that include this module.
+    pub fn passthrough(
+        container: Container,
+        query: &str,
+        request_page_size: usize,
+    ) -> Result<Self, azure_data_cosmos_engine::Error> {
+        let pipeline = QueryPipeline::new_passthrough(query, Self::partitions(&container))?;
+        Ok(Engine {
+            container,
+            pipeline,
+            request_page_size,
+        })
+    }
+
+    /// Creates a new engine that resumes a query from the state captured by [`Engine::serialize_state`].
+    #[allow(dead_code)] // Only used by some of the test binaries that include this module.
+    pub fn resume(
+        container: Container,
+        query: &str,
+        plan: QueryPlan,
+        request_page_size: usize,
+        state: &str,
+    ) -> Result<Self, azure_data_cosmos_engine::Error> {
+        let pipeline = QueryPipeline::resume(query, plan, Self::partitions(&container), state)?;
+        Ok(Engine {
+            container,
+            pipeline,
+            request_page_size,
+        })
+    }
+
+    /// Captures the state of the query pipeline, see [`QueryPipeline::serialize_state`].
+    #[allow(dead_code)] // Only used by some of the test binaries that include this module.
+    pub fn serialize_state(&self) -> Result<String, azure_data_cosmos_engine::Error> {
+        self.pipeline.serialize_state()
+    }
+
+    /// Gets the query text the partitions are queried with, see [`QueryPipeline::query`].
+    #[allow(dead_code)] // Only used by some of the test binaries that include this module.
+    pub fn query(&self) -> Option<&str> {
+        self.pipeline.query()
+    }
+
+    /// Gets the partition key ranges the pipeline queries, see [`QueryPipeline::targeted_partitions`].
+    #[allow(dead_code)] // Only used by some of the test binaries that include this module.
+    pub fn targeted_partitions(&self) -> Vec<String> {
+        self.pipeline.targeted_partitions()
+    }
+
+    /// Divides the EPK space evenly among the partitions in the container.
+    fn partitions(container: &Container) -> Vec<PartitionKeyRange> {
         const MAX_EPK: u32 = 0xFFFF_FFFF;
         const MIN_EPK: u32 = 0x0000_0000;
         let epks_per_partition = (MAX_EPK - MIN_EPK) / (container.partitions.len() as u32);
 
-        let partitions = container
+        container
             .partitions
             .keys()
             .enumerate()
@@ -69,14 +128,8 @@ impl Engine {
                         )
                     },
                 )
-            });
-        let partitions = partitions.collect::<Vec<_>>();
-        let pipeline = QueryPipeline::new(query, plan, partitions)?;
-        Ok(Engine {
-            container,
-            pipeline,
-            request_page_size,
-        })
+            })
+            .collect()
     }
 
     /// Executes the query, returning the result in individual batches.
@@ -84,12 +137,48 @@ impl Engine {
     /// Each separate `Vec<T>` represents a single [`PipelineResponse`] received from the query pipeline.
     /// After each batch, the engine automatically fulfills any requests for additional data from the pipeline and moves to the next batch.
     pub fn execute(mut self) -> Result<Vec<EngineResult>, azure_data_cosmos_engine::Error> {
+        self.execute_turns(usize::MAX)
+    }
+
+    /// Executes at most `max_turns` turns of the query, returning the result of each turn.
+    ///
+    /// Any requests made by the last turn are fulfilled before returning, so the engine can be resumed by calling this again.
+    pub fn execute_turns(
+        &mut self,
+        max_turns: usize,
+    ) -> Result<Vec<EngineResult>, azure_data_cosmos_engine::Error> {
+        self.execute_turns_with(max_turns, |error| {
+            panic!("unexpected simulated error: {error:?}")
+        })
+        .map_err(|e| match e {
+            EngineError::Pipeline(e) => e,
+            EngineError::Aborted(_) => unreachable!("the error policy never aborts"),
+        })
+    }
+
+    /// Executes the query like [`Engine::execute`], calling `on_error` when a partition returns a [`SimulatedError`] instead of a page.
+    ///
+    /// If `on_error` decides to retry, the request is left unfulfilled, so the pipeline issues it again on the next turn.
+    /// If it decides to abort, the query is cancelled and [`EngineError::Aborted`] is returned.
+    #[allow(dead_code)] // Only used by some of the test binaries that include this module.
+    pub fn execute_with(
+        mut self,
+        on_error: impl FnMut(&SimulatedError) -> RetryDecision,
+    ) -> Result<Vec<EngineResult>, EngineError> {
+        self.execute_turns_with(usize::MAX, on_error)
+    }
+
+    fn execute_turns_with(
+        &mut self,
+        max_turns: usize,
+        mut on_error: impl FnMut(&SimulatedError) -> RetryDecision,
+    ) -> Result<Vec<EngineResult>, EngineError> {
         let _ = tracing_subscriber::fmt()
             .with_env_filter(EnvFilter::from_default_env())
             .try_init();
 
         let mut responses = Vec::new();
-        loop {
+        while responses.len() < max_turns {
             let result = self.pipeline.run()?;
 
             let result = EngineResult {
@@ -108,11 +197,20 @@ impl Engine {
             }
 
             for request in result.requests {
-                let page = self.container.get_data(
+                let page = match self.container.get_data(
                     &request.pkrange_id,
                     request.continuation.as_deref(),
                     self.request_page_size,
-                );
+                ) {
+                    Ok(page) => page,
+                    Err(error) => match on_error(&error) {
+                        RetryDecision::Retry => continue,
+                        RetryDecision::Abort => {
+                            self.pipeline.cancel();
+                            return Err(EngineError::Aborted(error));
+                        }
+                    },
+                };
                 // Serialize the QueryResult items to bytes
                 let json_bytes = serialize_query_results(page.items)?;
                 self.pipeline.provide_data(
@@ -120,6 +218,7 @@ impl Engine {
                     request.id,
                     &json_bytes,
                     page.continuation,
+                    None,
                 )?;
             }
         }
@@ -128,6 +227,63 @@ impl Engine {
     }
 }
 
+/// The reasons a simulated partition can fail a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)] // Only used by some of the test binaries that include this module.
+pub enum SimulatedErrorKind {
+    /// The request was throttled, like a `429 Too Many Requests` response.
+    Throttled,
+
+    /// The partition is temporarily unavailable, like a `503 Service Unavailable` response.
+    ServiceUnavailable,
+}
+
+/// An error returned by a simulated partition instead of a page, see [`Container::insert_error`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulatedError {
+    pub kind: SimulatedErrorKind,
+
+    /// How long the backend asked the client to wait before retrying, like the `x-ms-retry-after-ms` header.
+    pub retry_after: Option<Duration>,
+}
+
+/// Decides what [`Engine::execute_with`] does when a partition returns a [`SimulatedError`].
+#[allow(dead_code)] // Only used by some of the test binaries that include this module.
+pub enum RetryDecision {
+    /// Leave the request unfulfilled, so it's issued again on the next turn.
+    Retry,
+
+    /// Cancel the query and return the error.
+    Abort,
+}
+
+/// An error from executing a query with [`Engine::execute_with`].
+#[derive(Debug)]
+pub enum EngineError {
+    /// The pipeline returned an error.
+    Pipeline(azure_data_cosmos_engine::Error),
+
+    /// A partition returned a simulated error, and the error policy aborted the query.
+    Aborted(SimulatedError),
+}
+
+impl From<azure_data_cosmos_engine::Error> for EngineError {
+    fn from(error: azure_data_cosmos_engine::Error) -> Self {
+        Self::Pipeline(error)
+    }
+}
+
+impl std::fmt::Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pipeline(error) => write!(f, "{error}"),
+            Self::Aborted(error) => write!(f, "query aborted after simulated error: {error:?}"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
 /// Helper function to serialize QueryResult items back into the JSON format expected by the gateway
 fn serialize_query_results(
     results: Vec<QueryResult>,
@@ -183,18 +339,39 @@ impl Container {
             .extend(items);
     }
 
+    /// Makes the next `count` requests for the page starting at item `index` of a partition fail with `error`, before the page is returned.
+    ///
+    /// Use `usize::MAX` as the `count` for a partition that never recovers.
+    #[allow(dead_code)] // Only used by some of the test binaries that include this module.
+    pub fn insert_error(
+        &mut self,
+        partition_key: impl Into<String>,
+        index: usize,
+        error: SimulatedError,
+        count: usize,
+    ) {
+        let partition_key = partition_key.into();
+        self.partitions
+            .entry(partition_key)
+            .or_insert_with(Partition::new)
+            .errors
+            .insert(index, (error, count));
+    }
+
     pub fn get_data(
-        &self,
+        &mut self,
         partition_key: &str,
         continuation: Option<&str>,
         page_size: usize,
-    ) -> Page {
+    ) -> Result<Page, SimulatedError> {
         self.partitions
-            .get(partition_key)
+            .get_mut(partition_key)
             .map(|partition| partition.get_data(continuation, page_size))
-            .unwrap_or_else(|| Page {
-                items: Vec::new(),
-                continuation: None,
+            .unwrap_or_else(|| {
+                Ok(Page {
+                    items: Vec::new(),
+                    continuation: None,
+                })
             })
     }
 }
@@ -202,22 +379,39 @@ impl Container {
 /// Represents the sequence of pages that will be returned by a given partition.
 pub struct Partition {
     data: Vec<QueryResult>,
+
+    /// The errors to return for the page starting at each item index, and how many more times to return them.
+    errors: BTreeMap<usize, (SimulatedError, usize)>,
 }
 
 impl Partition {
     pub fn new() -> Self {
-        Partition { data: Vec::new() }
+        Partition {
+            data: Vec::new(),
+            errors: BTreeMap::new(),
+        }
     }
 
     pub fn extend(&mut self, items: impl IntoIterator<Item = QueryResult>) {
         self.data.extend(items)
     }
 
-    pub fn get_data(&self, continuation: Option<&str>, page_size: usize) -> Page {
+    pub fn get_data(
+        &mut self,
+        continuation: Option<&str>,
+        page_size: usize,
+    ) -> Result<Page, SimulatedError> {
         let index = continuation
             .map(|c| c.parse::<usize>().unwrap())
             .unwrap_or(0);
 
+        if let Some((error, count)) = self.errors.get_mut(&index) {
+            if *count > 0 {
+                *count -= 1;
+                return Err(error.clone());
+            }
+        }
+
         let items = self
             .data
             .iter()
@@ -234,9 +428,9 @@ impl Partition {
             None
         };
 
-        Page {
+        Ok(Page {
             items,
             continuation,
-        }
+        })
     }
 }
@@ -0,0 +1,157 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::query::{
+    PartitionKeyRange, PipelineOptions, QueryInfo, QueryPipeline, QueryPlan, SortOrder,
+};
+use pretty_assertions::assert_eq;
+
+const PARTITION_COUNT: usize = 4;
+const PAGES_PER_PARTITION: usize = 5;
+const PAGE_SIZE: usize = 20;
+
+fn create_pipeline(query_info: QueryInfo, options: PipelineOptions) -> QueryPipeline {
+    QueryPipeline::with_options(
+        "SELECT TOP 10 c.id FROM c ORDER BY VectorDistance(c.embedding, [0.1, 0.2])",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(query_info),
+            ..Default::default()
+        },
+        (0..PARTITION_COUNT).map(|i| {
+            PartitionKeyRange::new(
+                format!("partition{i}"),
+                format!("{i:02X}"),
+                format!("{:02X}", i + 1),
+            )
+        }),
+        options,
+    )
+    .unwrap()
+}
+
+fn vector_query(top: Option<u64>, offset: Option<u64>, limit: Option<u64>) -> QueryInfo {
+    QueryInfo {
+        top,
+        offset,
+        limit,
+        order_by: vec![SortOrder::Descending],
+        has_non_streaming_order_by: true,
+        ..Default::default()
+    }
+}
+
+/// Generates a distinct, pseudo-random similarity score for every item, so that the expected order is unambiguous.
+fn score(partition: usize, page: usize, index: usize) -> f64 {
+    let n = ((partition * PAGES_PER_PARTITION + page) * PAGE_SIZE + index) as u64;
+    let scrambled = n.wrapping_mul(2654435761) % 1_000_003;
+    scrambled as f64 / 1_000_003.0
+}
+
+fn page(partition: usize, page: usize) -> Vec<u8> {
+    let documents = (0..PAGE_SIZE)
+        .map(|i| {
+            let score = score(partition, page, i);
+            format!(
+                r#"{{"orderByItems":[{{"item":{score}}}],"payload":{{"id":"p{partition}-{page}-{i}","score":{score}}}}}"#
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes()
+}
+
+/// Runs the pipeline to completion, providing the pages for each turn's requests in reverse order.
+fn run(mut pipeline: QueryPipeline) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut items = Vec::new();
+    loop {
+        let response = pipeline.run()?;
+        items.extend(response.items.iter().map(|i| i.get().to_string()));
+        if response.terminated {
+            return Ok(items);
+        }
+
+        for request in response.requests.iter().rev() {
+            let partition = request.pkrange_id["partition".len()..].parse::<usize>()?;
+            let page_number = request
+                .continuation
+                .as_deref()
+                .map_or(Ok(0), |c| c["page".len()..].parse::<usize>())?;
+            let continuation =
+                (page_number + 1 < PAGES_PER_PARTITION).then(|| format!("page{}", page_number + 1));
+            pipeline.provide_data(
+                &request.pkrange_id,
+                request.id,
+                &page(partition, page_number),
+                continuation,
+                None,
+            )?;
+        }
+    }
+}
+
+#[test]
+pub fn top_matches_unbounded_results() -> Result<(), Box<dyn std::error::Error>> {
+    let unbounded = run(create_pipeline(
+        vector_query(None, None, None),
+        PipelineOptions::default(),
+    ))?;
+    assert_eq!(
+        PARTITION_COUNT * PAGES_PER_PARTITION * PAGE_SIZE,
+        unbounded.len()
+    );
+
+    for top in [1, 10, 25, PAGE_SIZE as u64 * 2] {
+        let bounded = run(create_pipeline(
+            vector_query(Some(top), None, None),
+            PipelineOptions::default(),
+        ))?;
+        assert_eq!(unbounded[..top as usize], bounded[..]);
+    }
+    Ok(())
+}
+
+#[test]
+pub fn offset_limit_matches_unbounded_results() -> Result<(), Box<dyn std::error::Error>> {
+    let unbounded = run(create_pipeline(
+        vector_query(None, None, None),
+        PipelineOptions::default(),
+    ))?;
+
+    let bounded = run(create_pipeline(
+        vector_query(None, Some(15), Some(10)),
+        PipelineOptions::default(),
+    ))?;
+    assert_eq!(unbounded[15..25], bounded[..]);
+    Ok(())
+}
+
+#[test]
+pub fn top_larger_than_results_yields_everything() -> Result<(), Box<dyn std::error::Error>> {
+    let unbounded = run(create_pipeline(
+        vector_query(None, None, None),
+        PipelineOptions::default(),
+    ))?;
+
+    let bounded = run(create_pipeline(
+        vector_query(Some(10_000), None, None),
+        PipelineOptions::default(),
+    ))?;
+    assert_eq!(unbounded, bounded);
+    Ok(())
+}
+
+#[test]
+pub fn top_only_counts_retained_items_against_buffer_limit(
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Every partition provides far more than 10 items, but only the best 10 are ever buffered.
+    let bounded = run(create_pipeline(
+        vector_query(Some(10), None, None),
+        PipelineOptions {
+            max_buffered_items: Some(10),
+            ..Default::default()
+        },
+    ))?;
+    assert_eq!(10, bounded.len());
+    Ok(())
+}
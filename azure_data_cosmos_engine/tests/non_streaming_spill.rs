@@ -0,0 +1,219 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::path::{Path, PathBuf};
+
+use azure_data_cosmos_engine::query::{
+    PartitionKeyRange, PipelineEvent, QueryInfo, QueryPipeline, QueryPlan, SortOrder,
+};
+use pretty_assertions::assert_eq;
+
+const PARTITION_COUNT: usize = 4;
+const PAGES_PER_PARTITION: usize = 5;
+const PAGE_SIZE: usize = 20;
+
+/// Creates an empty directory for a test's spill files.
+fn spill_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("cosmoscx-spill-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn file_count(dir: &Path) -> usize {
+    std::fs::read_dir(dir).unwrap().count()
+}
+
+fn create_pipeline() -> QueryPipeline {
+    QueryPipeline::new(
+        "SELECT * FROM c ORDER BY VectorDistance(c.embedding, [0.1, 0.2]), c.id DESC",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending, SortOrder::Descending],
+                has_non_streaming_order_by: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        (0..PARTITION_COUNT).map(|i| {
+            PartitionKeyRange::new(
+                format!("partition{i}"),
+                format!("{i:02X}"),
+                format!("{:02X}", i + 1),
+            )
+        }),
+    )
+    .unwrap()
+}
+
+/// Generates a page of items, whose scores repeat across partitions so that the second `ORDER BY` value is needed to break ties.
+///
+/// The payloads are spread over several lines, like the backend may return them.
+fn page(partition: usize, page: usize) -> Vec<u8> {
+    let documents = (0..PAGE_SIZE)
+        .map(|i| {
+            let n = (page * PAGE_SIZE + i) as u64;
+            let score = (n.wrapping_mul(2654435761) % 1_000) as f64 / 1_000.0;
+            let id = format!("p{partition}-{page}-{i}");
+            format!(
+                r#"{{"orderByItems":[{{"item":{score}}},{{"item":"{id}"}}],"payload":{{
+                    "id": "{id}",
+                    "score": {score}
+                }}}}"#
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes()
+}
+
+/// Provides a page for every request the pipeline makes, until it produces its first item.
+fn provide_all(pipeline: &mut QueryPipeline) -> Result<String, Box<dyn std::error::Error>> {
+    loop {
+        let requests = match pipeline.next_item()? {
+            PipelineEvent::Item(item) => return Ok(item.get().to_string()),
+            PipelineEvent::NeedsData(requests) => requests,
+            PipelineEvent::Done => return Err("pipeline completed without any items".into()),
+        };
+        for request in requests.iter().rev() {
+            let partition = request.pkrange_id["partition".len()..].parse::<usize>()?;
+            let page_number = request
+                .continuation
+                .as_deref()
+                .map_or(Ok(0), |c| c["page".len()..].parse::<usize>())?;
+            let continuation =
+                (page_number + 1 < PAGES_PER_PARTITION).then(|| format!("page{}", page_number + 1));
+            pipeline.provide_data(
+                &request.pkrange_id,
+                request.id,
+                &page(partition, page_number),
+                continuation,
+                None,
+            )?;
+        }
+    }
+}
+
+/// Produces the remaining items, after the first one returned by [`provide_all`].
+fn drain(
+    pipeline: &mut QueryPipeline,
+    first: String,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut items = vec![first];
+    loop {
+        let response = pipeline.run()?;
+        items.extend(response.items.iter().map(|i| i.get().to_string()));
+        if response.terminated {
+            return Ok(items);
+        }
+    }
+}
+
+#[test]
+pub fn spilled_results_match_in_memory_results() -> Result<(), Box<dyn std::error::Error>> {
+    let mut in_memory = create_pipeline();
+    let first = provide_all(&mut in_memory)?;
+    let expected = drain(&mut in_memory, first)?;
+    assert_eq!(
+        PARTITION_COUNT * PAGES_PER_PARTITION * PAGE_SIZE,
+        expected.len()
+    );
+
+    // A tiny threshold forces every page to be spilled to its own run.
+    let dir = spill_dir("match");
+    let mut spilling = create_pipeline().with_spill(64, &dir);
+    let first = provide_all(&mut spilling)?;
+    assert_eq!(PARTITION_COUNT * PAGES_PER_PARTITION, file_count(&dir));
+    let actual = drain(&mut spilling, first)?;
+    assert_eq!(expected, actual);
+
+    // The runs are deleted once the pipeline is dropped.
+    drop(spilling);
+    assert_eq!(0, file_count(&dir));
+
+    // With a larger threshold, several pages share a run, and the items left in memory at the end are merged too.
+    let mut spilling = create_pipeline().with_spill(5_000, &dir);
+    let first = provide_all(&mut spilling)?;
+    // Three pages fit in each of the six runs, and the last two pages are spilled to a seventh when the merge starts.
+    assert_eq!(7, file_count(&dir));
+    assert_eq!(expected, drain(&mut spilling, first)?);
+    drop(spilling);
+    assert_eq!(0, file_count(&dir));
+    std::fs::remove_dir(&dir)?;
+    Ok(())
+}
+
+#[test]
+pub fn results_below_threshold_are_not_spilled() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = spill_dir("below-threshold");
+    let mut pipeline = create_pipeline().with_spill(usize::MAX, &dir);
+    let first = provide_all(&mut pipeline)?;
+    assert_eq!(0, file_count(&dir));
+    assert_eq!(
+        PARTITION_COUNT * PAGES_PER_PARTITION * PAGE_SIZE,
+        drain(&mut pipeline, first)?.len()
+    );
+    std::fs::remove_dir(&dir)?;
+    Ok(())
+}
+
+#[test]
+pub fn cancel_deletes_spilled_runs() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = spill_dir("cancel");
+    let mut pipeline = create_pipeline().with_spill(64, &dir);
+    let requests = pipeline.run()?.requests;
+    pipeline.provide_data(
+        &requests[0].pkrange_id,
+        requests[0].id,
+        &page(0, 0),
+        Some("page1".into()),
+        None,
+    )?;
+    assert_eq!(1, file_count(&dir));
+
+    pipeline.cancel();
+    assert_eq!(0, file_count(&dir));
+    std::fs::remove_dir(&dir)?;
+    Ok(())
+}
+
+#[test]
+pub fn spilled_results_keep_adjacent_floats_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    // The scores differ only in their last bit, and the IDs sort the other way, so the order changes if a score is rounded when it's parsed.
+    let low = 0.9075896783935383_f64;
+    let high = low.next_up();
+    let page = |score: f64, id: &str| {
+        format!(
+            r#"{{"Documents":[{{"orderByItems":[{{"item":{score:?}}},{{"item":"{id}"}}],"payload":{{"id":"{id}"}}}}]}}"#
+        )
+        .into_bytes()
+    };
+    let run = |mut pipeline: QueryPipeline| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        for request in pipeline.run()?.requests {
+            let data = match &*request.pkrange_id {
+                "partition0" => page(low, "a"),
+                "partition1" => page(high, "b"),
+                _ => br#"{"Documents":[]}"#.to_vec(),
+            };
+            pipeline.provide_data(&request.pkrange_id, request.id, &data, None, None)?;
+        }
+        let mut items = Vec::new();
+        loop {
+            let response = pipeline.run()?;
+            items.extend(response.items.iter().map(|i| i.get().to_string()));
+            if response.terminated {
+                return Ok(items);
+            }
+        }
+    };
+
+    let expected = run(create_pipeline())?;
+    assert_eq!(vec![r#"{"id":"a"}"#, r#"{"id":"b"}"#], expected);
+
+    let dir = spill_dir("adjacent-floats");
+    assert_eq!(expected, run(create_pipeline().with_spill(1, &dir))?);
+    std::fs::remove_dir(&dir)?;
+    Ok(())
+}
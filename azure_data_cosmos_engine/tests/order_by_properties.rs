@@ -0,0 +1,276 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::cmp::Ordering;
+
+use azure_data_cosmos_engine::query::{
+    PartitionKeyRange, QueryClauseItem, QueryInfo, QueryPipeline, QueryPlan, SortOrder,
+};
+use proptest::prelude::*;
+use serde_json::json;
+
+/// Keeps the suite fast enough to run with every `cargo test`.
+const CASES: u32 = 256;
+
+/// Numbers near the limits of `f64` precision, as both integers and floats, since that's where integer and float comparisons disagree.
+fn edge_numbers() -> Vec<serde_json::Value> {
+    vec![
+        json!(0),
+        json!(0.0),
+        json!(-0.0),
+        json!(1),
+        json!(1.0),
+        json!(-1),
+        json!(f64::MIN_POSITIVE),
+        json!(f64::MAX),
+        json!(f64::MIN),
+        json!(9007199254740991_i64),
+        json!(9007199254740992_i64),
+        json!(9007199254740993_i64),
+        json!(9007199254740992.0),
+        json!(9007199254740994.0),
+        json!(i64::MAX),
+        json!(i64::MIN),
+        json!(9223372036854775808_u64),
+        json!(u64::MAX),
+        json!(9223372036854775808.0),
+        json!(18446744073709551616.0),
+    ]
+}
+
+/// Generates `ORDER BY` values of every type, including undefined, with enough repeated values that ties are common.
+///
+/// Arrays and objects nest the other values, using only a few keys, so objects often share keys, or have the properties of another object as a prefix.
+/// Floats don't always survive a round trip through JSON exactly, so the values are round tripped first, to match the values the pipeline parses.
+fn clause_item() -> impl Strategy<Value = QueryClauseItem> {
+    let primitive = prop_oneof![
+        Just(serde_json::Value::Null),
+        any::<bool>().prop_map(|b| json!(b)),
+        (-3_i64..3).prop_map(|i| json!(i)),
+        any::<i64>().prop_map(|i| json!(i)),
+        any::<u64>().prop_map(|u| json!(u)),
+        // JSON can't represent NaN or infinity, so the backend never returns them.
+        (prop::num::f64::NORMAL | prop::num::f64::SUBNORMAL | prop::num::f64::ZERO)
+            .prop_map(|f| json!(f)),
+        prop::sample::select(edge_numbers()),
+        prop::sample::select(vec!["", "a", "B", "b", "ab", "\u{e9}", "\u{1f600}"])
+            .prop_map(|s| json!(s)),
+        "\\PC{0,4}".prop_map(|s| json!(s)),
+    ];
+    let value = primitive.prop_recursive(2, 16, 4, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..4).prop_map(serde_json::Value::Array),
+            prop::collection::btree_map(
+                prop::sample::select(vec!["a", "b", "c"]).prop_map(String::from),
+                inner,
+                0..4
+            )
+            .prop_map(|properties| serde_json::Value::Object(properties.into_iter().collect())),
+        ]
+    });
+    prop_oneof![
+        1 => Just(QueryClauseItem::default()),
+        9 => value.prop_map(QueryClauseItem::from_value),
+    ]
+    .prop_map(|item| serde_json::from_str(&serde_json::to_string(&item).unwrap()).unwrap())
+}
+
+fn compare(left: &QueryClauseItem, right: &QueryClauseItem) -> Ordering {
+    left.compare(right)
+        .expect("finite values should always compare")
+}
+
+/// Checks the properties of a total order that involve up to three items.
+fn check_total_order(
+    a: &QueryClauseItem,
+    b: &QueryClauseItem,
+    c: &QueryClauseItem,
+) -> Result<(), TestCaseError> {
+    let (ab, bc, ac) = (compare(a, b), compare(b, c), compare(a, c));
+    prop_assert_eq!(
+        Ordering::Equal,
+        compare(a, a),
+        "{:?} is not equal to itself",
+        a
+    );
+    prop_assert_eq!(ab, compare(b, a).reverse(), "comparing {:?} and {:?}", a, b);
+    if ab != Ordering::Greater && bc != Ordering::Greater {
+        prop_assert_ne!(Ordering::Greater, ac, "{:?} <= {:?} <= {:?}", a, b, c);
+    }
+    if ab == Ordering::Equal && bc == Ordering::Equal {
+        prop_assert_eq!(Ordering::Equal, ac, "{:?} == {:?} == {:?}", a, b, c);
+    }
+    Ok(())
+}
+
+/// Compares items in the order they should be produced for `order`.
+fn compare_in(order: SortOrder, left: &QueryClauseItem, right: &QueryClauseItem) -> Ordering {
+    match order {
+        SortOrder::Ascending => compare(left, right),
+        SortOrder::Descending => compare(right, left),
+    }
+}
+
+/// Generates the items in each partition, and the size of the pages each partition returns them in.
+fn partitions() -> impl Strategy<Value = Vec<(Vec<QueryClauseItem>, usize)>> {
+    prop::collection::vec(
+        (prop::collection::vec(clause_item(), 0..20), 1_usize..6),
+        1..5,
+    )
+}
+
+fn sort_order() -> impl Strategy<Value = SortOrder> {
+    prop_oneof![Just(SortOrder::Ascending), Just(SortOrder::Descending)]
+}
+
+/// Runs an `ORDER BY` query over the partitions, returning the position of every item produced, as `(partition, index)`, in order.
+fn run_query(
+    partitions: &[(Vec<QueryClauseItem>, usize)],
+    order: SortOrder,
+    non_streaming: bool,
+) -> Vec<(usize, usize)> {
+    let mut pipeline = QueryPipeline::new(
+        "SELECT * FROM c ORDER BY c.x",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![order],
+                has_non_streaming_order_by: non_streaming,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        (0..partitions.len()).map(|i| {
+            PartitionKeyRange::new(
+                format!("partition{i}"),
+                format!("{i:02X}"),
+                format!("{:02X}", i + 1),
+            )
+        }),
+    )
+    .unwrap();
+
+    let items = pipeline
+        .drain(|request| {
+            let partition = request.pkrange_id["partition".len()..]
+                .parse::<usize>()
+                .unwrap();
+            let page = request
+                .continuation
+                .as_deref()
+                .map_or(0, |c| c["page".len()..].parse::<usize>().unwrap());
+            let (items, page_size) = &partitions[partition];
+            let start = page * page_size;
+            let end = (start + page_size).min(items.len());
+            let documents = (start..end)
+                .map(|i| {
+                    json!({
+                        "orderByItems": [&items[i]],
+                        "payload": {"partition": partition, "index": i},
+                    })
+                })
+                .collect::<Vec<_>>();
+            let continuation = (end < items.len()).then(|| format!("page{}", page + 1));
+            Ok((
+                serde_json::to_vec(&json!({ "Documents": documents })).unwrap(),
+                continuation,
+            ))
+        })
+        .unwrap();
+
+    items
+        .iter()
+        .map(|item| serde_json::from_str(item.get()).unwrap())
+        .map(|payload: serde_json::Value| {
+            (
+                payload["partition"].as_u64().unwrap() as usize,
+                payload["index"].as_u64().unwrap() as usize,
+            )
+        })
+        .collect()
+}
+
+/// Checks that the query produced every input item exactly once, in sorted order.
+fn assert_merged(
+    partitions: &[(Vec<QueryClauseItem>, usize)],
+    order: SortOrder,
+    results: &[(usize, usize)],
+) -> Result<(), TestCaseError> {
+    let item = |(partition, index): (usize, usize)| &partitions[partition].0[index];
+    for pair in results.windows(2) {
+        prop_assert_ne!(
+            Ordering::Greater,
+            compare_in(order, item(pair[0]), item(pair[1])),
+            "{:?} was produced before {:?}",
+            item(pair[0]),
+            item(pair[1])
+        );
+    }
+
+    let mut expected = partitions
+        .iter()
+        .enumerate()
+        .flat_map(|(p, (items, _))| (0..items.len()).map(move |i| (p, i)))
+        .collect::<Vec<_>>();
+    let mut actual = results.to_vec();
+    expected.sort();
+    actual.sort();
+    prop_assert_eq!(expected, actual);
+    Ok(())
+}
+
+#[test]
+pub fn edge_numbers_are_totally_ordered() {
+    let numbers = edge_numbers()
+        .into_iter()
+        .map(QueryClauseItem::from_value)
+        .collect::<Vec<_>>();
+    for a in &numbers {
+        for b in &numbers {
+            for c in &numbers {
+                check_total_order(a, b, c).unwrap();
+            }
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+    #[test]
+    fn compare_is_a_total_order(a in clause_item(), b in clause_item(), c in clause_item()) {
+        check_total_order(&a, &b, &c)?;
+    }
+
+    #[test]
+    fn sorted_items_are_ordered_pairwise(mut items in prop::collection::vec(clause_item(), 0..30)) {
+        items.sort_by(compare);
+        for (i, left) in items.iter().enumerate() {
+            for right in &items[i + 1..] {
+                prop_assert_ne!(Ordering::Greater, compare(left, right), "{:?} sorted before {:?}", left, right);
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_merge_of_sorted_partitions_is_sorted(
+        mut partitions in partitions(),
+        order in sort_order(),
+    ) {
+        // The streaming producer relies on each partition returning its items already sorted, as the backend does.
+        for (items, _) in &mut partitions {
+            items.sort_by(|a, b| compare_in(order, a, b));
+        }
+        let results = run_query(&partitions, order, false);
+        assert_merged(&partitions, order, &results)?;
+    }
+
+    #[test]
+    fn non_streaming_merge_of_unsorted_partitions_is_sorted(
+        partitions in partitions(),
+        order in sort_order(),
+    ) {
+        let results = run_query(&partitions, order, true);
+        assert_merged(&partitions, order, &results)?;
+    }
+}
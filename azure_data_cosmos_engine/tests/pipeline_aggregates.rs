@@ -0,0 +1,290 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::{BTreeMap, HashMap};
+
+use azure_data_cosmos_engine::{
+    query::{QueryClauseItem, QueryInfo, QueryPlan, QueryResult, SortOrder},
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+use mock_engine::{Container, Engine};
+use serde_json::json;
+
+mod mock_engine;
+
+fn named_aggregates(values: Vec<(&str, serde_json::Value)>) -> QueryResult {
+    QueryResult::Aggregates(
+        values
+            .into_iter()
+            .map(|(alias, value)| {
+                (
+                    alias.to_string(),
+                    serde_json::from_value::<QueryClauseItem>(value).unwrap(),
+                )
+            })
+            .collect::<BTreeMap<_, _>>(),
+    )
+}
+
+fn non_value_aggregate_plan(aliases: &[(&str, &str)]) -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(QueryInfo {
+            group_by_aliases: aliases.iter().map(|(alias, _)| alias.to_string()).collect(),
+            group_by_alias_to_aggregate_type: aliases
+                .iter()
+                .map(|(alias, name)| (alias.to_string(), name.to_string()))
+                .collect::<HashMap<_, _>>(),
+            has_select_value: false,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn run_to_completion(engine: Engine) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    Ok(engine
+        .execute()?
+        .into_iter()
+        .flat_map(|r| r.items)
+        .collect())
+}
+
+#[test]
+pub fn value_aggregate() -> Result<(), Box<dyn std::error::Error>> {
+    let mut container = Container::new();
+    container.insert(
+        "partition0",
+        vec![QueryResult::ValueAggregates(vec![
+            QueryClauseItem::from_value(json!(3)),
+        ])],
+    );
+    container.insert(
+        "partition1",
+        vec![QueryResult::ValueAggregates(vec![
+            QueryClauseItem::from_value(json!(0)),
+        ])],
+    );
+
+    let engine = Engine::new(
+        container,
+        "SELECT VALUE COUNT(1) FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                aggregates: vec!["Count".to_string()],
+                has_select_value: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        10,
+    )?;
+
+    assert_eq!(vec![json!(3)], run_to_completion(engine)?);
+    Ok(())
+}
+
+#[test]
+pub fn single_named_aggregate() -> Result<(), Box<dyn std::error::Error>> {
+    let mut container = Container::new();
+    container.insert(
+        "partition0",
+        vec![named_aggregates(vec![("total", json!({"item": 3}))])],
+    );
+    container.insert(
+        "partition1",
+        vec![named_aggregates(vec![("total", json!({"item": 4}))])],
+    );
+
+    let engine = Engine::new(
+        container,
+        "SELECT COUNT(1) AS total FROM c",
+        non_value_aggregate_plan(&[("total", "Count")]),
+        10,
+    )?;
+
+    assert_eq!(vec![json!({"total": 7})], run_to_completion(engine)?);
+    Ok(())
+}
+
+#[test]
+pub fn multiple_named_aggregates() -> Result<(), Box<dyn std::error::Error>> {
+    let mut container = Container::new();
+    container.insert(
+        "partition0",
+        vec![named_aggregates(vec![
+            ("total", json!({"item": 3})),
+            ("maxPrice", json!({"item": 10.5})),
+            ("sumPrice", json!({"item": 20})),
+        ])],
+    );
+    // An empty partition produces no rows at all.
+    container.insert("partition1", vec![]);
+    container.insert(
+        "partition2",
+        vec![named_aggregates(vec![
+            ("total", json!({"item": 2})),
+            ("maxPrice", json!({"item": 42})),
+            ("sumPrice", json!({"item": 1.5})),
+        ])],
+    );
+
+    let engine = Engine::new(
+        container,
+        "SELECT COUNT(1) AS total, MAX(c.price) AS maxPrice, SUM(c.price) AS sumPrice FROM c",
+        non_value_aggregate_plan(&[("total", "Count"), ("maxPrice", "Max"), ("sumPrice", "Sum")]),
+        10,
+    )?;
+
+    assert_eq!(
+        vec![json!({"total": 5, "maxPrice": 42, "sumPrice": 21.5})],
+        run_to_completion(engine)?
+    );
+    Ok(())
+}
+
+#[test]
+pub fn named_aggregates_omit_undefined_values() -> Result<(), Box<dyn std::error::Error>> {
+    let mut container = Container::new();
+    container.insert(
+        "partition0",
+        vec![named_aggregates(vec![("total", json!({"item": 0}))])],
+    );
+    container.insert("partition1", vec![]);
+
+    let engine = Engine::new(
+        container,
+        "SELECT COUNT(1) AS total, MAX(c.price) AS maxPrice FROM c WHERE false",
+        non_value_aggregate_plan(&[("total", "Count"), ("maxPrice", "Max")]),
+        10,
+    )?;
+
+    assert_eq!(vec![json!({"total": 0})], run_to_completion(engine)?);
+    Ok(())
+}
+
+/// Generates the ORDER BY rows a partition returns for an aggregate query that is also ordered, with the aggregates in the payload.
+fn order_by_aggregates(
+    order_by_item: serde_json::Value,
+    payload: serde_json::Value,
+) -> QueryResult {
+    QueryResult::OrderBy {
+        order_by_items: vec![QueryClauseItem::from_value(order_by_item)],
+        payload: serde_json::value::to_raw_value(&payload).unwrap(),
+        rid: None,
+    }
+}
+
+fn ordered_value_aggregate_plan(aggregate: &str, order: SortOrder) -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(QueryInfo {
+            aggregates: vec![aggregate.to_string()],
+            has_select_value: true,
+            order_by: vec![order],
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[test]
+pub fn value_aggregates_with_order_by() -> Result<(), Box<dyn std::error::Error>> {
+    for (aggregate, order, expected) in [
+        ("Max", SortOrder::Ascending, json!(42.5)),
+        ("Max", SortOrder::Descending, json!(42.5)),
+        ("Min", SortOrder::Ascending, json!(-3)),
+        ("Min", SortOrder::Descending, json!(-3)),
+    ] {
+        let mut container = Container::new();
+        container.insert(
+            "partition0",
+            vec![order_by_aggregates(json!(10), json!([{"item": 10}]))],
+        );
+        container.insert(
+            "partition1",
+            vec![order_by_aggregates(json!(42.5), json!([{"item": 42.5}]))],
+        );
+        container.insert(
+            "partition2",
+            vec![order_by_aggregates(json!(-3), json!([{"item": -3}]))],
+        );
+
+        let engine = Engine::new(
+            container,
+            &format!(
+                "SELECT VALUE {}(c.price) FROM c ORDER BY c.price",
+                aggregate.to_uppercase()
+            ),
+            ordered_value_aggregate_plan(aggregate, order),
+            10,
+        )?;
+
+        assert_eq!(vec![expected], run_to_completion(engine)?);
+    }
+    Ok(())
+}
+
+#[test]
+pub fn named_aggregates_with_order_by() -> Result<(), Box<dyn std::error::Error>> {
+    let mut container = Container::new();
+    container.insert(
+        "partition0",
+        vec![order_by_aggregates(
+            json!(10),
+            json!({"payload": {"maxPrice": {"item": 10}, "minPrice": {"item": 1}}}),
+        )],
+    );
+    // A partition with no items doesn't define its aggregates.
+    container.insert(
+        "partition1",
+        vec![order_by_aggregates(json!(null), json!({"payload": {}}))],
+    );
+    container.insert(
+        "partition2",
+        vec![order_by_aggregates(
+            json!(42),
+            json!({"maxPrice": {"item": 42}, "minPrice": {"item": 7}}),
+        )],
+    );
+
+    let mut plan = non_value_aggregate_plan(&[("maxPrice", "Max"), ("minPrice", "Min")]);
+    plan.query_info.as_mut().unwrap().order_by = vec![SortOrder::Descending];
+    let engine = Engine::new(
+        container,
+        "SELECT MAX(c.price) AS maxPrice, MIN(c.price) AS minPrice FROM c ORDER BY c.price DESC",
+        plan,
+        10,
+    )?;
+
+    assert_eq!(
+        vec![json!({"maxPrice": 42, "minPrice": 1})],
+        run_to_completion(engine)?
+    );
+    Ok(())
+}
+
+#[test]
+pub fn order_by_rows_without_aggregates_are_rejected() {
+    let mut container = Container::new();
+    container.insert(
+        "partition0",
+        vec![order_by_aggregates(
+            json!(10),
+            json!({"id": "1", "price": 10}),
+        )],
+    );
+
+    let err = Engine::new(
+        container,
+        "SELECT VALUE MAX(c.price) FROM c ORDER BY c.price",
+        ordered_value_aggregate_plan("Max", SortOrder::Ascending),
+        10,
+    )
+    .and_then(|engine| engine.execute())
+    .unwrap_err();
+    assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
+}
@@ -0,0 +1,141 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{PartitionKeyRange, PipelineOptions, QueryInfo, QueryPipeline, QueryPlan, SortOrder},
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+const QUERY: &str = "SELECT * FROM c ORDER BY c.id";
+
+fn plan() -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn non_streaming_plan() -> QueryPlan {
+    QueryPlan {
+        query_info: Some(QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            has_non_streaming_order_by: true,
+            ..Default::default()
+        }),
+        ..plan()
+    }
+}
+
+fn pkranges() -> Vec<PartitionKeyRange> {
+    vec![
+        PartitionKeyRange::new("partition0", "", "80"),
+        PartitionKeyRange::new("partition1", "80", "FF"),
+    ]
+}
+
+fn order_by_page(values: &[u32]) -> Vec<u8> {
+    let documents = values
+        .iter()
+        .map(|v| format!(r#"{{"orderByItems":[{{"item":{v}}}],"payload":{v}}}"#))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes()
+}
+
+/// Runs the pipeline to completion, recording the requests and items of every turn.
+fn run(mut pipeline: QueryPipeline) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut turns = Vec::new();
+    loop {
+        let response = pipeline.run()?;
+        turns.push(format!(
+            "items: {:?}, requests: {:?}",
+            response.items.iter().map(|i| i.get()).collect::<Vec<_>>(),
+            response.requests
+        ));
+        if response.terminated {
+            return Ok(turns);
+        }
+
+        for request in response.requests {
+            let (values, continuation) = match (request.pkrange_id.as_ref(), &request.continuation)
+            {
+                ("partition0", None) => (vec![1, 4], Some("page1".to_string())),
+                ("partition0", Some(_)) => (vec![5], None),
+                (_, _) => (vec![2, 3, 6], None),
+            };
+            pipeline.provide_data(
+                &request.pkrange_id,
+                request.id,
+                &order_by_page(&values),
+                continuation,
+                None,
+            )?;
+        }
+    }
+}
+
+#[test]
+pub fn builder_matches_new() -> Result<(), Box<dyn std::error::Error>> {
+    let from_new = QueryPipeline::new(QUERY, plan(), pkranges())?;
+    let from_builder = QueryPipeline::builder()
+        .query(QUERY)
+        .plan(plan())
+        .pkranges(pkranges())
+        .build()?;
+    assert_eq!(from_new.query(), from_builder.query());
+
+    let expected = run(from_new)?;
+    assert!(expected.last().unwrap().contains(r#"["5", "6"]"#));
+    assert_eq!(expected, run(from_builder)?);
+    Ok(())
+}
+
+#[test]
+pub fn builder_max_buffered_items_matches_options() -> Result<(), Box<dyn std::error::Error>> {
+    for max_buffered_items in [6, 2] {
+        let from_options = QueryPipeline::with_options(
+            QUERY,
+            non_streaming_plan(),
+            pkranges(),
+            PipelineOptions {
+                max_buffered_items: Some(max_buffered_items),
+                ..Default::default()
+            },
+        )?;
+        let from_builder = QueryPipeline::builder()
+            .query(QUERY)
+            .plan(non_streaming_plan())
+            .pkranges(pkranges())
+            .max_buffered_items(max_buffered_items)
+            .build()?;
+
+        match (run(from_options), run(from_builder)) {
+            (Ok(expected), Ok(actual)) => assert_eq!(expected, actual),
+            (Err(expected), Err(actual)) => assert_eq!(expected.to_string(), actual.to_string()),
+            (expected, actual) => panic!("expected {expected:?}, got {actual:?}"),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+pub fn builder_requires_query_and_plan() {
+    let err = QueryPipeline::builder()
+        .plan(plan())
+        .pkranges(pkranges())
+        .build()
+        .unwrap_err();
+    assert_eq!(ErrorKind::ArgumentNull, err.kind());
+
+    let err = QueryPipeline::builder()
+        .query(QUERY)
+        .pkranges(pkranges())
+        .build()
+        .unwrap_err();
+    assert_eq!(ErrorKind::ArgumentNull, err.kind());
+}
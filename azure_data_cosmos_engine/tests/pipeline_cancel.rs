@@ -0,0 +1,322 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::path::{Path, PathBuf};
+
+use azure_data_cosmos_engine::{
+    query::{
+        CancellationToken, PartitionKeyRange, PipelineEvent, QueryInfo, QueryPipeline, QueryPlan,
+        SortOrder,
+    },
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+const PARTITION_COUNT: usize = 8;
+const PAGES_PER_PARTITION: usize = 10;
+const PAGE_SIZE: usize = 100;
+
+fn plan(query_info: QueryInfo) -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(query_info),
+        ..Default::default()
+    }
+}
+
+fn pkranges() -> Vec<PartitionKeyRange> {
+    vec![
+        PartitionKeyRange::new("partition0", "", "80"),
+        PartitionKeyRange::new("partition1", "80", "FF"),
+    ]
+}
+
+fn create_pipeline(query_info: QueryInfo) -> QueryPipeline {
+    QueryPipeline::new("SELECT * FROM c", plan(query_info), pkranges()).unwrap()
+}
+
+fn order_by_page(values: &[u32]) -> Vec<u8> {
+    let documents = values
+        .iter()
+        .map(|v| format!(r#"{{"orderByItems":[{{"item":{v}}}],"payload":{v}}}"#))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes()
+}
+
+#[test]
+pub fn cancel_mid_stream_stops_items_and_requests() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo {
+        order_by: vec![SortOrder::Ascending],
+        ..Default::default()
+    });
+
+    let requests = pipeline.run()?.requests;
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        &order_by_page(&[1, 3]),
+        Some("p0-page1".into()),
+        None,
+    )?;
+    pipeline.provide_data(
+        "partition1",
+        requests[1].id,
+        &order_by_page(&[2, 4, 6]),
+        Some("p1-page1".into()),
+        Some(1.5),
+    )?;
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["1", "2", "3"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert!(!response.terminated);
+    let outstanding = response.requests;
+    assert_eq!(2, outstanding.len());
+
+    // Items 4 and 6 are still buffered, but they're never yielded.
+    pipeline.cancel();
+    assert!(pipeline.complete());
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert!(response.requests.is_empty());
+    assert!(response.terminated);
+    assert!(matches!(pipeline.next_item()?, PipelineEvent::Done));
+
+    // Data for a request that was outstanding is discarded, but its charge is still counted.
+    pipeline.provide_data(
+        "partition0",
+        outstanding[0].id,
+        &order_by_page(&[5]),
+        None,
+        Some(2.0),
+    )?;
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert!(response.requests.is_empty());
+    assert!(response.terminated);
+    assert_eq!(3.5, response.cumulative_request_charge);
+    Ok(())
+}
+
+#[test]
+pub fn cancel_before_any_data() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo::default());
+    pipeline.cancel();
+
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert!(response.requests.is_empty());
+    assert!(response.terminated);
+    Ok(())
+}
+
+#[test]
+pub fn cancelled_pipeline_resumes_as_terminated() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo::default());
+    let requests = pipeline.run()?.requests;
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        br#"{"Documents": [1, 2]}"#,
+        Some("page1".into()),
+        None,
+    )?;
+    pipeline.cancel();
+
+    let state = pipeline.serialize_state()?;
+    let mut resumed = QueryPipeline::resume(
+        "SELECT * FROM c",
+        plan(QueryInfo::default()),
+        pkranges(),
+        &state,
+    )?;
+    let response = resumed.run()?;
+    assert!(response.items.is_empty());
+    assert!(response.requests.is_empty());
+    assert!(response.terminated);
+    Ok(())
+}
+
+/// Creates an empty directory for a test's spill files.
+fn spill_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "cosmoscx-cancel-test-{}-{name}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn file_count(dir: &Path) -> usize {
+    std::fs::read_dir(dir).unwrap().count()
+}
+
+fn create_merge_pipeline() -> QueryPipeline {
+    QueryPipeline::new(
+        "SELECT * FROM c ORDER BY c.score",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending],
+                has_non_streaming_order_by: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        (0..PARTITION_COUNT).map(|i| {
+            PartitionKeyRange::new(
+                format!("partition{i}"),
+                format!("{i:02X}"),
+                format!("{:02X}", i + 1),
+            )
+        }),
+    )
+    .unwrap()
+}
+
+fn merge_page(partition: usize, page: usize) -> Vec<u8> {
+    let documents = (0..PAGE_SIZE)
+        .map(|i| {
+            let n = ((partition * PAGES_PER_PARTITION + page) * PAGE_SIZE + i) as u64;
+            let score = n.wrapping_mul(2654435761) % 100_000;
+            format!(r#"{{"orderByItems":[{{"item":{score}}}],"payload":{{"id":"{n}","score":{score}}}}}"#)
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes()
+}
+
+/// Provides every page of every partition, and pulls the first few items, so the merge of the whole dataset is under way.
+fn start_merge(pipeline: &mut QueryPipeline) -> Result<(), Box<dyn std::error::Error>> {
+    let mut items = 0;
+    while items < 10 {
+        let requests = match pipeline.next_item()? {
+            PipelineEvent::Item(_) => {
+                items += 1;
+                continue;
+            }
+            PipelineEvent::NeedsData(requests) => requests,
+            PipelineEvent::Done => return Err("pipeline completed without enough items".into()),
+        };
+        for request in requests {
+            let partition = request.pkrange_id["partition".len()..].parse::<usize>()?;
+            let page_number = request
+                .continuation
+                .as_deref()
+                .map_or(Ok(0), |c| c["page".len()..].parse::<usize>())?;
+            let continuation =
+                (page_number + 1 < PAGES_PER_PARTITION).then(|| format!("page{}", page_number + 1));
+            pipeline.provide_data(
+                &request.pkrange_id,
+                request.id,
+                &merge_page(partition, page_number),
+                continuation,
+                None,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a cancelled pipeline is left terminated, rather than corrupted, for every call that follows.
+fn assert_cancelled(pipeline: &mut QueryPipeline) -> Result<(), Box<dyn std::error::Error>> {
+    assert!(pipeline.complete());
+    for _ in 0..2 {
+        let response = pipeline.run()?;
+        assert!(response.items.is_empty());
+        assert!(response.requests.is_empty());
+        assert!(response.terminated);
+    }
+    assert!(matches!(pipeline.next_item()?, PipelineEvent::Done));
+
+    // Late data is discarded, but its charge is still counted.
+    pipeline.provide_data("partition0", 0, &merge_page(0, 0), None, Some(1.5))?;
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert_eq!(1.5, response.cumulative_request_charge);
+    Ok(())
+}
+
+#[test]
+pub fn cancel_from_another_thread_stops_merge() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = spill_dir("merge");
+    let mut pipeline = create_merge_pipeline().with_spill(50_000, &dir);
+    start_merge(&mut pipeline)?;
+    assert!(file_count(&dir) > 0);
+
+    let token = pipeline.cancellation_token();
+    std::thread::spawn(move || token.cancel()).join().unwrap();
+
+    // The turn stops before producing another item, rather than merging the rest of the dataset.
+    let err = pipeline.run().unwrap_err();
+    assert_eq!(ErrorKind::Cancelled, err.kind());
+    assert_eq!(0, file_count(&dir));
+    assert_cancelled(&mut pipeline)?;
+
+    drop(pipeline);
+    std::fs::remove_dir(&dir)?;
+    Ok(())
+}
+
+#[test]
+pub fn run_with_cancel_checks_callers_token() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_merge_pipeline();
+    start_merge(&mut pipeline)?;
+
+    // A token that isn't cancelled doesn't affect the turn.
+    let cancel = CancellationToken::new();
+    let response = pipeline.run_with_cancel(&cancel)?;
+    assert_eq!(
+        PARTITION_COUNT * PAGES_PER_PARTITION * PAGE_SIZE - 10,
+        response.items.len()
+    );
+    assert!(response.terminated);
+
+    let mut pipeline = create_merge_pipeline();
+    start_merge(&mut pipeline)?;
+    cancel.cancel();
+    let err = pipeline.run_with_cancel(&cancel).unwrap_err();
+    assert_eq!(ErrorKind::Cancelled, err.kind());
+    assert!(pipeline.cancellation_token().is_cancelled());
+    assert_cancelled(&mut pipeline)
+}
+
+#[test]
+pub fn cancel_stops_provide_data_batch() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_merge_pipeline();
+    let requests = pipeline.run()?.requests;
+    pipeline.cancellation_token().cancel();
+
+    let responses = requests
+        .iter()
+        .map(|r| {
+            let partition = r.pkrange_id["partition".len()..].parse::<usize>().unwrap();
+            (
+                r.pkrange_id.to_string(),
+                r.id,
+                merge_page(partition, 0),
+                None,
+            )
+        })
+        .collect();
+    let err = pipeline.provide_data_batch(responses).unwrap_err();
+    assert_eq!(ErrorKind::Cancelled, err.kind());
+    assert!(err.to_string().contains("failed to provide page 0"));
+    assert_cancelled(&mut pipeline)
+}
+
+#[test]
+pub fn cancel_after_completion_has_no_effect() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_merge_pipeline();
+    start_merge(&mut pipeline)?;
+    assert!(pipeline.run()?.terminated);
+
+    pipeline.cancellation_token().cancel();
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert!(response.terminated);
+    Ok(())
+}
@@ -0,0 +1,107 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+#![cfg(feature = "diagnostics")]
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use azure_data_cosmos_engine::query::{
+    Clock, ParseTimings, PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan, SortOrder,
+};
+use pretty_assertions::assert_eq;
+
+/// A clock that advances by a fixed number of milliseconds every time it's read.
+#[derive(Debug)]
+struct SteppingClock {
+    step_millis: u64,
+    now_millis: AtomicU64,
+}
+
+impl SteppingClock {
+    fn new(step_millis: u64) -> Self {
+        Self {
+            step_millis,
+            now_millis: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Clock for SteppingClock {
+    fn now(&self) -> Duration {
+        Duration::from_millis(
+            self.now_millis
+                .fetch_add(self.step_millis, Ordering::Relaxed),
+        )
+    }
+}
+
+fn create_pipeline(query_info: QueryInfo) -> QueryPipeline {
+    QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(query_info),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )
+    .unwrap()
+    .with_clock(SteppingClock::new(5))
+}
+
+#[test]
+pub fn records_parse_time_per_page() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo::default());
+    assert_eq!(&ParseTimings::default(), pipeline.parse_timings());
+
+    let page0 = br#"{"Documents": [1, 2, 3]}"#;
+    let page1 = br#"{"Documents": [4]}"#;
+    pipeline.provide_data("partition0", 0, page0, Some("c0".to_string()), None)?;
+    pipeline.provide_data("partition0", 1, page1, None, None)?;
+
+    // Each page reads the clock twice, once before and once after parsing, so each page takes one step.
+    assert_eq!(
+        &ParseTimings {
+            pages: 2,
+            bytes: (page0.len() + page1.len()) as u64,
+            total: Duration::from_millis(10),
+            max: Duration::from_millis(5),
+        },
+        pipeline.parse_timings()
+    );
+    assert_eq!(
+        Some(Duration::from_millis(5)),
+        pipeline.parse_timings().average()
+    );
+
+    // Yielding items doesn't count as parsing.
+    let items = pipeline.run()?.items;
+    assert_eq!(4, items.len());
+    assert_eq!(2, pipeline.parse_timings().pages);
+    Ok(())
+}
+
+#[test]
+pub fn records_parse_time_for_rejected_pages() {
+    let mut pipeline = create_pipeline(QueryInfo {
+        order_by: vec![SortOrder::Ascending],
+        ..Default::default()
+    });
+
+    // The page doesn't have the ORDER BY shape, but the time spent discovering that is still recorded.
+    let err = pipeline
+        .provide_data("partition0", 0, br#"{"Documents": [1]}"#, None, None)
+        .unwrap_err();
+    assert_eq!(
+        azure_data_cosmos_engine::ErrorKind::InvalidGatewayResponse,
+        err.kind()
+    );
+    assert_eq!(1, pipeline.parse_timings().pages);
+    assert_eq!(Duration::from_millis(5), pipeline.parse_timings().total);
+}
@@ -0,0 +1,122 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{
+        CompositeContinuationToken, ContinuationRange, PartitionKeyRange, QueryInfo, QueryPipeline,
+        QueryPlan,
+    },
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+fn create_pipeline(
+    query_info: QueryInfo,
+) -> Result<QueryPipeline, azure_data_cosmos_engine::Error> {
+    QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(query_info),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "00", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )
+}
+
+#[test]
+pub fn gateway_page_envelope_and_continuation() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo::default())?;
+
+    // The first page has no items, but does have a continuation describing both partitions.
+    let page = pipeline.run_as_gateway_page()?;
+    assert_eq!(
+        json!({"Documents": [], "_count": 0}),
+        serde_json::from_str::<serde_json::Value>(&page.to_json()?)?
+    );
+    assert_eq!(
+        vec![
+            CompositeContinuationToken {
+                token: None,
+                range: ContinuationRange {
+                    min: "00".into(),
+                    max: "80".into()
+                },
+            },
+            CompositeContinuationToken {
+                token: None,
+                range: ContinuationRange {
+                    min: "80".into(),
+                    max: "FF".into()
+                },
+            },
+        ],
+        CompositeContinuationToken::parse_list(page.continuation.as_deref().unwrap())?
+    );
+    assert_eq!(1, page.requests.len());
+    assert_eq!("partition0", page.requests[0].pkrange_id);
+
+    pipeline.provide_data(
+        "partition0",
+        page.requests[0].id,
+        br#"{"Documents":[{"id":"a"},{"id":"b"}]}"#,
+        Some("p0-page1".into()),
+        None,
+    )?;
+
+    let page = pipeline.run_as_gateway_page()?;
+    assert_eq!(
+        json!({"Documents": [{"id": "a"}, {"id": "b"}], "_count": 2}),
+        serde_json::from_str::<serde_json::Value>(&page.to_json()?)?
+    );
+    let tokens = CompositeContinuationToken::parse_list(page.continuation.as_deref().unwrap())?;
+    assert_eq!(2, tokens.len());
+    assert_eq!(Some("p0-page1"), tokens[0].token.as_deref());
+    assert_eq!(None, tokens[1].token);
+
+    // Drain the first partition, after which only the second partition remains in the continuation.
+    pipeline.provide_data(
+        "partition0",
+        page.requests[0].id,
+        br#"{"Documents":[{"id":"c"}]}"#,
+        None,
+        None,
+    )?;
+    let page = pipeline.run_as_gateway_page()?;
+    assert_eq!(1, page.count());
+    let tokens = CompositeContinuationToken::parse_list(page.continuation.as_deref().unwrap())?;
+    assert_eq!(1, tokens.len());
+    assert_eq!("80", tokens[0].range.min);
+
+    pipeline.provide_data(
+        "partition1",
+        page.requests[0].id,
+        br#"{"Documents":[{"id":"d"}]}"#,
+        None,
+        None,
+    )?;
+    let page = pipeline.run_as_gateway_page()?;
+    assert_eq!(
+        json!({"Documents": [{"id": "d"}], "_count": 1}),
+        serde_json::from_str::<serde_json::Value>(&page.to_json()?)?
+    );
+    assert_eq!(None, page.continuation);
+
+    Ok(())
+}
+
+#[test]
+pub fn gateway_page_rejects_limit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo {
+        limit: Some(10),
+        ..Default::default()
+    })?;
+
+    let err = pipeline.run_as_gateway_page().unwrap_err();
+    assert_eq!(ErrorKind::UnsupportedQueryPlan, err.kind());
+    Ok(())
+}
@@ -4,7 +4,8 @@
 use std::vec;
 
 use azure_data_cosmos_engine::query::{
-    DataRequest, QueryClauseItem, QueryInfo, QueryPlan, QueryResult, SortOrder,
+    DataRequest, PartitionKeyRange, QueryClauseItem, QueryInfo, QueryPipeline, QueryPlan,
+    QueryResult, SortOrder,
 };
 use pretty_assertions::assert_eq;
 
@@ -44,6 +45,18 @@ impl Item {
     }
 }
 
+fn request(
+    id: u64,
+    pkrange_id: &'static str,
+    continuation: Option<&str>,
+    max_item_count: Option<u32>,
+) -> DataRequest {
+    DataRequest {
+        max_item_count,
+        ..DataRequest::new(id, pkrange_id, continuation.map(String::from))
+    }
+}
+
 impl From<Item> for QueryResult {
     fn from(item: Item) -> Self {
         let raw = serde_json::value::to_raw_value(&item.title).unwrap();
@@ -54,6 +67,7 @@ impl From<Item> for QueryResult {
         QueryResult::OrderBy {
             order_by_items: vec![sort0, sort1],
             payload: raw,
+            rid: None,
         }
     }
 }
@@ -104,8 +118,8 @@ pub fn top() -> Result<(), Box<dyn std::error::Error>> {
             EngineResult {
                 items: vec![],
                 requests: vec![
-                    DataRequest::new(0, "partition0", None),
-                    DataRequest::new(0, "partition1", None),
+                    request(0, "partition0", None, Some(6)),
+                    request(0, "partition1", None, Some(6)),
                 ],
                 terminated: false,
             },
@@ -117,7 +131,7 @@ pub fn top() -> Result<(), Box<dyn std::error::Error>> {
                     json!("partition1/item1"),
                     json!("partition1/item2"),
                 ],
-                requests: vec![DataRequest::new(1, "partition1", Some("3".into())),],
+                requests: vec![request(1, "partition1", Some("3"), Some(6))],
                 terminated: false,
             },
             EngineResult {
@@ -132,6 +146,59 @@ pub fn top() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+pub fn top_stops_requesting_data() -> Result<(), Box<dyn std::error::Error>> {
+    let mut container = Container::new();
+    for partition in ["partition0", "partition1"] {
+        container.insert(
+            partition,
+            (0..10).map(|i| Item::new(format!("item{i}"), partition, i, "aaaa").into()),
+        );
+    }
+
+    let engine = Engine::new(
+        container,
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending, SortOrder::Ascending],
+                top: Some(3),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        2,
+    )?;
+
+    // Both partitions still have data when the TOP is reached, but the pipeline doesn't ask for it.
+    let results = engine.execute()?;
+    assert_eq!(
+        vec![
+            EngineResult {
+                items: vec![],
+                requests: vec![
+                    request(0, "partition0", None, Some(3)),
+                    request(0, "partition1", None, Some(3)),
+                ],
+                terminated: false,
+            },
+            EngineResult {
+                items: vec![
+                    json!("partition0/item0"),
+                    json!("partition1/item0"),
+                    json!("partition0/item1"),
+                ],
+                requests: vec![],
+                terminated: true,
+            },
+        ],
+        results
+    );
+
+    Ok(())
+}
+
 #[test]
 pub fn offset_limit() -> Result<(), Box<dyn std::error::Error>> {
     let mut container = Container::new();
@@ -179,16 +246,16 @@ pub fn offset_limit() -> Result<(), Box<dyn std::error::Error>> {
             EngineResult {
                 items: vec![],
                 requests: vec![
-                    DataRequest::new(0, "partition0", None),
-                    DataRequest::new(0, "partition1", None),
+                    request(0, "partition0", None, Some(6)),
+                    request(0, "partition1", None, Some(6)),
                 ],
                 terminated: false
             },
             EngineResult {
                 items: vec![],
                 requests: vec![
-                    DataRequest::new(1, "partition0", Some("2".into())),
-                    DataRequest::new(1, "partition1", Some("2".into()))
+                    request(1, "partition0", Some("2"), Some(6)),
+                    request(1, "partition1", Some("2"), Some(6)),
                 ],
                 terminated: false
             },
@@ -198,7 +265,7 @@ pub fn offset_limit() -> Result<(), Box<dyn std::error::Error>> {
                     json!("partition1/item2"),
                     json!("partition0/item2"),
                 ],
-                requests: vec![DataRequest::new(2, "partition1", Some("4".into())),],
+                requests: vec![],
                 terminated: true
             },
         ],
@@ -207,3 +274,164 @@ pub fn offset_limit() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+pub fn top_is_added_to_rewritten_order_by_query() -> Result<(), Box<dyn std::error::Error>> {
+    const REWRITTEN_QUERY: &str = "SELECT c._rid, [{\"item\": c.sort0}, {\"item\": c.sort1}] AS orderByItems, c AS payload FROM c WHERE {documentdb-formattableorderbyquery-filter} ORDER BY c.sort0, c.sort1";
+
+    // Partition 0 runs out of items before the TOP is reached.
+    fn engine(query_info: QueryInfo) -> Result<Engine, azure_data_cosmos_engine::Error> {
+        let mut container = Container::new();
+        container.insert(
+            "partition0",
+            (0..3).map(|i| Item::new(format!("item{i}"), "partition0", i * 2, "aaaa").into()),
+        );
+        container.insert(
+            "partition1",
+            (0..10).map(|i| Item::new(format!("item{i}"), "partition1", i * 2 + 1, "aaaa").into()),
+        );
+        Engine::new(
+            container,
+            "SELECT * FROM c ORDER BY c.sort0, c.sort1",
+            QueryPlan {
+                partitioned_query_execution_info_version: 1,
+                query_info: Some(query_info),
+                ..Default::default()
+            },
+            2,
+        )
+    }
+
+    fn items(engine: Engine) -> Result<Vec<serde_json::Value>, azure_data_cosmos_engine::Error> {
+        Ok(engine
+            .execute()?
+            .into_iter()
+            .flat_map(|result| result.items)
+            .collect())
+    }
+
+    for (top, offset, limit, rewritten_query, expected_query) in [
+        (
+            Some(6),
+            None,
+            None,
+            REWRITTEN_QUERY.to_string(),
+            REWRITTEN_QUERY.replacen("SELECT", "SELECT TOP 6", 1),
+        ),
+        (
+            None,
+            Some(2),
+            Some(5),
+            REWRITTEN_QUERY.to_string(),
+            REWRITTEN_QUERY.replacen("SELECT", "SELECT TOP 7", 1),
+        ),
+        // The gateway already limited the query, so it's left alone.
+        (
+            Some(6),
+            None,
+            None,
+            REWRITTEN_QUERY.replacen("SELECT", "SELECT TOP 6", 1),
+            REWRITTEN_QUERY.replacen("SELECT", "SELECT TOP 6", 1),
+        ),
+    ] {
+        let query_info = || QueryInfo {
+            order_by: vec![SortOrder::Ascending, SortOrder::Ascending],
+            top,
+            offset,
+            limit,
+            ..Default::default()
+        };
+        let rewritten = engine(QueryInfo {
+            rewritten_query: rewritten_query.clone(),
+            ..query_info()
+        })?;
+        assert_eq!(
+            Some(expected_query.replace("{documentdb-formattableorderbyquery-filter}", "true"))
+                .as_deref(),
+            rewritten.query()
+        );
+
+        // The mock backend ignores the query text, so the results are the same as if the query hadn't been rewritten at all.
+        assert_eq!(items(engine(query_info())?)?, items(rewritten)?);
+    }
+
+    // Without a TOP or LIMIT, the rewritten query is used as-is.
+    let unlimited = engine(QueryInfo {
+        order_by: vec![SortOrder::Ascending, SortOrder::Ascending],
+        rewritten_query: REWRITTEN_QUERY.to_string(),
+        ..Default::default()
+    })?;
+    assert_eq!(
+        Some(REWRITTEN_QUERY.replace("{documentdb-formattableorderbyquery-filter}", "true"))
+            .as_deref(),
+        unlimited.query()
+    );
+    Ok(())
+}
+
+fn first_requests(query_info: QueryInfo) -> Result<Vec<DataRequest>, Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(query_info),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "00", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )?;
+    Ok(pipeline.run()?.requests)
+}
+
+#[test]
+pub fn max_item_count_hint() -> Result<(), Box<dyn std::error::Error>> {
+    // Unordered queries only request the current partition.
+    assert_eq!(
+        vec![request(0, "partition0", None, Some(10))],
+        first_requests(QueryInfo {
+            top: Some(10),
+            ..Default::default()
+        })?
+    );
+    assert_eq!(
+        vec![request(0, "partition0", None, Some(25))],
+        first_requests(QueryInfo {
+            offset: Some(5),
+            limit: Some(20),
+            ..Default::default()
+        })?
+    );
+
+    // Without a TOP or LIMIT, the binding chooses the page size.
+    assert_eq!(
+        vec![request(0, "partition0", None, None)],
+        first_requests(QueryInfo::default())?
+    );
+    assert_eq!(
+        vec![
+            request(0, "partition0", None, None),
+            request(0, "partition1", None, None),
+        ],
+        first_requests(QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            ..Default::default()
+        })?
+    );
+
+    // Non-streaming ORDER BY reads every partition to the end, so no hint is provided even with a TOP.
+    assert_eq!(
+        vec![
+            request(0, "partition0", None, None),
+            request(0, "partition1", None, None),
+        ],
+        first_requests(QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            has_non_streaming_order_by: true,
+            top: Some(10),
+            ..Default::default()
+        })?
+    );
+    Ok(())
+}
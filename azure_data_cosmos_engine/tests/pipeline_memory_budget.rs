@@ -0,0 +1,129 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan, SortOrder},
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+fn create_pipeline(
+    query_info: QueryInfo,
+    budget: usize,
+) -> Result<QueryPipeline, azure_data_cosmos_engine::Error> {
+    Ok(QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(query_info),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "00", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )?
+    .with_memory_budget(budget))
+}
+
+fn order_by_page(start: usize, count: usize) -> Vec<u8> {
+    let documents = (start..start + count)
+        .map(|i| format!(r#"{{"orderByItems":[{{"item":{i}}}],"payload":{{"id":"item{i}"}}}}"#))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes()
+}
+
+#[test]
+pub fn non_streaming_order_by_trips_budget() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(
+        QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            has_non_streaming_order_by: true,
+            ..Default::default()
+        },
+        512,
+    )?;
+
+    // A non-streaming ORDER BY buffers everything, so feeding pages from a partition that never completes must eventually trip the budget.
+    let mut pages_accepted = 0;
+    let err = loop {
+        let response = pipeline.run()?;
+        assert!(response.items.is_empty());
+        let request = response
+            .requests
+            .iter()
+            .find(|r| r.pkrange_id == "partition0")
+            .expect("partition0 should still be requested");
+
+        let page = order_by_page(pages_accepted * 2, 2);
+        match pipeline.provide_data(
+            "partition0",
+            request.id,
+            &page,
+            Some(format!("p0-page{pages_accepted}")),
+            None,
+        ) {
+            Ok(()) => pages_accepted += 1,
+            Err(e) => break e,
+        }
+        assert!(pages_accepted < 100, "the memory budget never tripped");
+    };
+
+    assert_eq!(ErrorKind::MemoryBudgetExceeded, err.kind());
+    assert!(pages_accepted > 0);
+
+    // The rejected page was not consumed, so the partition is still requested from the last accepted continuation.
+    let response = pipeline.run()?;
+    let request = response
+        .requests
+        .iter()
+        .find(|r| r.pkrange_id == "partition0")
+        .unwrap();
+    assert_eq!(
+        Some(format!("p0-page{}", pages_accepted - 1)),
+        request.continuation
+    );
+    Ok(())
+}
+
+#[test]
+pub fn unordered_budget_is_released_as_items_are_yielded() -> Result<(), Box<dyn std::error::Error>>
+{
+    let page = br#"{"Documents":[{"id":"a"},{"id":"b"},{"id":"c"}]}"#;
+    let mut pipeline = create_pipeline(QueryInfo::default(), page.len() + 10)?;
+
+    let response = pipeline.run()?;
+    let request = response.requests[0].clone();
+    pipeline.provide_data(
+        "partition0",
+        request.id,
+        page,
+        Some("p0-page1".into()),
+        None,
+    )?;
+
+    // The first page is still buffered, so a second page doesn't fit.
+    let err = pipeline
+        .provide_data(
+            "partition0",
+            request.id,
+            page,
+            Some("p0-page2".into()),
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(ErrorKind::MemoryBudgetExceeded, err.kind());
+
+    // Yielding the buffered items releases the budget, and the same page can be provided again.
+    let response = pipeline.run()?;
+    assert_eq!(3, response.items.len());
+    let request = response.requests[0].clone();
+    assert_eq!(Some("p0-page1"), request.continuation.as_deref());
+    pipeline.provide_data("partition0", request.id, page, None, None)?;
+
+    let response = pipeline.run()?;
+    assert_eq!(3, response.items.len());
+    assert_eq!("partition1", response.requests[0].pkrange_id);
+    Ok(())
+}
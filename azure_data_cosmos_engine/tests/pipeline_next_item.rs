@@ -0,0 +1,102 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::query::{
+    PartitionKeyRange, PipelineEvent, QueryInfo, QueryPipeline, QueryPlan,
+};
+use pretty_assertions::assert_eq;
+
+fn create_pipeline(query_info: QueryInfo) -> QueryPipeline {
+    QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(query_info),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )
+    .unwrap()
+}
+
+fn item(event: PipelineEvent) -> String {
+    match event {
+        PipelineEvent::Item(item) => item.get().to_string(),
+        e => panic!("expected an item, got {:?}", e),
+    }
+}
+
+#[test]
+pub fn pulls_items_one_at_a_time() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo::default());
+
+    let PipelineEvent::NeedsData(requests) = pipeline.next_item()? else {
+        panic!("expected the pipeline to need data");
+    };
+    assert_eq!(1, requests.len());
+    assert_eq!("partition0", requests[0].pkrange_id);
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        br#"{"Documents": [1, 2, 3]}"#,
+        None,
+        None,
+    )?;
+
+    // Stopping after one item leaves the rest buffered in the pipeline.
+    assert_eq!("1", item(pipeline.next_item()?));
+    assert_eq!("2", item(pipeline.next_item()?));
+
+    // The remaining items are returned by the next turn, which then asks for the next partition.
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["3"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert_eq!(1, response.requests.len());
+    assert_eq!("partition1", response.requests[0].pkrange_id);
+    Ok(())
+}
+
+#[test]
+pub fn repeats_requests_until_data_is_provided() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo::default());
+
+    let PipelineEvent::NeedsData(first) = pipeline.next_item()? else {
+        panic!("expected the pipeline to need data");
+    };
+    let PipelineEvent::NeedsData(second) = pipeline.next_item()? else {
+        panic!("expected the pipeline to need data");
+    };
+    assert_eq!(first, second);
+    Ok(())
+}
+
+#[test]
+pub fn keeps_returning_done() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo {
+        top: Some(1),
+        ..Default::default()
+    });
+
+    let PipelineEvent::NeedsData(requests) = pipeline.next_item()? else {
+        panic!("expected the pipeline to need data");
+    };
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        br#"{"Documents": [1, 2]}"#,
+        Some("c0".to_string()),
+        None,
+    )?;
+
+    assert_eq!("1", item(pipeline.next_item()?));
+    for _ in 0..3 {
+        assert!(matches!(pipeline.next_item()?, PipelineEvent::Done));
+    }
+    assert!(pipeline.run()?.terminated);
+    Ok(())
+}
@@ -0,0 +1,482 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{PartitionKeyRange, PipelineOptions, QueryInfo, QueryPipeline, QueryPlan, SortOrder},
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+fn create_pipeline(
+    query_info: QueryInfo,
+    options: PipelineOptions,
+) -> Result<QueryPipeline, azure_data_cosmos_engine::Error> {
+    QueryPipeline::with_options(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(query_info),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "00", "55"),
+            PartitionKeyRange::new("partition1", "55", "AA"),
+            PartitionKeyRange::new("partition2", "AA", "FF"),
+        ],
+        options,
+    )
+}
+
+fn create_many_partition_pipeline(
+    query_info: QueryInfo,
+    partitions: usize,
+    options: PipelineOptions,
+) -> Result<QueryPipeline, azure_data_cosmos_engine::Error> {
+    QueryPipeline::with_options(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(query_info),
+            ..Default::default()
+        },
+        (0..partitions).map(|i| {
+            PartitionKeyRange::new(
+                format!("partition{i}"),
+                format!("{i:02X}"),
+                format!("{:02X}", i + 1),
+            )
+        }),
+        options,
+    )
+}
+
+fn order_by_page(start: usize, count: usize) -> Vec<u8> {
+    let documents = (start..start + count)
+        .map(|i| format!(r#"{{"orderByItems":[{{"item":{i}}}],"payload":{{"id":"item{i}"}}}}"#))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes()
+}
+
+fn non_streaming_order_by() -> QueryInfo {
+    QueryInfo {
+        order_by: vec![SortOrder::Ascending],
+        has_non_streaming_order_by: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+pub fn non_streaming_order_by_within_buffer_limit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(
+        non_streaming_order_by(),
+        PipelineOptions {
+            max_buffered_items: Some(6),
+            ..Default::default()
+        },
+    )?;
+
+    let requests = pipeline.run()?.requests;
+    for (i, request) in requests.iter().enumerate() {
+        pipeline.provide_data(
+            &request.pkrange_id,
+            request.id,
+            &order_by_page(i * 2, 2),
+            None,
+            None,
+        )?;
+    }
+    let response = pipeline.run()?;
+    assert!(response.terminated);
+    assert_eq!(6, response.items.len());
+    Ok(())
+}
+
+#[test]
+pub fn non_streaming_order_by_exceeds_buffer_limit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(
+        non_streaming_order_by(),
+        PipelineOptions {
+            max_buffered_items: Some(5),
+            ..Default::default()
+        },
+    )?;
+
+    let requests = pipeline.run()?.requests;
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        &order_by_page(0, 2),
+        None,
+        None,
+    )?;
+    pipeline.provide_data(
+        "partition1",
+        requests[1].id,
+        &order_by_page(2, 2),
+        None,
+        None,
+    )?;
+    let err = pipeline
+        .provide_data(
+            "partition2",
+            requests[2].id,
+            &order_by_page(4, 2),
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(ErrorKind::BufferLimitExceeded, err.kind());
+    Ok(())
+}
+
+#[test]
+pub fn unordered_requests_one_partition_by_default() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo::default(), PipelineOptions::default())?;
+    let requests = pipeline.run()?.requests;
+    assert_eq!(
+        vec!["partition0"],
+        requests
+            .iter()
+            .map(|r| r.pkrange_id.to_string())
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+pub fn unordered_prefetches_all_partitions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(
+        QueryInfo::default(),
+        PipelineOptions {
+            prefetch_all_partitions: true,
+            ..Default::default()
+        },
+    )?;
+    let requests = pipeline.run()?.requests;
+    assert_eq!(
+        vec!["partition0", "partition1", "partition2"],
+        requests
+            .iter()
+            .map(|r| r.pkrange_id.to_string())
+            .collect::<Vec<_>>()
+    );
+
+    // Pages from later partitions are buffered until the earlier partitions are exhausted.
+    pipeline.provide_data(
+        "partition2",
+        requests[2].id,
+        br#"{"Documents": [5, 6]}"#,
+        None,
+        None,
+    )?;
+    pipeline.provide_data(
+        "partition1",
+        requests[1].id,
+        br#"{"Documents": [3]}"#,
+        Some("p1-page1".to_string()),
+        None,
+    )?;
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert_eq!(
+        vec!["partition0", "partition1"],
+        response
+            .requests
+            .iter()
+            .map(|r| r.pkrange_id.to_string())
+            .collect::<Vec<_>>()
+    );
+
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        br#"{"Documents": [1, 2]}"#,
+        None,
+        None,
+    )?;
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["1", "2", "3"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert!(!response.terminated);
+
+    let request = &response.requests[0];
+    assert_eq!("partition1", request.pkrange_id);
+    pipeline.provide_data(
+        "partition1",
+        request.id,
+        br#"{"Documents": [4]}"#,
+        None,
+        None,
+    )?;
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["4", "5", "6"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert!(response.terminated);
+    Ok(())
+}
+
+#[test]
+pub fn options_from_json() -> Result<(), Box<dyn std::error::Error>> {
+    let options: PipelineOptions =
+        serde_json::from_str(r#"{"max_buffered_items": 10, "prefetch_all_partitions": true}"#)?;
+    assert_eq!(Some(10), options.max_buffered_items);
+    assert!(options.prefetch_all_partitions);
+    assert!(!options.reject_unrecognized_fields);
+
+    assert_eq!(None, options.max_concurrent_requests);
+
+    let options: PipelineOptions = serde_json::from_str(r#"{"max_concurrent_requests": 4}"#)?;
+    assert_eq!(Some(4), options.max_concurrent_requests);
+
+    assert!(serde_json::from_str::<PipelineOptions>(r#"{"max_buffered_itmes": 10}"#).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn unordered_interleaves_partitions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(
+        QueryInfo::default(),
+        PipelineOptions {
+            interleave_partitions: true,
+            ..Default::default()
+        },
+    )?;
+    let requests = pipeline.run()?.requests;
+    assert_eq!(3, requests.len());
+
+    // Items are yielded as soon as their page arrives, even if earlier partitions haven't provided anything yet.
+    pipeline.provide_data(
+        "partition2",
+        requests[2].id,
+        br#"{"Documents": [5, 6]}"#,
+        None,
+        None,
+    )?;
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["5", "6"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec!["partition0", "partition1"],
+        response
+            .requests
+            .iter()
+            .map(|r| r.pkrange_id.to_string())
+            .collect::<Vec<_>>()
+    );
+
+    pipeline.provide_data(
+        "partition1",
+        requests[1].id,
+        br#"{"Documents": [3]}"#,
+        None,
+        None,
+    )?;
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        br#"{"Documents": [1, 2]}"#,
+        None,
+        None,
+    )?;
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["1", "2", "3"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert!(response.terminated);
+    Ok(())
+}
+
+#[test]
+pub fn interleaved_pages_yield_each_item_once() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(
+        QueryInfo::default(),
+        PipelineOptions {
+            interleave_partitions: true,
+            ..Default::default()
+        },
+    )?;
+
+    // Each partition has three pages of two items, which arrive in a different order each turn.
+    let mut items = Vec::new();
+    let mut turn = 0;
+    loop {
+        let response = pipeline.run()?;
+        items.extend(response.items.iter().map(|i| i.get().to_string()));
+        if response.terminated {
+            break;
+        }
+
+        let mut requests = response.requests;
+        let len = requests.len();
+        requests.rotate_left(turn % len);
+        for request in requests {
+            let partition = &request.pkrange_id[request.pkrange_id.len() - 1..];
+            let page = request.id;
+            let documents = (0..2)
+                .map(|i| format!(r#""p{partition}-page{page}-{i}""#))
+                .collect::<Vec<_>>()
+                .join(",");
+            let continuation = (page < 2).then(|| format!("page{}", page + 1));
+            pipeline.provide_data(
+                &request.pkrange_id,
+                request.id,
+                format!(r#"{{"Documents": [{documents}]}}"#).as_bytes(),
+                continuation,
+                None,
+            )?;
+        }
+        turn += 1;
+    }
+
+    assert_eq!(18, items.len());
+    let mut sorted = items.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(18, sorted.len());
+
+    // Items from the same partition are still yielded in order.
+    for partition in 0..3 {
+        let prefix = format!(r#""p{partition}-"#);
+        let from_partition = items
+            .iter()
+            .filter(|i| i.starts_with(&prefix))
+            .collect::<Vec<_>>();
+        let mut expected = from_partition.clone();
+        expected.sort();
+        assert_eq!(expected, from_partition);
+    }
+    Ok(())
+}
+
+fn pkrange_ids(requests: &[azure_data_cosmos_engine::query::DataRequest]) -> Vec<String> {
+    requests.iter().map(|r| r.pkrange_id.to_string()).collect()
+}
+
+#[test]
+pub fn order_by_limits_concurrent_requests() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_many_partition_pipeline(
+        QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            ..Default::default()
+        },
+        20,
+        PipelineOptions {
+            max_concurrent_requests: Some(4),
+            ..Default::default()
+        },
+    )?;
+
+    // The same requests are returned until data is provided.
+    let requests = pipeline.run()?.requests;
+    assert_eq!(
+        vec!["partition0", "partition1", "partition2", "partition3"],
+        pkrange_ids(&requests)
+    );
+    assert_eq!(
+        pkrange_ids(&requests),
+        pkrange_ids(&pipeline.run()?.requests)
+    );
+
+    // Each partition returns two pages of one item, with the items interleaved across partitions.
+    let mut items = Vec::new();
+    let mut requests = requests;
+    let mut turns = 0;
+    loop {
+        assert!(requests.len() <= 4);
+        assert!(turns < 40, "the query should finish");
+        for request in &requests {
+            let partition = request.pkrange_id["partition".len()..].parse::<usize>()?;
+            let page = request.continuation.is_some() as usize;
+            pipeline.provide_data(
+                &request.pkrange_id,
+                request.id,
+                &order_by_page(page * 20 + partition, 1),
+                (page == 0).then(|| "page1".to_string()),
+                None,
+            )?;
+        }
+        turns += 1;
+
+        let response = pipeline.run()?;
+        items.extend(response.items.iter().map(|i| i.get().to_string()));
+        if response.terminated {
+            break;
+        }
+        if turns == 1 {
+            // The next requests continue from the partitions that haven't provided any data yet.
+            assert_eq!(
+                vec!["partition4", "partition5", "partition6", "partition7"],
+                pkrange_ids(&response.requests)
+            );
+        }
+        requests = response.requests;
+    }
+
+    let expected = (0..40)
+        .map(|i| format!(r#"{{"id":"item{i}"}}"#))
+        .collect::<Vec<_>>();
+    assert_eq!(expected, items);
+    Ok(())
+}
+
+#[test]
+pub fn unordered_limits_concurrent_requests() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_many_partition_pipeline(
+        QueryInfo::default(),
+        20,
+        PipelineOptions {
+            prefetch_all_partitions: true,
+            max_concurrent_requests: Some(2),
+            ..Default::default()
+        },
+    )?;
+
+    // Unordered queries drain their partitions in order, so the earliest partitions are requested first.
+    let requests = pipeline.run()?.requests;
+    assert_eq!(vec!["partition0", "partition1"], pkrange_ids(&requests));
+    pipeline.provide_data(
+        "partition1",
+        requests[1].id,
+        br#"{"Documents": [2]}"#,
+        None,
+        None,
+    )?;
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        br#"{"Documents": [1]}"#,
+        None,
+        None,
+    )?;
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["1", "2"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec!["partition2", "partition3"],
+        pkrange_ids(&response.requests)
+    );
+    Ok(())
+}
+
+#[test]
+pub fn zero_concurrent_requests_is_treated_as_one() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(
+        non_streaming_order_by(),
+        PipelineOptions {
+            max_concurrent_requests: Some(0),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(vec!["partition0"], pkrange_ids(&pipeline.run()?.requests));
+    Ok(())
+}
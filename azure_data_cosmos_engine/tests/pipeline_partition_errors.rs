@@ -0,0 +1,121 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::time::Duration;
+
+use azure_data_cosmos_engine::query::{
+    DataRequest, QueryClauseItem, QueryInfo, QueryPlan, QueryResult, SortOrder,
+};
+use pretty_assertions::assert_eq;
+
+use mock_engine::{
+    Container, Engine, EngineError, RetryDecision, SimulatedError, SimulatedErrorKind,
+};
+
+mod mock_engine;
+
+fn item(partition: &str, value: u32) -> QueryResult {
+    QueryResult::OrderBy {
+        order_by_items: vec![QueryClauseItem::from_value(value.into())],
+        payload: serde_json::value::to_raw_value(&format!("{partition}/{value}")).unwrap(),
+        rid: None,
+    }
+}
+
+fn throttled() -> SimulatedError {
+    SimulatedError {
+        kind: SimulatedErrorKind::Throttled,
+        retry_after: Some(Duration::from_millis(100)),
+    }
+}
+
+fn create_container() -> Container {
+    let mut container = Container::new();
+    container.insert(
+        "partition0",
+        [1, 3, 5, 7].into_iter().map(|v| item("partition0", v)),
+    );
+    container.insert(
+        "partition1",
+        [2, 4, 6, 8].into_iter().map(|v| item("partition1", v)),
+    );
+    container
+}
+
+fn create_engine(container: Container) -> Result<Engine, azure_data_cosmos_engine::Error> {
+    Engine::new(
+        container,
+        "SELECT * FROM c ORDER BY c.value",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        2,
+    )
+}
+
+fn items(results: &[mock_engine::EngineResult]) -> Vec<String> {
+    results
+        .iter()
+        .flat_map(|r| r.items.iter().map(|i| i.as_str().unwrap().to_string()))
+        .collect()
+}
+
+#[test]
+pub fn throttled_partition_eventually_succeeds() -> Result<(), Box<dyn std::error::Error>> {
+    let expected = items(&create_engine(create_container())?.execute()?);
+
+    // The second page of partition1 is throttled three times before it's returned.
+    let mut container = create_container();
+    container.insert_error("partition1", 2, throttled(), 3);
+    let mut errors = Vec::new();
+    let results = create_engine(container)?.execute_with(|error| {
+        errors.push(error.clone());
+        RetryDecision::Retry
+    })?;
+
+    assert_eq!(vec![throttled(); 3], errors);
+    assert_eq!(expected, items(&results));
+
+    // Each turn after the request was left unsatisfied issued the same request again.
+    let retried = results
+        .iter()
+        .flat_map(|r| r.requests.iter())
+        .filter(|r| r.pkrange_id == "partition1" && r.continuation.as_deref() == Some("2"))
+        .collect::<Vec<&DataRequest>>();
+    assert_eq!(4, retried.len());
+    assert!(retried.iter().all(|request| request == &retried[0]));
+    Ok(())
+}
+
+#[test]
+pub fn permanently_failing_partition_aborts() -> Result<(), Box<dyn std::error::Error>> {
+    let unavailable = SimulatedError {
+        kind: SimulatedErrorKind::ServiceUnavailable,
+        retry_after: None,
+    };
+    let mut container = create_container();
+    container.insert_error("partition0", 2, unavailable.clone(), usize::MAX);
+
+    // Retry a few times, then give up.
+    let mut attempts = 0;
+    let result = create_engine(container)?.execute_with(|_| {
+        attempts += 1;
+        if attempts < 5 {
+            RetryDecision::Retry
+        } else {
+            RetryDecision::Abort
+        }
+    });
+
+    match result {
+        Err(EngineError::Aborted(error)) => assert_eq!(unavailable, error),
+        other => panic!("expected the query to be aborted, got {other:?}"),
+    }
+    assert_eq!(5, attempts);
+    Ok(())
+}
@@ -0,0 +1,83 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan},
+    PartitionKeyDefinition, PartitionKeyKind, PartitionKeyValue,
+};
+use pretty_assertions::assert_eq;
+
+fn create_pipeline() -> Result<QueryPipeline, azure_data_cosmos_engine::Error> {
+    QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo::default()),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )
+}
+
+#[test]
+pub fn no_partition_key_definition() -> Result<(), Box<dyn std::error::Error>> {
+    let pipeline = create_pipeline()?;
+    assert_eq!(None, pipeline.partition_key_definition());
+    Ok(())
+}
+
+#[test]
+pub fn single_path_partition_key_definition() -> Result<(), Box<dyn std::error::Error>> {
+    let definition: PartitionKeyDefinition =
+        serde_json::from_str(r#"{"paths": ["/pk"], "kind": "Hash", "version": 2}"#)?;
+    let pipeline = create_pipeline()?.with_partition_key_definition(definition);
+
+    let definition = pipeline.partition_key_definition().unwrap();
+    assert_eq!(vec!["/pk".to_string()], definition.paths);
+    assert_eq!(PartitionKeyKind::Hash, definition.kind);
+    assert_eq!(2, definition.version);
+    assert_eq!(
+        32,
+        definition
+            .effective_partition_key(&[PartitionKeyValue::String("value".into())])
+            .len()
+    );
+    Ok(())
+}
+
+#[test]
+pub fn multi_path_partition_key_definition() -> Result<(), Box<dyn std::error::Error>> {
+    let pipeline = create_pipeline()?.with_partition_key_definition(PartitionKeyDefinition::new(
+        ["/tenantId", "/userId"],
+        PartitionKeyKind::MultiHash,
+        2,
+    ));
+
+    let definition = pipeline.partition_key_definition().unwrap();
+    assert_eq!(
+        vec!["/tenantId".to_string(), "/userId".to_string()],
+        definition.paths
+    );
+    assert_eq!(PartitionKeyKind::MultiHash, definition.kind);
+
+    // Expected values come from the Python SDK.
+    assert_eq!(
+        "2306FDF78C35ED4FD1C5835B075FC0B0248E1F58635558D12708326234F93A21",
+        definition.effective_partition_key(&[
+            PartitionKeyValue::String("title_player_account!9E711EFBD3BBB492".into()),
+            PartitionKeyValue::String("Title-B60C1".into()),
+        ])
+    );
+
+    // A prefix of the key hashes to a prefix of the effective partition key.
+    assert_eq!(
+        "2306FDF78C35ED4FD1C5835B075FC0B0",
+        definition.effective_partition_key(&[PartitionKeyValue::String(
+            "title_player_account!9E711EFBD3BBB492".into()
+        )])
+    );
+    Ok(())
+}
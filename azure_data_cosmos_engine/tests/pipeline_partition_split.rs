@@ -0,0 +1,288 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan},
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+fn create_pipeline() -> QueryPipeline {
+    QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo::default()),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )
+    .unwrap()
+}
+
+fn children() -> Vec<PartitionKeyRange> {
+    vec![
+        PartitionKeyRange::new("partition2", "", "40"),
+        PartitionKeyRange::new("partition3", "40", "80"),
+    ]
+}
+
+fn request_summary(pipeline: &mut QueryPipeline) -> Vec<(String, u64, Option<String>)> {
+    pipeline
+        .run()
+        .unwrap()
+        .requests
+        .into_iter()
+        .map(|r| (r.pkrange_id.into_owned(), r.id, r.continuation))
+        .collect()
+}
+
+/// Follows the protocol a language binding uses when a partition splits after its first page.
+#[test]
+pub fn unordered_query_continues_from_children_after_split(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline();
+    assert_eq!(
+        vec![("partition0".to_string(), 0, None)],
+        request_summary(&mut pipeline)
+    );
+    pipeline.provide_data(
+        "partition0",
+        0,
+        br#"{"Documents": [1, 2]}"#,
+        Some("c1".into()),
+        None,
+    )?;
+
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["1", "2"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert_eq!("partition0", response.requests[0].pkrange_id);
+    assert_eq!(Some("c1"), response.requests[0].continuation.as_deref());
+
+    // The request for the second page fails with 410/1002, so the binding refreshes the ranges and reports the split, instead of providing data.
+    pipeline.handle_pkrange_gone("partition0", children())?;
+    assert_eq!(
+        vec!["partition2", "partition3", "partition1"],
+        pipeline.targeted_partitions()
+    );
+
+    // The dead range is never requested again. Each child continues from the parent's continuation, in turn.
+    assert_eq!(
+        vec![("partition2".to_string(), 1, Some("c1".to_string()))],
+        request_summary(&mut pipeline)
+    );
+    let err = pipeline
+        .provide_data("partition0", 1, br#"{"Documents": [3]}"#, None, None)
+        .unwrap_err();
+    assert_eq!(ErrorKind::UnknownPartitionKeyRange, err.kind());
+
+    pipeline.provide_data("partition2", 1, br#"{"Documents": [3]}"#, None, None)?;
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["3"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert_eq!("partition3", response.requests[0].pkrange_id);
+    assert_eq!(Some("c1"), response.requests[0].continuation.as_deref());
+    let request_id = response.requests[0].id;
+
+    pipeline.provide_data(
+        "partition3",
+        request_id,
+        br#"{"Documents": [4]}"#,
+        None,
+        None,
+    )?;
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["4"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert_eq!("partition1", response.requests[0].pkrange_id);
+    assert_eq!(None, response.requests[0].continuation);
+
+    pipeline.provide_data("partition1", 0, br#"{"Documents": [5]}"#, None, None)?;
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["5"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert!(response.terminated);
+    Ok(())
+}
+
+#[test]
+pub fn split_state_survives_resume() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline();
+    pipeline.run()?;
+    pipeline.provide_data(
+        "partition0",
+        0,
+        br#"{"Documents": [1]}"#,
+        Some("c1".into()),
+        None,
+    )?;
+    assert_eq!(1, pipeline.run()?.items.len());
+    pipeline.handle_pkrange_gone("partition0", children())?;
+    let state = pipeline.serialize_state()?;
+
+    // The binding resumes with the refreshed ranges, which no longer include the parent.
+    let mut resumed = QueryPipeline::resume(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo::default()),
+            ..Default::default()
+        },
+        children()
+            .into_iter()
+            .chain([PartitionKeyRange::new("partition1", "80", "FF")]),
+        &state,
+    )?;
+    let response = resumed.run()?;
+    assert!(response.items.is_empty());
+    assert_eq!("partition2", response.requests[0].pkrange_id);
+    assert_eq!(Some("c1"), response.requests[0].continuation.as_deref());
+    Ok(())
+}
+
+#[test]
+pub fn split_must_cover_parent_range() {
+    let mut pipeline = create_pipeline();
+    pipeline.run().unwrap();
+    let err = pipeline
+        .handle_pkrange_gone(
+            "partition0",
+            vec![PartitionKeyRange::new("partition2", "", "40")],
+        )
+        .unwrap_err();
+    assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
+
+    // The pipeline is unchanged, and keeps requesting the parent.
+    assert_eq!(
+        vec![("partition0".to_string(), 0, None)],
+        request_summary(&mut pipeline)
+    );
+}
+
+#[test]
+pub fn pkranges_deserialize_from_rest_payload() -> Result<(), Box<dyn std::error::Error>> {
+    #[derive(serde::Deserialize)]
+    struct PartitionKeyRangeResult {
+        #[serde(rename = "PartitionKeyRanges")]
+        ranges: Vec<PartitionKeyRange>,
+    }
+
+    // A response from the `pkranges` REST API for a container whose first range was split, and whose other two ranges were merged.
+    let result: PartitionKeyRangeResult = serde_json::from_str(
+        r#"{
+            "_rid": "PaZbAOLxDJY=",
+            "PartitionKeyRanges": [
+                {
+                    "_rid": "PaZbAOLxDJYCAAAAAAAAUA==",
+                    "id": "3",
+                    "_etag": "\"00007d00-0000-0800-0000-64f9a1e50000\"",
+                    "minInclusive": "",
+                    "maxExclusive": "05C1DFFFFFFFFC",
+                    "ridPrefix": 3,
+                    "_self": "dbs/PaZbAA==/colls/PaZbAOLxDJY=/pkranges/PaZbAOLxDJYCAAAAAAAAUA==/",
+                    "throughputFraction": 0.25,
+                    "status": "online",
+                    "parents": ["0"],
+                    "ownedArchivalPKRangeIds": ["0"],
+                    "_ts": 1694081509,
+                    "lsn": 44
+                },
+                {
+                    "_rid": "PaZbAOLxDJYDAAAAAAAAUA==",
+                    "id": "4",
+                    "_etag": "\"00007e00-0000-0800-0000-64f9a1e50000\"",
+                    "minInclusive": "05C1DFFFFFFFFC",
+                    "maxExclusive": "05C1E9CD673398",
+                    "ridPrefix": 4,
+                    "_self": "dbs/PaZbAA==/colls/PaZbAOLxDJY=/pkranges/PaZbAOLxDJYDAAAAAAAAUA==/",
+                    "throughputFraction": 0.25,
+                    "status": "online",
+                    "parents": ["0"],
+                    "_ts": 1694081509,
+                    "lsn": 44
+                },
+                {
+                    "_rid": "PaZbAOLxDJYFAAAAAAAAUA==",
+                    "id": "5",
+                    "_etag": "\"00007f00-0000-0800-0000-64f9a1e50000\"",
+                    "minInclusive": "05C1E9CD673398",
+                    "maxExclusive": "FF",
+                    "ridPrefix": 5,
+                    "_self": "dbs/PaZbAA==/colls/PaZbAOLxDJY=/pkranges/PaZbAOLxDJYFAAAAAAAAUA==/",
+                    "throughputFraction": 0.5,
+                    "status": "online",
+                    "parents": ["1", "2"],
+                    "_ts": 1694081612,
+                    "lsn": 12
+                }
+            ],
+            "_count": 3
+        }"#,
+    )?;
+    assert_eq!(
+        vec![("3", vec!["0"]), ("4", vec!["0"]), ("5", vec!["1", "2"]),],
+        result
+            .ranges
+            .iter()
+            .map(|r| (r.id(), r.parents().iter().map(|p| p.as_str()).collect()))
+            .collect::<Vec<(&str, Vec<&str>)>>()
+    );
+
+    // Ranges that were never split or merged have no parents.
+    let range: PartitionKeyRange =
+        serde_json::from_str(r#"{"id": "0", "minInclusive": "", "maxExclusive": "FF"}"#)?;
+    assert!(range.parents().is_empty());
+    Ok(())
+}
+
+#[test]
+pub fn resolves_old_pkrange_ids_by_parents() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo::default()),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("3", "", "40").with_parents(["0"]),
+            PartitionKeyRange::new("4", "40", "80").with_parents(["0"]),
+            PartitionKeyRange::new("5", "80", "FF").with_parents(["1", "2"]),
+        ],
+    )?;
+    let resolve = |pipeline: &QueryPipeline, old_id| {
+        pipeline.resolve_pkrange(old_id).map(|r| r.id().to_string())
+    };
+
+    // Current ranges resolve to themselves, a merged range replaces each of its parents, and a split range resolves to its first child.
+    assert_eq!(Some("4".to_string()), resolve(&pipeline, "4"));
+    assert_eq!(Some("5".to_string()), resolve(&pipeline, "1"));
+    assert_eq!(Some("5".to_string()), resolve(&pipeline, "2"));
+    assert_eq!(Some("3".to_string()), resolve(&pipeline, "0"));
+    assert_eq!(None, resolve(&pipeline, "6"));
+
+    // Ranges that replace a split partition are resolved the same way.
+    pipeline.run()?;
+    pipeline.handle_pkrange_gone(
+        "4",
+        vec![
+            PartitionKeyRange::new("6", "40", "60").with_parents(["0", "4"]),
+            PartitionKeyRange::new("7", "60", "80").with_parents(["0", "4"]),
+        ],
+    )?;
+    assert_eq!(Some("6".to_string()), resolve(&pipeline, "4"));
+    assert_eq!(Some("7".to_string()), resolve(&pipeline, "7"));
+    Ok(())
+}
@@ -0,0 +1,84 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan, QueryRange, QueryResult},
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+use mock_engine::{Container, Engine};
+
+mod mock_engine;
+
+const QUERY: &str = "SELECT * FROM c WHERE c.category = 'books'";
+
+fn create_container() -> Container {
+    let mut container = Container::new();
+    for partition in 0..3 {
+        container.insert(
+            format!("partition{partition}"),
+            (0..5).map(|i| {
+                QueryResult::RawPayload(
+                    serde_json::value::to_raw_value(&format!("partition{partition}/item{i}"))
+                        .unwrap(),
+                )
+            }),
+        );
+    }
+    container
+}
+
+/// The plan the gateway returns for a query without any cross-partition clauses.
+fn gateway_plan() -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(QueryInfo::default()),
+        query_ranges: vec![QueryRange {
+            min: "".into(),
+            max: "FF".into(),
+            is_min_inclusive: true,
+            is_max_inclusive: false,
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+pub fn passthrough_matches_gateway_plan() -> Result<(), Box<dyn std::error::Error>> {
+    let expected = Engine::new(create_container(), QUERY, gateway_plan(), 2)?.execute()?;
+    let actual = Engine::passthrough(create_container(), QUERY, 2)?.execute()?;
+    assert_eq!(expected, actual);
+    assert_eq!(
+        15,
+        actual.iter().map(|r| r.items.len()).sum::<usize>(),
+        "every item should be returned"
+    );
+    Ok(())
+}
+
+#[test]
+pub fn passthrough_uses_original_query() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline =
+        QueryPipeline::new_passthrough(QUERY, [PartitionKeyRange::new("partition0", "", "FF")])?;
+    assert_eq!(Some(QUERY), pipeline.query());
+    let requests = pipeline.run()?.requests;
+    assert_eq!(1, requests.len());
+    assert_eq!("partition0", requests[0].pkrange_id);
+    Ok(())
+}
+
+#[test]
+pub fn passthrough_rejects_queries_that_need_a_plan() {
+    for query in [
+        "SELECT * FROM c ORDER BY c.id",
+        "SELECT VALUE COUNT(1) FROM c",
+        "SELECT DISTINCT c.category FROM c",
+        "SELECT TOP 5 * FROM c",
+        "SELECT * FROM c OFFSET 5 LIMIT 5",
+        "SELECT c.category, SUM(c.price) AS total FROM c GROUP BY c.category",
+    ] {
+        let err = QueryPipeline::new_passthrough(query, []).unwrap_err();
+        assert_eq!(ErrorKind::InvalidQuery, err.kind(), "{query}");
+    }
+}
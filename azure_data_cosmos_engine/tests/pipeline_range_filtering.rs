@@ -3,7 +3,10 @@
 
 use std::vec;
 
-use azure_data_cosmos_engine::query::{DataRequest, QueryInfo, QueryPlan, QueryRange, QueryResult};
+use azure_data_cosmos_engine::query::{
+    DataRequest, PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan, QueryRange, QueryResult,
+    SortOrder,
+};
 use pretty_assertions::assert_eq;
 
 use mock_engine::{Container, Engine};
@@ -94,6 +97,7 @@ pub fn pkranges_filtered_by_query_ranges() -> Result<(), Box<dyn std::error::Err
         query_plan,
         3,
     )?;
+    assert_eq!(vec!["partition1"], engine.targeted_partitions());
 
     let results = engine.execute()?;
 
@@ -190,6 +194,10 @@ pub fn pkranges_filtered_by_overlapping_query_ranges() -> Result<(), Box<dyn std
         query_plan,
         3,
     )?;
+    assert_eq!(
+        vec!["partition1", "partition2"],
+        engine.targeted_partitions()
+    );
 
     let results = engine.execute()?;
 
@@ -284,6 +292,10 @@ pub fn pkranges_filtered_by_all_partitions_query_range() -> Result<(), Box<dyn s
     };
 
     let engine = Engine::new(container, "SELECT * FROM c", query_plan, 3)?;
+    assert_eq!(
+        vec!["partition0", "partition1", "partition2", "partition3"],
+        engine.targeted_partitions()
+    );
 
     let results = engine.execute()?;
 
@@ -379,6 +391,10 @@ pub fn pkranges_no_query_ranges_queries_all_partitions() -> Result<(), Box<dyn s
     };
 
     let engine = Engine::new(container, "SELECT * FROM c", query_plan, 3)?;
+    assert_eq!(
+        vec!["partition0", "partition1", "partition2", "partition3"],
+        engine.targeted_partitions()
+    );
 
     let results = engine.execute()?;
 
@@ -427,3 +443,39 @@ pub fn pkranges_no_query_ranges_queries_all_partitions() -> Result<(), Box<dyn s
 
     Ok(())
 }
+
+#[test]
+pub fn no_targeted_partitions_when_query_ranges_miss_every_pkrange(
+) -> Result<(), Box<dyn std::error::Error>> {
+    for order_by in [vec![], vec![SortOrder::Ascending]] {
+        let mut pipeline = QueryPipeline::new(
+            "SELECT * FROM c WHERE c.partitionKey = 'missing'",
+            QueryPlan {
+                partitioned_query_execution_info_version: 1,
+                query_info: Some(QueryInfo {
+                    order_by,
+                    ..Default::default()
+                }),
+                query_ranges: vec![QueryRange {
+                    min: "A0".to_string(),
+                    max: "B0".to_string(),
+                    is_min_inclusive: true,
+                    is_max_inclusive: false,
+                }],
+                ..Default::default()
+            },
+            vec![
+                PartitionKeyRange::new("partition0", "", "40"),
+                PartitionKeyRange::new("partition1", "40", "80"),
+            ],
+        )?;
+        assert!(pipeline.targeted_partitions().is_empty());
+
+        // With no partitions to query, the pipeline completes without any requests.
+        let result = pipeline.run()?;
+        assert!(result.items.is_empty());
+        assert!(result.requests.is_empty());
+        assert!(result.terminated);
+    }
+    Ok(())
+}
@@ -0,0 +1,240 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::time::Duration;
+
+use azure_data_cosmos_engine::{
+    query::{
+        MergePipeline, PartitionKeyRange, PipelineResponse, QueryInfo, QueryMetrics, QueryPipeline,
+        QueryPlan, ResponseMetadata, SortOrder,
+    },
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+fn create_pipeline() -> QueryPipeline {
+    QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo::default()),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )
+    .unwrap()
+}
+
+#[test]
+pub fn sums_request_charges_across_partitions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline();
+    assert_eq!(0.0, pipeline.run()?.cumulative_request_charge);
+
+    let page = br#"{"Documents": [1]}"#;
+    pipeline.provide_data("partition0", 0, page, Some("c0".into()), Some(2.5))?;
+    pipeline.provide_data("partition0", 1, page, None, Some(1.25))?;
+    let response = pipeline.run()?;
+    assert_eq!(2, response.items.len());
+    assert_eq!(3.75, response.cumulative_request_charge);
+
+    // Pages without a charge don't add anything.
+    pipeline.provide_data("partition1", 0, page, None, None)?;
+    let response = pipeline.run()?;
+    assert!(response.terminated);
+    assert_eq!(3.75, response.cumulative_request_charge);
+    assert_eq!(3.75, pipeline.cumulative_request_charge());
+    Ok(())
+}
+
+fn metadata(request_charge: f64, activity_id: &str) -> ResponseMetadata {
+    ResponseMetadata {
+        request_charge,
+        activity_id: Some(activity_id.to_string()),
+        retry_after_ms: None,
+        query_metrics: None,
+    }
+}
+
+#[test]
+pub fn collects_metadata_across_partitions_and_pages() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline();
+    let response = pipeline.run()?;
+    assert!(response.activity_ids.is_empty());
+
+    let page = br#"{"Documents": [1]}"#;
+    pipeline.provide_data_with_metadata(
+        "partition0",
+        0,
+        page,
+        Some("c0".into()),
+        metadata(2.5, "activity0"),
+    )?;
+    pipeline.provide_data_with_metadata(
+        "partition0",
+        1,
+        page,
+        None,
+        ResponseMetadata {
+            retry_after_ms: Some(100),
+            ..metadata(1.0, "activity1")
+        },
+    )?;
+    let response = pipeline.run()?;
+    assert_eq!(2, response.items.len());
+    assert_eq!(3.5, response.cumulative_request_charge);
+    assert_eq!(vec!["activity0", "activity1"], response.activity_ids);
+
+    // Each turn only reports the activity IDs provided since the previous one, and pages without metadata still work.
+    pipeline.provide_data_with_metadata(
+        "partition1",
+        0,
+        page,
+        Some("c1".into()),
+        metadata(0.75, "activity2"),
+    )?;
+    pipeline.provide_data("partition1", 1, page, None, Some(1.0))?;
+    let response = pipeline.run()?;
+    assert!(response.terminated);
+    assert_eq!(5.25, response.cumulative_request_charge);
+    assert_eq!(vec!["activity2"], response.activity_ids);
+    assert_eq!(5.25, pipeline.cumulative_request_charge());
+    assert_eq!(
+        ["activity0", "activity1", "activity2"],
+        pipeline.activity_ids()
+    );
+    Ok(())
+}
+
+#[test]
+pub fn cancelled_pipeline_still_collects_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline();
+    pipeline.run()?;
+    pipeline.cancel();
+
+    pipeline.provide_data_with_metadata(
+        "partition0",
+        0,
+        br#"{"Documents": [1]}"#,
+        None,
+        metadata(2.0, "activity0"),
+    )?;
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert_eq!(2.0, response.cumulative_request_charge);
+    assert_eq!(vec!["activity0"], response.activity_ids);
+    Ok(())
+}
+
+#[test]
+pub fn rejected_pages_are_not_charged() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline().with_memory_budget(1);
+
+    let err = pipeline
+        .provide_data("partition0", 0, br#"{"Documents": [1]}"#, None, Some(5.0))
+        .unwrap_err();
+    assert_eq!(ErrorKind::MemoryBudgetExceeded, err.kind());
+    assert_eq!(0.0, pipeline.cumulative_request_charge());
+
+    let err = pipeline
+        .provide_data_with_metadata(
+            "partition0",
+            0,
+            br#"{"Documents": [1]}"#,
+            None,
+            metadata(5.0, "activity0"),
+        )
+        .unwrap_err();
+    assert_eq!(ErrorKind::MemoryBudgetExceeded, err.kind());
+    assert!(pipeline.activity_ids().is_empty());
+    Ok(())
+}
+
+#[test]
+pub fn sums_query_metrics_across_partitions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline();
+    pipeline.run()?;
+
+    let page = br#"{"Documents": [1]}"#;
+    let with_metrics = |query_metrics: &str| ResponseMetadata {
+        query_metrics: Some(query_metrics.to_string()),
+        ..Default::default()
+    };
+    pipeline.provide_data_with_metadata(
+        "partition0",
+        0,
+        page,
+        Some("c0".into()),
+        with_metrics("totalExecutionTimeInMs=2.00;retrievedDocumentCount=30;outputDocumentCount=10;indexUtilizationRatio=1.00"),
+    )?;
+
+    // Pages without metrics, or with metrics that can't be parsed, are still accepted, but don't add anything.
+    pipeline.provide_data_with_metadata(
+        "partition0",
+        1,
+        page,
+        None,
+        with_metrics("totalExecutionTimeInMs=soon"),
+    )?;
+    assert_eq!(2, pipeline.run()?.items.len());
+    pipeline.provide_data("partition1", 0, page, Some("c1".into()), None)?;
+    pipeline.provide_data_with_metadata(
+        "partition1",
+        1,
+        page,
+        None,
+        with_metrics("totalExecutionTimeInMs=0.50;retrievedDocumentCount=10;outputDocumentCount=10;indexUtilizationRatio=0.00;someNewMetric=1"),
+    )?;
+    assert!(pipeline.run()?.terminated);
+
+    let metrics = pipeline.query_metrics();
+    assert_eq!(
+        QueryMetrics {
+            total_execution_time: Duration::from_micros(2500),
+            retrieved_document_count: 40,
+            output_document_count: 20,
+            index_hit_ratio: 0.75,
+            ..Default::default()
+        },
+        *metrics
+    );
+    assert!(metrics
+        .to_string()
+        .starts_with("totalExecutionTimeInMs=2.50;"));
+    Ok(())
+}
+
+#[test]
+pub fn merge_sums_latest_charge_of_each_stream() -> Result<(), Box<dyn std::error::Error>> {
+    fn page(
+        cumulative_request_charge: f64,
+        activity_ids: &[&str],
+        terminated: bool,
+    ) -> PipelineResponse {
+        PipelineResponse {
+            items: Vec::new(),
+            requests: Vec::new(),
+            terminated,
+            cumulative_request_charge,
+            activity_ids: activity_ids.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    let mut pipeline = MergePipeline::new(["left", "right"], vec![SortOrder::Ascending]);
+    pipeline.provide_response("left", page(1.0, &["left0"], false))?;
+    pipeline.provide_response("right", page(2.0, &["right0", "right1"], false))?;
+    let response = pipeline.run()?;
+    assert_eq!(3.0, response.cumulative_request_charge);
+    assert_eq!(vec!["left0", "right0", "right1"], response.activity_ids);
+
+    // Each stream reports its own running total, which replaces the one it reported before.
+    pipeline.provide_response("left", page(4.0, &[], true))?;
+    pipeline.provide_response("right", page(2.5, &["right2"], true))?;
+    let response = pipeline.run()?;
+    assert!(response.terminated);
+    assert_eq!(6.5, response.cumulative_request_charge);
+    assert_eq!(vec!["right2"], response.activity_ids);
+    Ok(())
+}
@@ -0,0 +1,116 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan, SortOrder},
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+fn create_pipeline(query_info: QueryInfo) -> QueryPipeline {
+    QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(query_info),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )
+    .unwrap()
+}
+
+fn order_by_query_info() -> QueryInfo {
+    QueryInfo {
+        order_by: vec![SortOrder::Ascending],
+        ..Default::default()
+    }
+}
+
+fn order_by_page(values: &[i64]) -> Vec<u8> {
+    let documents = values
+        .iter()
+        .map(|v| format!(r#"{{"orderByItems": [{{"item": {v}}}], "payload": {v}}}"#))
+        .collect::<Vec<_>>();
+    format!(r#"{{"Documents": [{}]}}"#, documents.join(",")).into_bytes()
+}
+
+#[test]
+pub fn rejects_duplicate_response() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(QueryInfo::default());
+    let request = pipeline.run()?.requests.remove(0);
+    pipeline.provide_data(
+        &request.pkrange_id,
+        request.id,
+        br#"{"Documents": [1]}"#,
+        Some("c0".into()),
+        None,
+    )?;
+
+    let err = pipeline
+        .provide_data(
+            &request.pkrange_id,
+            request.id,
+            br#"{"Documents": [1]}"#,
+            Some("c0".into()),
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(ErrorKind::InvalidRequestId, err.kind());
+
+    // The duplicate page was not buffered.
+    let response = pipeline.run()?;
+    assert_eq!(
+        vec!["1"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+pub fn rejects_out_of_order_response() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(order_by_query_info());
+    let requests = pipeline.run()?.requests;
+    assert_eq!(
+        vec![0, 0],
+        requests.iter().map(|r| r.id).collect::<Vec<_>>()
+    );
+
+    // The second page of a partition can't arrive before the first.
+    let err = pipeline
+        .provide_data("partition0", 1, &order_by_page(&[1]), None, None)
+        .unwrap_err();
+    assert_eq!(ErrorKind::InvalidRequestId, err.kind());
+
+    pipeline.provide_data(
+        "partition0",
+        0,
+        &order_by_page(&[1]),
+        Some("c0".into()),
+        None,
+    )?;
+    pipeline.provide_data("partition1", 0, &order_by_page(&[2]), None, None)?;
+    pipeline.provide_data("partition0", 1, &order_by_page(&[3]), None, None)?;
+    let response = pipeline.run()?;
+    assert!(response.terminated);
+    assert_eq!(
+        vec!["1", "2", "3"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+pub fn rejects_response_for_exhausted_partition() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = create_pipeline(order_by_query_info());
+    pipeline.provide_data("partition0", 0, &order_by_page(&[1]), None, None)?;
+
+    let err = pipeline
+        .provide_data("partition0", 1, &order_by_page(&[2]), None, None)
+        .unwrap_err();
+    assert_eq!(ErrorKind::InvalidRequestId, err.kind());
+    Ok(())
+}
@@ -0,0 +1,130 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::query::{
+    DistinctType, PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan, QueryRange, SortOrder,
+};
+
+fn pkranges() -> Vec<PartitionKeyRange> {
+    vec![
+        PartitionKeyRange::new("partition0", "", "80"),
+        PartitionKeyRange::new("partition1", "80", "FF"),
+    ]
+}
+
+fn single_partition_plan(query_info: QueryInfo) -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(query_info),
+        query_ranges: vec![QueryRange {
+            min: "40".to_string(),
+            max: "40".to_string(),
+            is_min_inclusive: true,
+            is_max_inclusive: true,
+        }],
+        ..Default::default()
+    }
+}
+
+fn cross_partition_plan(query_info: QueryInfo) -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(query_info),
+        ..Default::default()
+    }
+}
+
+#[test]
+pub fn single_partition_limiting_queries_skip_engine() {
+    for query_info in [
+        QueryInfo::default(),
+        QueryInfo {
+            top: Some(10),
+            ..Default::default()
+        },
+        QueryInfo {
+            offset: Some(5),
+            limit: Some(10),
+            rewritten_query: "SELECT * FROM c OFFSET 0 LIMIT 15".to_string(),
+            ..Default::default()
+        },
+    ] {
+        let plan = single_partition_plan(query_info);
+        assert!(
+            !QueryPipeline::requires_query_engine(&plan, pkranges()),
+            "{:?}",
+            plan
+        );
+    }
+}
+
+#[test]
+pub fn cross_partition_limiting_queries_require_engine() {
+    for query_info in [
+        QueryInfo::default(),
+        QueryInfo {
+            top: Some(10),
+            ..Default::default()
+        },
+        QueryInfo {
+            offset: Some(5),
+            limit: Some(10),
+            ..Default::default()
+        },
+    ] {
+        let plan = cross_partition_plan(query_info);
+        assert!(
+            QueryPipeline::requires_query_engine(&plan, pkranges()),
+            "{:?}",
+            plan
+        );
+    }
+
+    // A single physical partition doesn't need the engine either, even without query ranges.
+    let plan = cross_partition_plan(QueryInfo {
+        limit: Some(10),
+        ..Default::default()
+    });
+    assert!(!QueryPipeline::requires_query_engine(
+        &plan,
+        vec![PartitionKeyRange::new("partition0", "", "FF")]
+    ));
+}
+
+#[test]
+pub fn single_partition_queries_with_other_clauses_require_engine() {
+    for query_info in [
+        QueryInfo {
+            top: Some(10),
+            order_by: vec![SortOrder::Ascending],
+            ..Default::default()
+        },
+        QueryInfo {
+            aggregates: vec!["Count".to_string()],
+            has_select_value: true,
+            ..Default::default()
+        },
+        QueryInfo {
+            limit: Some(10),
+            distinct_type: DistinctType::Unordered,
+            ..Default::default()
+        },
+        QueryInfo {
+            group_by_expressions: vec!["c.category".to_string()],
+            ..Default::default()
+        },
+    ] {
+        let plan = single_partition_plan(query_info);
+        assert!(
+            QueryPipeline::requires_query_engine(&plan, pkranges()),
+            "{:?}",
+            plan
+        );
+    }
+
+    let plan = QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        ..Default::default()
+    };
+    assert!(QueryPipeline::requires_query_engine(&plan, pkranges()));
+}
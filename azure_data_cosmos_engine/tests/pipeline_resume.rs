@@ -0,0 +1,313 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{
+        PartitionKeyRange, PipelineEvent, QueryClauseItem, QueryInfo, QueryPipeline, QueryPlan,
+        QueryResult, SortOrder,
+    },
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+use mock_engine::{Container, Engine};
+
+mod mock_engine;
+
+fn raw_item(partition: &str, index: usize) -> QueryResult {
+    QueryResult::RawPayload(
+        serde_json::value::to_raw_value(&json!({"id": format!("{partition}/{index}")})).unwrap(),
+    )
+}
+
+fn order_by_item(partition: &str, sort: u32) -> QueryResult {
+    QueryResult::OrderBy {
+        order_by_items: vec![QueryClauseItem::from_value(json!(sort))],
+        payload: serde_json::value::to_raw_value(&json!({"id": format!("{partition}/{sort}")}))
+            .unwrap(),
+        rid: None,
+    }
+}
+
+fn unordered_container() -> Container {
+    let mut container = Container::new();
+    container.insert("partition0", (0..5).map(|i| raw_item("partition0", i)));
+    container.insert("partition1", (0..3).map(|i| raw_item("partition1", i)));
+    container.insert("partition2", (0..4).map(|i| raw_item("partition2", i)));
+    container
+}
+
+fn order_by_container() -> Container {
+    let mut container = Container::new();
+    container.insert(
+        "partition0",
+        [1, 4, 5, 9, 12].map(|s| order_by_item("partition0", s)),
+    );
+    container.insert(
+        "partition1",
+        [2, 3, 10, 11].map(|s| order_by_item("partition1", s)),
+    );
+    container.insert(
+        "partition2",
+        [0, 6, 7, 8, 13, 14].map(|s| order_by_item("partition2", s)),
+    );
+    container
+}
+
+fn plan(query_info: QueryInfo) -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(query_info),
+        ..Default::default()
+    }
+}
+
+fn items(results: Vec<mock_engine::EngineResult>) -> Vec<serde_json::Value> {
+    results.into_iter().flat_map(|r| r.items).collect()
+}
+
+/// Runs the query once without interruption, then once for every possible stopping point, resuming a new engine from the serialized state.
+fn assert_resumable(
+    container: fn() -> Container,
+    query_info: fn() -> QueryInfo,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let expected = Engine::new(container(), "SELECT * FROM c", plan(query_info()), 2)?.execute()?;
+    let turns = expected.len();
+    let expected = items(expected);
+
+    for stop_after in 1..turns {
+        let mut engine = Engine::new(container(), "SELECT * FROM c", plan(query_info()), 2)?;
+        let mut actual = items(engine.execute_turns(stop_after)?);
+        let state = engine.serialize_state()?;
+        drop(engine);
+
+        let resumed = Engine::resume(
+            container(),
+            "SELECT * FROM c",
+            plan(query_info()),
+            2,
+            &state,
+        )?;
+        actual.extend(items(resumed.execute()?));
+
+        assert_eq!(expected, actual, "stopped after {stop_after} turns");
+    }
+    Ok(())
+}
+
+#[test]
+pub fn resume_unordered() -> Result<(), Box<dyn std::error::Error>> {
+    assert_resumable(unordered_container, QueryInfo::default)
+}
+
+#[test]
+pub fn resume_unordered_offset_limit() -> Result<(), Box<dyn std::error::Error>> {
+    assert_resumable(unordered_container, || QueryInfo {
+        offset: Some(3),
+        limit: Some(6),
+        ..Default::default()
+    })
+}
+
+#[test]
+pub fn resume_streaming_order_by() -> Result<(), Box<dyn std::error::Error>> {
+    assert_resumable(order_by_container, || QueryInfo {
+        order_by: vec![SortOrder::Ascending],
+        ..Default::default()
+    })
+}
+
+#[test]
+pub fn resume_streaming_order_by_top() -> Result<(), Box<dyn std::error::Error>> {
+    assert_resumable(order_by_container, || QueryInfo {
+        order_by: vec![SortOrder::Ascending],
+        top: Some(8),
+        ..Default::default()
+    })
+}
+
+#[test]
+pub fn resume_streaming_order_by_offset_top() -> Result<(), Box<dyn std::error::Error>> {
+    assert_resumable(order_by_container, || QueryInfo {
+        order_by: vec![SortOrder::Ascending],
+        offset: Some(2),
+        top: Some(8),
+        ..Default::default()
+    })
+}
+
+#[test]
+pub fn resume_partway_through_limit() -> Result<(), Box<dyn std::error::Error>> {
+    const PAGES: usize = 5;
+    const PAGE_SIZE: usize = 4;
+
+    fn offset_limit_plan() -> QueryPlan {
+        plan(QueryInfo {
+            offset: Some(3),
+            limit: Some(20),
+            ..Default::default()
+        })
+    }
+
+    fn pkranges() -> Vec<PartitionKeyRange> {
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ]
+    }
+
+    /// Pulls items until `count` have been produced or the pipeline is done, providing pages of 4 items as they're requested.
+    fn pull(
+        pipeline: &mut QueryPipeline,
+        count: usize,
+        max_item_counts: &mut Vec<Option<u32>>,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut items = Vec::new();
+        while items.len() < count {
+            match pipeline.next_item()? {
+                PipelineEvent::Item(item) => items.push(item.get().to_string()),
+                PipelineEvent::NeedsData(requests) => {
+                    let request = &requests[0];
+                    max_item_counts.push(request.max_item_count);
+                    let page = request
+                        .continuation
+                        .as_deref()
+                        .map_or(Ok(0), |c| c.parse::<usize>())?;
+                    let documents = (0..PAGE_SIZE)
+                        .map(|i| format!(r#""{}/{}""#, request.pkrange_id, page * PAGE_SIZE + i))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    pipeline.provide_data(
+                        &request.pkrange_id,
+                        request.id,
+                        format!(r#"{{"Documents":[{documents}]}}"#).as_bytes(),
+                        (page + 1 < PAGES).then(|| (page + 1).to_string()),
+                        None,
+                    )?;
+                }
+                PipelineEvent::Done => break,
+            }
+        }
+        Ok(items)
+    }
+
+    let mut pipeline = QueryPipeline::new("SELECT * FROM c", offset_limit_plan(), pkranges())?;
+    let expected = pull(&mut pipeline, usize::MAX, &mut Vec::new())?;
+    assert_eq!(20, expected.len());
+    assert_eq!(r#""partition0/3""#, expected[0]);
+
+    // Suspend after the first 5 of the 20 items.
+    let mut pipeline = QueryPipeline::new("SELECT * FROM c", offset_limit_plan(), pkranges())?;
+    let mut max_item_counts = Vec::new();
+    let mut actual = pull(&mut pipeline, 5, &mut max_item_counts)?;
+    assert_eq!(vec![Some(23), Some(23)], max_item_counts);
+    let state = pipeline.serialize_state()?;
+    drop(pipeline);
+
+    // The OFFSET was already skipped, and only 15 more items are needed, so the page size hint shrinks.
+    let mut resumed =
+        QueryPipeline::resume("SELECT * FROM c", offset_limit_plan(), pkranges(), &state)?;
+    let mut max_item_counts = Vec::new();
+    actual.extend(pull(&mut resumed, usize::MAX, &mut max_item_counts)?);
+    assert_eq!(expected, actual);
+    assert_eq!(Some(15), max_item_counts[0]);
+    Ok(())
+}
+
+#[test]
+pub fn resume_completed_query() -> Result<(), Box<dyn std::error::Error>> {
+    let mut engine = Engine::new(
+        unordered_container(),
+        "SELECT * FROM c",
+        plan(QueryInfo::default()),
+        100,
+    )?;
+    engine.execute_turns(usize::MAX)?;
+    let state = engine.serialize_state()?;
+
+    let resumed = Engine::resume(
+        unordered_container(),
+        "SELECT * FROM c",
+        plan(QueryInfo::default()),
+        100,
+        &state,
+    )?;
+    let results = resumed.execute()?;
+    assert_eq!(1, results.len());
+    assert!(results[0].terminated);
+    assert!(results[0].items.is_empty());
+    Ok(())
+}
+
+#[test]
+pub fn non_resumable_queries() -> Result<(), Box<dyn std::error::Error>> {
+    let non_streaming = Engine::new(
+        order_by_container(),
+        "SELECT * FROM c",
+        plan(QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            has_non_streaming_order_by: true,
+            ..Default::default()
+        }),
+        2,
+    )?;
+    assert_eq!(
+        ErrorKind::StateNotSerializable,
+        non_streaming.serialize_state().unwrap_err().kind()
+    );
+
+    let aggregate = Engine::new(
+        unordered_container(),
+        "SELECT VALUE COUNT(1) FROM c",
+        plan(QueryInfo {
+            aggregates: vec!["Count".to_string()],
+            has_select_value: true,
+            ..Default::default()
+        }),
+        2,
+    )?;
+    assert_eq!(
+        ErrorKind::StateNotSerializable,
+        aggregate.serialize_state().unwrap_err().kind()
+    );
+    Ok(())
+}
+
+#[test]
+pub fn resume_rejects_mismatched_state() -> Result<(), Box<dyn std::error::Error>> {
+    let mut engine = Engine::new(
+        unordered_container(),
+        "SELECT * FROM c",
+        plan(QueryInfo::default()),
+        2,
+    )?;
+    engine.execute_turns(1)?;
+    let state = engine.serialize_state()?;
+
+    let err = Engine::resume(
+        order_by_container(),
+        "SELECT * FROM c",
+        plan(QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            ..Default::default()
+        }),
+        2,
+        &state,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(ErrorKind::DeserializationError, err.kind());
+
+    let err = Engine::resume(
+        unordered_container(),
+        "SELECT * FROM c",
+        plan(QueryInfo::default()),
+        2,
+        "not a pipeline state",
+    )
+    .err()
+    .unwrap();
+    assert_eq!(ErrorKind::DeserializationError, err.kind());
+    Ok(())
+}
@@ -3,8 +3,12 @@
 
 use std::vec;
 
-use azure_data_cosmos_engine::query::{
-    DataRequest, QueryClauseItem, QueryInfo, QueryPlan, QueryResult, SortOrder,
+use azure_data_cosmos_engine::{
+    query::{
+        DataRequest, OrderByTieBreak, OrderByTypeMismatch, PartitionKeyRange, QueryClauseItem,
+        QueryInfo, QueryPipeline, QueryPlan, QueryResult, SortOrder,
+    },
+    ErrorKind,
 };
 use pretty_assertions::assert_eq;
 
@@ -54,6 +58,7 @@ impl From<Item> for QueryResult {
         QueryResult::OrderBy {
             order_by_items: vec![sort0, sort1],
             payload: raw,
+            rid: None,
         }
     }
 }
@@ -137,3 +142,633 @@ pub fn streaming_order_by() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+pub fn streaming_order_by_ties_prefer_lowest_partition() -> Result<(), Box<dyn std::error::Error>> {
+    let mut container = Container::new();
+    container.insert(
+        "partition0",
+        vec![
+            Item::new("item0", "partition0", 1, "aaaa").into(),
+            Item::new("item1", "partition0", 1, "aaaa").into(),
+            Item::new("item2", "partition0", 2, "aaaa").into(),
+        ],
+    );
+    container.insert(
+        "partition1",
+        vec![
+            Item::new("item0", "partition1", 1, "aaaa").into(),
+            Item::new("item1", "partition1", 2, "aaaa").into(),
+        ],
+    );
+    container.insert(
+        "partition2",
+        vec![
+            Item::new("item0", "partition2", 0, "aaaa").into(),
+            Item::new("item1", "partition2", 1, "aaaa").into(),
+            Item::new("item2", "partition2", 2, "aaaa").into(),
+        ],
+    );
+
+    let engine = Engine::new(
+        container,
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending, SortOrder::Descending],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        10,
+    )?;
+
+    let items = engine
+        .execute()?
+        .into_iter()
+        .flat_map(|r| r.items)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            json!("partition2/item0"),
+            json!("partition0/item0"),
+            json!("partition0/item1"),
+            json!("partition1/item0"),
+            json!("partition2/item1"),
+            json!("partition0/item2"),
+            json!("partition1/item1"),
+            json!("partition2/item2"),
+        ],
+        items
+    );
+    Ok(())
+}
+
+#[test]
+pub fn streaming_order_by_rejects_inconsistent_items() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )?;
+
+    // The wrong number of order by items is rejected when the page is provided, rather than when the items are merged.
+    let err = pipeline
+        .provide_data(
+            "partition1",
+            0,
+            br#"{"Documents":[{"orderByItems":[{"item":1},{"item":2}],"payload":{"id":"a"}}]}"#,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(ErrorKind::InvalidGatewayResponse, err.kind());
+    Ok(())
+}
+
+#[test]
+pub fn streaming_order_by_sorts_arrays_and_objects() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )?;
+
+    // Arrays sort after every primitive, and objects after arrays. Objects compare by their sorted keys, and then their values.
+    pipeline.provide_data(
+        "partition0",
+        0,
+        br#"{"Documents":[
+            {"orderByItems":[{"item":"z"}],"payload":"p0-string"},
+            {"orderByItems":[{"item":[1,2]}],"payload":"p0-short-array"},
+            {"orderByItems":[{"item":{"b":1}}],"payload":"p0-object"}
+        ]}"#,
+        None,
+        None,
+    )?;
+    pipeline.provide_data(
+        "partition1",
+        0,
+        br#"{"Documents":[
+            {"orderByItems":[{"item":1}],"payload":"p1-number"},
+            {"orderByItems":[{"item":[1,2,0]}],"payload":"p1-long-array"},
+            {"orderByItems":[{"item":[1,3]}],"payload":"p1-array"},
+            {"orderByItems":[{"item":{"a":1}}],"payload":"p1-object"}
+        ]}"#,
+        None,
+        None,
+    )?;
+
+    let result = pipeline.run()?;
+    assert!(result.terminated);
+    assert_eq!(
+        vec![
+            r#""p1-number""#,
+            r#""p0-string""#,
+            r#""p0-short-array""#,
+            r#""p1-long-array""#,
+            r#""p1-array""#,
+            r#""p1-object""#,
+            r#""p0-object""#,
+        ],
+        result.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+pub fn streaming_order_by_sorts_every_type_in_both_directions(
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Null sorts first, then booleans, numbers, strings, arrays and objects.
+    let ascending = [
+        ("null", json!(null)),
+        ("false", json!(false)),
+        ("true", json!(true)),
+        ("number-neg", json!(-1)),
+        ("number-float", json!(2.5)),
+        ("number-10", json!(10)),
+        ("string-a", json!("apple")),
+        ("string-b", json!("banana")),
+        ("array-empty", json!([])),
+        ("array-1", json!([1])),
+        ("array-1-2", json!([1, 2])),
+        ("array-1-a", json!([1, "a"])),
+        ("array-2", json!([2])),
+        ("object", json!({"a": 1})),
+    ];
+
+    for order in [SortOrder::Ascending, SortOrder::Descending] {
+        let mut values = ascending.to_vec();
+        if order == SortOrder::Descending {
+            values.reverse();
+        }
+        let mut pipeline = QueryPipeline::new(
+            "SELECT * FROM c",
+            QueryPlan {
+                partitioned_query_execution_info_version: 1,
+                query_info: Some(QueryInfo {
+                    order_by: vec![order],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            vec![
+                PartitionKeyRange::new("partition0", "", "80"),
+                PartitionKeyRange::new("partition1", "80", "FF"),
+            ],
+        )?;
+
+        // Every other value comes from the second partition, and each partition returns its values in order.
+        for (partition, pkrange_id) in ["partition0", "partition1"].into_iter().enumerate() {
+            let documents = values
+                .iter()
+                .skip(partition)
+                .step_by(2)
+                .map(|(id, value)| json!({"orderByItems": [{"item": value}], "payload": id}))
+                .collect::<Vec<_>>();
+            let page = json!({ "Documents": documents }).to_string();
+            pipeline.provide_data(pkrange_id, 0, page.as_bytes(), None, None)?;
+        }
+
+        let result = pipeline.run()?;
+        assert!(result.terminated);
+        assert_eq!(
+            values
+                .iter()
+                .map(|(id, _)| format!(r#""{id}""#))
+                .collect::<Vec<_>>(),
+            result.items.iter().map(|i| i.get()).collect::<Vec<_>>(),
+            "unexpected order for {order:?}"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+pub fn streaming_order_by_reports_mixed_types() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )?
+    .with_order_by_type_check();
+
+    pipeline.provide_data(
+        "partition0",
+        0,
+        br#"{"Documents":[{"orderByItems":[{"item":"b"}],"payload":"p0/b"},{"orderByItems":[{"item":"d"}],"payload":"p0/d"}]}"#,
+        None, None,
+    )?;
+    assert!(pipeline.order_by_type_mismatches().is_empty());
+    pipeline.provide_data(
+        "partition1",
+        0,
+        br#"{"Documents":[{"orderByItems":[{"item":2}],"payload":"p1/2"},{"orderByItems":[{"item":"c"}],"payload":"p1/c"}]}"#,
+        None, None,
+    )?;
+
+    assert_eq!(
+        &[OrderByTypeMismatch {
+            position: 0,
+            first_pkrange_id: "partition0".to_string(),
+            first_type: "string",
+            pkrange_id: "partition1".to_string(),
+            found_type: "number",
+        }],
+        pipeline.order_by_type_mismatches()
+    );
+
+    // The results are still ordered by type, then by value.
+    let result = pipeline.run()?;
+    assert!(result.terminated);
+    assert_eq!(
+        vec![json!("p1/2"), json!("p0/b"), json!("p1/c"), json!("p0/d"),],
+        result
+            .items
+            .iter()
+            .map(|i| serde_json::from_str::<serde_json::Value>(i.get()))
+            .collect::<Result<Vec<_>, _>>()?
+    );
+    Ok(())
+}
+
+#[test]
+pub fn streaming_order_by_batch_reports_failed_page() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )?;
+
+    let page = |sort: u32| {
+        format!(r#"{{"Documents":[{{"orderByItems":[{{"item":{sort}}}],"payload":{sort}}}]}}"#)
+            .into_bytes()
+    };
+    let err = pipeline
+        .provide_data_batch(vec![
+            ("partition0".to_string(), 0, page(1), None),
+            ("partition2".to_string(), 0, page(2), None),
+            ("partition1".to_string(), 0, page(3), None),
+        ])
+        .unwrap_err();
+    assert_eq!(ErrorKind::UnknownPartitionKeyRange, err.kind());
+    assert!(
+        err.to_string()
+            .starts_with("failed to provide page 1 (partition key range ID partition2): "),
+        "{}",
+        err
+    );
+
+    // Pages before the failed one were applied, but the ones after it were not.
+    let response = pipeline.run()?;
+    assert!(response.items.is_empty());
+    assert_eq!(
+        vec!["partition1"],
+        response
+            .requests
+            .iter()
+            .map(|r| r.pkrange_id.as_ref())
+            .collect::<Vec<_>>()
+    );
+
+    pipeline.provide_data_batch(vec![("partition1".to_string(), 0, page(3), None)])?;
+    let response = pipeline.run()?;
+    assert!(response.terminated);
+    assert_eq!(
+        vec!["1", "3"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+pub fn streaming_order_by_breaks_ties_by_timestamp() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "80"),
+            PartitionKeyRange::new("partition1", "80", "FF"),
+        ],
+    )?
+    .with_order_by_tie_break(OrderByTieBreak::Timestamp);
+
+    // Every item has the same ORDER BY value, so only the tie break decides the order.
+    // Each partition returns its items chronologically, the tie break decides how they're merged.
+    pipeline.provide_data(
+        "partition0",
+        0,
+        br#"{"Documents":[
+            {"orderByItems":[{"item":1}],"payload":{"id":"p0-tied","_ts":200,"_rid":"c"}},
+            {"orderByItems":[{"item":1}],"payload":{"id":"p0-late","_ts":300,"_rid":"a"}}
+        ]}"#,
+        None,
+        None,
+    )?;
+    pipeline.provide_data(
+        "partition1",
+        0,
+        br#"{"Documents":[
+            {"orderByItems":[{"item":1}],"payload":{"id":"p1-early","_ts":100,"_rid":"d"}},
+            {"orderByItems":[{"item":1}],"payload":{"id":"p1-tied","_ts":200,"_rid":"b"}},
+            {"orderByItems":[{"item":1}],"payload":{"id":"p1-late","_ts":400,"_rid":"e"}}
+        ]}"#,
+        None,
+        None,
+    )?;
+
+    let result = pipeline.run()?;
+    assert!(result.terminated);
+    let ids = result
+        .items
+        .iter()
+        .map(|i| {
+            let value: serde_json::Value = serde_json::from_str(i.get()).unwrap();
+            value["id"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+
+    // Items with the same timestamp are ordered by their _rid.
+    assert_eq!(
+        vec!["p1-early", "p1-tied", "p0-tied", "p0-late", "p1-late"],
+        ids
+    );
+    Ok(())
+}
+
+#[test]
+pub fn streaming_safe_vector_order_by_is_streamed() -> Result<(), Box<dyn std::error::Error>> {
+    // Like a flat vector index, every partition returns its items already sorted by score.
+    fn container() -> Container {
+        let mut container = Container::new();
+        container.insert(
+            "partition0",
+            vec![
+                Item::new("item0", "partition0", 9, "a").into(),
+                Item::new("item1", "partition0", 7, "a").into(),
+                Item::new("item2", "partition0", 4, "a").into(),
+                Item::new("item3", "partition0", 1, "a").into(),
+            ],
+        );
+        container.insert(
+            "partition1",
+            vec![
+                Item::new("item0", "partition1", 8, "a").into(),
+                Item::new("item1", "partition1", 6, "a").into(),
+                Item::new("item2", "partition1", 5, "a").into(),
+                Item::new("item3", "partition1", 2, "a").into(),
+            ],
+        );
+        container
+    }
+
+    fn execute(streaming_safe: bool) -> Result<Vec<EngineResult>, azure_data_cosmos_engine::Error> {
+        Engine::new(
+            container(),
+            "SELECT * FROM c ORDER BY VectorDistance(c.embedding, [0.1, 0.2])",
+            QueryPlan {
+                partitioned_query_execution_info_version: 1,
+                query_info: Some(QueryInfo {
+                    order_by: vec![SortOrder::Descending, SortOrder::Ascending],
+                    has_non_streaming_order_by: true,
+                    has_streaming_safe_order_by: streaming_safe,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            2,
+        )?
+        .execute()
+    }
+
+    fn items(results: &[EngineResult]) -> Vec<serde_json::Value> {
+        results.iter().flat_map(|r| r.items.clone()).collect()
+    }
+
+    let buffered = execute(false)?;
+    let streamed = execute(true)?;
+    assert_eq!(items(&buffered), items(&streamed));
+    assert_eq!(
+        vec![
+            json!("partition0/item0"),
+            json!("partition1/item0"),
+            json!("partition0/item1"),
+            json!("partition1/item1"),
+            json!("partition1/item2"),
+            json!("partition0/item2"),
+            json!("partition1/item3"),
+            json!("partition0/item3"),
+        ],
+        items(&streamed)
+    );
+
+    // The non-streaming producer buffers every partition before yielding anything, but the streaming producer yields items from the first pages.
+    assert!(buffered[1].items.is_empty());
+    assert_eq!(
+        vec![
+            json!("partition0/item0"),
+            json!("partition1/item0"),
+            json!("partition0/item1"),
+        ],
+        streamed[1].items
+    );
+    Ok(())
+}
+
+/// Creates a pipeline for an ascending `ORDER BY` across three partitions.
+fn three_partition_pipeline() -> Result<QueryPipeline, azure_data_cosmos_engine::Error> {
+    QueryPipeline::new(
+        "SELECT * FROM c",
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                order_by: vec![SortOrder::Ascending],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        vec![
+            PartitionKeyRange::new("partition0", "", "55"),
+            PartitionKeyRange::new("partition1", "55", "AA"),
+            PartitionKeyRange::new("partition2", "AA", "FF"),
+        ],
+    )
+}
+
+/// Generates a page of items, where an `ORDER BY` value of `None` is undefined, as the backend encodes it with an empty object.
+fn undefined_page(items: &[(Option<serde_json::Value>, &str)]) -> Vec<u8> {
+    let documents = items
+        .iter()
+        .map(|(value, payload)| {
+            let item = value
+                .as_ref()
+                .map_or(String::new(), |v| format!(r#""item":{v}"#));
+            format!(r#"{{"orderByItems":[{{{item}}}],"payload":"{payload}"}}"#)
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes()
+}
+
+#[test]
+pub fn streaming_order_by_orders_undefined_values_deterministically(
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Each partition returns two pages. Undefined values sort before null, and ties go to the lowest partition.
+    let pages = [
+        [
+            undefined_page(&[(None, "p0-a"), (None, "p0-b")]),
+            undefined_page(&[(Some(json!(null)), "p0-null"), (Some(json!(2)), "p0-2")]),
+        ],
+        [
+            undefined_page(&[(None, "p1-a")]),
+            undefined_page(&[(Some(json!(1)), "p1-1"), (Some(json!(3)), "p1-3")]),
+        ],
+        [
+            undefined_page(&[(None, "p2-a"), (Some(json!(null)), "p2-null")]),
+            undefined_page(&[(Some(json!("x")), "p2-x")]),
+        ],
+    ];
+    let expected = vec![
+        r#""p0-a""#,
+        r#""p0-b""#,
+        r#""p1-a""#,
+        r#""p2-a""#,
+        r#""p0-null""#,
+        r#""p2-null""#,
+        r#""p1-1""#,
+        r#""p0-2""#,
+        r#""p1-3""#,
+        r#""p2-x""#,
+    ];
+
+    // The order in which the partitions respond must not change the results.
+    for order in [[0, 1, 2], [2, 1, 0], [1, 2, 0]] {
+        let mut pipeline = three_partition_pipeline()?;
+        let mut items = Vec::new();
+        loop {
+            let result = pipeline.run()?;
+            items.extend(result.items.iter().map(|i| i.get().to_string()));
+            if result.terminated {
+                break;
+            }
+
+            let mut requests = result.requests;
+            requests.sort_by_key(|r| {
+                let partition = r.pkrange_id["partition".len()..].parse::<usize>().unwrap();
+                order.iter().position(|p| *p == partition)
+            });
+            for request in requests {
+                let partition = request.pkrange_id["partition".len()..].parse::<usize>()?;
+                let (page, continuation) = match request.continuation {
+                    None => (&pages[partition][0], Some("page1".to_string())),
+                    Some(_) => (&pages[partition][1], None),
+                };
+                pipeline.provide_data(&request.pkrange_id, request.id, page, continuation, None)?;
+            }
+        }
+        assert_eq!(expected, items, "partitions responded in order {order:?}");
+    }
+    Ok(())
+}
+
+#[test]
+pub fn streaming_order_by_undefined_values_are_not_mistaken_for_empty_partitions(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = three_partition_pipeline()?;
+
+    // Undefined values from partition1 can't be yielded while partition0, whose undefined values would sort first, hasn't responded.
+    pipeline.provide_data(
+        "partition1",
+        0,
+        &undefined_page(&[(None, "p1-a")]),
+        Some("page1".into()),
+        None,
+    )?;
+    pipeline.provide_data(
+        "partition2",
+        0,
+        &undefined_page(&[(None, "p2-a")]),
+        None,
+        None,
+    )?;
+    assert!(pipeline.run()?.items.is_empty());
+
+    // An empty page, with more to come, still leaves partition0 waiting for data.
+    pipeline.provide_data(
+        "partition0",
+        0,
+        br#"{"Documents":[]}"#,
+        Some("page1".into()),
+        None,
+    )?;
+    let result = pipeline.run()?;
+    assert!(result.items.is_empty());
+    assert!(!result.terminated);
+    let request = result
+        .requests
+        .iter()
+        .find(|r| r.pkrange_id == "partition0")
+        .expect("partition0 should be asked for its next page");
+
+    pipeline.provide_data(
+        "partition0",
+        request.id,
+        &undefined_page(&[(None, "p0-a")]),
+        None,
+        None,
+    )?;
+    let result = pipeline.run()?;
+    assert_eq!(
+        vec![r#""p0-a""#, r#""p1-a""#],
+        result.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    Ok(())
+}
@@ -0,0 +1,130 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::HashMap;
+
+use azure_data_cosmos_engine::{
+    query::{DistinctType, QueryInfo, QueryPipeline, QueryPlan, SortOrder},
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+fn plan(query_info: QueryInfo) -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(query_info),
+        ..Default::default()
+    }
+}
+
+fn aliases(aliases: &[(&str, &str)]) -> HashMap<String, String> {
+    aliases
+        .iter()
+        .map(|(alias, name)| (alias.to_string(), name.to_string()))
+        .collect()
+}
+
+fn unsupported_reason(plan: &QueryPlan) -> String {
+    let err = QueryPipeline::validate_plan(plan).unwrap_err();
+    assert_eq!(ErrorKind::UnsupportedQueryPlan, err.kind());
+    err.to_string()
+}
+
+#[test]
+pub fn accepts_supported_plans() {
+    for query_info in [
+        QueryInfo::default(),
+        QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            top: Some(10),
+            ..Default::default()
+        },
+        QueryInfo {
+            aggregates: vec!["Count".to_string()],
+            has_select_value: true,
+            ..Default::default()
+        },
+        QueryInfo {
+            group_by_aliases: vec!["total".to_string()],
+            group_by_alias_to_aggregate_type: aliases(&[("total", "Sum")]),
+            ..Default::default()
+        },
+        QueryInfo {
+            aggregates: vec!["Max".to_string()],
+            has_select_value: true,
+            order_by: vec![SortOrder::Ascending],
+            ..Default::default()
+        },
+        QueryInfo {
+            group_by_alias_to_aggregate_type: aliases(&[("total", "Count")]),
+            order_by: vec![SortOrder::Descending],
+            ..Default::default()
+        },
+    ] {
+        QueryPipeline::validate_plan(&plan(query_info)).unwrap();
+    }
+}
+
+#[test]
+pub fn rejects_missing_query_info() {
+    assert_eq!(
+        "query plan is missing both hybrid_search_query_info and query_info sections",
+        unsupported_reason(&QueryPlan::default())
+    );
+}
+
+#[test]
+pub fn rejects_aggregates_without_aliases() {
+    assert_eq!(
+        "non-value aggregates must provide an alias for each aggregate",
+        unsupported_reason(&plan(QueryInfo {
+            aggregates: vec!["Count".to_string()],
+            has_select_value: false,
+            ..Default::default()
+        }))
+    );
+}
+
+#[test]
+pub fn rejects_unknown_aggregates() {
+    assert_eq!(
+        "unknown aggregator: Median",
+        unsupported_reason(&plan(QueryInfo {
+            aggregates: vec!["Median".to_string()],
+            has_select_value: true,
+            ..Default::default()
+        }))
+    );
+    assert_eq!(
+        "unknown aggregator: Median",
+        unsupported_reason(&plan(QueryInfo {
+            group_by_alias_to_aggregate_type: aliases(&[("middle", "Median")]),
+            ..Default::default()
+        }))
+    );
+}
+
+#[test]
+pub fn rejects_group_by() {
+    assert_eq!(
+        "GROUP BY queries are not supported",
+        unsupported_reason(&plan(QueryInfo {
+            group_by_expressions: vec!["c.category".to_string()],
+            group_by_aliases: vec!["category".to_string()],
+            ..Default::default()
+        }))
+    );
+}
+
+#[test]
+pub fn rejects_distinct() {
+    for distinct_type in [DistinctType::Ordered, DistinctType::Unordered] {
+        assert_eq!(
+            "DISTINCT queries are not supported",
+            unsupported_reason(&plan(QueryInfo {
+                distinct_type,
+                ..Default::default()
+            }))
+        );
+    }
+}
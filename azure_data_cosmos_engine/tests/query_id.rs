@@ -0,0 +1,66 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::query::{PartitionKeyRange, QueryInfo, QueryPipeline, QueryPlan};
+use pretty_assertions::assert_eq;
+
+fn plan() -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(QueryInfo::default()),
+        ..Default::default()
+    }
+}
+
+fn pkranges() -> Vec<PartitionKeyRange> {
+    vec![
+        PartitionKeyRange::new("partition0", "", "80"),
+        PartitionKeyRange::new("partition1", "80", "FF"),
+    ]
+}
+
+#[test]
+pub fn id_is_stable_across_turns() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new("SELECT * FROM c", plan(), pkranges())?;
+    let id = pipeline.id().to_string();
+    assert!(!id.is_empty());
+
+    let requests = pipeline.run()?.requests;
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        br#"{"Documents": [1, 2]}"#,
+        None,
+        None,
+    )?;
+    pipeline.run()?;
+    pipeline.run()?;
+    assert_eq!(id, pipeline.id());
+    Ok(())
+}
+
+#[test]
+pub fn each_pipeline_has_its_own_id() -> Result<(), Box<dyn std::error::Error>> {
+    let first = QueryPipeline::new("SELECT * FROM c", plan(), pkranges())?;
+    let second = QueryPipeline::new("SELECT * FROM c", plan(), pkranges())?;
+    assert_ne!(first.id(), second.id());
+    Ok(())
+}
+
+#[test]
+pub fn resumed_pipeline_keeps_id() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new("SELECT * FROM c", plan(), pkranges())?;
+    let requests = pipeline.run()?.requests;
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        br#"{"Documents": [1]}"#,
+        Some("page1".into()),
+        None,
+    )?;
+    let state = pipeline.serialize_state()?;
+
+    let resumed = QueryPipeline::resume("SELECT * FROM c", plan(), pkranges(), &state)?;
+    assert_eq!(pipeline.id(), resumed.id());
+    Ok(())
+}
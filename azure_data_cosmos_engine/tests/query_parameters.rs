@@ -0,0 +1,114 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::query::{
+    PartitionKeyRange, Query, QueryInfo, QueryPipeline, QueryPlan, SortOrder,
+};
+use pretty_assertions::assert_eq;
+use serde_json::value::RawValue;
+
+const PARAMETERS: &str = r#"[{"name":"@id","value":"item1"}]"#;
+
+fn parameterized_query() -> Query {
+    Query::with_parameters(
+        "SELECT * FROM c WHERE c.id = @id",
+        RawValue::from_string(PARAMETERS.to_string()).unwrap(),
+    )
+}
+
+fn pkranges() -> Vec<PartitionKeyRange> {
+    vec![
+        PartitionKeyRange::new("partition0", "", "80"),
+        PartitionKeyRange::new("partition1", "80", "FF"),
+    ]
+}
+
+fn plan(query_info: QueryInfo) -> QueryPlan {
+    QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(query_info),
+        ..Default::default()
+    }
+}
+
+fn encoded_parameters(pipeline: &mut QueryPipeline) -> Vec<Option<String>> {
+    pipeline
+        .run()
+        .unwrap()
+        .requests
+        .iter()
+        .map(|r| r.encoded_parameters.as_ref().map(|p| p.get().to_string()))
+        .collect()
+}
+
+#[test]
+pub fn requests_include_query_parameters() -> Result<(), Box<dyn std::error::Error>> {
+    for query_info in [
+        QueryInfo::default(),
+        QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            ..Default::default()
+        },
+        QueryInfo {
+            order_by: vec![SortOrder::Ascending],
+            has_non_streaming_order_by: true,
+            ..Default::default()
+        },
+    ] {
+        let mut pipeline = QueryPipeline::new(parameterized_query(), plan(query_info), pkranges())?;
+        assert_eq!(
+            "SELECT * FROM c WHERE c.id = @id",
+            pipeline.query().unwrap()
+        );
+        for parameters in encoded_parameters(&mut pipeline) {
+            assert_eq!(Some(PARAMETERS), parameters.as_deref());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+pub fn query_text_has_no_parameters() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline =
+        QueryPipeline::new("SELECT * FROM c", plan(QueryInfo::default()), pkranges())?;
+    assert_eq!(vec![None], encoded_parameters(&mut pipeline));
+    Ok(())
+}
+
+#[test]
+pub fn resumed_pipeline_keeps_query_parameters() -> Result<(), Box<dyn std::error::Error>> {
+    let mut pipeline = QueryPipeline::new(
+        parameterized_query(),
+        plan(QueryInfo::default()),
+        pkranges(),
+    )?;
+    let requests = pipeline.run()?.requests;
+    pipeline.provide_data(
+        "partition0",
+        requests[0].id,
+        br#"{"Documents": [1]}"#,
+        Some("page1".into()),
+        None,
+    )?;
+    let state = pipeline.serialize_state()?;
+
+    let mut resumed = QueryPipeline::resume(
+        parameterized_query(),
+        plan(QueryInfo::default()),
+        pkranges(),
+        &state,
+    )?;
+    let response = resumed.run()?;
+    assert_eq!(
+        vec!["1"],
+        response.items.iter().map(|i| i.get()).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Some(PARAMETERS),
+        response.requests[0]
+            .encoded_parameters
+            .as_ref()
+            .map(|p| p.get())
+    );
+    Ok(())
+}
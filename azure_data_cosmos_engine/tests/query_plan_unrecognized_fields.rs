@@ -0,0 +1,137 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{PartitionKeyRange, PipelineOptions, QueryPipeline, QueryPlan},
+    ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+// A plan captured from the gateway for `SELECT * FROM c ORDER BY c.id`, with unknown properties injected.
+const ORDER_BY_PLAN: &str = r#"{
+    "partitionedQueryExecutionInfoVersion": 2,
+    "queryInfo": {
+        "distinctType": "None",
+        "top": null,
+        "offset": null,
+        "limit": null,
+        "orderBy": ["Ascending"],
+        "orderByExpressions": ["c.id"],
+        "groupByExpressions": [],
+        "groupByAliases": [],
+        "aggregates": [],
+        "groupByAliasToAggregateType": {},
+        "rewrittenQuery": "SELECT c._rid, [{\"item\": c.id}] AS orderByItems, c AS payload\nFROM c\nWHERE ({documentdb-formattableorderbyquery-filter})\nORDER BY c.id",
+        "hasSelectValue": false,
+        "dCountInfo": null,
+        "hasNonStreamingOrderBy": false,
+        "orderByNullsLast": true
+    },
+    "queryRanges": [
+        {"min": "", "max": "FF", "isMinInclusive": true, "isMaxInclusive": false}
+    ],
+    "queryPlanVersion": 3
+}"#;
+
+// A plan captured from the gateway for a hybrid search query, with an unknown property injected into a component query.
+const HYBRID_PLAN: &str = r#"{
+    "partitionedQueryExecutionInfoVersion": 2,
+    "queryInfo": null,
+    "queryRanges": [
+        {"min": "", "max": "FF", "isMinInclusive": true, "isMaxInclusive": false}
+    ],
+    "hybridSearchQueryInfo": {
+        "globalStatisticsQuery": "SELECT COUNT(1) AS documentCount, [] AS fullTextStatistics FROM c",
+        "componentQueryInfos": [
+            {
+                "orderBy": ["Descending"],
+                "orderByExpressions": ["_FullTextScore(c.text, ['swim'], {documentdb-formattablehybridsearchquery-totaldocumentcount}, {documentdb-formattablehybridsearchquery-totalwordcount-0}, {documentdb-formattablehybridsearchquery-hitcountsarray-0})"],
+                "rewrittenQuery": "SELECT TOP 20 c._rid, {\"payload\": c, \"componentScores\": []} AS payload FROM c",
+                "hasNonStreamingOrderBy": true,
+                "scoreNormalization": "MinMax"
+            }
+        ],
+        "skip": 0,
+        "take": 10,
+        "requiresGlobalStatistics": false
+    }
+}"#;
+
+fn pkranges() -> Vec<PartitionKeyRange> {
+    vec![PartitionKeyRange::new("partition0", "", "FF")]
+}
+
+#[test]
+pub fn records_unrecognized_fields() -> Result<(), Box<dyn std::error::Error>> {
+    let plan = QueryPlan::from_slice_strict(ORDER_BY_PLAN.as_bytes())?;
+    assert_eq!(
+        vec![
+            "queryPlanVersion",
+            "queryInfo.dCountInfo",
+            "queryInfo.orderByNullsLast"
+        ],
+        plan.unrecognized_fields()
+    );
+
+    let plan = QueryPlan::from_slice_strict(HYBRID_PLAN.as_bytes())?;
+    assert_eq!(
+        vec!["hybridSearchQueryInfo.componentQueryInfos[0].scoreNormalization"],
+        plan.unrecognized_fields()
+    );
+    Ok(())
+}
+
+#[test]
+pub fn plans_without_unrecognized_fields() -> Result<(), Box<dyn std::error::Error>> {
+    let plan = QueryPlan::from_slice_strict(
+        br#"{"partitionedQueryExecutionInfoVersion": 2, "queryInfo": {"orderBy": ["Descending"]}, "queryRanges": []}"#,
+    )?;
+    assert!(plan.unrecognized_fields().is_empty());
+    Ok(())
+}
+
+#[test]
+pub fn unrecognized_fields_are_allowed_by_default() -> Result<(), Box<dyn std::error::Error>> {
+    let plan: QueryPlan = serde_json::from_str(ORDER_BY_PLAN)?;
+    QueryPipeline::new("SELECT * FROM c ORDER BY c.id", plan, pkranges())?;
+    Ok(())
+}
+
+#[test]
+pub fn rejects_unrecognized_execution_fields() -> Result<(), Box<dyn std::error::Error>> {
+    let options = PipelineOptions {
+        reject_unrecognized_fields: true,
+        ..Default::default()
+    };
+
+    for (query, plan_json) in [
+        ("SELECT * FROM c ORDER BY c.id", ORDER_BY_PLAN),
+        (
+            "SELECT TOP 10 * FROM c ORDER BY RANK FullTextScore(c.text, 'swim')",
+            HYBRID_PLAN,
+        ),
+    ] {
+        let plan: QueryPlan = serde_json::from_str(plan_json)?;
+        let err =
+            QueryPipeline::with_options(query, plan, pkranges(), options.clone()).unwrap_err();
+        assert_eq!(ErrorKind::UnsupportedQueryPlan, err.kind());
+    }
+    Ok(())
+}
+
+#[test]
+pub fn allows_unrecognized_top_level_fields() -> Result<(), Box<dyn std::error::Error>> {
+    let plan: QueryPlan = serde_json::from_str(
+        r#"{"partitionedQueryExecutionInfoVersion": 2, "queryInfo": {}, "queryRanges": [], "queryPlanVersion": 3}"#,
+    )?;
+    QueryPipeline::with_options(
+        "SELECT * FROM c",
+        plan,
+        pkranges(),
+        PipelineOptions {
+            reject_unrecognized_fields: true,
+            ..Default::default()
+        },
+    )?;
+    Ok(())
+}
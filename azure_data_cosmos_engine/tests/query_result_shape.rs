@@ -0,0 +1,118 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::HashMap;
+
+use azure_data_cosmos_engine::query::{QueryInfo, QueryPlan, QueryResultShape, SortOrder};
+use pretty_assertions::assert_eq;
+
+fn shape(query_info: QueryInfo) -> QueryResultShape {
+    QueryResultShape::from_plan(&QueryPlan {
+        partitioned_query_execution_info_version: 1,
+        query_info: Some(query_info),
+        ..Default::default()
+    })
+}
+
+fn aliases(aliases: &[(&str, &str)]) -> HashMap<String, String> {
+    aliases
+        .iter()
+        .map(|(alias, name)| (alias.to_string(), name.to_string()))
+        .collect()
+}
+
+#[test]
+pub fn plain_queries_return_raw_payloads() {
+    assert_eq!(QueryResultShape::RawPayload, shape(QueryInfo::default()));
+    assert_eq!(
+        QueryResultShape::RawPayload,
+        shape(QueryInfo {
+            has_select_value: true,
+            offset: Some(5),
+            limit: Some(10),
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        QueryResultShape::RawPayload,
+        QueryResultShape::from_plan(&QueryPlan::default())
+    );
+}
+
+#[test]
+pub fn order_by_queries_return_order_by_rows() {
+    assert_eq!(
+        QueryResultShape::OrderBy,
+        shape(QueryInfo {
+            order_by: vec![SortOrder::Ascending, SortOrder::Descending],
+            has_non_streaming_order_by: true,
+            ..Default::default()
+        })
+    );
+}
+
+#[test]
+pub fn select_value_aggregates_return_value_aggregates() {
+    assert_eq!(
+        QueryResultShape::ValueAggregate,
+        shape(QueryInfo {
+            aggregates: vec!["Count".to_string()],
+            has_select_value: true,
+            ..Default::default()
+        })
+    );
+}
+
+#[test]
+pub fn named_aggregates_return_aggregates() {
+    assert_eq!(
+        QueryResultShape::Aggregate,
+        shape(QueryInfo {
+            group_by_aliases: vec!["total".to_string(), "maxPrice".to_string()],
+            group_by_alias_to_aggregate_type: aliases(&[("total", "Count"), ("maxPrice", "Max")]),
+            ..Default::default()
+        })
+    );
+}
+
+#[test]
+pub fn aggregates_with_order_by_return_order_by_rows() {
+    assert_eq!(
+        QueryResultShape::OrderBy,
+        shape(QueryInfo {
+            aggregates: vec!["Max".to_string()],
+            has_select_value: true,
+            order_by: vec![SortOrder::Ascending],
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        QueryResultShape::OrderBy,
+        shape(QueryInfo {
+            group_by_alias_to_aggregate_type: aliases(&[("total", "Count")]),
+            order_by: vec![SortOrder::Descending],
+            ..Default::default()
+        })
+    );
+}
+
+#[test]
+pub fn hybrid_search_queries_return_components() {
+    let plan: QueryPlan = serde_json::from_str(
+        r#"{
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryRanges": [],
+            "hybridSearchQueryInfo": {
+                "globalStatisticsQuery": "SELECT COUNT(1) AS documentCount FROM c",
+                "componentQueryInfos": [{"orderBy": ["Descending"]}],
+                "take": 10,
+                "requiresGlobalStatistics": false
+            }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        QueryResultShape::HybridComponent,
+        QueryResultShape::from_plan(&plan)
+    );
+}
@@ -0,0 +1,180 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use azure_data_cosmos_engine::{
+    query::{PartitionKeyRange, PipelineOptions, QueryInfo, QueryPipeline, QueryPlan, SortOrder},
+    Error, ErrorKind,
+};
+use pretty_assertions::assert_eq;
+
+fn pkranges() -> Vec<PartitionKeyRange> {
+    vec![
+        PartitionKeyRange::new("partition0", "", "80"),
+        PartitionKeyRange::new("partition1", "80", "FF"),
+    ]
+}
+
+fn create_pipeline(
+    query: &str,
+    query_info: QueryInfo,
+    project_select_value: bool,
+) -> Result<QueryPipeline, Error> {
+    QueryPipeline::with_options(
+        query,
+        QueryPlan {
+            partitioned_query_execution_info_version: 1,
+            query_info: Some(QueryInfo {
+                has_select_value: true,
+                ..query_info
+            }),
+            ..Default::default()
+        },
+        pkranges(),
+        PipelineOptions {
+            project_select_value,
+            ..Default::default()
+        },
+    )
+}
+
+fn ordered_by_name() -> QueryInfo {
+    QueryInfo {
+        order_by: vec![SortOrder::Ascending],
+        ..Default::default()
+    }
+}
+
+/// Generates a page of whole documents, ordered by their name.
+fn order_by_page(documents: &[&str]) -> Vec<u8> {
+    let documents = documents
+        .iter()
+        .map(|d| {
+            let name = serde_json::from_str::<serde_json::Value>(d).unwrap()["name"].clone();
+            format!(r#"{{"orderByItems":[{{"item":{name}}}],"payload":{d}}}"#)
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"Documents":[{documents}]}}"#).into_bytes()
+}
+
+/// Runs the pipeline to completion, providing a single page for each partition.
+fn run(
+    mut pipeline: QueryPipeline,
+    pages: [Vec<u8>; 2],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut items = Vec::new();
+    loop {
+        let response = pipeline.run()?;
+        items.extend(response.items.iter().map(|i| i.get().to_string()));
+        if response.terminated {
+            return Ok(items);
+        }
+
+        for request in response.requests {
+            let partition = request.pkrange_id["partition".len()..].parse::<usize>()?;
+            pipeline.provide_data(
+                &request.pkrange_id,
+                request.id,
+                &pages[partition],
+                None,
+                None,
+            )?;
+        }
+    }
+}
+
+#[test]
+pub fn scalar_projection_yields_values() -> Result<(), Box<dyn std::error::Error>> {
+    let pipeline = create_pipeline(
+        "SELECT VALUE c.name FROM c ORDER BY c.name",
+        ordered_by_name(),
+        true,
+    )?;
+    let items = run(
+        pipeline,
+        [
+            order_by_page(&[
+                r#"{"id":"1","name":"alice"}"#,
+                r#"{"id":"3","name":"carol"}"#,
+            ]),
+            order_by_page(&[r#"{"id":"2","name":"bob"}"#]),
+        ],
+    )?;
+    assert_eq!(vec![r#""alice""#, r#""bob""#, r#""carol""#], items);
+    Ok(())
+}
+
+#[test]
+pub fn object_projection_yields_objects() -> Result<(), Box<dyn std::error::Error>> {
+    let pipeline = create_pipeline(
+        "SELECT VALUE c[\"address\"] FROM c",
+        QueryInfo::default(),
+        true,
+    )?;
+    let items = run(
+        pipeline,
+        [
+            br#"{"Documents":[{"id":"1","address":{"city":"Seattle","zip":98101}}]}"#.to_vec(),
+            br#"{"Documents":[{"id":"2","address":{"city":"Redmond"}}]}"#.to_vec(),
+        ],
+    )?;
+    assert_eq!(
+        vec![r#"{"city":"Seattle","zip":98101}"#, r#"{"city":"Redmond"}"#],
+        items
+    );
+    Ok(())
+}
+
+#[test]
+pub fn undefined_values_are_skipped() -> Result<(), Box<dyn std::error::Error>> {
+    // Undefined values don't count against OFFSET and LIMIT either.
+    let pipeline = create_pipeline(
+        "SELECT VALUE c.address.city FROM c OFFSET 1 LIMIT 2",
+        QueryInfo {
+            offset: Some(1),
+            limit: Some(2),
+            ..Default::default()
+        },
+        true,
+    )?;
+    let items = run(
+        pipeline,
+        [
+            br#"{"Documents":[{"address":{"city":"Seattle"}},{"address":"Main St"},{"id":"x"}]}"#
+                .to_vec(),
+            br#"{"Documents":[{"address":{"city":null}},{"address":{}},{"address":{"city":"Redmond"}}]}"#
+                .to_vec(),
+        ],
+    )?;
+    assert_eq!(vec!["null", r#""Redmond""#], items);
+    Ok(())
+}
+
+#[test]
+pub fn payloads_are_unchanged_without_option() -> Result<(), Box<dyn std::error::Error>> {
+    let pipeline = create_pipeline(
+        "SELECT VALUE c.name FROM c ORDER BY c.name",
+        ordered_by_name(),
+        false,
+    )?;
+    let items = run(
+        pipeline,
+        [
+            order_by_page(&[r#"{"name":"alice"}"#]),
+            order_by_page(&[r#"{"name":"bob"}"#]),
+        ],
+    )?;
+    assert_eq!(vec![r#"{"name":"alice"}"#, r#"{"name":"bob"}"#], items);
+    Ok(())
+}
+
+#[test]
+pub fn unsupported_projection_is_rejected() {
+    let err = create_pipeline(
+        "SELECT VALUE c.price * 2 FROM c",
+        QueryInfo::default(),
+        true,
+    )
+    .unwrap_err();
+    assert_eq!(ErrorKind::UnsupportedQueryPlan, err.kind());
+}
@@ -13,8 +13,15 @@ use tracing_subscriber::EnvFilter;
 /// Once enabled in this way, tracing cannot be disabled.
 #[no_mangle]
 pub extern "C" fn cosmoscx_v0_tracing_enable() {
-    // Ignore errors from multiple initializations.
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_env("COSMOSCX_LOG"))
-        .try_init();
+    // This function can't return an error, but a panic still must not unwind into the caller.
+    let result = std::panic::catch_unwind(|| {
+        // Ignore errors from multiple initializations.
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(EnvFilter::from_env("COSMOSCX_LOG"))
+            .try_init();
+    });
+    if let Err(payload) = result {
+        let error = azure_data_cosmos_engine::Error::from_panic(payload);
+        tracing::error!(%error, "panicked while enabling tracing");
+    }
 }
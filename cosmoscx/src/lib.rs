@@ -8,7 +8,17 @@
 //!
 //! NOTE: All Cosmos DB Client Engine functions are prefixed with `cosmoscx_` to ensure they don't conflict with any other APIs the application may be referencing.
 
-use azure_data_cosmos_engine::query::SUPPORTED_FEATURES;
+use std::{
+    ffi::{c_char, CStr},
+    panic::AssertUnwindSafe,
+};
+
+use azure_data_cosmos_engine::{
+    query::{QueryFeature, SUPPORTED_FEATURES},
+    ErrorKind,
+};
+use result::{catch_panic, FfiResult, ResultCode};
+use slice::OwnedString;
 
 pub mod diag;
 pub mod pipeline;
@@ -19,7 +29,12 @@ unsafe fn free<T>(ptr: *mut T) {
     // SAFETY: We have to trust that the caller is giving us a valid pipeline result from calling "run"
     let owned = unsafe { Box::from_raw(ptr) };
     tracing::trace!(?ptr, typ = std::any::type_name_of_val(&owned), "freeing");
-    drop(owned);
+
+    // Dropping runs engine code, like deleting spilled results, and a panic there must not unwind into the caller either.
+    if let Err(payload) = std::panic::catch_unwind(AssertUnwindSafe(|| drop(owned))) {
+        let error = azure_data_cosmos_engine::Error::from_panic(payload);
+        tracing::error!(?ptr, %error, "panicked while freeing");
+    }
 }
 
 /// Returns the version of the Cosmos Client Engine in use.
@@ -36,6 +51,211 @@ pub extern "C" fn cosmoscx_v0_query_supported_features() -> *const std::ffi::c_c
     SUPPORTED_FEATURES.as_cstr().as_ptr()
 }
 
+/// Returns a string that describes the query features supported by the Cosmos Client Engine, leaving out the features named in `exclude`.
+///
+/// `exclude` is a comma-separated list of feature names, like `HybridSearch,DCount`. Names of features that aren't supported are ignored, since they're never reported anyway.
+/// Sending this string as the `x-ms-cosmos-supported-query-features` header, instead of [`cosmoscx_v0_query_supported_features`], makes the gateway reject queries that need the excluded features.
+///
+/// The [`OwnedString`] returned by this function MUST be freed using [`cosmoscx_v0_query_free_supported_features`].
+///
+/// # Parameters
+/// - `exclude`: A NUL-terminated, UTF-8 string containing the names of the features to leave out.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_supported_features_filtered(
+    exclude: *const c_char,
+) -> FfiResult<OwnedString> {
+    fn inner(exclude: *const c_char) -> Result<Box<OwnedString>, azure_data_cosmos_engine::Error> {
+        if exclude.is_null() {
+            return Err(ErrorKind::ArgumentNull.with_message("exclude was null"));
+        }
+        let exclude = unsafe { CStr::from_ptr(exclude) }
+            .to_str()
+            .map_err(|_| ErrorKind::InvalidUtf8String)?;
+        let excluded = exclude
+            .split(',')
+            .filter_map(|name| {
+                SUPPORTED_FEATURES
+                    .as_slice()
+                    .iter()
+                    .copied()
+                    .find(|feature| feature.name() == name.trim())
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(SUPPORTED_FEATURES.without(&excluded).into()))
+    }
+
+    catch_panic(|| inner(exclude)).into()
+}
+
+/// Identifies a query feature in the mask passed to [`cosmoscx_v0_query_supported_features_for`].
+///
+/// Each value is the [`QueryFeature::bit`] of the matching [`QueryFeature`], so values can be combined with a bitwise OR.
+/// cbindgen:prefix-with-name
+/// cbindgen:rename-all=SCREAMING_SNAKE_CASE
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryFeatureFlag {
+    None = 1 << 0,
+    Aggregate = 1 << 1,
+    CompositeAggregate = 1 << 2,
+    Distinct = 1 << 3,
+    GroupBy = 1 << 4,
+    MultipleAggregates = 1 << 5,
+    MultipleOrderBy = 1 << 6,
+    OffsetAndLimit = 1 << 7,
+    OrderBy = 1 << 8,
+    Top = 1 << 9,
+    NonValueAggregate = 1 << 10,
+    DCount = 1 << 11,
+    NonStreamingOrderBy = 1 << 12,
+    ListAndSetAggregate = 1 << 13,
+    CountIf = 1 << 14,
+    HybridSearch = 1 << 15,
+    WeightedRankFusion = 1 << 16,
+    HybridSearchSkipOrderByRewrite = 1 << 17,
+}
+
+impl From<QueryFeature> for QueryFeatureFlag {
+    fn from(feature: QueryFeature) -> Self {
+        match feature {
+            QueryFeature::None => Self::None,
+            QueryFeature::Aggregate => Self::Aggregate,
+            QueryFeature::CompositeAggregate => Self::CompositeAggregate,
+            QueryFeature::Distinct => Self::Distinct,
+            QueryFeature::GroupBy => Self::GroupBy,
+            QueryFeature::MultipleAggregates => Self::MultipleAggregates,
+            QueryFeature::MultipleOrderBy => Self::MultipleOrderBy,
+            QueryFeature::OffsetAndLimit => Self::OffsetAndLimit,
+            QueryFeature::OrderBy => Self::OrderBy,
+            QueryFeature::Top => Self::Top,
+            QueryFeature::NonValueAggregate => Self::NonValueAggregate,
+            QueryFeature::DCount => Self::DCount,
+            QueryFeature::NonStreamingOrderBy => Self::NonStreamingOrderBy,
+            QueryFeature::ListAndSetAggregate => Self::ListAndSetAggregate,
+            QueryFeature::CountIf => Self::CountIf,
+            QueryFeature::HybridSearch => Self::HybridSearch,
+            QueryFeature::WeightedRankFusion => Self::WeightedRankFusion,
+            QueryFeature::HybridSearchSkipOrderByRewrite => Self::HybridSearchSkipOrderByRewrite,
+        }
+    }
+}
+
+/// Returns a string that describes the query features supported by the Cosmos Client Engine, leaving out the features set in `exclude_mask`.
+///
+/// `exclude_mask` is a bitwise OR of [`QueryFeatureFlag`] values. For example, excluding [`QueryFeatureFlag::NonStreamingOrderBy`] makes the gateway
+/// reject queries that would buffer every result, rather than returning a plan the engine executes by buffering.
+/// Bits that don't identify a feature are ignored.
+///
+/// The [`OwnedString`] returned by this function MUST be freed using [`cosmoscx_v0_query_free_supported_features`].
+///
+/// # Parameters
+/// - `exclude_mask`: The features to leave out.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_supported_features_for(
+    exclude_mask: u64,
+) -> FfiResult<OwnedString> {
+    fn inner(exclude_mask: u64) -> Result<Box<OwnedString>, azure_data_cosmos_engine::Error> {
+        let excluded = QueryFeature::ALL
+            .iter()
+            .copied()
+            .filter(|feature| exclude_mask & feature.bit() != 0)
+            .collect::<Vec<_>>();
+        Ok(Box::new(SUPPORTED_FEATURES.without(&excluded).into()))
+    }
+
+    catch_panic(|| inner(exclude_mask)).into()
+}
+
+/// Frees the memory associated with a string returned by [`cosmoscx_v0_query_supported_features_filtered`] or [`cosmoscx_v0_query_supported_features_for`].
+///
+/// # Safety
+///
+/// The caller must ensure that the pointer passed to this function is a valid pointer to an [`OwnedString`] returned by [`cosmoscx_v0_query_supported_features_filtered`] or [`cosmoscx_v0_query_supported_features_for`].
+#[no_mangle]
+pub unsafe extern "C" fn cosmoscx_v0_query_free_supported_features(features: *mut OwnedString) {
+    unsafe { free(features) }
+}
+
+/// Panics inside the engine, so that language bindings can test that panics are returned as [`ResultCode::InternalPanic`].
+///
+/// This function is only intended for testing, it always fails.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_panic() -> ResultCode {
+    catch_panic(|| -> Result<(), azure_data_cosmos_engine::Error> {
+        panic!("cosmoscx_v0_panic was called")
+    })
+    .into()
+}
+
 #[no_mangle]
 /// cbindgen:ignore
 pub static BUILD_IDENTIFIER: &str = env!("BUILD_IDENTIFIER");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filtered_features(exclude: &CStr) -> String {
+        let features = cosmoscx_v0_query_supported_features_filtered(exclude.as_ptr());
+        let features = unsafe { Box::from_raw(features.unwrap()) };
+        unsafe { features.into_string() }.unwrap().unwrap()
+    }
+
+    #[test]
+    pub fn filtered_features_omit_excluded_features() {
+        let all = filtered_features(c"");
+        assert!(all.split(',').any(|f| f == "HybridSearch"));
+        assert!(all.split(',').any(|f| f == "OffsetAndLimit"));
+
+        let filtered = filtered_features(c"HybridSearch, OffsetAndLimit,NotAFeature");
+        assert_eq!(
+            all.split(',')
+                .filter(|f| *f != "HybridSearch" && *f != "OffsetAndLimit")
+                .collect::<Vec<_>>(),
+            filtered.split(',').collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    pub fn filtered_features_rejects_null() {
+        let code = cosmoscx_v0_query_supported_features_filtered(std::ptr::null()).unwrap_err();
+        assert!(matches!(code, ResultCode::ArgumentNull));
+    }
+
+    #[test]
+    pub fn feature_flags_match_feature_bits() {
+        for &feature in QueryFeature::ALL {
+            assert_eq!(
+                feature.bit(),
+                QueryFeatureFlag::from(feature) as u64,
+                "mismatched bit for {feature:?}"
+            );
+        }
+    }
+
+    #[test]
+    pub fn masked_features_are_omitted() {
+        let features = |mask| {
+            let features = cosmoscx_v0_query_supported_features_for(mask);
+            let features = unsafe { Box::from_raw(features.unwrap()) };
+            unsafe { features.into_string() }.unwrap().unwrap()
+        };
+
+        let all = features(0);
+        assert_eq!(filtered_features(c""), all);
+        assert!(all.split(',').any(|f| f == "NonStreamingOrderBy"));
+
+        let filtered = features(
+            QueryFeatureFlag::NonStreamingOrderBy as u64
+                | QueryFeatureFlag::HybridSearch as u64
+                | QueryFeatureFlag::Distinct as u64
+                | 1 << 63,
+        );
+        assert_eq!(
+            all.split(',')
+                .filter(|f| *f != "NonStreamingOrderBy" && *f != "HybridSearch")
+                .collect::<Vec<_>>(),
+            filtered.split(',').collect::<Vec<_>>()
+        );
+    }
+}
@@ -3,14 +3,22 @@
 
 //! Functions related to creating and executing query pipelines.
 
+use std::{
+    cell::RefCell,
+    sync::{Mutex, MutexGuard, PoisonError, TryLockError},
+};
+
 use azure_data_cosmos_engine::{
-    query::{PartitionKeyRange, QueryPipeline, QueryPlan},
+    query::{
+        CancellationToken, ItemArena, PartitionKeyRange, PipelineOptions, Query, QueryPipeline,
+        QueryPlan,
+    },
     ErrorKind,
 };
 use serde::Deserialize;
 
 use crate::{
-    result::ResultExt,
+    result::{catch_panic, ResultExt},
     slice::{OwnedSlice, Slice},
 };
 
@@ -21,23 +29,109 @@ use super::{
 
 /// Opaque type representing the query pipeline.
 /// Callers should not attempt to access the fields of this struct directly.
-pub struct Pipeline;
+pub struct Pipeline {
+    // Held outside the lock, so [`cosmoscx_v0_query_pipeline_cancel`] can stop a call that's running on another thread.
+    cancellation: CancellationToken,
+    pipeline: Mutex<QueryPipeline>,
+}
 
 impl Pipeline {
+    fn new(pipeline: QueryPipeline) -> Box<Self> {
+        Box::new(Self {
+            cancellation: pipeline.cancellation_token(),
+            pipeline: Mutex::new(pipeline),
+        })
+    }
+
     // We can't make this into a "method" without the arbitrary_self_types feature
     // (https://github.com/rust-lang/rust/issues/44874)
 
-    /// Unwraps the pointer to the underlying `QueryPipeline` type.
+    /// Unwraps the pointer to the underlying `QueryPipeline` type, locking it for the duration of the call.
     ///
     /// # Safety
     ///
-    /// The caller must ensure that the pointer passed to this function is a valid pointer to a `QueryPipeline`.
+    /// The caller must ensure that the pointer passed to this function is a valid pointer to a [`Pipeline`].
     pub unsafe fn unwrap_ptr(
         pipeline: *mut Self,
-    ) -> Result<&'static mut QueryPipeline, azure_data_cosmos_engine::Error> {
-        (pipeline as *mut QueryPipeline)
-            .as_mut()
-            .ok_or_else(|| ErrorKind::ArgumentNull.with_message("pipeline was null"))
+    ) -> Result<MutexGuard<'static, QueryPipeline>, azure_data_cosmos_engine::Error> {
+        let pipeline = pipeline
+            .as_ref()
+            .ok_or_else(|| ErrorKind::ArgumentNull.with_message("pipeline was null"))?;
+
+        // A call that panicked is reported as `ResultCode::InternalPanic`, which already tells the caller to discard the pipeline.
+        Ok(pipeline
+            .pipeline
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Borrows a string owned by the pipeline, beyond the lock taken by [`Pipeline::unwrap_ptr`].
+    ///
+    /// # Safety
+    ///
+    /// The string must never be changed while the pipeline exists, so that it remains valid until the pipeline is freed.
+    unsafe fn borrow_str(value: &str) -> Str<'static> {
+        Str::from(unsafe { std::slice::from_raw_parts(value.as_ptr(), value.len()) })
+    }
+}
+
+/// Parses the arguments shared by [`cosmoscx_v0_query_pipeline_create`] and [`cosmoscx_v0_query_pipeline_resume`].
+fn parse_pipeline_args<'a>(
+    query: Str<'a>,
+    query_plan_json: Str<'a>,
+    pkranges: Str<'a>,
+) -> Result<(&'a str, QueryPlan, Vec<PartitionKeyRange>), azure_data_cosmos_engine::Error> {
+    let query = unsafe { query.as_str().not_null() }?;
+    let query_plan_json = unsafe { query_plan_json.as_str().not_null() }?;
+
+    let query_plan: QueryPlan = serde_json::from_str(query_plan_json)
+        .map_err(|e| ErrorKind::InvalidGatewayResponse.with_source(e))?;
+    let pkranges = parse_pkranges(pkranges)?;
+
+    // SAFETY: We should no longer need either of the parameter slices, we copied them into owned data.
+
+    Ok((query, query_plan, pkranges))
+}
+
+/// Parses the partition key ranges list, as recieved from the gateway.
+fn parse_pkranges(
+    pkranges: Str<'_>,
+) -> Result<Vec<PartitionKeyRange>, azure_data_cosmos_engine::Error> {
+    #[derive(Deserialize)]
+    struct PartitionKeyRangeResult {
+        #[serde(rename = "PartitionKeyRanges")]
+        pub ranges: Vec<PartitionKeyRange>,
+    }
+
+    let pkranges_json = unsafe { pkranges.as_str().not_null() }?;
+    let pkranges: PartitionKeyRangeResult = serde_json::from_str(pkranges_json)
+        .map_err(|e| ErrorKind::InvalidGatewayResponse.with_source(e))?;
+    Ok(pkranges.ranges)
+}
+
+/// Creates the [`Query`] from the query text and the `parameters_json` argument, which may be empty or null if the query has no parameters.
+fn parse_query(
+    query: &str,
+    parameters_json: Str<'_>,
+) -> Result<Query, azure_data_cosmos_engine::Error> {
+    Ok(match unsafe { parameters_json.as_str() }? {
+        Some(parameters_json) if !parameters_json.is_empty() => Query::with_parameters(
+            query,
+            serde_json::from_str(parameters_json)
+                .map_err(|e| ErrorKind::DeserializationError.with_source(e))?,
+        ),
+        _ => query.into(),
+    })
+}
+
+/// Parses the `options_json` argument, using the default options if it is empty or null.
+fn parse_options(
+    options_json: Str<'_>,
+) -> Result<PipelineOptions, azure_data_cosmos_engine::Error> {
+    match unsafe { options_json.as_str() }? {
+        Some(options_json) if !options_json.is_empty() => serde_json::from_str(options_json)
+            .map_err(|e| ErrorKind::DeserializationError.with_source(e)),
+        _ => Ok(PipelineOptions::default()),
     }
 }
 
@@ -52,34 +146,210 @@ pub extern "C" fn cosmoscx_v0_query_pipeline_create<'a>(
     query_plan_json: Str<'a>,
     pkranges: Str<'a>,
 ) -> FfiResult<Pipeline> {
-    #[derive(Deserialize)]
-    struct PartitionKeyRangeResult {
-        #[serde(rename = "PartitionKeyRanges")]
-        pub ranges: Vec<PartitionKeyRange>,
+    fn inner<'a>(
+        query: Str<'a>,
+        query_plan_json: Str<'a>,
+        pkranges: Str<'a>,
+    ) -> Result<Box<Pipeline>, azure_data_cosmos_engine::Error> {
+        let (query, query_plan, pkranges) = parse_pipeline_args(query, query_plan_json, pkranges)?;
+
+        tracing::debug!(query = ?query, query_plan = ?query_plan, pkranges = ?pkranges, "creating query pipeline");
+        let pipeline = QueryPipeline::new(query, query_plan, pkranges)?;
+        Ok(Pipeline::new(pipeline))
     }
 
+    catch_panic(|| inner(query, query_plan_json, pkranges)).into()
+}
+
+/// Creates a new query pipeline from a JSON query plan and list of partitions, using the provided options.
+///
+/// See [`PipelineOptions`] for the available options, which are provided as a JSON object using the field names as-is.
+/// Missing fields use their default values, and unknown fields are rejected with [`ResultCode::DeserializationError`].
+///
+/// # Parameters
+/// - `query_plan_json`: A [`Str`] containing the serialized query plan, as recieved from the gateway, in JSON.
+/// - `pkranges`: A [`Str`] containing the serialized partition key ranges list, as recieved from the gateway, in JSON.
+/// - `options_json`: A [`Str`] containing the serialized [`PipelineOptions`], in JSON, or an empty or null slice to use the default options.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_create_with_options<'a>(
+    query: Str<'a>,
+    query_plan_json: Str<'a>,
+    pkranges: Str<'a>,
+    options_json: Str<'a>,
+) -> FfiResult<Pipeline> {
     fn inner<'a>(
         query: Str<'a>,
         query_plan_json: Str<'a>,
         pkranges: Str<'a>,
-    ) -> Result<Box<QueryPipeline>, azure_data_cosmos_engine::Error> {
+        options_json: Str<'a>,
+    ) -> Result<Box<Pipeline>, azure_data_cosmos_engine::Error> {
+        let (query, query_plan, pkranges) = parse_pipeline_args(query, query_plan_json, pkranges)?;
+        let options = parse_options(options_json)?;
+
+        tracing::debug!(query = ?query, query_plan = ?query_plan, pkranges = ?pkranges, options = ?options, "creating query pipeline");
+        let pipeline = QueryPipeline::with_options(query, query_plan, pkranges, options)?;
+        Ok(Pipeline::new(pipeline))
+    }
+
+    catch_panic(|| inner(query, query_plan_json, pkranges, options_json)).into()
+}
+
+/// Creates a new query pipeline for a parameterized query, using the provided options.
+///
+/// The engine attaches the parameters to each [`DataRequest`] that needs them, see [`DataRequest::encoded_parameters`].
+/// Otherwise, this is the same as [`cosmoscx_v0_query_pipeline_create_with_options`].
+///
+/// # Parameters
+/// - `parameters_json`: A [`Str`] containing the query parameters, encoded as the JSON `parameters` field of a Cosmos query, or an empty or null slice if the query has no parameters.
+/// - `query_plan_json`: A [`Str`] containing the serialized query plan, as recieved from the gateway, in JSON.
+/// - `pkranges`: A [`Str`] containing the serialized partition key ranges list, as recieved from the gateway, in JSON.
+/// - `options_json`: A [`Str`] containing the serialized [`PipelineOptions`], in JSON, or an empty or null slice to use the default options.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_create_with_parameters<'a>(
+    query: Str<'a>,
+    parameters_json: Str<'a>,
+    query_plan_json: Str<'a>,
+    pkranges: Str<'a>,
+    options_json: Str<'a>,
+) -> FfiResult<Pipeline> {
+    fn inner<'a>(
+        query: Str<'a>,
+        parameters_json: Str<'a>,
+        query_plan_json: Str<'a>,
+        pkranges: Str<'a>,
+        options_json: Str<'a>,
+    ) -> Result<Box<Pipeline>, azure_data_cosmos_engine::Error> {
+        let (query, query_plan, pkranges) = parse_pipeline_args(query, query_plan_json, pkranges)?;
+        let options = parse_options(options_json)?;
+        let query = parse_query(query, parameters_json)?;
+
+        tracing::debug!(query = ?query, query_plan = ?query_plan, pkranges = ?pkranges, options = ?options, "creating query pipeline");
+        let pipeline = QueryPipeline::with_options(query, query_plan, pkranges, options)?;
+        Ok(Pipeline::new(pipeline))
+    }
+
+    catch_panic(|| {
+        inner(
+            query,
+            parameters_json,
+            query_plan_json,
+            pkranges,
+            options_json,
+        )
+    })
+    .into()
+}
+
+/// Creates a new query pipeline for a simple query, without a query plan from the gateway.
+///
+/// Language bindings can use this to skip the query plan request for queries that each partition executes completely on its own.
+/// The query must not contain `ORDER BY`, `GROUP BY`, `DISTINCT`, `TOP`, `OFFSET`, `LIMIT` or aggregate functions,
+/// otherwise this returns [`ResultCode::InvalidQuery`], and the binding should request a query plan as usual.
+/// See [`QueryPipeline::new_passthrough`] for more information.
+///
+/// # Parameters
+/// - `parameters_json`: A [`Str`] containing the query parameters, encoded as the JSON `parameters` field of a Cosmos query, or an empty or null slice if the query has no parameters.
+/// - `pkranges`: A [`Str`] containing the serialized partition key ranges list, as recieved from the gateway, in JSON.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_create_passthrough<'a>(
+    query: Str<'a>,
+    parameters_json: Str<'a>,
+    pkranges: Str<'a>,
+) -> FfiResult<Pipeline> {
+    fn inner<'a>(
+        query: Str<'a>,
+        parameters_json: Str<'a>,
+        pkranges: Str<'a>,
+    ) -> Result<Box<Pipeline>, azure_data_cosmos_engine::Error> {
         let query = unsafe { query.as_str().not_null() }?;
-        let query_plan_json = unsafe { query_plan_json.as_str().not_null() }?;
-        let pkranges_json = unsafe { pkranges.as_str().not_null() }?;
+        let query = parse_query(query, parameters_json)?;
+        let pkranges = parse_pkranges(pkranges)?;
+
+        tracing::debug!(query = ?query, pkranges = ?pkranges, "creating passthrough query pipeline");
+        let pipeline = QueryPipeline::new_passthrough(query, pkranges)?;
+        Ok(Pipeline::new(pipeline))
+    }
+
+    catch_panic(|| inner(query, parameters_json, pkranges)).into()
+}
+
+thread_local! {
+    /// The reason the last plan passed to [`cosmoscx_v0_query_pipeline_validate_plan`] on this thread was rejected.
+    static PLAN_VALIDATION_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
 
+/// Checks that the engine can execute a JSON query plan, without creating a pipeline for it.
+///
+/// See [`QueryPipeline::validate_plan`] for more information.
+///
+/// If the plan can't be executed, this returns the error code and, if `reason` is not null, sets it to a message describing why.
+/// The message is BORROWED from the engine, it must not be freed and only remains valid until the next call to this function on the same thread.
+/// If the plan can be executed, `reason` is set to an empty [`Str`].
+///
+/// # Parameters
+/// - `query_plan_json`: A [`Str`] containing the serialized query plan, as recieved from the gateway, in JSON.
+/// - `reason`: A pointer to a [`Str`] to receive the reason the plan was rejected, or null if the caller doesn't need it.
+///
+/// # Safety
+///
+/// The caller must ensure that `reason` is either null, or a valid, properly aligned pointer to a [`Str`] that the engine can write to.
+#[no_mangle]
+pub unsafe extern "C" fn cosmoscx_v0_query_pipeline_validate_plan(
+    query_plan_json: Str<'_>,
+    reason: *mut Str<'static>,
+) -> ResultCode {
+    fn inner(query_plan_json: Str<'_>) -> Result<(), azure_data_cosmos_engine::Error> {
+        let query_plan_json = unsafe { query_plan_json.as_str().not_null() }?;
         let query_plan: QueryPlan = serde_json::from_str(query_plan_json)
             .map_err(|e| ErrorKind::InvalidGatewayResponse.with_source(e))?;
-        let pkranges: PartitionKeyRangeResult = serde_json::from_str(pkranges_json)
-            .map_err(|e| ErrorKind::InvalidGatewayResponse.with_source(e))?;
+        QueryPipeline::validate_plan(&query_plan)
+    }
 
-        // SAFETY: We should no longer need either of the parameter slices, we copied them into owned data.
+    let result = catch_panic(|| inner(query_plan_json));
+    if let Some(reason) = unsafe { reason.as_mut() } {
+        *reason = match &result {
+            Ok(()) => Str::EMPTY,
+            Err(e) => PLAN_VALIDATION_ERROR.with_borrow_mut(|message| {
+                *message = e.to_string();
+
+                // SAFETY: The string lives in a thread-local, and is only replaced by the next call on this thread, as documented above.
+                Str::from(unsafe { std::slice::from_raw_parts(message.as_ptr(), message.len()) })
+            }),
+        };
+    }
+    result.into()
+}
+
+/// Re-creates a query pipeline from the state captured by [`cosmoscx_v0_query_pipeline_serialize_state`].
+///
+/// See [`QueryPipeline::resume`](azure_data_cosmos_engine::query::QueryPipeline::resume) for more information.
+///
+/// # Parameters
+/// - `query_plan_json`: A [`Str`] containing the serialized query plan, as recieved from the gateway, in JSON.
+/// - `pkranges`: A [`Str`] containing the serialized partition key ranges list, as recieved from the gateway, in JSON.
+/// - `state`: A [`Str`] containing the state returned by [`cosmoscx_v0_query_pipeline_serialize_state`].
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_resume<'a>(
+    query: Str<'a>,
+    query_plan_json: Str<'a>,
+    pkranges: Str<'a>,
+    state: Str<'a>,
+) -> FfiResult<Pipeline> {
+    fn inner<'a>(
+        query: Str<'a>,
+        query_plan_json: Str<'a>,
+        pkranges: Str<'a>,
+        state: Str<'a>,
+    ) -> Result<Box<Pipeline>, azure_data_cosmos_engine::Error> {
+        let (query, query_plan, pkranges) = parse_pipeline_args(query, query_plan_json, pkranges)?;
+        let state = unsafe { state.as_str().not_null() }?;
 
-        tracing::debug!(query = ?query, query_plan = ?query_plan, pkranges = ?pkranges.ranges, "creating query pipeline");
-        let pipeline = QueryPipeline::new(query, query_plan, pkranges.ranges)?;
-        Ok(Box::new(pipeline))
+        tracing::debug!(query = ?query, query_plan = ?query_plan, pkranges = ?pkranges, "resuming query pipeline");
+        let pipeline = QueryPipeline::resume(query, query_plan, pkranges, state)?;
+        Ok(Pipeline::new(pipeline))
     }
 
-    inner(query, query_plan_json, pkranges).into()
+    catch_panic(|| inner(query, query_plan_json, pkranges, state)).into()
 }
 
 /// Frees the memory associated with a pipeline.
@@ -108,13 +378,31 @@ pub extern "C" fn cosmoscx_v0_query_pipeline_query(
         let pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
 
         let query = match pipeline.query() {
-            Some(x) => x.into(),
+            Some(x) => unsafe { Pipeline::borrow_str(x) },
             None => Str::EMPTY,
         };
         Ok(Box::new(query))
     }
 
-    inner(pipeline).into()
+    catch_panic(|| inner(pipeline)).into()
+}
+
+/// Gets the ID of the query this pipeline is executing, which the pipeline includes in its tracing spans.
+///
+/// The string returned here should be copied to a language-specific string type before being used.
+/// It remains valid until the pipeline is freed by a call to [`cosmoscx_v0_query_pipeline_free`].
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_id(
+    pipeline: *mut Pipeline,
+) -> FfiResult<Str<'static>> {
+    fn inner(
+        pipeline: *mut Pipeline,
+    ) -> Result<Box<Str<'static>>, azure_data_cosmos_engine::Error> {
+        let pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+        Ok(Box::new(unsafe { Pipeline::borrow_str(pipeline.id()) }))
+    }
+
+    catch_panic(|| inner(pipeline)).into()
 }
 
 /// Represents a request for more data from the pipeline.
@@ -138,6 +426,42 @@ pub struct DataRequest {
     /// A boolean indicating if parameters should be included in the query request.
     /// If this value is false, the query should be executed without parameters.
     include_parameters: bool,
+
+    /// A hint for the maximum number of items to request in this page, or 0 if the language binding should use its own default.
+    /// See [`DataRequest::max_item_count`](azure_data_cosmos_engine::query::DataRequest::max_item_count).
+    max_item_count: u32,
+
+    /// An [`OwnedString`] containing the parameters to send with the query, encoded as the JSON `parameters` field of a Cosmos query,
+    /// or an empty slice (len == 0) if the query should be executed without parameters.
+    /// This is only set for pipelines created by [`cosmoscx_v0_query_pipeline_create_with_parameters`].
+    encoded_parameters: OwnedString,
+}
+
+fn into_data_requests(
+    requests: Vec<azure_data_cosmos_engine::query::DataRequest>,
+) -> OwnedSlice<DataRequest> {
+    requests
+        .into_iter()
+        .map(|r| DataRequest {
+            id: r.id,
+            pkrangeid: r.pkrange_id.into_owned().into(),
+            continuation: match r.continuation {
+                None => OwnedSlice::EMPTY,
+                Some(s) => s.into(),
+            },
+            query: match r.query {
+                None => OwnedSlice::EMPTY,
+                Some(s) => s.into(),
+            },
+            include_parameters: r.include_parameters,
+            max_item_count: r.max_item_count.unwrap_or(0),
+            encoded_parameters: match r.encoded_parameters {
+                None => OwnedSlice::EMPTY,
+                Some(p) => Box::<str>::from(p).into_string().into(),
+            },
+        })
+        .collect::<Vec<_>>()
+        .into()
 }
 
 /// Represents the result of a single execution of the query pipeline.
@@ -151,6 +475,78 @@ pub struct PipelineResult {
 
     /// An [`OwnedSlice`] of [`DataRequest`]s describing additional requests that must be made and provided to [`cosmoscx_v0_query_pipeline_provide_data`] before retrieving the next batch.
     requests: OwnedSlice<DataRequest>,
+
+    /// The total request charge of every response provided to the pipeline so far.
+    cumulative_request_charge: f64,
+
+    /// An [`OwnedSlice`] of [`OwnedString`]s containing the activity IDs of the responses the pipeline accepted since the previous turn.
+    /// See [`PipelineResponse::activity_ids`](azure_data_cosmos_engine::query::PipelineResponse::activity_ids).
+    activity_ids: OwnedSlice<OwnedString>,
+}
+
+/// The location of a single item in [`ResultBatch::item_data`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemSpan {
+    /// The offset, in bytes, of the first byte of the item's JSON.
+    offset: usize,
+
+    /// The length, in bytes, of the item's JSON.
+    len: usize,
+}
+
+/// Represents the result of a single execution of the query pipeline, with every item in a single buffer.
+///
+/// This is returned by [`cosmoscx_v0_query_pipeline_run_batch`], and has the same fields as [`PipelineResult`], except for the items.
+#[repr(C)]
+pub struct ResultBatch {
+    /// A boolean indicating if the pipeline has completed.
+    completed: bool,
+
+    /// An [`OwnedString`] containing the JSON of every item in the output, one after the other, with nothing between them.
+    item_data: OwnedString,
+
+    /// An [`OwnedSlice`] of [`ItemSpan`]s locating the JSON for each item in the output, in order, within [`ResultBatch::item_data`].
+    items: OwnedSlice<ItemSpan>,
+
+    /// An [`OwnedSlice`] of [`DataRequest`]s describing additional requests that must be made and provided to [`cosmoscx_v0_query_pipeline_provide_data`] before retrieving the next batch.
+    requests: OwnedSlice<DataRequest>,
+
+    /// The total request charge of every response provided to the pipeline so far.
+    cumulative_request_charge: f64,
+
+    /// An [`OwnedSlice`] of [`OwnedString`]s containing the activity IDs of the responses the pipeline accepted since the previous turn.
+    /// See [`PipelineResponse::activity_ids`](azure_data_cosmos_engine::query::PipelineResponse::activity_ids).
+    activity_ids: OwnedSlice<OwnedString>,
+}
+
+/// Identifies the kind of [`PipelineEvent`] returned by [`cosmoscx_v0_query_pipeline_next_item`].
+/// cbindgen:prefix-with-name
+/// cbindgen:rename-all=SCREAMING_SNAKE_CASE
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineEventKind {
+    /// The event contains the next item in the query results, in [`PipelineEvent::item`].
+    Item = 0,
+
+    /// No item can be produced until the requests in [`PipelineEvent::requests`] are fulfilled.
+    NeedsData = 1,
+
+    /// The query has completed, and no further items will be produced.
+    Done = 2,
+}
+
+/// Represents the result of pulling a single item from the query pipeline.
+#[repr(C)]
+pub struct PipelineEvent {
+    /// The kind of event, which determines which of the other fields are populated.
+    kind: PipelineEventKind,
+
+    /// An [`OwnedString`] containing the JSON for the item, if [`PipelineEvent::kind`] is [`PipelineEventKind::Item`]. Otherwise, an empty slice (len == 0).
+    item: OwnedString,
+
+    /// An [`OwnedSlice`] of [`DataRequest`]s that must be made and provided to the pipeline, if [`PipelineEvent::kind`] is [`PipelineEventKind::NeedsData`]. Otherwise, an empty slice (len == 0).
+    requests: OwnedSlice<DataRequest>,
 }
 
 /// Represents a response to a single data request from the pipeline.
@@ -169,6 +565,100 @@ pub struct QueryResponse<'a> {
     continuation: Str<'a>,
 }
 
+/// Represents a response to a single data request from the pipeline, along with its request charge.
+///
+/// This has the same fields as [`QueryResponse`], followed by the request charge. It's a separate struct, accepted by [`cosmoscx_v0_query_pipeline_provide_data_v2`],
+/// so that adding the request charge doesn't change the layout of [`QueryResponse`] for existing callers of [`cosmoscx_v0_query_pipeline_provide_data`].
+#[repr(C)]
+pub struct QueryResponseV2<'a> {
+    /// The Partition Key Range ID this response is for.
+    pkrange_id: Str<'a>,
+
+    /// The unique identifier for the request this response is for. This must exactly match the [`DataRequest::id`] field of the corresponding [`DataRequest`].
+    request_id: u64,
+
+    /// The raw data being provided to the pipeline in response to the request.
+    data: Str<'a>,
+
+    /// The continuation token to provide, or an empty slice (len == 0) if no continuation should be provided.
+    continuation: Str<'a>,
+
+    /// The request charge reported with the data, or `0.0` if it isn't known. This is only used for [`PipelineResult::cumulative_request_charge`].
+    request_charge: f64,
+}
+
+/// A response to a data request, in one of the layouts accepted by the C API.
+trait ProvidedResponse {
+    /// Gets the request ID, Partition Key Range ID, data, continuation and request charge of this response.
+    fn parts(&self) -> (u64, &Str<'_>, &Str<'_>, &Str<'_>, f64);
+}
+
+impl ProvidedResponse for QueryResponse<'_> {
+    fn parts(&self) -> (u64, &Str<'_>, &Str<'_>, &Str<'_>, f64) {
+        (
+            self.request_id,
+            &self.pkrange_id,
+            &self.data,
+            &self.continuation,
+            0.0,
+        )
+    }
+}
+
+impl ProvidedResponse for QueryResponseV2<'_> {
+    fn parts(&self) -> (u64, &Str<'_>, &Str<'_>, &Str<'_>, f64) {
+        (
+            self.request_id,
+            &self.pkrange_id,
+            &self.data,
+            &self.continuation,
+            self.request_charge,
+        )
+    }
+}
+
+/// The metadata the backend returned, in its response headers, with the data provided to [`cosmoscx_v0_query_pipeline_provide_data_with_metadata`].
+///
+/// See [`ResponseMetadata`](azure_data_cosmos_engine::query::ResponseMetadata) for more information.
+#[repr(C)]
+pub struct ResponseMetadata<'a> {
+    /// The request charge reported with the data, from the `x-ms-request-charge` header, or `0.0` if it isn't known.
+    request_charge: f64,
+
+    /// The activity ID of the request, from the `x-ms-activity-id` header, or an empty or null slice if it isn't known.
+    activity_id: Str<'a>,
+
+    /// The delay the backend asked for before the next request, in milliseconds, from the `x-ms-retry-after-ms` header, or `0` if there was none.
+    retry_after_ms: u64,
+}
+
+/// Captures the state of the pipeline, so that the query can be resumed later by [`cosmoscx_v0_query_pipeline_resume`].
+///
+/// See [`QueryPipeline::serialize_state`](azure_data_cosmos_engine::query::QueryPipeline::serialize_state) for more information.
+///
+/// The [`OwnedString`] returned by this function MUST be freed using [`cosmoscx_v0_query_pipeline_free_state`].
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_serialize_state(
+    pipeline: *mut Pipeline,
+) -> FfiResult<OwnedString> {
+    fn inner(pipeline: *mut Pipeline) -> Result<Box<OwnedString>, azure_data_cosmos_engine::Error> {
+        let pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+        Ok(Box::new(pipeline.serialize_state()?.into()))
+    }
+
+    catch_panic(|| inner(pipeline)).into()
+}
+
+/// Frees the memory associated with a state string returned by [`cosmoscx_v0_query_pipeline_serialize_state`].
+///
+/// # Safety
+///
+/// The caller must ensure that the pointer passed to this function is a valid pointer to an [`OwnedString`] returned by [`cosmoscx_v0_query_pipeline_serialize_state`].
+#[no_mangle]
+pub unsafe extern "C" fn cosmoscx_v0_query_pipeline_free_state(state: *mut OwnedString) {
+    unsafe { crate::free(state) }
+}
+
 /// Executes a single turn of the query pipeline.
 ///
 /// See [`QueryPipeline::run`](azure_data_cosmos_engine::query::QueryPipeline::run) for more information on "turns".
@@ -183,7 +673,7 @@ pub extern "C" fn cosmoscx_v0_query_pipeline_run(
     fn inner(
         pipeline: *mut Pipeline,
     ) -> Result<Box<PipelineResult>, azure_data_cosmos_engine::Error> {
-        let pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+        let mut pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
         let result = pipeline.run()?;
 
         // Box up each of the JSON values in the batch.
@@ -195,33 +685,23 @@ pub extern "C" fn cosmoscx_v0_query_pipeline_run(
             .into();
 
         // And box up the requests.
-        let requests = result
-            .requests
-            .into_iter()
-            .map(|r| DataRequest {
-                id: r.id,
-                pkrangeid: r.pkrange_id.into_owned().into(),
-                continuation: match r.continuation {
-                    None => OwnedSlice::EMPTY,
-                    Some(s) => s.into(),
-                },
-                query: match r.query {
-                    None => OwnedSlice::EMPTY,
-                    Some(s) => s.into(),
-                },
-                include_parameters: r.include_parameters,
-            })
-            .collect::<Vec<_>>()
-            .into();
+        let requests = into_data_requests(result.requests);
 
         Ok(Box::new(PipelineResult {
             completed: result.terminated,
             items,
             requests,
+            cumulative_request_charge: result.cumulative_request_charge,
+            activity_ids: result
+                .activity_ids
+                .into_iter()
+                .map(OwnedString::from)
+                .collect::<Vec<_>>()
+                .into(),
         }))
     }
 
-    inner(pipeline).into()
+    catch_panic(|| inner(pipeline)).into()
 }
 
 /// Frees all the memory associated with a [`PipelineResult`].
@@ -236,43 +716,1383 @@ pub unsafe extern "C" fn cosmoscx_v0_query_pipeline_free_result(result: *mut Pip
     unsafe { crate::free(result) }
 }
 
-/// Inserts additional raw data, in response to a [`DataRequest`] from the pipeline.
+/// Executes a single turn of the query pipeline, like [`cosmoscx_v0_query_pipeline_run`], but returns every item in a single buffer.
+///
+/// Each item in a [`PipelineResult`] is a separate allocation, so a language binding that copies every item out of the result
+/// makes a copy per item. With a [`ResultBatch`], the binding can copy [`ResultBatch::item_data`] once, and use [`ResultBatch::items`] to find each item in it.
+///
+/// The [`ResultBatch`] returned by this function MUST be freed using [`cosmoscx_v0_query_pipeline_free_batch`] to release the memory associated with the batch.
 #[no_mangle]
-pub extern "C" fn cosmoscx_v0_query_pipeline_provide_data<'a>(
+pub extern "C" fn cosmoscx_v0_query_pipeline_run_batch(
     pipeline: *mut Pipeline,
-    responses: Slice<'a, QueryResponse<'a>>,
+) -> FfiResult<ResultBatch> {
+    fn inner(pipeline: *mut Pipeline) -> Result<Box<ResultBatch>, azure_data_cosmos_engine::Error> {
+        let mut pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+        let result = pipeline.run()?;
+
+        let (item_data, spans) = ItemArena::new(&result.items).into_parts();
+        let items = spans
+            .into_iter()
+            .map(|span| ItemSpan {
+                offset: span.start,
+                len: span.len(),
+            })
+            .collect::<Vec<_>>()
+            .into();
+
+        Ok(Box::new(ResultBatch {
+            completed: result.terminated,
+            item_data: item_data.into(),
+            items,
+            requests: into_data_requests(result.requests),
+            cumulative_request_charge: result.cumulative_request_charge,
+            activity_ids: result
+                .activity_ids
+                .into_iter()
+                .map(OwnedString::from)
+                .collect::<Vec<_>>()
+                .into(),
+        }))
+    }
+
+    catch_panic(|| inner(pipeline)).into()
+}
+
+/// Frees all the memory associated with a [`ResultBatch`], including the buffer holding its items.
+///
+/// # Safety
+///
+/// The caller must ensure that the pointer passed to this function is a valid pointer to a [`ResultBatch`] returned by [`cosmoscx_v0_query_pipeline_run_batch`].
+#[no_mangle]
+pub unsafe extern "C" fn cosmoscx_v0_query_pipeline_free_batch(batch: *mut ResultBatch) {
+    unsafe { crate::free(batch) }
+}
+
+/// Pulls a single item from the query pipeline.
+///
+/// See [`QueryPipeline::next_item`](azure_data_cosmos_engine::query::QueryPipeline::next_item) for more information.
+///
+/// The [`PipelineEvent`] returned by this function MUST be freed using [`cosmoscx_v0_query_pipeline_free_event`].
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_next_item(
+    pipeline: *mut Pipeline,
+) -> FfiResult<PipelineEvent> {
+    fn inner(
+        pipeline: *mut Pipeline,
+    ) -> Result<Box<PipelineEvent>, azure_data_cosmos_engine::Error> {
+        use azure_data_cosmos_engine::query::PipelineEvent as Event;
+
+        let mut pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+        let event = match pipeline.next_item()? {
+            Event::Item(item) => PipelineEvent {
+                kind: PipelineEventKind::Item,
+                item: Box::<str>::from(item).into_boxed_bytes().into(),
+                requests: OwnedSlice::EMPTY,
+            },
+            Event::NeedsData(requests) => PipelineEvent {
+                kind: PipelineEventKind::NeedsData,
+                item: OwnedSlice::EMPTY,
+                requests: into_data_requests(requests),
+            },
+            Event::Done => PipelineEvent {
+                kind: PipelineEventKind::Done,
+                item: OwnedSlice::EMPTY,
+                requests: OwnedSlice::EMPTY,
+            },
+        };
+        Ok(Box::new(event))
+    }
+
+    catch_panic(|| inner(pipeline)).into()
+}
+
+/// Frees all the memory associated with a [`PipelineEvent`].
+///
+/// # Safety
+///
+/// The caller must ensure that the pointer passed to this function is a valid pointer to a [`PipelineEvent`] returned by [`cosmoscx_v0_query_pipeline_next_item`].
+#[no_mangle]
+pub unsafe extern "C" fn cosmoscx_v0_query_pipeline_free_event(event: *mut PipelineEvent) {
+    unsafe { crate::free(event) }
+}
+
+/// Cancels the query, releasing all the items the pipeline has buffered.
+///
+/// This is safe to call from any thread, even while another thread is calling a function on the same pipeline.
+/// In that case, the function in progress stops at the next item or page and returns [`ResultCode::Cancelled`], and cancels the query itself.
+/// After this, [`cosmoscx_v0_query_pipeline_run`] returns no items and no requests, and reports that the pipeline has completed.
+/// The pipeline must still be freed using [`cosmoscx_v0_query_pipeline_free`], once no other thread is using it.
+///
+/// See [`QueryPipeline::cancellation_token`](azure_data_cosmos_engine::query::QueryPipeline::cancellation_token) for more information.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_cancel(pipeline: *mut Pipeline) -> ResultCode {
+    fn inner(pipeline: *mut Pipeline) -> Result<(), azure_data_cosmos_engine::Error> {
+        let pipeline = unsafe { pipeline.as_ref() }
+            .ok_or_else(|| ErrorKind::ArgumentNull.with_message("pipeline was null"))?;
+        pipeline.cancellation.cancel();
+
+        // The thread using the pipeline, if any, cancels the query itself, once it sees the token.
+        match pipeline.pipeline.try_lock() {
+            Ok(mut pipeline) => pipeline.cancel(),
+            Err(TryLockError::Poisoned(e)) => e.into_inner().cancel(),
+            Err(TryLockError::WouldBlock) => {}
+        }
+        Ok(())
+    }
+
+    catch_panic(|| inner(pipeline)).into()
+}
+
+/// Replaces a partition that the backend reported as gone, because it was split, with the partitions it was split into.
+///
+/// The language binding calls this, instead of providing data, when a request fails with HTTP 410 and sub-status 1002 (partition key range gone).
+/// The next call to [`cosmoscx_v0_query_pipeline_run`] requests data from each replacement.
+/// Queries that can't handle the split fail with [`ResultCode::UnsupportedQueryPlan`], and must be restarted.
+///
+/// See [`QueryPipeline::handle_pkrange_gone`](azure_data_cosmos_engine::query::QueryPipeline::handle_pkrange_gone) for more information.
+///
+/// # Parameters
+/// - `old_pkrange_id`: A [`Str`] containing the ID of the Partition Key Range that is gone.
+/// - `replacements`: A [`Str`] containing the Partition Key Ranges that replaced it, in the same JSON format as the partition key ranges list recieved from the gateway.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_handle_pkrange_gone<'a>(
+    pipeline: *mut Pipeline,
+    old_pkrange_id: Str<'a>,
+    replacements: Str<'a>,
 ) -> ResultCode {
     fn inner<'a>(
         pipeline: *mut Pipeline,
-        responses: Slice<'a, QueryResponse<'a>>,
+        old_pkrange_id: Str<'a>,
+        replacements: Str<'a>,
     ) -> Result<(), azure_data_cosmos_engine::Error> {
-        let pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+        let mut pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+        let old_pkrange_id = unsafe { old_pkrange_id.as_str().not_null() }?;
+        let replacements = parse_pkranges(replacements)?;
+        pipeline.handle_pkrange_gone(old_pkrange_id, replacements)
+    }
 
-        let responses = unsafe { responses.as_slice() }.ok_or_else(|| {
-            ErrorKind::ArgumentNull.with_message("responses slice pointer was null")
-        })?;
-
-        for response in responses {
-            let pkrange_id = unsafe { response.pkrange_id.as_str().not_null()? };
-            let data = unsafe { response.data.as_str().not_null()? };
-            let continuation = unsafe {
-                match response.continuation.into_string()? {
-                    // Normalize empty strings to 'None'
-                    Some(s) if s.is_empty() => None,
-                    x => x,
-                }
+    catch_panic(|| inner(pipeline, old_pkrange_id, replacements)).into()
+}
+
+/// Inserts additional raw data, in response to a single [`DataRequest`] from the pipeline.
+///
+/// Some pipelines, such as the one used for hybrid search queries, issue several requests to the same partition at once.
+/// The `request_id` is used to route the data to the request it fulfills, so it must exactly match the [`DataRequest::id`] field of that request.
+///
+/// # Parameters
+/// - `request_id`: The [`DataRequest::id`] of the request this data is for.
+/// - `pkrange_id`: A [`Str`] containing the Partition Key Range ID the data was retrieved from.
+/// - `data`: A [`Str`] containing the raw response body, in JSON.
+/// - `continuation`: A [`Str`] containing the continuation token returned with the data, or an empty or null slice if there was no continuation, which indicates the partition has no more data.
+/// - `request_charge`: The request charge reported with the data, or `0.0` if it isn't known. This is only used for [`PipelineResult::cumulative_request_charge`].
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_provide_data_for_request<'a>(
+    pipeline: *mut Pipeline,
+    request_id: u64,
+    pkrange_id: Str<'a>,
+    data: Str<'a>,
+    continuation: Str<'a>,
+    request_charge: f64,
+) -> ResultCode {
+    fn inner<'a>(
+        pipeline: *mut Pipeline,
+        request_id: u64,
+        pkrange_id: Str<'a>,
+        data: Str<'a>,
+        continuation: Str<'a>,
+        request_charge: f64,
+    ) -> Result<(), azure_data_cosmos_engine::Error> {
+        let mut pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+        provide_response(
+            &mut pipeline,
+            request_id,
+            &pkrange_id,
+            &data,
+            &continuation,
+            azure_data_cosmos_engine::query::ResponseMetadata {
+                request_charge,
+                ..Default::default()
+            },
+        )
+    }
+
+    catch_panic(|| {
+        inner(
+            pipeline,
+            request_id,
+            pkrange_id,
+            data,
+            continuation,
+            request_charge,
+        )
+    })
+    .into()
+}
+
+/// Inserts additional raw data, in response to a single [`DataRequest`] from the pipeline, along with the metadata from the response headers.
+///
+/// This is equivalent to [`cosmoscx_v0_query_pipeline_provide_data_for_request`], except that the activity ID is also collected,
+/// and returned in [`PipelineResult::activity_ids`].
+/// See [`QueryPipeline::provide_data_with_metadata`](azure_data_cosmos_engine::query::QueryPipeline::provide_data_with_metadata) for more information.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_provide_data_with_metadata<'a>(
+    pipeline: *mut Pipeline,
+    request_id: u64,
+    pkrange_id: Str<'a>,
+    data: Str<'a>,
+    continuation: Str<'a>,
+    metadata: ResponseMetadata<'a>,
+) -> ResultCode {
+    fn inner<'a>(
+        pipeline: *mut Pipeline,
+        request_id: u64,
+        pkrange_id: Str<'a>,
+        data: Str<'a>,
+        continuation: Str<'a>,
+        metadata: ResponseMetadata<'a>,
+    ) -> Result<(), azure_data_cosmos_engine::Error> {
+        let mut pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+        let activity_id = unsafe {
+            match metadata.activity_id.into_string()? {
+                Some(s) if s.is_empty() => None,
+                x => x,
+            }
+        };
+        provide_response(
+            &mut pipeline,
+            request_id,
+            &pkrange_id,
+            &data,
+            &continuation,
+            azure_data_cosmos_engine::query::ResponseMetadata {
+                request_charge: metadata.request_charge,
+                activity_id,
+                retry_after_ms: (metadata.retry_after_ms != 0).then_some(metadata.retry_after_ms),
+                query_metrics: None,
+            },
+        )
+    }
+
+    catch_panic(|| {
+        inner(
+            pipeline,
+            request_id,
+            pkrange_id,
+            data,
+            continuation,
+            metadata,
+        )
+    })
+    .into()
+}
+
+/// Inserts additional raw data, in response to a [`DataRequest`] from the pipeline.
+///
+/// This is equivalent to [`cosmoscx_v0_query_pipeline_provide_data_v2`], except that the request charges aren't known, so they aren't counted in [`PipelineResult::cumulative_request_charge`].
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_provide_data<'a>(
+    pipeline: *mut Pipeline,
+    responses: Slice<'a, QueryResponse<'a>>,
+) -> ResultCode {
+    catch_panic(|| provide_responses(pipeline, responses, &mut 0)).into()
+}
+
+/// Inserts additional raw data, along with its request charge, in response to a [`DataRequest`] from the pipeline.
+///
+/// This is equivalent to [`cosmoscx_v0_query_pipeline_provide_data_batch`], without reporting how many responses were applied.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_query_pipeline_provide_data_v2<'a>(
+    pipeline: *mut Pipeline,
+    responses: Slice<'a, QueryResponseV2<'a>>,
+) -> ResultCode {
+    catch_panic(|| provide_responses(pipeline, responses, &mut 0)).into()
+}
+
+/// Inserts the responses to several [`DataRequest`]s from the pipeline in a single call.
+///
+/// This is equivalent to calling [`cosmoscx_v0_query_pipeline_provide_data_for_request`] for each [`QueryResponseV2`] in `responses`, in order,
+/// and allows language bindings to hand over every response from a fan-out of requests with a single call across the FFI boundary.
+///
+/// The responses are applied one at a time, so a failure part way through does NOT undo the responses before it.
+/// If the response at index `N` fails, this returns the error for that response, responses `0..N` have been applied, and the responses after `N` have not been provided.
+/// The message returned by [`cosmoscx_v0_last_error_message`](crate::result::cosmoscx_v0_last_error_message) also includes the index `N`.
+/// The language binding can retry the responses from `N` onwards once it has handled the error, for example after a partition split.
+///
+/// See [`QueryPipeline::provide_data_batch`](azure_data_cosmos_engine::query::QueryPipeline::provide_data_batch) for more information.
+///
+/// # Parameters
+/// - `responses`: A [`Slice`] of the [`QueryResponseV2`]s to provide, which may be empty, with a null data pointer, if there are no responses.
+/// - `applied`: Receives the number of responses that were applied, which is the index of the failed response if this fails, or `responses.len` if it succeeds.
+///   This is always set, even if the call fails before any response is applied. It may be null if the caller doesn't need it.
+///
+/// # Safety
+///
+/// The caller must ensure that `applied` is either null, or a valid, properly aligned pointer to a `uintptr_t` that the engine can write to.
+#[no_mangle]
+pub unsafe extern "C" fn cosmoscx_v0_query_pipeline_provide_data_batch<'a>(
+    pipeline: *mut Pipeline,
+    responses: Slice<'a, QueryResponseV2<'a>>,
+    applied: *mut usize,
+) -> ResultCode {
+    let mut count = 0;
+    let result = catch_panic(|| provide_responses(pipeline, responses, &mut count));
+    if let Some(applied) = unsafe { applied.as_mut() } {
+        *applied = count;
+    }
+    result.into()
+}
+
+/// Provides each of the `responses` to the pipeline, in order, counting the responses that were applied in `applied`.
+fn provide_responses<'a, R: ProvidedResponse>(
+    pipeline: *mut Pipeline,
+    responses: Slice<'a, R>,
+    applied: &mut usize,
+) -> Result<(), azure_data_cosmos_engine::Error> {
+    let mut pipeline = unsafe { Pipeline::unwrap_ptr(pipeline) }?;
+    let responses = unsafe { responses.as_slice_or_empty("responses") }?;
+
+    for (index, response) in responses.iter().enumerate() {
+        let (request_id, pkrange_id, data, continuation, request_charge) = response.parts();
+        pipeline
+            .check_cancelled()
+            .and_then(|()| {
+                provide_response(
+                    &mut pipeline,
+                    request_id,
+                    pkrange_id,
+                    data,
+                    continuation,
+                    azure_data_cosmos_engine::query::ResponseMetadata {
+                        request_charge,
+                        ..Default::default()
+                    },
+                )
+            })
+            .map_err(|e| e.with_context(format!("failed to provide response {index}")))?;
+        *applied = index + 1;
+    }
+    Ok(())
+}
+
+/// Provides the data for a single request to the pipeline.
+fn provide_response(
+    pipeline: &mut QueryPipeline,
+    request_id: u64,
+    pkrange_id: &Str<'_>,
+    data: &Str<'_>,
+    continuation: &Str<'_>,
+    metadata: azure_data_cosmos_engine::query::ResponseMetadata,
+) -> Result<(), azure_data_cosmos_engine::Error> {
+    let pkrange_id = unsafe { pkrange_id.as_str().not_null()? };
+    let data = unsafe { data.as_str().not_null()? };
+    let continuation = unsafe {
+        match continuation.into_string()? {
+            // Normalize empty strings to 'None'
+            Some(s) if s.is_empty() => None,
+            x => x,
+        }
+    };
+
+    // Pass the raw bytes directly to the pipeline
+    pipeline.provide_data_with_metadata(
+        pkrange_id,
+        request_id,
+        data.as_bytes(),
+        continuation,
+        metadata,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HYBRID_PLAN: &str = r#"{
+        "partitionedQueryExecutionInfoVersion": 1,
+        "queryRanges": [],
+        "hybridSearchQueryInfo": {
+            "globalStatisticsQuery": "SELECT COUNT(1) AS documentCount, [] AS fullTextStatistics FROM c",
+            "componentQueryInfos": [
+                {"rewrittenQuery": "SELECT * FROM c WHERE c.docCount = {documentdb-formattablehybridsearchquery-totaldocumentcount} ORDER BY c.score0 DESC", "orderBy": ["Descending"]},
+                {"rewrittenQuery": "SELECT * FROM c ORDER BY c.score1 DESC", "orderBy": ["Descending"]}
+            ],
+            "componentWeights": [1.0, 1.0],
+            "skip": 0,
+            "take": 2,
+            "requiresGlobalStatistics": true
+        }
+    }"#;
+
+    const PKRANGES: &str =
+        r#"{"PartitionKeyRanges":[{"id":"partition0","minInclusive":"","maxExclusive":"FF"}]}"#;
+
+    fn component_response(scores: &[(&str, [f64; 2])]) -> String {
+        let documents = scores
+            .iter()
+            .map(|(rid, component_scores)| {
+                serde_json::json!({
+                    "_rid": rid,
+                    "payload": {
+                        "componentScores": component_scores,
+                        "payload": {"id": rid},
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({ "Documents": documents }).to_string()
+    }
+
+    struct OwnedRequest {
+        id: u64,
+        pkrange_id: String,
+        continuation: Option<String>,
+        query: Option<String>,
+        include_parameters: bool,
+        encoded_parameters: Option<String>,
+    }
+
+    impl From<DataRequest> for OwnedRequest {
+        fn from(request: DataRequest) -> Self {
+            Self {
+                id: request.id,
+                pkrange_id: unsafe { request.pkrangeid.into_string() }.unwrap().unwrap(),
+                continuation: unsafe { request.continuation.into_string() }.unwrap(),
+                query: unsafe { request.query.into_string() }.unwrap(),
+                include_parameters: request.include_parameters,
+                encoded_parameters: unsafe { request.encoded_parameters.into_string() }.unwrap(),
+            }
+        }
+    }
+
+    /// Runs a turn of the pipeline, returning the items, the requests, and whether the pipeline completed.
+    fn run(pipeline: *mut Pipeline) -> (Vec<String>, Vec<OwnedRequest>, bool) {
+        let result = unsafe { Box::from_raw(cosmoscx_v0_query_pipeline_run(pipeline).unwrap()) };
+        let PipelineResult {
+            completed,
+            items,
+            requests,
+            ..
+        } = *result;
+        let items = items
+            .into_boxed_slice()
+            .unwrap_or_default()
+            .into_vec()
+            .into_iter()
+            .map(|s| unsafe { s.into_string() }.unwrap().unwrap())
+            .collect();
+        let requests = requests
+            .into_boxed_slice()
+            .unwrap_or_default()
+            .into_vec()
+            .into_iter()
+            .map(OwnedRequest::from)
+            .collect();
+        (items, requests, completed)
+    }
+
+    /// Runs a turn of the pipeline like [`run`], but with [`cosmoscx_v0_query_pipeline_run_batch`].
+    fn run_batch(pipeline: *mut Pipeline) -> (Vec<String>, Vec<OwnedRequest>, bool) {
+        let batch =
+            unsafe { Box::from_raw(cosmoscx_v0_query_pipeline_run_batch(pipeline).unwrap()) };
+        let ResultBatch {
+            completed,
+            item_data,
+            items,
+            requests,
+            ..
+        } = *batch;
+        let item_data = item_data.into_boxed_slice().unwrap_or_default();
+        let items = items
+            .into_boxed_slice()
+            .unwrap_or_default()
+            .iter()
+            .map(|span| {
+                std::str::from_utf8(&item_data[span.offset..span.offset + span.len])
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        let requests = requests
+            .into_boxed_slice()
+            .unwrap_or_default()
+            .into_vec()
+            .into_iter()
+            .map(OwnedRequest::from)
+            .collect();
+        (items, requests, completed)
+    }
+
+    fn provide(pipeline: *mut Pipeline, request: &OwnedRequest, data: &str) {
+        let code = cosmoscx_v0_query_pipeline_provide_data_for_request(
+            pipeline,
+            request.id,
+            request.pkrange_id.as_str().into(),
+            data.into(),
+            "".into(),
+            1.5,
+        );
+        assert!(matches!(code, ResultCode::Success));
+    }
+
+    #[test]
+    pub fn order_by_query_through_c_api() {
+        const PLAN: &str = r#"{
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryRanges": [],
+            "queryInfo": {"orderBy": ["Ascending"]}
+        }"#;
+        const PKRANGES: &str = r#"{"PartitionKeyRanges":[
+            {"id":"partition0","minInclusive":"","maxExclusive":"80"},
+            {"id":"partition1","minInclusive":"80","maxExclusive":"FF"}
+        ]}"#;
+
+        fn page(values: &[u32]) -> String {
+            let documents = values
+                .iter()
+                .map(|n| serde_json::json!({"orderByItems": [{"item": n}], "payload": {"n": n}}))
+                .collect::<Vec<_>>();
+            serde_json::json!({ "Documents": documents }).to_string()
+        }
+
+        fn provide(
+            pipeline: *mut Pipeline,
+            request: &OwnedRequest,
+            data: &str,
+            continuation: &str,
+        ) {
+            let code = cosmoscx_v0_query_pipeline_provide_data_for_request(
+                pipeline,
+                request.id,
+                request.pkrange_id.as_str().into(),
+                data.into(),
+                continuation.into(),
+                1.0,
+            );
+            assert!(matches!(code, ResultCode::Success));
+        }
+
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c ORDER BY c.n".into(),
+            PLAN.into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+
+        let (items, requests, completed) = run(pipeline);
+        assert!(items.is_empty());
+        assert!(!completed);
+        assert_eq!(
+            vec!["partition0", "partition1"],
+            requests
+                .iter()
+                .map(|r| r.pkrange_id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert!(requests.iter().all(|r| r.continuation.is_none()));
+        provide(pipeline, &requests[0], &page(&[1, 4]), "page2");
+        provide(pipeline, &requests[1], &page(&[2, 3, 6]), "");
+
+        // The second page of partition0 is needed to know what comes after 4.
+        let (items, requests, completed) = run(pipeline);
+        assert_eq!(
+            vec![r#"{"n":1}"#, r#"{"n":2}"#, r#"{"n":3}"#, r#"{"n":4}"#],
+            items
+        );
+        assert!(!completed);
+        assert_eq!(1, requests.len());
+        assert_eq!("partition0", requests[0].pkrange_id);
+        assert_eq!(Some("page2"), requests[0].continuation.as_deref());
+        provide(pipeline, &requests[0], &page(&[5]), "");
+
+        let (items, requests, completed) = run(pipeline);
+        assert_eq!(vec![r#"{"n":5}"#, r#"{"n":6}"#], items);
+        assert!(requests.is_empty());
+        assert!(completed);
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+    }
+
+    #[test]
+    pub fn result_batch_matches_result() {
+        const PLAN: &str = r#"{
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryRanges": [],
+            "queryInfo": {"orderBy": ["Ascending"]}
+        }"#;
+        const PKRANGES: &str = r#"{"PartitionKeyRanges":[
+            {"id":"partition0","minInclusive":"","maxExclusive":"80"},
+            {"id":"partition1","minInclusive":"80","maxExclusive":"FF"}
+        ]}"#;
+
+        fn page(values: &[u32]) -> String {
+            let documents = values
+                .iter()
+                .map(|n| serde_json::json!({"orderByItems": [{"item": n}], "payload": {"n": n, "name": format!("item{n}")}}))
+                .collect::<Vec<_>>();
+            serde_json::json!({ "Documents": documents }).to_string()
+        }
+
+        let create = || {
+            cosmoscx_v0_query_pipeline_create(
+                "SELECT * FROM c ORDER BY c.n".into(),
+                PLAN.into(),
+                PKRANGES.into(),
+            )
+            .unwrap()
+        };
+        let pipeline = create();
+        let batched = create();
+
+        let pages = [page(&[1, 4, 5]), page(&[2, 3, 6])];
+        let mut all_items = Vec::new();
+        loop {
+            let (items, requests, completed) = run(pipeline);
+            let (batch_items, batch_requests, batch_completed) = run_batch(batched);
+            assert_eq!(items, batch_items);
+            assert_eq!(completed, batch_completed);
+            assert_eq!(requests.len(), batch_requests.len());
+            all_items.extend(batch_items);
+            if completed {
+                break;
+            }
+            for (request, batch_request) in requests.iter().zip(&batch_requests) {
+                let data = &pages[request.pkrange_id["partition".len()..]
+                    .parse::<usize>()
+                    .unwrap()];
+                provide(pipeline, request, data);
+                provide(batched, batch_request, data);
+            }
+        }
+        assert_eq!(
+            (1..=6)
+                .map(|n| format!(r#"{{"n":{n},"name":"item{n}"}}"#))
+                .collect::<Vec<_>>(),
+            all_items
+        );
+
+        // A turn without items has an empty buffer.
+        let (items, _, completed) = run_batch(batched);
+        assert!(items.is_empty());
+        assert!(completed);
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+        unsafe { cosmoscx_v0_query_pipeline_free(batched) };
+    }
+
+    #[test]
+    pub fn provide_data_batch_applies_every_response() {
+        const PLAN: &str = r#"{
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryRanges": [],
+            "queryInfo": {"orderBy": ["Ascending"]}
+        }"#;
+        const PKRANGES: &str = r#"{"PartitionKeyRanges":[
+            {"id":"partition0","minInclusive":"","maxExclusive":"55"},
+            {"id":"partition1","minInclusive":"55","maxExclusive":"AA"},
+            {"id":"partition2","minInclusive":"AA","maxExclusive":"FF"}
+        ]}"#;
+
+        fn page(values: &[u32]) -> String {
+            let documents = values
+                .iter()
+                .map(|n| serde_json::json!({"orderByItems": [{"item": n}], "payload": {"n": n}}))
+                .collect::<Vec<_>>();
+            serde_json::json!({ "Documents": documents }).to_string()
+        }
+
+        fn response<'a>(
+            request: &'a OwnedRequest,
+            request_id: u64,
+            data: &'a str,
+        ) -> QueryResponseV2<'a> {
+            QueryResponseV2 {
+                pkrange_id: request.pkrange_id.as_str().into(),
+                request_id,
+                data: data.into(),
+                continuation: Str::EMPTY,
+                request_charge: 1.0,
+            }
+        }
+
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c ORDER BY c.n".into(),
+            PLAN.into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+        let (_, requests, _) = run(pipeline);
+        assert_eq!(3, requests.len());
+        let pages = [page(&[1, 4]), page(&[2, 5]), page(&[3, 6])];
+
+        // The second response has the wrong request ID, so only the first is applied.
+        let responses = [
+            response(&requests[0], requests[0].id, &pages[0]),
+            response(&requests[1], requests[1].id + 1, &pages[1]),
+            response(&requests[2], requests[2].id, &pages[2]),
+        ];
+        let mut applied = usize::MAX;
+        let code = unsafe {
+            cosmoscx_v0_query_pipeline_provide_data_batch(
+                pipeline,
+                responses.as_slice().into(),
+                &mut applied,
+            )
+        };
+        assert!(matches!(code, ResultCode::InvalidRequestId));
+        assert_eq!(1, applied);
+        let message =
+            unsafe { std::ffi::CStr::from_ptr(crate::result::cosmoscx_v0_last_error_message()) };
+        assert!(message
+            .to_str()
+            .unwrap()
+            .starts_with("failed to provide response 1"));
+
+        // The caller retries from the failed response.
+        let responses = [
+            response(&requests[1], requests[1].id, &pages[1]),
+            response(&requests[2], requests[2].id, &pages[2]),
+        ];
+        let code = unsafe {
+            cosmoscx_v0_query_pipeline_provide_data_batch(
+                pipeline,
+                responses.as_slice().into(),
+                &mut applied,
+            )
+        };
+        assert!(matches!(code, ResultCode::Success));
+        assert_eq!(2, applied);
+
+        let (items, requests, completed) = run(pipeline);
+        assert_eq!(
+            (1..=6)
+                .map(|n| format!(r#"{{"n":{n}}}"#))
+                .collect::<Vec<_>>(),
+            items
+        );
+        assert!(requests.is_empty());
+        assert!(completed);
+
+        // Only the three applied responses are charged.
+        let result = unsafe { Box::from_raw(cosmoscx_v0_query_pipeline_run(pipeline).unwrap()) };
+        assert_eq!(3.0, result.cumulative_request_charge);
+
+        // An empty batch may use a null pointer, and applies nothing.
+        let code = unsafe {
+            cosmoscx_v0_query_pipeline_provide_data_batch(pipeline, Slice::EMPTY, &mut applied)
+        };
+        assert!(matches!(code, ResultCode::Success));
+        assert_eq!(0, applied);
+
+        // The count is reported even if the call fails before any response is applied.
+        applied = usize::MAX;
+        let code = unsafe {
+            cosmoscx_v0_query_pipeline_provide_data_batch(
+                std::ptr::null_mut(),
+                Slice::EMPTY,
+                &mut applied,
+            )
+        };
+        assert!(matches!(code, ResultCode::ArgumentNull));
+        assert_eq!(0, applied);
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+    }
+
+    #[test]
+    pub fn provide_data_v2_counts_request_charges() {
+        let charge = |v2: bool| {
+            let pipeline = cosmoscx_v0_query_pipeline_create(
+                "SELECT * FROM c".into(),
+                r#"{"partitionedQueryExecutionInfoVersion": 1, "queryRanges": [], "queryInfo": {}}"#
+                    .into(),
+                r#"{"PartitionKeyRanges":[{"id":"partition0","minInclusive":"","maxExclusive":"FF"}]}"#
+                    .into(),
+            )
+            .unwrap();
+            let (_, requests, _) = run(pipeline);
+            let pkrange_id: Str = requests[0].pkrange_id.as_str().into();
+            let data: Str = r#"{"Documents":[1,2]}"#.into();
+            let code = if v2 {
+                let responses = [QueryResponseV2 {
+                    pkrange_id,
+                    request_id: requests[0].id,
+                    data,
+                    continuation: Str::EMPTY,
+                    request_charge: 2.5,
+                }];
+                cosmoscx_v0_query_pipeline_provide_data_v2(pipeline, responses.as_slice().into())
+            } else {
+                let responses = [QueryResponse {
+                    pkrange_id,
+                    request_id: requests[0].id,
+                    data,
+                    continuation: Str::EMPTY,
+                }];
+                cosmoscx_v0_query_pipeline_provide_data(pipeline, responses.as_slice().into())
             };
+            assert!(matches!(code, ResultCode::Success));
+
+            let result =
+                unsafe { Box::from_raw(cosmoscx_v0_query_pipeline_run(pipeline).unwrap()) };
+            let charge = result.cumulative_request_charge;
+            drop(result);
+            unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+            charge
+        };
+
+        // QueryResponse keeps the layout it had before request charges were reported, so its charges aren't known.
+        assert_eq!(0.0, charge(false));
+        assert_eq!(2.5, charge(true));
+        assert_eq!(
+            std::mem::size_of::<QueryResponse>() + std::mem::size_of::<f64>(),
+            std::mem::size_of::<QueryResponseV2>()
+        );
+    }
+
+    #[test]
+    pub fn pipeline_id_is_stable() {
+        let create = || {
+            cosmoscx_v0_query_pipeline_create(
+                "SELECT * FROM c".into(),
+                r#"{"partitionedQueryExecutionInfoVersion": 1, "queryRanges": [], "queryInfo": {}}"#
+                    .into(),
+                PKRANGES.into(),
+            )
+            .unwrap()
+        };
+        let id = |pipeline| {
+            let id = unsafe { Box::from_raw(cosmoscx_v0_query_pipeline_id(pipeline).unwrap()) };
+            unsafe { id.as_str() }.unwrap().unwrap().to_string()
+        };
+
+        let pipeline = create();
+        let first = id(pipeline);
+        assert!(!first.is_empty());
+        run(pipeline);
+        run(pipeline);
+        assert_eq!(first, id(pipeline));
+
+        let other = create();
+        assert_ne!(first, id(other));
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+        unsafe { cosmoscx_v0_query_pipeline_free(other) };
+    }
+
+    #[test]
+    pub fn hybrid_search_through_c_api() {
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c".into(),
+            HYBRID_PLAN.into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+
+        // First, the global statistics query.
+        let (items, requests, completed) = run(pipeline);
+        assert!(items.is_empty());
+        assert!(!completed);
+        assert_eq!(1, requests.len());
+        assert_eq!(
+            Some("SELECT COUNT(1) AS documentCount, [] AS fullTextStatistics FROM c"),
+            requests[0].query.as_deref()
+        );
+        assert!(!requests[0].include_parameters);
+        provide(
+            pipeline,
+            &requests[0],
+            r#"{"Documents":[{"documentCount":42,"fullTextStatistics":[]}]}"#,
+        );
+
+        // Then one request for each component query, each with a distinct ID and the rewritten query.
+        let (items, requests, completed) = run(pipeline);
+        assert!(items.is_empty());
+        assert!(!completed);
+        assert_eq!(2, requests.len());
+        assert_ne!(requests[0].id, requests[1].id);
+        assert_eq!(
+            Some("SELECT * FROM c WHERE c.docCount = 42 ORDER BY c.score0 DESC"),
+            requests[0].query.as_deref()
+        );
+        assert_eq!(
+            Some("SELECT * FROM c ORDER BY c.score1 DESC"),
+            requests[1].query.as_deref()
+        );
+
+        // Both requests target the same partition, so only the request ID tells the responses apart.
+        provide(
+            pipeline,
+            &requests[1],
+            &component_response(&[("b", [0.2, 0.9]), ("c", [0.1, 0.8])]),
+        );
+        provide(
+            pipeline,
+            &requests[0],
+            &component_response(&[("a", [0.9, 0.1]), ("b", [0.2, 0.9])]),
+        );
+
+        let (items, requests, completed) = run(pipeline);
+        assert!(requests.is_empty());
+        assert!(completed);
+        assert_eq!(vec![r#"{"id":"b"}"#, r#"{"id":"a"}"#], items);
+
+        // Each of the three responses was provided with a charge of 1.5.
+        let result = unsafe { Box::from_raw(cosmoscx_v0_query_pipeline_run(pipeline).unwrap()) };
+        assert_eq!(4.5, result.cumulative_request_charge);
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+    }
+
+    #[test]
+    pub fn parameters_through_c_api() {
+        const PARAMETERS: &str = r#"[{"name":"@score","value":0.5}]"#;
+        let pipeline = cosmoscx_v0_query_pipeline_create_with_parameters(
+            "SELECT * FROM c".into(),
+            PARAMETERS.into(),
+            HYBRID_PLAN.into(),
+            PKRANGES.into(),
+            "".into(),
+        )
+        .unwrap();
 
-            // Pass the raw bytes directly to the pipeline
-            pipeline.provide_data(
-                pkrange_id,
-                response.request_id,
-                data.as_bytes(),
-                continuation,
-            )?;
+        // The global statistics query never includes the parameters.
+        let (_, requests, _) = run(pipeline);
+        assert_eq!(1, requests.len());
+        assert!(!requests[0].include_parameters);
+        assert_eq!(None, requests[0].encoded_parameters);
+        provide(
+            pipeline,
+            &requests[0],
+            r#"{"Documents":[{"documentCount":42,"fullTextStatistics":[]}]}"#,
+        );
+
+        // But the component queries do.
+        let (_, requests, _) = run(pipeline);
+        assert_eq!(2, requests.len());
+        for request in &requests {
+            assert!(request.include_parameters);
+            assert_eq!(Some(PARAMETERS), request.encoded_parameters.as_deref());
         }
-        Ok(())
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+
+        // Malformed parameters are rejected.
+        let code = cosmoscx_v0_query_pipeline_create_with_parameters(
+            "SELECT * FROM c".into(),
+            "[".into(),
+            HYBRID_PLAN.into(),
+            PKRANGES.into(),
+            "".into(),
+        )
+        .unwrap_err();
+        assert!(matches!(code, ResultCode::DeserializationError));
+    }
+
+    #[test]
+    pub fn passthrough_through_c_api() {
+        const PARAMETERS: &str = r#"[{"name":"@id","value":"item1"}]"#;
+        let pipeline = cosmoscx_v0_query_pipeline_create_passthrough(
+            "SELECT * FROM c WHERE c.id = @id".into(),
+            PARAMETERS.into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+
+        let (_, requests, _) = run(pipeline);
+        assert_eq!(1, requests.len());
+        assert_eq!("partition0", requests[0].pkrange_id);
+        assert_eq!(Some(PARAMETERS), requests[0].encoded_parameters.as_deref());
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+
+        // Queries that need a query plan are rejected.
+        let code = cosmoscx_v0_query_pipeline_create_passthrough(
+            "SELECT * FROM c ORDER BY c.id".into(),
+            Str::EMPTY,
+            PKRANGES.into(),
+        )
+        .unwrap_err();
+        assert!(matches!(code, ResultCode::InvalidQuery));
+    }
+
+    #[test]
+    pub fn metadata_through_c_api() {
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c".into(),
+            r#"{"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {}, "queryRanges": []}"#
+                .into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+        let (_, requests, _) = run(pipeline);
+        let code = cosmoscx_v0_query_pipeline_provide_data_with_metadata(
+            pipeline,
+            requests[0].id,
+            requests[0].pkrange_id.as_str().into(),
+            r#"{"Documents": [1]}"#.into(),
+            "page1".into(),
+            ResponseMetadata {
+                request_charge: 2.5,
+                activity_id: "activity0".into(),
+                retry_after_ms: 0,
+            },
+        );
+        assert!(matches!(code, ResultCode::Success));
+
+        /// Runs a turn of the pipeline, returning the request charge and activity IDs, and the requests.
+        fn run_metadata(pipeline: *mut Pipeline) -> (f64, Vec<String>, Vec<OwnedRequest>) {
+            let result =
+                unsafe { Box::from_raw(cosmoscx_v0_query_pipeline_run(pipeline).unwrap()) };
+            let PipelineResult {
+                cumulative_request_charge,
+                activity_ids,
+                requests,
+                ..
+            } = *result;
+            let activity_ids = activity_ids
+                .into_boxed_slice()
+                .unwrap_or_default()
+                .into_vec()
+                .into_iter()
+                .map(|s| unsafe { s.into_string() }.unwrap().unwrap())
+                .collect();
+            let requests = requests
+                .into_boxed_slice()
+                .unwrap_or_default()
+                .into_vec()
+                .into_iter()
+                .map(OwnedRequest::from)
+                .collect();
+            (cumulative_request_charge, activity_ids, requests)
+        }
+
+        let (charge, activity_ids, requests) = run_metadata(pipeline);
+        assert_eq!(2.5, charge);
+        assert_eq!(vec!["activity0"], activity_ids);
+
+        // Pages provided without metadata have no activity ID, and "activity0" was already reported.
+        provide(pipeline, &requests[0], r#"{"Documents": [2]}"#);
+        let (charge, activity_ids, _) = run_metadata(pipeline);
+        assert_eq!(4.0, charge);
+        assert!(activity_ids.is_empty());
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
     }
 
-    inner(pipeline, responses).into()
+    #[test]
+    pub fn pkrange_gone_through_c_api() {
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c".into(),
+            r#"{"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {}, "queryRanges": []}"#
+                .into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+        let (_, requests, _) = run(pipeline);
+        assert!(matches!(
+            cosmoscx_v0_query_pipeline_handle_pkrange_gone(
+                pipeline,
+                requests[0].pkrange_id.as_str().into(),
+                r#"{"PartitionKeyRanges":[
+                    {"id":"partition1","minInclusive":"","maxExclusive":"80"},
+                    {"id":"partition2","minInclusive":"80","maxExclusive":"FF"}
+                ]}"#
+                .into(),
+            ),
+            ResultCode::Success
+        ));
+
+        let (_, requests, _) = run(pipeline);
+        assert_eq!(1, requests.len());
+        assert_eq!("partition1", requests[0].pkrange_id);
+
+        // The replacements must cover the gone partition's range.
+        assert!(matches!(
+            cosmoscx_v0_query_pipeline_handle_pkrange_gone(
+                pipeline,
+                "partition1".into(),
+                r#"{"PartitionKeyRanges":[{"id":"partition3","minInclusive":"","maxExclusive":"40"}]}"#
+                    .into(),
+            ),
+            ResultCode::InvalidGatewayResponse
+        ));
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+    }
+
+    /// Pulls a single item from the pipeline, returning the event kind, the item, and the requests.
+    fn next_item(
+        pipeline: *mut Pipeline,
+    ) -> (PipelineEventKind, Option<String>, Vec<OwnedRequest>) {
+        let event =
+            unsafe { Box::from_raw(cosmoscx_v0_query_pipeline_next_item(pipeline).unwrap()) };
+        let PipelineEvent {
+            kind,
+            item,
+            requests,
+        } = *event;
+        let item = unsafe { item.into_string() }.unwrap();
+        let requests = requests
+            .into_boxed_slice()
+            .unwrap_or_default()
+            .into_vec()
+            .into_iter()
+            .map(OwnedRequest::from)
+            .collect();
+        (kind, item, requests)
+    }
+
+    #[test]
+    pub fn next_item_through_c_api() {
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c".into(),
+            HYBRID_PLAN.into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+
+        // Pulling again without providing data returns the same request.
+        let (kind, item, requests) = next_item(pipeline);
+        assert_eq!(PipelineEventKind::NeedsData, kind);
+        assert_eq!(None, item);
+        assert_eq!(1, requests.len());
+        let (kind, _, repeated) = next_item(pipeline);
+        assert_eq!(PipelineEventKind::NeedsData, kind);
+        assert_eq!(requests[0].id, repeated[0].id);
+        provide(
+            pipeline,
+            &requests[0],
+            r#"{"Documents":[{"documentCount":42,"fullTextStatistics":[]}]}"#,
+        );
+
+        let (kind, _, requests) = next_item(pipeline);
+        assert_eq!(PipelineEventKind::NeedsData, kind);
+        assert_eq!(2, requests.len());
+        provide(
+            pipeline,
+            &requests[0],
+            &component_response(&[("a", [0.9, 0.1]), ("b", [0.2, 0.9])]),
+        );
+        provide(
+            pipeline,
+            &requests[1],
+            &component_response(&[("b", [0.2, 0.9]), ("c", [0.1, 0.8])]),
+        );
+
+        let (kind, item, requests) = next_item(pipeline);
+        assert_eq!(PipelineEventKind::Item, kind);
+        assert_eq!(Some(r#"{"id":"b"}"#), item.as_deref());
+        assert!(requests.is_empty());
+        let (kind, item, _) = next_item(pipeline);
+        assert_eq!(PipelineEventKind::Item, kind);
+        assert_eq!(Some(r#"{"id":"a"}"#), item.as_deref());
+
+        // Once done, the pipeline stays done.
+        for _ in 0..2 {
+            let (kind, item, requests) = next_item(pipeline);
+            assert_eq!(PipelineEventKind::Done, kind);
+            assert_eq!(None, item);
+            assert!(requests.is_empty());
+        }
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+    }
+
+    #[test]
+    pub fn null_continuation_completes_partition() {
+        const PLAN: &str =
+            r#"{"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {}, "queryRanges": []}"#;
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c".into(),
+            PLAN.into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+
+        let (_, requests, _) = run(pipeline);
+        let code = cosmoscx_v0_query_pipeline_provide_data_for_request(
+            pipeline,
+            requests[0].id,
+            requests[0].pkrange_id.as_str().into(),
+            r#"{"Documents":[1,2]}"#.into(),
+            Str::EMPTY,
+            0.0,
+        );
+        assert!(matches!(code, ResultCode::Success));
+
+        let (items, requests, completed) = run(pipeline);
+        assert_eq!(vec!["1", "2"], items);
+        assert!(requests.is_empty());
+        assert!(completed);
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+    }
+
+    #[test]
+    pub fn create_with_options_through_c_api() {
+        const PLAN: &str =
+            r#"{"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {}, "queryRanges": []}"#;
+        const PKRANGES: &str = r#"{"PartitionKeyRanges":[
+            {"id":"partition0","minInclusive":"","maxExclusive":"80"},
+            {"id":"partition1","minInclusive":"80","maxExclusive":"FF"}
+        ]}"#;
+
+        for (options, expected_requests) in [
+            (Str::EMPTY, vec!["partition0"]),
+            (r#"{}"#.into(), vec!["partition0"]),
+            (
+                r#"{"prefetch_all_partitions": true}"#.into(),
+                vec!["partition0", "partition1"],
+            ),
+        ] {
+            let pipeline = cosmoscx_v0_query_pipeline_create_with_options(
+                "SELECT * FROM c".into(),
+                PLAN.into(),
+                PKRANGES.into(),
+                options,
+            )
+            .unwrap();
+            let (_, requests, _) = run(pipeline);
+            assert_eq!(
+                expected_requests,
+                requests
+                    .iter()
+                    .map(|r| r.pkrange_id.as_str())
+                    .collect::<Vec<_>>()
+            );
+            unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+        }
+
+        let code = cosmoscx_v0_query_pipeline_create_with_options(
+            "SELECT * FROM c".into(),
+            PLAN.into(),
+            PKRANGES.into(),
+            r#"{"prefetch": true}"#.into(),
+        )
+        .unwrap_err();
+        assert!(matches!(code, ResultCode::DeserializationError));
+    }
+
+    #[test]
+    pub fn cancel_through_c_api() {
+        const PLAN: &str =
+            r#"{"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {}, "queryRanges": []}"#;
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c".into(),
+            PLAN.into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+
+        let (_, requests, _) = run(pipeline);
+        let code = cosmoscx_v0_query_pipeline_provide_data_for_request(
+            pipeline,
+            requests[0].id,
+            requests[0].pkrange_id.as_str().into(),
+            r#"{"Documents":[1,2]}"#.into(),
+            "page1".into(),
+            0.0,
+        );
+        assert!(matches!(code, ResultCode::Success));
+
+        let code = cosmoscx_v0_query_pipeline_cancel(pipeline);
+        assert!(matches!(code, ResultCode::Success));
+        let (items, requests, completed) = run(pipeline);
+        assert!(items.is_empty());
+        assert!(requests.is_empty());
+        assert!(completed);
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+
+        let code = cosmoscx_v0_query_pipeline_cancel(std::ptr::null_mut());
+        assert!(matches!(code, ResultCode::ArgumentNull));
+    }
+
+    #[test]
+    pub fn cancel_from_another_thread_stops_call_in_progress() {
+        const PLAN: &str =
+            r#"{"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {}, "queryRanges": []}"#;
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c".into(),
+            PLAN.into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+
+        // Holding the lock stands in for a call in progress on this thread, which the cancellation mustn't wait for.
+        let guard = unsafe { Pipeline::unwrap_ptr(pipeline) }.unwrap();
+        let address = pipeline as usize;
+        let code =
+            std::thread::spawn(move || cosmoscx_v0_query_pipeline_cancel(address as *mut Pipeline))
+                .join()
+                .unwrap();
+        assert!(matches!(code, ResultCode::Success));
+        drop(guard);
+
+        // The call that sees the cancellation fails, and the pipeline has completed from then on.
+        let code = cosmoscx_v0_query_pipeline_run(pipeline).unwrap_err();
+        assert!(matches!(code, ResultCode::Cancelled));
+        let (items, requests, completed) = run(pipeline);
+        assert!(items.is_empty());
+        assert!(requests.is_empty());
+        assert!(completed);
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+    }
+
+    #[test]
+    pub fn provide_data_for_unknown_request_fails() {
+        let pipeline = cosmoscx_v0_query_pipeline_create(
+            "SELECT * FROM c".into(),
+            HYBRID_PLAN.into(),
+            PKRANGES.into(),
+        )
+        .unwrap();
+        let (_, requests, _) = run(pipeline);
+        provide(
+            pipeline,
+            &requests[0],
+            r#"{"Documents":[{"documentCount":42,"fullTextStatistics":[]}]}"#,
+        );
+        run(pipeline);
+
+        let code = cosmoscx_v0_query_pipeline_provide_data_for_request(
+            pipeline,
+            (7 << 32) | 1,
+            "partition0".into(),
+            r#"{"Documents":[]}"#.into(),
+            "".into(),
+            0.0,
+        );
+        assert!(matches!(code, ResultCode::InvalidRequestId));
+
+        unsafe { cosmoscx_v0_query_pipeline_free(pipeline) };
+    }
+
+    #[test]
+    pub fn validate_plan_through_c_api() {
+        let mut reason = Str::EMPTY;
+        let code =
+            unsafe { cosmoscx_v0_query_pipeline_validate_plan(HYBRID_PLAN.into(), &mut reason) };
+        assert!(matches!(code, ResultCode::Success));
+        assert_eq!(None, unsafe { reason.as_str() }.unwrap());
+
+        let code = unsafe {
+            cosmoscx_v0_query_pipeline_validate_plan(
+                r#"{"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {"distinctType": "Ordered"}, "queryRanges": []}"#.into(),
+                &mut reason,
+            )
+        };
+        assert!(matches!(code, ResultCode::UnsupportedQueryPlan));
+        assert_eq!(
+            Some("DISTINCT queries are not supported"),
+            unsafe { reason.as_str() }.unwrap()
+        );
+
+        // The reason is optional.
+        let code = unsafe {
+            cosmoscx_v0_query_pipeline_validate_plan(
+                r#"{"partitionedQueryExecutionInfoVersion": 1, "queryRanges": []}"#.into(),
+                std::ptr::null_mut(),
+            )
+        };
+        assert!(matches!(code, ResultCode::UnsupportedQueryPlan));
+    }
 }
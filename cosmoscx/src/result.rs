@@ -3,11 +3,16 @@
 
 //! FFI-safe types for communicating errors and the result of fallible functions.
 
+use std::{cell::RefCell, ffi::CString, panic::AssertUnwindSafe};
+
 use azure_data_cosmos_engine::ErrorKind;
 
 /// A result code for FFI functions, which indicates the success or failure of the operation.
 ///
 /// Values of `ResultCode` have the same representation as the C type `intptr_t`
+///
+/// Each error code is the negation of the stable code of the matching [`ErrorKind`], see [`ErrorKind::code`].
+/// Use [`ErrorKind::is_user_error`] and [`ErrorKind::is_internal`] to decide how an error should be reported.
 /// cbindgen:prefix-with-name
 /// cbindgen:rename-all=SCREAMING_SNAKE_CASE
 #[repr(isize)]
@@ -44,6 +49,24 @@ pub enum ResultCode {
 
     /// See [`ErrorKind::InvalidQuery`].
     InvalidQuery = -11,
+
+    /// See [`ErrorKind::MemoryBudgetExceeded`].
+    MemoryBudgetExceeded = -12,
+
+    /// See [`ErrorKind::StateNotSerializable`].
+    StateNotSerializable = -13,
+
+    /// See [`ErrorKind::BufferLimitExceeded`].
+    BufferLimitExceeded = -15,
+
+    /// See [`ErrorKind::SpillError`].
+    SpillError = -16,
+
+    /// See [`ErrorKind::InternalPanic`].
+    InternalPanic = -17,
+
+    /// See [`ErrorKind::Cancelled`].
+    Cancelled = -18,
 }
 
 impl From<azure_data_cosmos_engine::Error> for ResultCode {
@@ -67,7 +90,13 @@ impl From<ErrorKind> for ResultCode {
             ErrorKind::ArithmeticOverflow => ResultCode::ArithmeticOverflow,
             ErrorKind::InvalidRequestId => ResultCode::InvalidRequestId,
             ErrorKind::InvalidQuery => ResultCode::InvalidQuery,
+            ErrorKind::MemoryBudgetExceeded => ResultCode::MemoryBudgetExceeded,
+            ErrorKind::StateNotSerializable => ResultCode::StateNotSerializable,
             ErrorKind::PythonError => ResultCode::InternalError,
+            ErrorKind::BufferLimitExceeded => ResultCode::BufferLimitExceeded,
+            ErrorKind::SpillError => ResultCode::SpillError,
+            ErrorKind::InternalPanic => ResultCode::InternalPanic,
+            ErrorKind::Cancelled => ResultCode::Cancelled,
         }
     }
 }
@@ -80,16 +109,68 @@ impl From<Result<(), azure_data_cosmos_engine::Error>> for ResultCode {
     fn from(value: Result<(), azure_data_cosmos_engine::Error>) -> Self {
         match value {
             Ok(_) => ResultCode::Success,
-            Err(e) => {
-                tracing::error!(error = ?e, "an error occurred");
-                // TODO: Store the error details in a thread local to be retrieved by a "get last error" function.
-
-                e.into()
-            }
+            Err(e) => record_error(e),
         }
     }
 }
 
+/// Runs the body of a C API function, converting a panic into an [`ErrorKind::InternalPanic`] error.
+///
+/// Every C API function that calls into the engine must use this, since unwinding across the FFI boundary is undefined behavior.
+pub(crate) fn catch_panic<T>(
+    f: impl FnOnce() -> Result<T, azure_data_cosmos_engine::Error>,
+) -> Result<T, azure_data_cosmos_engine::Error> {
+    // A pipeline that panicked may be left in an inconsistent state, which ErrorKind::InternalPanic tells the caller to discard.
+    std::panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(azure_data_cosmos_engine::Error::from_panic(payload)))
+}
+
+thread_local! {
+    /// The kind and message of the last error returned by a C API function on this thread, see [`cosmoscx_v0_last_error_code`].
+    static LAST_ERROR: RefCell<Option<(ErrorKind, CString)>> = const { RefCell::new(None) };
+}
+
+/// Logs an error, and records it as the last error on this thread, before it's returned across the C API.
+fn record_error(error: azure_data_cosmos_engine::Error) -> ResultCode {
+    tracing::error!(error = ?error, "an error occurred");
+
+    // Include the sources, since errors wrapping a source often have no message of their own.
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(&error);
+    while let Some(s) = source {
+        message.push_str(": ");
+        message.push_str(&s.to_string());
+        source = s.source();
+    }
+    let message = CString::new(message.replace('\0', " ")).expect("interior NULs were replaced");
+    LAST_ERROR.set(Some((error.kind(), message)));
+    error.into()
+}
+
+/// Gets the result code of the last C API call on this thread that failed.
+///
+/// Returns [`ResultCode::Success`] if no call on this thread has failed. Successful calls don't reset the last error.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_last_error_code() -> ResultCode {
+    LAST_ERROR.with_borrow(|last| match last {
+        Some((kind, _)) => (*kind).into(),
+        None => ResultCode::Success,
+    })
+}
+
+/// Gets a message describing the error returned by the last C API call on this thread that failed, as a NUL-terminated string.
+///
+/// Returns null if no call on this thread has failed. The message is BORROWED from the engine, it must not be freed,
+/// and only remains valid until another call on the same thread fails.
+#[no_mangle]
+pub extern "C" fn cosmoscx_v0_last_error_message() -> *const std::ffi::c_char {
+    LAST_ERROR.with_borrow(|last| match last {
+        // SAFETY: The string lives in a thread-local, and is only replaced by the next failure on this thread, as documented above.
+        Some((_, message)) => message.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
 /// A result type for FFI functions.
 ///
 /// An `FfiResult` is returned from a function that both returns a value AND can fail.
@@ -125,15 +206,10 @@ impl<T, U> From<Result<Box<T>, azure_data_cosmos_engine::Error>> for FfiResult<U
                     value: ptr,
                 }
             }
-            Err(e) => {
-                tracing::error!(error = ?e, "an error occurred");
-                // TODO: Store the error details in a thread local to be retrieved by a "get last error" function.
-
-                Self {
-                    code: e.into(),
-                    value: std::ptr::null(),
-                }
-            }
+            Err(e) => Self {
+                code: record_error(e),
+                value: std::ptr::null(),
+            },
         }
     }
 }
@@ -160,3 +236,105 @@ impl<T> ResultExt for Result<Option<T>, azure_data_cosmos_engine::Error> {
         }
     }
 }
+
+#[cfg(test)]
+impl<T> FfiResult<T> {
+    /// Returns the value pointer, panicking if the result is not successful.
+    pub fn unwrap(self) -> *mut T {
+        assert!(
+            matches!(self.code, ResultCode::Success),
+            "expected a successful result"
+        );
+        self.value as *mut T
+    }
+
+    /// Returns the error code, panicking if the result is successful.
+    pub fn unwrap_err(self) -> ResultCode {
+        assert!(
+            !matches!(self.code, ResultCode::Success),
+            "expected a failed result"
+        );
+        self.code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use crate::pipeline::{cosmoscx_v0_query_pipeline_cancel, cosmoscx_v0_query_pipeline_create};
+
+    use super::*;
+
+    fn last_error_message() -> String {
+        let message = cosmoscx_v0_last_error_message();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    pub fn last_error_describes_failed_call() {
+        assert!(cosmoscx_v0_last_error_message().is_null());
+        assert_eq!(0, cosmoscx_v0_last_error_code() as isize);
+
+        let code = cosmoscx_v0_query_pipeline_create(
+            "SELECT DISTINCT c.id FROM c".into(),
+            r#"{"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {"distinctType": "Ordered"}, "queryRanges": []}"#.into(),
+            r#"{"PartitionKeyRanges":[{"id":"partition0","minInclusive":"","maxExclusive":"FF"}]}"#.into(),
+        )
+        .unwrap_err();
+
+        // The codes are part of the C API, so they must never change.
+        assert_eq!(-6, code as isize);
+        assert_eq!(-6, cosmoscx_v0_last_error_code() as isize);
+        assert_eq!("DISTINCT queries are not supported", last_error_message());
+
+        assert_eq!(
+            -8,
+            cosmoscx_v0_query_pipeline_cancel(std::ptr::null_mut()) as isize
+        );
+        assert_eq!(-8, cosmoscx_v0_last_error_code() as isize);
+        assert_eq!("pipeline was null", last_error_message());
+    }
+
+    #[test]
+    pub fn panic_is_returned_as_error() {
+        assert_eq!(-17, crate::cosmoscx_v0_panic() as isize);
+        assert_eq!(-17, cosmoscx_v0_last_error_code() as isize);
+        assert_eq!(
+            "client engine panicked: cosmoscx_v0_panic was called",
+            last_error_message()
+        );
+
+        let result: FfiResult<u32> = catch_panic(|| -> Result<Box<u32>, _> {
+            let items: Vec<u32> = Vec::new();
+            Ok(Box::new(items[1]))
+        })
+        .into();
+        assert!(result.value.is_null());
+        assert!(matches!(result.unwrap_err(), ResultCode::InternalPanic));
+
+        // Results that don't panic pass through unchanged.
+        let result: FfiResult<u32> = catch_panic(|| Ok(Box::new(42))).into();
+        assert_eq!(42, *unsafe { Box::from_raw(result.unwrap()) });
+    }
+
+    #[test]
+    pub fn result_codes_match_error_kind_codes() {
+        for &kind in ErrorKind::ALL {
+            // Python errors never cross the C API.
+            if kind == ErrorKind::PythonError {
+                continue;
+            }
+            let code = ResultCode::from(kind) as isize;
+            assert_eq!(
+                -(kind.code() as isize),
+                code,
+                "mismatched code for {kind:?}"
+            );
+        }
+    }
+}
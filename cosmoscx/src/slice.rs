@@ -46,6 +46,28 @@ impl<'a, T> Slice<'a, T> {
             Some(std::slice::from_raw_parts(self.data, self.len))
         }
     }
+
+    /// Returns a `&[T]` pointing to the underlying data, like [`Slice::as_slice`], but treats a null pointer with a `0` length as an empty slice.
+    ///
+    /// This allows language bindings to pass an empty array without allocating it.
+    /// If the underlying pointer is null and the length is not `0`, this returns an [`ErrorKind::ArgumentNull`] error.
+    ///
+    /// # Safety
+    ///
+    /// The caller must assert that the memory range referenced by the slice is valid.
+    pub unsafe fn as_slice_or_empty(
+        &self,
+        name: &str,
+    ) -> Result<&'a [T], azure_data_cosmos_engine::Error> {
+        match self.as_slice() {
+            Some(slice) => Ok(slice),
+            None if self.len == 0 => Ok(&[]),
+            None => Err(ErrorKind::ArgumentNull.with_message(format!(
+                "{name} slice pointer was null, but its length was {}",
+                self.len
+            ))),
+        }
+    }
 }
 
 impl<'a, T> From<&'a [T]> for Slice<'a, T> {
@@ -43,6 +43,10 @@ func (e *Error) Error() string {
 		return "provided argument was null"
 	case C.COSMOS_CX_RESULT_CODE_ARITHMETIC_OVERFLOW:
 		return "arithmetic overflow occurred"
+	case C.COSMOS_CX_RESULT_CODE_INTERNAL_PANIC:
+		return "client engine panicked"
+	case C.COSMOS_CX_RESULT_CODE_CANCELLED:
+		return "query was cancelled"
 	default:
 		return "unknown error"
 	}
@@ -58,6 +58,18 @@ func (p *Pipeline) Query() (string, error) {
 	return strings.Clone(s), nil
 }
 
+// ID gets the ID of the query, which the pipeline includes in its tracing spans, so it can be used to correlate them with the caller's logs.
+func (p *Pipeline) ID() (string, error) {
+	r := C.cosmoscx_v0_query_pipeline_id(p.ptr)
+	if err := mapErr(r.code); err != nil {
+		return "", err
+	}
+	s := unsafe.String((*byte)(r.value.data), r.value.len)
+
+	// Clone the string into Go memory
+	return strings.Clone(s), nil
+}
+
 func (p *Pipeline) NextBatch() (*PipelineResult, error) {
 	r := C.cosmoscx_v0_query_pipeline_run(p.ptr)
 	if err := mapErr(r.code); err != nil {
@@ -188,6 +200,17 @@ func (r *DataRequest) IncludeParameters() bool {
 	return bool(r.include_parameters)
 }
 
+// EncodedParameters gets the parameters to send with this request, encoded as the JSON `parameters` field of a Cosmos query.
+// The string is empty if the request should be sent without parameters.
+func (r *DataRequest) EncodedParameters() EngineString {
+	return EngineString(r.encoded_parameters)
+}
+
+// MaxItemCount gets the page size hint for this request, or 0 if the default page size should be used.
+func (r *DataRequest) MaxItemCount() uint32 {
+	return uint32(r.max_item_count)
+}
+
 func (r *DataRequest) PartitionKeyRangeID() EngineString {
 	return EngineString(r.pkrangeid)
 }
@@ -16,6 +16,9 @@
  * A result code for FFI functions, which indicates the success or failure of the operation.
  *
  * Values of `ResultCode` have the same representation as the C type `intptr_t`
+ *
+ * Each error code is the negation of the stable code of the matching [`ErrorKind`], see [`ErrorKind::code`].
+ * Use [`ErrorKind::is_user_error`] and [`ErrorKind::is_internal`] to decide how an error should be reported.
  */
 enum CosmosCxResultCode {
   /**
@@ -62,9 +65,80 @@ enum CosmosCxResultCode {
    * See [`ErrorKind::InvalidQuery`].
    */
   COSMOS_CX_RESULT_CODE_INVALID_QUERY = -11,
+  /**
+   * See [`ErrorKind::MemoryBudgetExceeded`].
+   */
+  COSMOS_CX_RESULT_CODE_MEMORY_BUDGET_EXCEEDED = -12,
+  /**
+   * See [`ErrorKind::StateNotSerializable`].
+   */
+  COSMOS_CX_RESULT_CODE_STATE_NOT_SERIALIZABLE = -13,
+  /**
+   * See [`ErrorKind::BufferLimitExceeded`].
+   */
+  COSMOS_CX_RESULT_CODE_BUFFER_LIMIT_EXCEEDED = -15,
+  /**
+   * See [`ErrorKind::SpillError`].
+   */
+  COSMOS_CX_RESULT_CODE_SPILL_ERROR = -16,
+  /**
+   * See [`ErrorKind::InternalPanic`].
+   */
+  COSMOS_CX_RESULT_CODE_INTERNAL_PANIC = -17,
+  /**
+   * See [`ErrorKind::Cancelled`].
+   */
+  COSMOS_CX_RESULT_CODE_CANCELLED = -18,
 };
 typedef intptr_t CosmosCxResultCode;
 
+/**
+ * Identifies the kind of [`PipelineEvent`] returned by [`cosmoscx_v0_query_pipeline_next_item`].
+ */
+typedef enum CosmosCxPipelineEventKind {
+  /**
+   * The event contains the next item in the query results, in [`PipelineEvent::item`].
+   */
+  COSMOS_CX_PIPELINE_EVENT_KIND_ITEM = 0,
+  /**
+   * No item can be produced until the requests in [`PipelineEvent::requests`] are fulfilled.
+   */
+  COSMOS_CX_PIPELINE_EVENT_KIND_NEEDS_DATA = 1,
+  /**
+   * The query has completed, and no further items will be produced.
+   */
+  COSMOS_CX_PIPELINE_EVENT_KIND_DONE = 2,
+} CosmosCxPipelineEventKind;
+
+/**
+ * Identifies a query feature in the mask passed to [`cosmoscx_v0_query_supported_features_for`].
+ *
+ * Values of `QueryFeatureFlag` have the same representation as the C type `uint64_t`
+ *
+ * Each value is the [`QueryFeature::bit`] of the matching [`QueryFeature`], so values can be combined with a bitwise OR.
+ */
+enum CosmosCxQueryFeatureFlag {
+  COSMOS_CX_QUERY_FEATURE_FLAG_NONE = (1 << 0),
+  COSMOS_CX_QUERY_FEATURE_FLAG_AGGREGATE = (1 << 1),
+  COSMOS_CX_QUERY_FEATURE_FLAG_COMPOSITE_AGGREGATE = (1 << 2),
+  COSMOS_CX_QUERY_FEATURE_FLAG_DISTINCT = (1 << 3),
+  COSMOS_CX_QUERY_FEATURE_FLAG_GROUP_BY = (1 << 4),
+  COSMOS_CX_QUERY_FEATURE_FLAG_MULTIPLE_AGGREGATES = (1 << 5),
+  COSMOS_CX_QUERY_FEATURE_FLAG_MULTIPLE_ORDER_BY = (1 << 6),
+  COSMOS_CX_QUERY_FEATURE_FLAG_OFFSET_AND_LIMIT = (1 << 7),
+  COSMOS_CX_QUERY_FEATURE_FLAG_ORDER_BY = (1 << 8),
+  COSMOS_CX_QUERY_FEATURE_FLAG_TOP = (1 << 9),
+  COSMOS_CX_QUERY_FEATURE_FLAG_NON_VALUE_AGGREGATE = (1 << 10),
+  COSMOS_CX_QUERY_FEATURE_FLAG_D_COUNT = (1 << 11),
+  COSMOS_CX_QUERY_FEATURE_FLAG_NON_STREAMING_ORDER_BY = (1 << 12),
+  COSMOS_CX_QUERY_FEATURE_FLAG_LIST_AND_SET_AGGREGATE = (1 << 13),
+  COSMOS_CX_QUERY_FEATURE_FLAG_COUNT_IF = (1 << 14),
+  COSMOS_CX_QUERY_FEATURE_FLAG_HYBRID_SEARCH = (1 << 15),
+  COSMOS_CX_QUERY_FEATURE_FLAG_WEIGHTED_RANK_FUSION = (1 << 16),
+  COSMOS_CX_QUERY_FEATURE_FLAG_HYBRID_SEARCH_SKIP_ORDER_BY_REWRITE = (1 << 17),
+};
+typedef uint64_t CosmosCxQueryFeatureFlag;
+
 /**
  * Opaque type representing the query pipeline.
  * Callers should not attempt to access the fields of this struct directly.
@@ -173,6 +247,25 @@ typedef struct CosmosCxOwnedSlice_u8 {
  */
 typedef struct CosmosCxOwnedSlice_u8 CosmosCxOwnedString;
 
+/**
+ * A result type for FFI functions.
+ *
+ * An `FfiResult` is returned from a function that both returns a value AND can fail.
+ *
+ * The C representation of this struct is:
+ *
+ * ```
+ * struct {
+ *   intptr_t code; // The result code, which will be '0' if the operation succeeded
+ *   const void *value; // A pointer to the returned value, which will be `nullptr`/`0` if the operation failed.
+ * };
+ * ```
+ */
+typedef struct CosmosCxFfiResult_OwnedString {
+  CosmosCxResultCode code;
+  const CosmosCxOwnedString *value;
+} CosmosCxFfiResult_OwnedString;
+
 /**
  * Represents a contiguous sequence of objects OWNED BY THE ENGINE.
  *
@@ -225,6 +318,17 @@ typedef struct CosmosCxDataRequest {
    * If this value is false, the query should be executed without parameters.
    */
   bool include_parameters;
+  /**
+   * A hint for the maximum number of items to request in this page, or 0 if the language binding should use its own default.
+   * See [`DataRequest::max_item_count`](azure_data_cosmos_engine::query::DataRequest::max_item_count).
+   */
+  uint32_t max_item_count;
+  /**
+   * An [`OwnedString`] containing the parameters to send with the query, encoded as the JSON `parameters` field of a Cosmos query,
+   * or an empty slice (len == 0) if the query should be executed without parameters.
+   * This is only set for pipelines created by [`cosmoscx_v0_query_pipeline_create_with_parameters`].
+   */
+  CosmosCxOwnedString encoded_parameters;
 } CosmosCxDataRequest;
 
 /**
@@ -267,6 +371,15 @@ typedef struct CosmosCxPipelineResult {
    * An [`OwnedSlice`] of [`DataRequest`]s describing additional requests that must be made and provided to [`cosmoscx_v0_query_pipeline_provide_data`] before retrieving the next batch.
    */
   struct CosmosCxOwnedSlice_DataRequest requests;
+  /**
+   * The total request charge of every response provided to the pipeline so far.
+   */
+  double cumulative_request_charge;
+  /**
+   * An [`OwnedSlice`] of [`OwnedString`]s containing the activity IDs of the responses the pipeline accepted since the previous turn.
+   * See [`PipelineResponse::activity_ids`](azure_data_cosmos_engine::query::PipelineResponse::activity_ids).
+   */
+  struct CosmosCxOwnedSlice_OwnedString activity_ids;
 } CosmosCxPipelineResult;
 
 /**
@@ -290,6 +403,137 @@ typedef struct CosmosCxFfiResult_PipelineResult {
   const struct CosmosCxPipelineResult *value;
 } CosmosCxFfiResult_PipelineResult;
 
+/**
+ * The location of a single item in [`ResultBatch::item_data`].
+ */
+typedef struct CosmosCxItemSpan {
+  /**
+   * The offset, in bytes, of the first byte of the item's JSON.
+   */
+  uintptr_t offset;
+  /**
+   * The length, in bytes, of the item's JSON.
+   */
+  uintptr_t len;
+} CosmosCxItemSpan;
+
+/**
+ * Represents a contiguous sequence of objects OWNED BY THE ENGINE.
+ *
+ * The language binding MUST free the memory associated with this sequence by calling the appropriate 'free' function.
+ * For example, all [`OwnedSlice`]s within a [`PipelineResponse`](azure_data_cosmos_engine::query::PipelineResponse) are freed by calling [`cosmoscx_v0_query_pipeline_free_result`](super::pipeline::cosmoscx_v0_query_pipeline_free_result).
+ *
+ * The C representation of this struct is:
+ *
+ * ```
+ * struct {
+ *   const void *data; // A pointer to the first item in the slice
+ *   intptr_t len; // The number of items in the slice.
+ * };
+ * ```
+ *
+ * The `data` pointer is guaranteed to point to a contiguous sequence of `T` values.
+ * Each `T` value will be properly aligned.
+ * Thus, the `data` pointer can be treated as a C-style array of length `len`.
+ */
+typedef struct CosmosCxOwnedSlice_ItemSpan {
+  struct CosmosCxItemSpan *data;
+  uintptr_t len;
+} CosmosCxOwnedSlice_ItemSpan;
+
+/**
+ * Represents the result of a single execution of the query pipeline, with every item in a single buffer.
+ *
+ * This is returned by [`cosmoscx_v0_query_pipeline_run_batch`], and has the same fields as [`PipelineResult`], except for the items.
+ */
+typedef struct CosmosCxResultBatch {
+  /**
+   * A boolean indicating if the pipeline has completed.
+   */
+  bool completed;
+  /**
+   * An [`OwnedString`] containing the JSON of every item in the output, one after the other, with nothing between them.
+   */
+  CosmosCxOwnedString item_data;
+  /**
+   * An [`OwnedSlice`] of [`ItemSpan`]s locating the JSON for each item in the output, in order, within [`ResultBatch::item_data`].
+   */
+  struct CosmosCxOwnedSlice_ItemSpan items;
+  /**
+   * An [`OwnedSlice`] of [`DataRequest`]s describing additional requests that must be made and provided to [`cosmoscx_v0_query_pipeline_provide_data`] before retrieving the next batch.
+   */
+  struct CosmosCxOwnedSlice_DataRequest requests;
+  /**
+   * The total request charge of every response provided to the pipeline so far.
+   */
+  double cumulative_request_charge;
+  /**
+   * An [`OwnedSlice`] of [`OwnedString`]s containing the activity IDs of the responses the pipeline accepted since the previous turn.
+   * See [`PipelineResponse::activity_ids`](azure_data_cosmos_engine::query::PipelineResponse::activity_ids).
+   */
+  struct CosmosCxOwnedSlice_OwnedString activity_ids;
+} CosmosCxResultBatch;
+
+/**
+ * A result type for FFI functions.
+ *
+ * An `FfiResult` is returned from a function that both returns a value AND can fail.
+ *
+ * The C representation of this struct is:
+ *
+ * ```
+ * struct {
+ *   intptr_t code; // The result code, which will be '0' if the operation succeeded
+ *   const void *value; // A pointer to the returned value, which will be `nullptr`/`0` if the operation failed.
+ * };
+ * ```
+ *
+ * The data pointed to by the `value` pointer is OWNED BY THE ENGINE and must be freed by calling the appropriate free function, depending on the data.
+ */
+typedef struct CosmosCxFfiResult_ResultBatch {
+  CosmosCxResultCode code;
+  const struct CosmosCxResultBatch *value;
+} CosmosCxFfiResult_ResultBatch;
+
+/**
+ * Represents the result of pulling a single item from the query pipeline.
+ */
+typedef struct CosmosCxPipelineEvent {
+  /**
+   * The kind of event, which determines which of the other fields are populated.
+   */
+  enum CosmosCxPipelineEventKind kind;
+  /**
+   * An [`OwnedString`] containing the JSON for the item, if [`PipelineEvent::kind`] is [`PipelineEventKind::Item`]. Otherwise, an empty slice (len == 0).
+   */
+  CosmosCxOwnedString item;
+  /**
+   * An [`OwnedSlice`] of [`DataRequest`]s that must be made and provided to the pipeline, if [`PipelineEvent::kind`] is [`PipelineEventKind::NeedsData`]. Otherwise, an empty slice (len == 0).
+   */
+  struct CosmosCxOwnedSlice_DataRequest requests;
+} CosmosCxPipelineEvent;
+
+/**
+ * A result type for FFI functions.
+ *
+ * An `FfiResult` is returned from a function that both returns a value AND can fail.
+ *
+ * The C representation of this struct is:
+ *
+ * ```
+ * struct {
+ *   intptr_t code; // The result code, which will be '0' if the operation succeeded
+ *   const void *value; // A pointer to the returned value, which will be `nullptr`/`0` if the operation failed.
+ * };
+ * ```
+ *
+ * The data pointed to by the `value` pointer is OWNED BY THE ENGINE and must be freed by calling the appropriate free function, depending on the data.
+ */
+typedef struct CosmosCxFfiResult_PipelineEvent {
+  CosmosCxResultCode code;
+  const struct CosmosCxPipelineEvent *value;
+} CosmosCxFfiResult_PipelineEvent;
+
 /**
  * Represents a response to a single data request from the pipeline.
  */
@@ -312,6 +556,55 @@ typedef struct CosmosCxQueryResponse {
   CosmosCxStr continuation;
 } CosmosCxQueryResponse;
 
+/**
+ * Represents a response to a single data request from the pipeline, along with its request charge.
+ *
+ * This has the same fields as [`QueryResponse`], followed by the request charge. It's a separate struct, accepted by [`cosmoscx_v0_query_pipeline_provide_data_v2`],
+ * so that adding the request charge doesn't change the layout of [`QueryResponse`] for existing callers of [`cosmoscx_v0_query_pipeline_provide_data`].
+ */
+typedef struct CosmosCxQueryResponseV2 {
+  /**
+   * The Partition Key Range ID this response is for.
+   */
+  CosmosCxStr pkrange_id;
+  /**
+   * The unique identifier for the request this response is for. This must exactly match the [`DataRequest::id`] field of the corresponding [`DataRequest`].
+   */
+  uint64_t request_id;
+  /**
+   * The raw data being provided to the pipeline in response to the request.
+   */
+  CosmosCxStr data;
+  /**
+   * The continuation token to provide, or an empty slice (len == 0) if no continuation should be provided.
+   */
+  CosmosCxStr continuation;
+  /**
+   * The request charge reported with the data, or `0.0` if it isn't known. This is only used for [`PipelineResult::cumulative_request_charge`].
+   */
+  double request_charge;
+} CosmosCxQueryResponseV2;
+
+/**
+ * The metadata the backend returned, in its response headers, with the data provided to [`cosmoscx_v0_query_pipeline_provide_data_with_metadata`].
+ *
+ * See [`ResponseMetadata`](azure_data_cosmos_engine::query::ResponseMetadata) for more information.
+ */
+typedef struct CosmosCxResponseMetadata {
+  /**
+   * The request charge reported with the data, from the `x-ms-request-charge` header, or `0.0` if it isn't known.
+   */
+  double request_charge;
+  /**
+   * The activity ID of the request, from the `x-ms-activity-id` header, or an empty or null slice if it isn't known.
+   */
+  CosmosCxStr activity_id;
+  /**
+   * The delay the backend asked for before the next request, in milliseconds, from the `x-ms-retry-after-ms` header, or `0` if there was none.
+   */
+  uint64_t retry_after_ms;
+} CosmosCxResponseMetadata;
+
 /**
  * Represents a contiguous sequence of objects OWNED BY THE CALLING CODE.
  *
@@ -328,6 +621,22 @@ typedef struct CosmosCxSlice_QueryResponse {
   uintptr_t len;
 } CosmosCxSlice_QueryResponse;
 
+/**
+ * Represents a contiguous sequence of objects OWNED BY THE CALLING CODE.
+ *
+ * The language binding owns this memory. It must keep the memory valid for the duration of any function call that receives it.
+ * For example, the [`Slice`]s passed to [`cosmoscx_v0_query_pipeline_create`](super::pipeline::cosmoscx_v0_query_pipeline_create) must remain valid until that function returns.
+ * After the function returns, the language binding may free the memory.
+ * This lifetime is represented by the lifetime parameter `'a`, which should prohibit Rust code from storing the value.
+ *
+ * The C representation of this struct is identical to [`OwnedSlice`], the only difference is that this type indicates that the language binding owns this memory.
+ * The language binding is responsible for ensuring the underlying `data` pointer and `len` are correct and the data is properly aligned such that the `data` pointer is a valid C-style array of `T` values.
+ */
+typedef struct CosmosCxSlice_QueryResponseV2 {
+  const struct CosmosCxQueryResponseV2 *data;
+  uintptr_t len;
+} CosmosCxSlice_QueryResponseV2;
+
 /**
  * Returns the version of the Cosmos Client Engine in use.
  */
@@ -340,6 +649,49 @@ const char *cosmoscx_version(void);
  */
 const char *cosmoscx_v0_query_supported_features(void);
 
+/**
+ * Returns a string that describes the query features supported by the Cosmos Client Engine, leaving out the features named in `exclude`.
+ *
+ * `exclude` is a comma-separated list of feature names, like `HybridSearch,DCount`. Names of features that aren't supported are ignored, since they're never reported anyway.
+ * Sending this string as the `x-ms-cosmos-supported-query-features` header, instead of [`cosmoscx_v0_query_supported_features`], makes the gateway reject queries that need the excluded features.
+ *
+ * The [`OwnedString`] returned by this function MUST be freed using [`cosmoscx_v0_query_free_supported_features`].
+ *
+ * # Parameters
+ * - `exclude`: A NUL-terminated, UTF-8 string containing the names of the features to leave out.
+ */
+struct CosmosCxFfiResult_OwnedString cosmoscx_v0_query_supported_features_filtered(const char *exclude);
+
+/**
+ * Returns a string that describes the query features supported by the Cosmos Client Engine, leaving out the features set in `exclude_mask`.
+ *
+ * `exclude_mask` is a bitwise OR of [`QueryFeatureFlag`] values. For example, excluding [`QueryFeatureFlag::NonStreamingOrderBy`] makes the gateway
+ * reject queries that would buffer every result, rather than returning a plan the engine executes by buffering.
+ * Bits that don't identify a feature are ignored.
+ *
+ * The [`OwnedString`] returned by this function MUST be freed using [`cosmoscx_v0_query_free_supported_features`].
+ *
+ * # Parameters
+ * - `exclude_mask`: The features to leave out.
+ */
+struct CosmosCxFfiResult_OwnedString cosmoscx_v0_query_supported_features_for(uint64_t exclude_mask);
+
+/**
+ * Frees the memory associated with a string returned by [`cosmoscx_v0_query_supported_features_filtered`] or [`cosmoscx_v0_query_supported_features_for`].
+ *
+ * # Safety
+ *
+ * The caller must ensure that the pointer passed to this function is a valid pointer to an [`OwnedString`] returned by [`cosmoscx_v0_query_supported_features_filtered`] or [`cosmoscx_v0_query_supported_features_for`].
+ */
+void cosmoscx_v0_query_free_supported_features(CosmosCxOwnedString *features);
+
+/**
+ * Panics inside the engine, so that language bindings can test that panics are returned as [`ResultCode::InternalPanic`].
+ *
+ * This function is only intended for testing, it always fails.
+ */
+CosmosCxResultCode cosmoscx_v0_panic(void);
+
 /**
  * Enables built-in tracing for the Cosmos Client Engine.
  *
@@ -361,6 +713,91 @@ struct CosmosCxFfiResult_Pipeline cosmoscx_v0_query_pipeline_create(CosmosCxStr
                                                                     CosmosCxStr query_plan_json,
                                                                     CosmosCxStr pkranges);
 
+/**
+ * Creates a new query pipeline from a JSON query plan and list of partitions, using the provided options.
+ *
+ * See [`PipelineOptions`] for the available options, which are provided as a JSON object using the field names as-is.
+ * Missing fields use their default values, and unknown fields are rejected with [`ResultCode::DeserializationError`].
+ *
+ * # Parameters
+ * - `query_plan_json`: A [`Str`] containing the serialized query plan, as recieved from the gateway, in JSON.
+ * - `pkranges`: A [`Str`] containing the serialized partition key ranges list, as recieved from the gateway, in JSON.
+ * - `options_json`: A [`Str`] containing the serialized [`PipelineOptions`], in JSON, or an empty or null slice to use the default options.
+ */
+struct CosmosCxFfiResult_Pipeline cosmoscx_v0_query_pipeline_create_with_options(CosmosCxStr query,
+                                                                                 CosmosCxStr query_plan_json,
+                                                                                 CosmosCxStr pkranges,
+                                                                                 CosmosCxStr options_json);
+
+/**
+ * Creates a new query pipeline for a parameterized query, using the provided options.
+ *
+ * The engine attaches the parameters to each [`DataRequest`] that needs them, see [`DataRequest::encoded_parameters`].
+ * Otherwise, this is the same as [`cosmoscx_v0_query_pipeline_create_with_options`].
+ *
+ * # Parameters
+ * - `parameters_json`: A [`Str`] containing the query parameters, encoded as the JSON `parameters` field of a Cosmos query, or an empty or null slice if the query has no parameters.
+ * - `query_plan_json`: A [`Str`] containing the serialized query plan, as recieved from the gateway, in JSON.
+ * - `pkranges`: A [`Str`] containing the serialized partition key ranges list, as recieved from the gateway, in JSON.
+ * - `options_json`: A [`Str`] containing the serialized [`PipelineOptions`], in JSON, or an empty or null slice to use the default options.
+ */
+struct CosmosCxFfiResult_Pipeline cosmoscx_v0_query_pipeline_create_with_parameters(CosmosCxStr query,
+                                                                                    CosmosCxStr parameters_json,
+                                                                                    CosmosCxStr query_plan_json,
+                                                                                    CosmosCxStr pkranges,
+                                                                                    CosmosCxStr options_json);
+
+/**
+ * Creates a new query pipeline for a simple query, without a query plan from the gateway.
+ *
+ * Language bindings can use this to skip the query plan request for queries that each partition executes completely on its own.
+ * The query must not contain `ORDER BY`, `GROUP BY`, `DISTINCT`, `TOP`, `OFFSET`, `LIMIT` or aggregate functions,
+ * otherwise this returns [`ResultCode::InvalidQuery`], and the binding should request a query plan as usual.
+ * See [`QueryPipeline::new_passthrough`] for more information.
+ *
+ * # Parameters
+ * - `parameters_json`: A [`Str`] containing the query parameters, encoded as the JSON `parameters` field of a Cosmos query, or an empty or null slice if the query has no parameters.
+ * - `pkranges`: A [`Str`] containing the serialized partition key ranges list, as recieved from the gateway, in JSON.
+ */
+struct CosmosCxFfiResult_Pipeline cosmoscx_v0_query_pipeline_create_passthrough(CosmosCxStr query,
+                                                                                CosmosCxStr parameters_json,
+                                                                                CosmosCxStr pkranges);
+
+/**
+ * Checks that the engine can execute a JSON query plan, without creating a pipeline for it.
+ *
+ * See [`QueryPipeline::validate_plan`] for more information.
+ *
+ * If the plan can't be executed, this returns the error code and, if `reason` is not null, sets it to a message describing why.
+ * The message is BORROWED from the engine, it must not be freed and only remains valid until the next call to this function on the same thread.
+ * If the plan can be executed, `reason` is set to an empty [`Str`].
+ *
+ * # Parameters
+ * - `query_plan_json`: A [`Str`] containing the serialized query plan, as recieved from the gateway, in JSON.
+ * - `reason`: A pointer to a [`Str`] to receive the reason the plan was rejected, or null if the caller doesn't need it.
+ *
+ * # Safety
+ *
+ * The caller must ensure that `reason` is either null, or a valid, properly aligned pointer to a [`Str`] that the engine can write to.
+ */
+CosmosCxResultCode cosmoscx_v0_query_pipeline_validate_plan(CosmosCxStr query_plan_json,
+                                                            CosmosCxStr *reason);
+
+/**
+ * Re-creates a query pipeline from the state captured by [`cosmoscx_v0_query_pipeline_serialize_state`].
+ *
+ * See [`QueryPipeline::resume`](azure_data_cosmos_engine::query::QueryPipeline::resume) for more information.
+ *
+ * # Parameters
+ * - `query_plan_json`: A [`Str`] containing the serialized query plan, as recieved from the gateway, in JSON.
+ * - `pkranges`: A [`Str`] containing the serialized partition key ranges list, as recieved from the gateway, in JSON.
+ * - `state`: A [`Str`] containing the state returned by [`cosmoscx_v0_query_pipeline_serialize_state`].
+ */
+struct CosmosCxFfiResult_Pipeline cosmoscx_v0_query_pipeline_resume(CosmosCxStr query,
+                                                                    CosmosCxStr query_plan_json,
+                                                                    CosmosCxStr pkranges,
+                                                                    CosmosCxStr state);
+
 /**
  * Frees the memory associated with a pipeline.
  *
@@ -380,6 +817,32 @@ void cosmoscx_v0_query_pipeline_free(struct CosmosCxPipeline *pipeline);
  */
 struct CosmosCxFfiResult_Str cosmoscx_v0_query_pipeline_query(struct CosmosCxPipeline *pipeline);
 
+/**
+ * Gets the ID of the query this pipeline is executing, which the pipeline includes in its tracing spans.
+ *
+ * The string returned here should be copied to a language-specific string type before being used.
+ * It remains valid until the pipeline is freed by a call to [`cosmoscx_v0_query_pipeline_free`].
+ */
+struct CosmosCxFfiResult_Str cosmoscx_v0_query_pipeline_id(struct CosmosCxPipeline *pipeline);
+
+/**
+ * Captures the state of the pipeline, so that the query can be resumed later by [`cosmoscx_v0_query_pipeline_resume`].
+ *
+ * See [`QueryPipeline::serialize_state`](azure_data_cosmos_engine::query::QueryPipeline::serialize_state) for more information.
+ *
+ * The [`OwnedString`] returned by this function MUST be freed using [`cosmoscx_v0_query_pipeline_free_state`].
+ */
+struct CosmosCxFfiResult_OwnedString cosmoscx_v0_query_pipeline_serialize_state(struct CosmosCxPipeline *pipeline);
+
+/**
+ * Frees the memory associated with a state string returned by [`cosmoscx_v0_query_pipeline_serialize_state`].
+ *
+ * # Safety
+ *
+ * The caller must ensure that the pointer passed to this function is a valid pointer to an [`OwnedString`] returned by [`cosmoscx_v0_query_pipeline_serialize_state`].
+ */
+void cosmoscx_v0_query_pipeline_free_state(CosmosCxOwnedString *state);
+
 /**
  * Executes a single turn of the query pipeline.
  *
@@ -402,8 +865,159 @@ struct CosmosCxFfiResult_PipelineResult cosmoscx_v0_query_pipeline_run(struct Co
  */
 void cosmoscx_v0_query_pipeline_free_result(struct CosmosCxPipelineResult *result);
 
+/**
+ * Executes a single turn of the query pipeline, like [`cosmoscx_v0_query_pipeline_run`], but returns every item in a single buffer.
+ *
+ * Each item in a [`PipelineResult`] is a separate allocation, so a language binding that copies every item out of the result
+ * makes a copy per item. With a [`ResultBatch`], the binding can copy [`ResultBatch::item_data`] once, and use [`ResultBatch::items`] to find each item in it.
+ *
+ * The [`ResultBatch`] returned by this function MUST be freed using [`cosmoscx_v0_query_pipeline_free_batch`] to release the memory associated with the batch.
+ */
+struct CosmosCxFfiResult_ResultBatch cosmoscx_v0_query_pipeline_run_batch(struct CosmosCxPipeline *pipeline);
+
+/**
+ * Frees all the memory associated with a [`ResultBatch`], including the buffer holding its items.
+ *
+ * # Safety
+ *
+ * The caller must ensure that the pointer passed to this function is a valid pointer to a [`ResultBatch`] returned by [`cosmoscx_v0_query_pipeline_run_batch`].
+ */
+void cosmoscx_v0_query_pipeline_free_batch(struct CosmosCxResultBatch *batch);
+
+/**
+ * Pulls a single item from the query pipeline.
+ *
+ * See [`QueryPipeline::next_item`](azure_data_cosmos_engine::query::QueryPipeline::next_item) for more information.
+ *
+ * The [`PipelineEvent`] returned by this function MUST be freed using [`cosmoscx_v0_query_pipeline_free_event`].
+ */
+struct CosmosCxFfiResult_PipelineEvent cosmoscx_v0_query_pipeline_next_item(struct CosmosCxPipeline *pipeline);
+
+/**
+ * Frees all the memory associated with a [`PipelineEvent`].
+ *
+ * # Safety
+ *
+ * The caller must ensure that the pointer passed to this function is a valid pointer to a [`PipelineEvent`] returned by [`cosmoscx_v0_query_pipeline_next_item`].
+ */
+void cosmoscx_v0_query_pipeline_free_event(struct CosmosCxPipelineEvent *event);
+
+/**
+ * Cancels the query, releasing all the items the pipeline has buffered.
+ *
+ * This is safe to call from any thread, even while another thread is calling a function on the same pipeline.
+ * In that case, the function in progress stops at the next item or page and returns [`ResultCode::Cancelled`], and cancels the query itself.
+ * After this, [`cosmoscx_v0_query_pipeline_run`] returns no items and no requests, and reports that the pipeline has completed.
+ * The pipeline must still be freed using [`cosmoscx_v0_query_pipeline_free`], once no other thread is using it.
+ *
+ * See [`QueryPipeline::cancellation_token`](azure_data_cosmos_engine::query::QueryPipeline::cancellation_token) for more information.
+ */
+CosmosCxResultCode cosmoscx_v0_query_pipeline_cancel(struct CosmosCxPipeline *pipeline);
+
+/**
+ * Replaces a partition that the backend reported as gone, because it was split, with the partitions it was split into.
+ *
+ * The language binding calls this, instead of providing data, when a request fails with HTTP 410 and sub-status 1002 (partition key range gone).
+ * The next call to [`cosmoscx_v0_query_pipeline_run`] requests data from each replacement.
+ * Queries that can't handle the split fail with [`ResultCode::UnsupportedQueryPlan`], and must be restarted.
+ *
+ * See [`QueryPipeline::handle_pkrange_gone`](azure_data_cosmos_engine::query::QueryPipeline::handle_pkrange_gone) for more information.
+ *
+ * # Parameters
+ * - `old_pkrange_id`: A [`Str`] containing the ID of the Partition Key Range that is gone.
+ * - `replacements`: A [`Str`] containing the Partition Key Ranges that replaced it, in the same JSON format as the partition key ranges list recieved from the gateway.
+ */
+CosmosCxResultCode cosmoscx_v0_query_pipeline_handle_pkrange_gone(struct CosmosCxPipeline *pipeline,
+                                                                  CosmosCxStr old_pkrange_id,
+                                                                  CosmosCxStr replacements);
+
+/**
+ * Inserts additional raw data, in response to a single [`DataRequest`] from the pipeline.
+ *
+ * Some pipelines, such as the one used for hybrid search queries, issue several requests to the same partition at once.
+ * The `request_id` is used to route the data to the request it fulfills, so it must exactly match the [`DataRequest::id`] field of that request.
+ *
+ * # Parameters
+ * - `request_id`: The [`DataRequest::id`] of the request this data is for.
+ * - `pkrange_id`: A [`Str`] containing the Partition Key Range ID the data was retrieved from.
+ * - `data`: A [`Str`] containing the raw response body, in JSON.
+ * - `continuation`: A [`Str`] containing the continuation token returned with the data, or an empty or null slice if there was no continuation, which indicates the partition has no more data.
+ * - `request_charge`: The request charge reported with the data, or `0.0` if it isn't known. This is only used for [`PipelineResult::cumulative_request_charge`].
+ */
+CosmosCxResultCode cosmoscx_v0_query_pipeline_provide_data_for_request(struct CosmosCxPipeline *pipeline,
+                                                                       uint64_t request_id,
+                                                                       CosmosCxStr pkrange_id,
+                                                                       CosmosCxStr data,
+                                                                       CosmosCxStr continuation,
+                                                                       double request_charge);
+
+/**
+ * Inserts additional raw data, in response to a single [`DataRequest`] from the pipeline, along with the metadata from the response headers.
+ *
+ * This is equivalent to [`cosmoscx_v0_query_pipeline_provide_data_for_request`], except that the activity ID is also collected,
+ * and returned in [`PipelineResult::activity_ids`].
+ * See [`QueryPipeline::provide_data_with_metadata`](azure_data_cosmos_engine::query::QueryPipeline::provide_data_with_metadata) for more information.
+ */
+CosmosCxResultCode cosmoscx_v0_query_pipeline_provide_data_with_metadata(struct CosmosCxPipeline *pipeline,
+                                                                         uint64_t request_id,
+                                                                         CosmosCxStr pkrange_id,
+                                                                         CosmosCxStr data,
+                                                                         CosmosCxStr continuation,
+                                                                         struct CosmosCxResponseMetadata metadata);
+
 /**
  * Inserts additional raw data, in response to a [`DataRequest`] from the pipeline.
+ *
+ * This is equivalent to [`cosmoscx_v0_query_pipeline_provide_data_v2`], except that the request charges aren't known, so they aren't counted in [`PipelineResult::cumulative_request_charge`].
  */
 CosmosCxResultCode cosmoscx_v0_query_pipeline_provide_data(struct CosmosCxPipeline *pipeline,
                                                            struct CosmosCxSlice_QueryResponse responses);
+
+/**
+ * Inserts additional raw data, along with its request charge, in response to a [`DataRequest`] from the pipeline.
+ *
+ * This is equivalent to [`cosmoscx_v0_query_pipeline_provide_data_batch`], without reporting how many responses were applied.
+ */
+CosmosCxResultCode cosmoscx_v0_query_pipeline_provide_data_v2(struct CosmosCxPipeline *pipeline,
+                                                              struct CosmosCxSlice_QueryResponseV2 responses);
+
+/**
+ * Inserts the responses to several [`DataRequest`]s from the pipeline in a single call.
+ *
+ * This is equivalent to calling [`cosmoscx_v0_query_pipeline_provide_data_for_request`] for each [`QueryResponseV2`] in `responses`, in order,
+ * and allows language bindings to hand over every response from a fan-out of requests with a single call across the FFI boundary.
+ *
+ * The responses are applied one at a time, so a failure part way through does NOT undo the responses before it.
+ * If the response at index `N` fails, this returns the error for that response, responses `0..N` have been applied, and the responses after `N` have not been provided.
+ * The message returned by [`cosmoscx_v0_last_error_message`](crate::result::cosmoscx_v0_last_error_message) also includes the index `N`.
+ * The language binding can retry the responses from `N` onwards once it has handled the error, for example after a partition split.
+ *
+ * See [`QueryPipeline::provide_data_batch`](azure_data_cosmos_engine::query::QueryPipeline::provide_data_batch) for more information.
+ *
+ * # Parameters
+ * - `responses`: A [`Slice`] of the [`QueryResponseV2`]s to provide, which may be empty, with a null data pointer, if there are no responses.
+ * - `applied`: Receives the number of responses that were applied, which is the index of the failed response if this fails, or `responses.len` if it succeeds.
+ *   This is always set, even if the call fails before any response is applied. It may be null if the caller doesn't need it.
+ *
+ * # Safety
+ *
+ * The caller must ensure that `applied` is either null, or a valid, properly aligned pointer to a `uintptr_t` that the engine can write to.
+ */
+CosmosCxResultCode cosmoscx_v0_query_pipeline_provide_data_batch(struct CosmosCxPipeline *pipeline,
+                                                                 struct CosmosCxSlice_QueryResponseV2 responses,
+                                                                 uintptr_t *applied);
+
+/**
+ * Gets the result code of the last C API call on this thread that failed.
+ *
+ * Returns [`ResultCode::Success`] if no call on this thread has failed. Successful calls don't reset the last error.
+ */
+CosmosCxResultCode cosmoscx_v0_last_error_code(void);
+
+/**
+ * Gets a message describing the error returned by the last C API call on this thread that failed, as a NUL-terminated string.
+ *
+ * Returns null if no call on this thread has failed. The message is BORROWED from the engine, it must not be freed,
+ * and only remains valid until another call on the same thread fails.
+ */
+const char *cosmoscx_v0_last_error_message(void);
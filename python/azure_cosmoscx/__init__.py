@@ -2,6 +2,15 @@
 # Licensed under the MIT License.
 
 from . import _azure_cosmoscx
+from ._azure_cosmoscx import (
+    QueryEngineError,
+    UnsupportedQueryError,
+    DeserializationError,
+    InvalidRequestError,
+    MemoryBudgetExceededError,
+    InternalError,
+    CancelledError,
+)
 from .query_engine import QueryEngine
 
 
@@ -11,3 +20,8 @@ def version():
 
 def enable_tracing():
     _azure_cosmoscx.enable_tracing()
+
+
+def supported_features(exclude=None):
+    # Returns a tuple of the supported feature names and the header value listing them, without any features named in exclude.
+    return _azure_cosmoscx.supported_features(exclude)
@@ -1,4 +1,4 @@
-from typing import Union
+from typing import Iterator, List, Optional, Sequence, Tuple, Union
 
 import azure.cosmos.query_engine
 
@@ -11,6 +11,10 @@ def enable_tracing() -> None:
     pass
 
 
+def supported_features(exclude: Optional[Sequence[str]] = None) -> Tuple[List[str], str]:
+    pass
+
+
 class QueryEngine(azure.cosmos.query_engine.QueryEngine):
     pass
 
@@ -25,3 +29,38 @@ class DataRequest(azure.cosmos.query_engine.DataRequest):
 
 class PipelineResult(azure.cosmos.query_engine.PipelineResult):
     pass
+
+
+class ItemIterator(Iterator[bytes]):
+    terminated: bool
+
+    def needs_data(self) -> List[DataRequest]:
+        pass
+
+
+class QueryEngineError(RuntimeError):
+    pass
+
+
+class UnsupportedQueryError(QueryEngineError):
+    pass
+
+
+class DeserializationError(QueryEngineError):
+    pass
+
+
+class InvalidRequestError(QueryEngineError):
+    pass
+
+
+class MemoryBudgetExceededError(QueryEngineError):
+    pass
+
+
+class InternalError(QueryEngineError):
+    pass
+
+
+class CancelledError(QueryEngineError):
+    pass
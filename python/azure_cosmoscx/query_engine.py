@@ -1,19 +1,40 @@
 # Copyright (c) Microsoft Corporation.
 # Licensed under the MIT License.
 
+import json
+
 import azure.cosmos.query_engine
 
 from . import _azure_cosmoscx
 
 
 class QueryEngine(azure.cosmos.query_engine.QueryEngine):
-    def create_pipeline(self, query, plan, pkranges):
-        # We don't care about query arguments
-        if isinstance(query, dict):
-            query = query['query']
+    def create_pipeline(self, query, plan, pkranges, options=None):
+        # The options, if any, are a dict with the fields of the engine's PipelineOptions, which the engine reads as JSON.
+        options_json = json.dumps(options) if options is not None else None
+        query, parameters_json = _query_args(query)
+
+        # If the SDK passes the raw gateway responses, deserialize them in the engine rather than converting them to Python objects.
+        if isinstance(plan, (bytes, str)) and isinstance(pkranges, (bytes, str)):
+            return _azure_cosmoscx.QueryPipeline.from_json(query, plan, pkranges, options_json, parameters_json)
+
+        return _azure_cosmoscx.QueryPipeline(query, plan, pkranges, options_json, parameters_json)
+
+    def create_passthrough_pipeline(self, query, pkranges):
+        # Only for queries without ORDER BY, GROUP BY, DISTINCT, TOP, OFFSET, LIMIT or aggregates, which otherwise raise UnsupportedQueryError.
+        query, parameters_json = _query_args(query)
+        return _azure_cosmoscx.QueryPipeline.passthrough(query, pkranges, parameters_json)
+
 
-        if not isinstance(query, str):
-            raise ValueError(
-                "query must be a string or dictionary containing the 'query' key")
+def _query_args(query):
+    # The engine attaches the parameters to the requests that need them, see DataRequest.encoded_parameters.
+    parameters_json = None
+    if isinstance(query, dict):
+        if query.get('parameters'):
+            parameters_json = json.dumps(query['parameters'])
+        query = query['query']
 
-        return _azure_cosmoscx.QueryPipeline(query, plan, pkranges)
+    if not isinstance(query, str):
+        raise ValueError(
+            "query must be a string or dictionary containing the 'query' key")
+    return query, parameters_json
@@ -0,0 +1,57 @@
+# Copyright (c) Microsoft Corporation.
+# Licensed under the MIT License.
+
+# Compares creating a pipeline from the raw gateway responses with creating one from Python dicts.
+# This doesn't need a Cosmos DB account, it only measures the cost of getting the plan and partition key ranges into the engine.
+
+import json
+import sys
+import timeit
+
+import azure_cosmoscx
+
+partition_count = 100
+if len(sys.argv) > 1:
+    partition_count = int(sys.argv[1])
+
+plan_json = json.dumps({
+    "partitionedQueryExecutionInfoVersion": 1,
+    "queryInfo": {
+        "distinctType": "None",
+        "orderBy": ["Ascending"],
+        "orderByExpressions": ["c.id"],
+        "rewrittenQuery": "SELECT c._rid, [{\"item\": c.id}] AS orderByItems, c AS payload FROM c WHERE ({documentdb-formattableorderbyquery-filter}) ORDER BY c.id",
+    },
+    "queryRanges": [],
+}).encode()
+pkranges_json = json.dumps({
+    "PartitionKeyRanges": [
+        {
+            "id": str(i),
+            "minInclusive": f"{i:04X}" if i > 0 else "",
+            "maxExclusive": f"{i + 1:04X}" if i < partition_count - 1 else "FF",
+        }
+        for i in range(partition_count)
+    ]
+}).encode()
+engine = azure_cosmoscx.QueryEngine()
+
+
+def from_dicts():
+    # The SDK currently parses the responses, and the engine then converts the resulting Python objects.
+    plan = json.loads(plan_json)
+    pkranges = json.loads(pkranges_json)["PartitionKeyRanges"]
+    engine.create_pipeline("SELECT * FROM c ORDER BY c.id", plan, pkranges)
+
+
+def from_json():
+    engine.create_pipeline("SELECT * FROM c ORDER BY c.id",
+                           plan_json, pkranges_json)
+
+
+count = 1000
+for name, func in [("dicts", from_dicts), ("json", from_json)]:
+    func()  # Warm up
+    time = timeit.timeit(stmt=func, number=count)
+    print(
+        f"{name}: {count} pipelines over {partition_count} partitions in {time * 1000:.1f}ms, {(time / count) * 1000000:.1f}us per pipeline")
@@ -6,6 +6,9 @@
 //! Building the crate produces the Python module, `azure_cosmoscx`,
 //! which can be imported in Python applications and exports an interface to the Cosmos Client Engine.
 
+use std::panic::AssertUnwindSafe;
+
+use azure_data_cosmos_engine::{python_exceptions as exceptions, query::SUPPORTED_FEATURES};
 use pyo3::{
     pyfunction, pymodule,
     types::{PyModule, PyModuleMethods},
@@ -19,9 +22,39 @@ mod pipeline;
 fn azure_cosmoscx(m: &Bound<PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(version, m)?)?;
     m.add_function(wrap_pyfunction!(enable_tracing, m)?)?;
+    m.add_function(wrap_pyfunction!(supported_features, m)?)?;
+    m.add_function(wrap_pyfunction!(force_panic, m)?)?;
     m.add_class::<pipeline::NativeQueryPipeline>()?;
     m.add_class::<pipeline::PyPipelineResult>()?;
     m.add_class::<pipeline::PyDataRequest>()?;
+    m.add_class::<pipeline::PyItemIterator>()?;
+
+    let py = m.py();
+    m.add(
+        "QueryEngineError",
+        py.get_type::<exceptions::QueryEngineError>(),
+    )?;
+    m.add(
+        "UnsupportedQueryError",
+        py.get_type::<exceptions::UnsupportedQueryError>(),
+    )?;
+    m.add(
+        "DeserializationError",
+        py.get_type::<exceptions::DeserializationError>(),
+    )?;
+    m.add(
+        "InvalidRequestError",
+        py.get_type::<exceptions::InvalidRequestError>(),
+    )?;
+    m.add(
+        "MemoryBudgetExceededError",
+        py.get_type::<exceptions::MemoryBudgetExceededError>(),
+    )?;
+    m.add("InternalError", py.get_type::<exceptions::InternalError>())?;
+    m.add(
+        "CancelledError",
+        py.get_type::<exceptions::CancelledError>(),
+    )?;
     Ok(())
 }
 
@@ -31,11 +64,52 @@ fn version() -> &'static str {
 }
 
 #[pyfunction]
-fn enable_tracing() {
+fn enable_tracing() -> PyResult<()> {
     // TODO: We could probably wrap Python's OpenTracing API here.
 
-    // Ignore failure to init, it just means tracing is already enabled.
-    _ = tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_env("COSMOSCX_LOG"))
-        .try_init();
+    catch_panic(|| {
+        // Ignore failure to init, it just means tracing is already enabled.
+        _ = tracing_subscriber::fmt()
+            .with_env_filter(EnvFilter::from_env("COSMOSCX_LOG"))
+            .try_init();
+        Ok(())
+    })
+}
+
+/// Gets the query features supported by the engine, leaving out any named in `exclude`.
+///
+/// Returns the feature names, and the same names, each followed by a comma, as a value for the `x-ms-cosmos-supported-query-features` header.
+/// Names of features that aren't supported are ignored.
+#[pyfunction]
+#[pyo3(signature = (exclude = None))]
+fn supported_features(exclude: Option<Vec<String>>) -> PyResult<(Vec<&'static str>, String)> {
+    catch_panic(|| {
+        let exclude = exclude.unwrap_or_default();
+        let (excluded, included): (Vec<_>, Vec<_>) = SUPPORTED_FEATURES
+            .as_slice()
+            .iter()
+            .copied()
+            .partition(|feature| exclude.iter().any(|name| name == feature.name()));
+        Ok((
+            included.iter().map(|feature| feature.name()).collect(),
+            SUPPORTED_FEATURES.without(&excluded),
+        ))
+    })
+}
+
+/// Runs the body of a Python-accessible function, raising a panic as an `InternalError`.
+///
+/// pyo3 would otherwise raise a `PanicException`, which derives from `BaseException` and escapes the handlers the SDK uses for engine errors.
+pub(crate) fn catch_panic<T>(f: impl FnOnce() -> PyResult<T>) -> PyResult<T> {
+    // A pipeline that panicked may be left in an inconsistent state, but its lock is poisoned, so it can't be used again.
+    std::panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(azure_data_cosmos_engine::Error::from_panic(payload).into()))
+}
+
+/// Panics inside the engine, so that tests can check that panics are raised as an `InternalError`.
+///
+/// This function is only intended for testing, it always raises.
+#[pyfunction(name = "_panic")]
+fn force_panic() -> PyResult<()> {
+    catch_panic(|| panic!("_panic was called"))
 }
@@ -1,23 +1,43 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::{ops::DerefMut, sync::Mutex};
+use std::{
+    ops::DerefMut,
+    sync::{Mutex, TryLockError},
+};
 
-use azure_data_cosmos_engine::query::{PartitionKeyRange, PipelineResponse, QueryPipeline};
+use azure_data_cosmos_engine::query::{
+    CancellationToken, DataRequest, PartitionKeyRange, PipelineEvent, PipelineOptions,
+    PipelineResponse, Query, QueryPipeline, QueryPlan, ResponseMetadata,
+};
 use pyo3::{
     exceptions, pyclass, pymethods,
     types::{PyAnyMethods, PyBytes, PyBytesMethods, PyInt, PyList, PyString, PyStringMethods},
-    Bound, Py, PyAny, PyErr, PyResult, Python,
+    Bound, Py, PyAny, PyErr, PyRef, PyResult, Python,
 };
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::value::RawValue;
+
+use crate::catch_panic;
 
 #[pyclass(frozen, name = "QueryPipeline")]
 pub struct NativeQueryPipeline {
     // Python may access this object on any thread.
     pipeline: Mutex<QueryPipeline>,
+
+    // Held outside the lock, so `cancel` can stop a turn that's running on another thread.
+    cancellation: CancellationToken,
 }
 
 // All methods in this block are NOT python-accessible, and only visible to Rust code
 impl NativeQueryPipeline {
+    fn from_pipeline(pipeline: QueryPipeline) -> Self {
+        Self {
+            cancellation: pipeline.cancellation_token(),
+            pipeline: Mutex::new(pipeline),
+        }
+    }
+
     #[inline(always)]
     fn pipeline(&self) -> PyResult<impl DerefMut<Target = QueryPipeline> + '_> {
         self.pipeline
@@ -26,48 +46,311 @@ impl NativeQueryPipeline {
     }
 }
 
+/// Deserializes a JSON argument, which may be provided as either `bytes` or `str`.
+///
+/// Malformed JSON raises a `ValueError` containing the deserialization error.
+fn from_json_arg<T: DeserializeOwned>(name: &str, value: &Bound<PyAny>) -> PyResult<T> {
+    let result = if let Ok(bytes) = value.downcast::<PyBytes>() {
+        serde_json::from_slice(bytes.as_bytes())
+    } else if let Ok(string) = value.downcast::<PyString>() {
+        serde_json::from_str(string.to_str()?)
+    } else {
+        return Err(PyErr::new::<exceptions::PyTypeError, _>(format!(
+            "{} must be bytes or str",
+            name
+        )));
+    };
+    result
+        .map_err(|e| PyErr::new::<exceptions::PyValueError, _>(format!("invalid {}: {}", name, e)))
+}
+
+/// Deserializes the optional `options_json` argument, using the default options if it wasn't provided.
+fn pipeline_options(options_json: Option<Bound<PyAny>>) -> PyResult<PipelineOptions> {
+    options_json
+        .map(|o| from_json_arg("options_json", &o))
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// Creates the [`Query`] from the query text and the optional `parameters_json` argument.
+///
+/// The parameters are the JSON `parameters` field of a Cosmos query, as either `bytes` or `str`.
+fn query_arg(query: &Bound<PyString>, parameters_json: Option<Bound<PyAny>>) -> PyResult<Query> {
+    let query = query.to_str()?;
+    Ok(match parameters_json {
+        Some(p) => Query::with_parameters(
+            query,
+            from_json_arg::<Box<RawValue>>("parameters_json", &p)?,
+        ),
+        None => query.into(),
+    })
+}
+
+/// The partition key ranges list, as returned by the gateway.
+#[derive(Deserialize)]
+struct PartitionKeyRangeResult {
+    #[serde(rename = "PartitionKeyRanges")]
+    ranges: Vec<PartitionKeyRange>,
+}
+
+/// Gets a list of partition key ranges, which may be converted to Python objects, or the raw JSON response from the gateway as either `bytes` or `str`.
+fn pkranges_arg(name: &str, value: &Bound<PyAny>) -> PyResult<Vec<PartitionKeyRange>> {
+    if value.is_instance_of::<PyBytes>() || value.is_instance_of::<PyString>() {
+        Ok(from_json_arg::<PartitionKeyRangeResult>(name, value)?.ranges)
+    } else {
+        value.extract()
+    }
+}
+
 // All methods in this block are python-accessible
 #[pymethods]
 impl NativeQueryPipeline {
+    /// Creates a pipeline from the query plan and partition key ranges, converted to Python objects.
+    ///
+    /// The `options_json`, if provided, is a JSON object with the fields of the engine's `PipelineOptions`, as either `bytes` or `str`.
+    /// Missing fields use their default values.
+    /// The `parameters_json`, if provided, is the JSON `parameters` field of the query, which the engine attaches to the requests that need it.
     #[new]
-    fn new(query: Bound<PyString>, plan: Bound<PyAny>, pkranges: Bound<PyAny>) -> PyResult<Self> {
-        let query = query.to_str()?;
-        let plan = plan.extract()?;
-        let pkranges: Vec<PartitionKeyRange> = pkranges.extract()?;
-        let pipeline = QueryPipeline::new(query, plan, pkranges)?;
+    #[pyo3(signature = (query, plan, pkranges, options_json=None, parameters_json=None))]
+    fn new(
+        query: Bound<PyString>,
+        plan: Bound<PyAny>,
+        pkranges: Bound<PyAny>,
+        options_json: Option<Bound<PyAny>>,
+        parameters_json: Option<Bound<PyAny>>,
+    ) -> PyResult<Self> {
+        catch_panic(|| {
+            let query = query_arg(&query, parameters_json)?;
+            let plan = plan.extract()?;
+            let pkranges: Vec<PartitionKeyRange> = pkranges.extract()?;
+            let options = pipeline_options(options_json)?;
+            let pipeline = QueryPipeline::with_options(query, plan, pkranges, options)?;
 
-        Ok(Self {
-            pipeline: Mutex::new(pipeline),
+            Ok(Self::from_pipeline(pipeline))
+        })
+    }
+
+    /// Creates a pipeline from the raw JSON responses returned by the gateway, without converting them to Python objects first.
+    ///
+    /// The `plan_json` is the query plan response, and the `pkranges_json` is the partition key ranges response
+    /// (an object with a `PartitionKeyRanges` property). Each may be provided as either `bytes` or `str`.
+    /// The `options_json`, if provided, is a JSON object with the fields of the engine's `PipelineOptions`.
+    /// The `parameters_json`, if provided, is the JSON `parameters` field of the query.
+    #[staticmethod]
+    #[pyo3(signature = (query, plan_json, pkranges_json, options_json=None, parameters_json=None))]
+    fn from_json(
+        query: Bound<PyString>,
+        plan_json: Bound<PyAny>,
+        pkranges_json: Bound<PyAny>,
+        options_json: Option<Bound<PyAny>>,
+        parameters_json: Option<Bound<PyAny>>,
+    ) -> PyResult<Self> {
+        catch_panic(|| {
+            let query = query_arg(&query, parameters_json)?;
+            let plan: QueryPlan = from_json_arg("plan_json", &plan_json)?;
+            let pkranges: PartitionKeyRangeResult = from_json_arg("pkranges_json", &pkranges_json)?;
+            let options = pipeline_options(options_json)?;
+            let pipeline = QueryPipeline::with_options(query, plan, pkranges.ranges, options)?;
+
+            Ok(Self::from_pipeline(pipeline))
+        })
+    }
+
+    /// Creates a pipeline for a simple query, without a query plan from the gateway.
+    ///
+    /// The `pkranges` may be the partition key ranges converted to Python objects, or the raw JSON response from the gateway, as either `bytes` or `str`.
+    /// Queries that need a query plan, like those with an `ORDER BY` or aggregates, raise an `UnsupportedQueryError`.
+    /// The `parameters_json`, if provided, is the JSON `parameters` field of the query.
+    #[staticmethod]
+    #[pyo3(signature = (query, pkranges, parameters_json=None))]
+    fn passthrough(
+        query: Bound<PyString>,
+        pkranges: Bound<PyAny>,
+        parameters_json: Option<Bound<PyAny>>,
+    ) -> PyResult<Self> {
+        catch_panic(|| {
+            let query = query_arg(&query, parameters_json)?;
+            let pkranges = pkranges_arg("pkranges", &pkranges)?;
+            let pipeline = QueryPipeline::new_passthrough(query, pkranges)?;
+
+            Ok(Self::from_pipeline(pipeline))
+        })
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (query, plan, pkranges, state, parameters_json=None))]
+    fn resume(
+        query: Bound<PyString>,
+        plan: Bound<PyAny>,
+        pkranges: Bound<PyAny>,
+        state: Bound<PyString>,
+        parameters_json: Option<Bound<PyAny>>,
+    ) -> PyResult<Self> {
+        catch_panic(|| {
+            let query = query_arg(&query, parameters_json)?;
+            let plan = plan.extract()?;
+            let pkranges: Vec<PartitionKeyRange> = pkranges.extract()?;
+            let pipeline = QueryPipeline::resume(query, plan, pkranges, state.to_str()?)?;
+
+            Ok(Self::from_pipeline(pipeline))
+        })
+    }
+
+    fn serialize_state<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyString>> {
+        catch_panic(|| {
+            let pipeline = self.pipeline()?;
+            Ok(PyString::new(py, &pipeline.serialize_state()?))
         })
     }
 
-    fn query<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyString>> {
-        let pipeline = self.pipeline()?;
-        Ok(PyString::new(py, pipeline.query()))
+    fn query<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyString>>> {
+        catch_panic(|| {
+            let pipeline = self.pipeline()?;
+            Ok(pipeline.query().map(|q| PyString::new(py, q)))
+        })
     }
 
+    fn id<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyString>> {
+        catch_panic(|| {
+            let pipeline = self.pipeline()?;
+            Ok(PyString::new(py, pipeline.id()))
+        })
+    }
+
+    /// Gets the query metrics of every page provided so far, added up across partitions, in the same format as the `x-ms-documentdb-query-metrics` header.
+    fn query_metrics<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyString>> {
+        catch_panic(|| {
+            let pipeline = self.pipeline()?;
+            Ok(PyString::new(py, &pipeline.query_metrics().to_string()))
+        })
+    }
+
+    /// Runs a turn of the pipeline, returning every item it can produce with the data it has.
+    ///
+    /// The merge runs with the GIL released. See `items` to take the items one at a time instead.
     fn next_batch(&self, py: Python) -> PyResult<Option<PyPipelineResult>> {
-        let mut pipeline = self.pipeline()?;
-        let result = pipeline.run()?;
-        Ok(Some(PyPipelineResult::new(py, result)?))
+        catch_panic(|| {
+            let result = py.allow_threads(|| -> PyResult<_> { Ok(self.pipeline()?.run()?) })?;
+            Ok(Some(PyPipelineResult::new(py, result)?))
+        })
+    }
+
+    /// Returns an iterator over the items the pipeline can produce with the data it has, which pulls them from the pipeline one at a time.
+    ///
+    /// Unlike `next_batch`, only the item being returned is converted to `bytes`, so the caller never holds a whole turn of items at once.
+    /// See `ItemIterator` for the protocol.
+    fn items(slf: Py<Self>) -> PyItemIterator {
+        PyItemIterator {
+            pipeline: slf,
+            requests: Vec::new(),
+            terminated: false,
+        }
     }
 
+    /// Provides a page of data for a request, along with the metadata from the response headers, if the caller has it.
+    ///
+    /// The request charges are summed up in `cumulative_request_charge`, the activity IDs are returned in `activity_ids`,
+    /// and the `query_metrics` header values are added up in `query_metrics()`.
+    #[pyo3(signature = (pkrange_id, request_id, data, continuation, request_charge=None, activity_id=None, retry_after_ms=None, query_metrics=None))]
+    #[allow(clippy::too_many_arguments)] // The metadata arguments are optional keyword arguments in Python.
     fn provide_data<'py>(
         &self,
+        py: Python<'py>,
         pkrange_id: Bound<'py, PyString>,
         request_id: Bound<'py, PyInt>,
         data: Bound<'py, PyBytes>,
         continuation: Option<Bound<'py, PyString>>,
+        request_charge: Option<f64>,
+        activity_id: Option<String>,
+        retry_after_ms: Option<u64>,
+        query_metrics: Option<String>,
+    ) -> PyResult<()> {
+        catch_panic(|| {
+            let pkrange_id = pkrange_id.to_str()?;
+            let request_id = request_id.extract()?;
+            let continuation = continuation
+                .map(|s| s.to_str().map(|s| s.to_string()))
+                .transpose()?;
+            // Pass the raw bytes directly to the pipeline, and deserialize them with the GIL released. `bytes` are immutable, so they can't change meanwhile.
+            let data = data.as_bytes();
+            py.allow_threads(|| -> PyResult<_> {
+                self.pipeline()?.provide_data_with_metadata(
+                    pkrange_id,
+                    request_id,
+                    data,
+                    continuation,
+                    ResponseMetadata {
+                        request_charge: request_charge.unwrap_or(0.0),
+                        activity_id,
+                        retry_after_ms,
+                        query_metrics,
+                    },
+                )?;
+                Ok(())
+            })
+        })
+    }
+
+    /// Replaces a partition that the backend reported as gone, because it was split, with the partitions it was split into.
+    ///
+    /// The `replacements` may be converted to Python objects, or the raw JSON partition key ranges response, as either `bytes` or `str`.
+    /// Queries that can't handle the split raise an `UnsupportedQueryError`, and must be restarted.
+    fn handle_pkrange_gone(
+        &self,
+        old_pkrange_id: Bound<PyString>,
+        replacements: Bound<PyAny>,
+    ) -> PyResult<()> {
+        catch_panic(|| {
+            let replacements = pkranges_arg("replacements", &replacements)?;
+            let mut pipeline = self.pipeline()?;
+            pipeline.handle_pkrange_gone(old_pkrange_id.to_str()?, replacements)?;
+            Ok(())
+        })
+    }
+
+    /// Provides several pages at once, as a list of `(pkrange_id, request_id, data, continuation)` tuples.
+    fn provide_data_batch<'py>(
+        &self,
+        py: Python<'py>,
+        responses: Vec<(String, u64, Bound<'py, PyBytes>, Option<String>)>,
     ) -> PyResult<()> {
-        let mut pipeline = self.pipeline()?;
-        let pkrange_id = pkrange_id.to_str()?;
-        let request_id = request_id.extract()?;
-        let continuation = continuation
-            .map(|s| s.to_str().map(|s| s.to_string()))
-            .transpose()?;
-        // Pass the raw bytes directly to the pipeline
-        pipeline.provide_data(pkrange_id, request_id, data.as_bytes(), continuation)?;
-        Ok(())
+        catch_panic(|| {
+            let responses = responses
+                .into_iter()
+                .map(|(pkrange_id, request_id, data, continuation)| {
+                    (
+                        pkrange_id,
+                        request_id,
+                        data.as_bytes().to_vec(),
+                        continuation,
+                    )
+                })
+                .collect();
+            py.allow_threads(|| -> PyResult<_> {
+                self.pipeline()?.provide_data_batch(responses)?;
+                Ok(())
+            })
+        })
+    }
+
+    /// Cancels the query, releasing all the items the pipeline has buffered.
+    ///
+    /// This can be called from any thread. If another thread is using the pipeline, the call it's making raises a `CancelledError`.
+    /// After this, `next_batch` returns a terminated result, with no items and no requests.
+    fn cancel(&self) -> PyResult<()> {
+        catch_panic(|| {
+            self.cancellation.cancel();
+
+            // The thread using the pipeline, if any, cancels the query itself, once it sees the token.
+            match self.pipeline.try_lock() {
+                Ok(mut pipeline) => pipeline.cancel(),
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Poisoned(_)) => {
+                    return Err(PyErr::new::<exceptions::PyRuntimeError, _>("lock poisoned"))
+                }
+            }
+            Ok(())
+        })
     }
 }
 
@@ -79,6 +362,11 @@ pub struct PyPipelineResult {
     requests: Py<PyList>,
     #[pyo3(get)]
     terminated: bool,
+    #[pyo3(get)]
+    cumulative_request_charge: f64,
+    /// The activity IDs of the pages the pipeline accepted since the previous batch.
+    #[pyo3(get)]
+    activity_ids: Vec<String>,
 }
 
 impl PyPipelineResult {
@@ -87,24 +375,219 @@ impl PyPipelineResult {
             .items
             .into_iter()
             .map(|r| PyBytes::new(py, r.get().as_bytes()));
-        let requests = result.requests.into_iter().map(|r| PyDataRequest {
-            pkrange_id: PyString::new(py, r.pkrange_id.as_ref()).unbind(),
-            continuation: r.continuation.map(|s| PyString::new(py, &s).unbind()),
-        });
+        let requests = result
+            .requests
+            .into_iter()
+            .map(|r| PyDataRequest::new(py, r));
         let items = PyList::new(py, items)?.unbind();
         let requests = PyList::new(py, requests)?.unbind();
         Ok(Self {
             items,
             requests,
             terminated: result.terminated,
+            cumulative_request_charge: result.cumulative_request_charge,
+            activity_ids: result.activity_ids,
         })
     }
 }
 
 #[pyclass(name = "DataRequest")]
 pub struct PyDataRequest {
+    /// The ID to pass to `provide_data` with the response to this request.
+    #[pyo3(get)]
+    pub id: u64,
     #[pyo3(get)]
     pub pkrange_id: Py<PyString>,
     #[pyo3(get)]
     pub continuation: Option<Py<PyString>>,
+    /// The query to execute for this request, or `None` to use the pipeline's query.
+    #[pyo3(get)]
+    pub query: Option<Py<PyString>>,
+    #[pyo3(get)]
+    pub include_parameters: bool,
+    /// The parameters to send with the request, encoded as the JSON `parameters` field of a Cosmos query, or `None` to send no parameters.
+    #[pyo3(get)]
+    pub encoded_parameters: Option<Py<PyString>>,
+    #[pyo3(get)]
+    pub max_item_count: Option<u32>,
+}
+
+impl PyDataRequest {
+    fn new(py: Python, request: DataRequest) -> Self {
+        Self {
+            id: request.id,
+            pkrange_id: PyString::new(py, request.pkrange_id.as_ref()).unbind(),
+            continuation: request.continuation.map(|s| PyString::new(py, &s).unbind()),
+            query: request.query.map(|s| PyString::new(py, &s).unbind()),
+            include_parameters: request.include_parameters,
+            encoded_parameters: request
+                .encoded_parameters
+                .map(|p| PyString::new(py, p.get()).unbind()),
+            max_item_count: request.max_item_count,
+        }
+    }
+}
+
+/// Iterates over the items a pipeline can produce with the data it has, pulling them one at a time, see `QueryPipeline.items`.
+///
+/// When the iterator stops, `terminated` indicates if the query has completed. If it hasn't, `needs_data` returns the requests
+/// the pipeline is waiting on. Once their data has been provided, iterating again continues with the next item.
+#[pyclass(name = "ItemIterator")]
+pub struct PyItemIterator {
+    pipeline: Py<NativeQueryPipeline>,
+
+    // The requests the pipeline was waiting on when the iterator last stopped.
+    requests: Vec<DataRequest>,
+
+    #[pyo3(get)]
+    terminated: bool,
+}
+
+#[pymethods]
+impl PyItemIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    /// Pulls the next item, with the GIL released while the pipeline merges, and converts it to `bytes`.
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        catch_panic(|| {
+            let pipeline = self.pipeline.get();
+            let event =
+                py.allow_threads(|| -> PyResult<_> { Ok(pipeline.pipeline()?.next_item()?) })?;
+            self.requests.clear();
+            match event {
+                PipelineEvent::Item(item) => Ok(Some(PyBytes::new(py, item.get().as_bytes()))),
+                PipelineEvent::NeedsData(requests) => {
+                    self.requests = requests;
+                    Ok(None)
+                }
+                PipelineEvent::Done => {
+                    self.terminated = true;
+                    Ok(None)
+                }
+            }
+        })
+    }
+
+    /// Gets the requests the pipeline was waiting on when the iterator last stopped.
+    ///
+    /// This is empty while the iterator is still producing items, and once the query has completed.
+    fn needs_data(&self, py: Python) -> Vec<PyDataRequest> {
+        self.requests
+            .iter()
+            .cloned()
+            .map(|r| PyDataRequest::new(py, r))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use azure_data_cosmos_engine::query::QueryInfo;
+
+    use super::*;
+
+    fn create_pipeline(py: Python) -> Py<NativeQueryPipeline> {
+        let pipeline = QueryPipeline::new(
+            "SELECT * FROM c",
+            QueryPlan {
+                partitioned_query_execution_info_version: 1,
+                query_info: Some(QueryInfo::default()),
+                ..Default::default()
+            },
+            vec![
+                PartitionKeyRange::new("partition0", "", "80"),
+                PartitionKeyRange::new("partition1", "80", "FF"),
+            ],
+        )
+        .unwrap();
+        Py::new(py, NativeQueryPipeline::from_pipeline(pipeline)).unwrap()
+    }
+
+    fn next(py: Python, items: &mut PyItemIterator) -> Option<String> {
+        items
+            .__next__(py)
+            .unwrap()
+            .map(|b| String::from_utf8(b.as_bytes().to_vec()).unwrap())
+    }
+
+    fn provide(pipeline: &NativeQueryPipeline, request: &PyDataRequest, py: Python, data: &[u8]) {
+        pipeline
+            .pipeline()
+            .unwrap()
+            .provide_data(
+                request.pkrange_id.to_str(py).unwrap(),
+                request.id,
+                data,
+                None,
+                None,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn items_are_pulled_one_at_a_time_across_turns() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let pipeline = create_pipeline(py);
+            let mut items = NativeQueryPipeline::items(pipeline.clone_ref(py));
+            assert!(items.needs_data(py).is_empty());
+
+            // The iterator stops as soon as the pipeline needs data, and keeps stopping until it's provided.
+            for _ in 0..2 {
+                assert_eq!(None, next(py, &mut items));
+                assert!(!items.terminated);
+                let requests = items.needs_data(py);
+                assert_eq!(1, requests.len());
+                assert_eq!("partition0", requests[0].pkrange_id.to_str(py).unwrap());
+            }
+
+            let request = items.needs_data(py).remove(0);
+            provide(pipeline.get(), &request, py, br#"{"Documents": [1, 2]}"#);
+            assert_eq!(Some("1".to_string()), next(py, &mut items));
+
+            // The outstanding requests are cleared once the iterator produces items again.
+            assert!(items.needs_data(py).is_empty());
+            assert_eq!(Some("2".to_string()), next(py, &mut items));
+            assert_eq!(None, next(py, &mut items));
+
+            let request = items.needs_data(py).remove(0);
+            assert_eq!("partition1", request.pkrange_id.to_str(py).unwrap());
+            provide(pipeline.get(), &request, py, br#"{"Documents": [3]}"#);
+            assert_eq!(Some("3".to_string()), next(py, &mut items));
+            assert_eq!(None, next(py, &mut items));
+            assert!(items.terminated);
+            assert!(items.needs_data(py).is_empty());
+
+            // An exhausted query stays exhausted.
+            assert_eq!(None, next(py, &mut items));
+            assert!(items.terminated);
+        });
+    }
+
+    #[test]
+    fn items_release_the_gil_while_pulling() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let pipeline = create_pipeline(py);
+            let mut items = NativeQueryPipeline::items(pipeline.clone_ref(py));
+
+            // While another thread holds the pipeline, pulling an item waits for it with the GIL released, so that thread can take the GIL.
+            let (locked, release) = (std::sync::Barrier::new(2), std::sync::Barrier::new(2));
+            std::thread::scope(|scope| {
+                let shared = pipeline.get();
+                scope.spawn(|| {
+                    let _guard = shared.pipeline().unwrap();
+                    locked.wait();
+                    release.wait();
+                    Python::with_gil(|_| {});
+                });
+                py.allow_threads(|| locked.wait());
+                release.wait();
+                assert_eq!(None, next(py, &mut items));
+            });
+            assert_eq!(1, items.needs_data(py).len());
+        });
+    }
 }
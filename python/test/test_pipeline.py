@@ -1,6 +1,7 @@
 # Copyright (c) Microsoft Corporation.
 # Licensed under the MIT License.
 
+import json
 import unittest
 import azure_cosmoscx
 
@@ -197,3 +198,433 @@ class TestPipeline(unittest.TestCase):
         self.assertTrue(result.terminated)
         self.assertEqual([], result.items)
         self.assertEqual([], result.requests)
+
+    def test_from_json(self):
+        plan = b"""{
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryInfo": {
+                "distinctType": "None",
+                "rewrittenQuery": "WAS REWRITTEN"
+            },
+            "queryRanges": []
+        }"""
+        pkranges = b"""{
+            "PartitionKeyRanges": [
+                {"id": "partition0", "minInclusive": "00", "maxExclusive": "99"},
+                {"id": "partition1", "minInclusive": "99", "maxExclusive": "FF"}
+            ]
+        }"""
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c", plan, pkranges)
+
+        self.assertEqual("WAS REWRITTEN", pipeline.query())
+
+        result = pipeline.next_batch()
+        requests = [(r.pkrange_id, r.continuation)
+                    for r in result.requests]
+        self.assertEqual([("partition0", None)], requests)
+
+    def test_from_json_matches_dict_constructor(self):
+        plan = {
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryInfo": {
+                "distinctType": "None",
+                "orderBy": ["Ascending"],
+            },
+            "queryRanges": []
+        }
+        pkranges = [
+            {"id": "partition0", "minInclusive": "00", "maxExclusive": "99"},
+            {"id": "partition1", "minInclusive": "99", "maxExclusive": "FF"}
+        ]
+        pipelines = [
+            azure_cosmoscx.QueryEngine().create_pipeline(
+                "SELECT * FROM c", plan, pkranges),
+            azure_cosmoscx.QueryEngine().create_pipeline(
+                "SELECT * FROM c", json.dumps(plan), json.dumps({"PartitionKeyRanges": pkranges})),
+        ]
+
+        results = []
+        for pipeline in pipelines:
+            pipeline.provide_data("partition0", 0, json.dumps({"Documents": [
+                {"orderByItems": [{"item": 1}], "payload": 1},
+                {"orderByItems": [{"item": 3}], "payload": 3},
+            ]}).encode(), None)
+            pipeline.provide_data("partition1", 0, json.dumps({"Documents": [
+                {"orderByItems": [{"item": 2}], "payload": 2},
+            ]}).encode(), None)
+            result = pipeline.next_batch()
+            results.append((result.items, result.terminated))
+
+        self.assertEqual(([b"1", b"2", b"3"], True), results[0])
+        self.assertEqual(results[0], results[1])
+
+    def test_request_charge(self):
+        plan = {
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryInfo": {
+                "distinctType": "None",
+            },
+            "queryRanges": []
+        }
+        pkranges = [
+            {"id": "partition0", "minInclusive": "00", "maxExclusive": "99"},
+            {"id": "partition1", "minInclusive": "99", "maxExclusive": "FF"}
+        ]
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c", plan, pkranges)
+        self.assertEqual(0.0, pipeline.next_batch().cumulative_request_charge)
+
+        pipeline.provide_data("partition0", 0, json.dumps(
+            {"Documents": [1]}).encode(), None, request_charge=2.5)
+        result = pipeline.next_batch()
+        self.assertEqual(2.5, result.cumulative_request_charge)
+
+        pipeline.provide_data("partition1", 0, json.dumps(
+            {"Documents": [2]}).encode(), None, request_charge=1.25)
+        result = pipeline.next_batch()
+        self.assertTrue(result.terminated)
+        self.assertEqual(3.75, result.cumulative_request_charge)
+
+    def test_response_metadata(self):
+        plan = {"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {}, "queryRanges": []}
+        pkranges = {"PartitionKeyRanges": [
+            {"id": "partition0", "minInclusive": "", "maxExclusive": "80"},
+            {"id": "partition1", "minInclusive": "80", "maxExclusive": "FF"}
+        ]}
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c", json.dumps(plan), json.dumps(pkranges))
+        self.assertEqual([], pipeline.next_batch().activity_ids)
+
+        page = json.dumps({"Documents": [1]}).encode()
+        pipeline.provide_data("partition0", 0, page, "token1",
+                              request_charge=2.5, activity_id="activity0")
+        pipeline.provide_data("partition0", 1, page, None,
+                              request_charge=1.0, activity_id="activity1", retry_after_ms=100)
+        result = pipeline.next_batch()
+        self.assertEqual(3.5, result.cumulative_request_charge)
+        self.assertEqual(["activity0", "activity1"], result.activity_ids)
+
+        # Each batch only has the activity IDs provided since the previous one.
+        pipeline.provide_data("partition1", 0, page, None, request_charge=0.5, activity_id="activity2")
+        result = pipeline.next_batch()
+        self.assertTrue(result.terminated)
+        self.assertEqual(4.0, result.cumulative_request_charge)
+        self.assertEqual(["activity2"], result.activity_ids)
+
+    def test_query_metrics(self):
+        plan = {"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {}, "queryRanges": []}
+        pkranges = {"PartitionKeyRanges": [
+            {"id": "partition0", "minInclusive": "", "maxExclusive": "80"},
+            {"id": "partition1", "minInclusive": "80", "maxExclusive": "FF"}
+        ]}
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c", json.dumps(plan), json.dumps(pkranges))
+        pipeline.next_batch()
+
+        page = json.dumps({"Documents": [1]}).encode()
+        pipeline.provide_data("partition0", 0, page, None,
+                              query_metrics="totalExecutionTimeInMs=1.50;retrievedDocumentCount=3;indexUtilizationRatio=1.00")
+        pipeline.next_batch()
+        pipeline.provide_data("partition1", 0, page, None,
+                              query_metrics="totalExecutionTimeInMs=0.25;retrievedDocumentCount=1;indexUtilizationRatio=0.00")
+        metrics = dict(pair.split("=") for pair in pipeline.query_metrics().split(";"))
+        self.assertEqual("1.75", metrics["totalExecutionTimeInMs"])
+        self.assertEqual("4", metrics["retrievedDocumentCount"])
+        self.assertEqual("0.75", metrics["indexUtilizationRatio"])
+
+    def test_items_iterator(self):
+        plan = {"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {"orderBy": ["Ascending"]}, "queryRanges": []}
+        pkranges = {"PartitionKeyRanges": [
+            {"id": "partition0", "minInclusive": "", "maxExclusive": "80"},
+            {"id": "partition1", "minInclusive": "80", "maxExclusive": "FF"}
+        ]}
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c ORDER BY c.x", json.dumps(plan), json.dumps(pkranges))
+
+        def page(*values):
+            return json.dumps({"Documents": [
+                {"orderByItems": [{"item": v}], "payload": {"x": v}} for v in values]}).encode()
+
+        pages = {
+            ("partition0", None): (page(1, 4), "p0c1"),
+            ("partition0", "p0c1"): (page(6), None),
+            ("partition1", None): (page(2, 3, 5), None),
+        }
+        items = pipeline.items()
+        self.assertIs(items, iter(items))
+        results = []
+        turns = 0
+        while True:
+            results.extend(json.loads(item) for item in items)
+            if items.terminated:
+                break
+            turns += 1
+            requests = items.needs_data()
+            self.assertNotEqual([], requests)
+            for request in requests:
+                data, continuation = pages[(request.pkrange_id, request.continuation)]
+                pipeline.provide_data(request.pkrange_id, request.id, data, continuation)
+
+        self.assertEqual([{"x": x} for x in range(1, 7)], results)
+        self.assertEqual(2, turns)
+        self.assertEqual([], items.needs_data())
+        self.assertEqual([], list(items))
+
+    def test_provide_data_batch(self):
+        plan = {
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryInfo": {
+                "distinctType": "None",
+                "orderBy": ["Ascending"],
+            },
+            "queryRanges": []
+        }
+        pkranges = [
+            {"id": "partition0", "minInclusive": "00", "maxExclusive": "99"},
+            {"id": "partition1", "minInclusive": "99", "maxExclusive": "FF"}
+        ]
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c", plan, pkranges)
+
+        def page(sort):
+            return json.dumps({"Documents": [
+                {"orderByItems": [{"item": sort}], "payload": sort},
+            ]}).encode()
+
+        with self.assertRaises(RuntimeError) as cm:
+            pipeline.provide_data_batch([
+                ("partition0", 0, page(1), None),
+                ("partition2", 0, page(2), None),
+            ])
+        self.assertIn(
+            "page 1 (partition key range ID partition2)", str(cm.exception))
+
+        pipeline.provide_data_batch([("partition1", 0, page(3), None)])
+        result = pipeline.next_batch()
+        self.assertTrue(result.terminated)
+        self.assertEqual([b"1", b"3"], result.items)
+
+    def test_from_json_rejects_malformed_json(self):
+        pkranges = b'{"PartitionKeyRanges": []}'
+        with self.assertRaises(ValueError) as cm:
+            azure_cosmoscx.QueryEngine().create_pipeline(
+                "SELECT * FROM c", b'{"queryInfo": ', pkranges)
+        self.assertIn("plan_json", str(cm.exception))
+
+        with self.assertRaises(ValueError):
+            azure_cosmoscx.QueryEngine().create_pipeline(
+                "SELECT * FROM c", b'{"partitionedQueryExecutionInfoVersion": 1, "queryRanges": []}', b"[]")
+
+    def test_errors_raise_specific_exceptions(self):
+        plan = {
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryInfo": {
+                "distinctType": "Ordered",
+            },
+            "queryRanges": []
+        }
+        pkranges = [
+            {"id": "partition0", "minInclusive": "00", "maxExclusive": "FF"}
+        ]
+        with self.assertRaises(azure_cosmoscx.UnsupportedQueryError) as cm:
+            azure_cosmoscx.QueryEngine().create_pipeline(
+                "SELECT DISTINCT * FROM c", plan, pkranges)
+        self.assertIn("DISTINCT", str(cm.exception))
+
+        plan["queryInfo"]["distinctType"] = "None"
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c", plan, pkranges)
+        with self.assertRaises(azure_cosmoscx.InvalidRequestError) as cm:
+            pipeline.provide_data("partition0", 1, b'{"Documents": []}', None)
+
+        # Every engine error is still a RuntimeError.
+        self.assertIsInstance(cm.exception, azure_cosmoscx.QueryEngineError)
+        self.assertIsInstance(cm.exception, RuntimeError)
+
+    def test_pipeline_options(self):
+        plan = {
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryInfo": {
+                "distinctType": "None",
+            },
+            "queryRanges": []
+        }
+        pkranges = [
+            {"id": "partition0", "minInclusive": "00", "maxExclusive": "99"},
+            {"id": "partition1", "minInclusive": "99", "maxExclusive": "FF"}
+        ]
+        options = {"prefetch_all_partitions": True}
+        pipelines = [
+            azure_cosmoscx.QueryEngine().create_pipeline(
+                "SELECT * FROM c", plan, pkranges, options),
+            azure_cosmoscx.QueryEngine().create_pipeline(
+                "SELECT * FROM c", json.dumps(plan), json.dumps({"PartitionKeyRanges": pkranges}), options),
+        ]
+        for pipeline in pipelines:
+            result = pipeline.next_batch()
+            self.assertEqual(["partition0", "partition1"], [
+                             r.pkrange_id for r in result.requests])
+
+        plan["queryInfo"]["orderBy"] = ["Ascending"]
+        plan["queryInfo"]["hasNonStreamingOrderBy"] = True
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c", plan, pkranges, {"max_buffered_items": 1})
+        with self.assertRaises(azure_cosmoscx.QueryEngineError) as cm:
+            pipeline.provide_data("partition0", 0, json.dumps({"Documents": [
+                {"orderByItems": [{"item": 1}], "payload": 1},
+                {"orderByItems": [{"item": 2}], "payload": 2},
+            ]}).encode(), None)
+        self.assertIn("limit of 1 items", str(cm.exception))
+
+    def test_cancel(self):
+        plan = {
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryInfo": {
+                "distinctType": "None",
+                "orderBy": ["Ascending"],
+            },
+            "queryRanges": []
+        }
+        pkranges = [
+            {"id": "partition0", "minInclusive": "00", "maxExclusive": "99"},
+            {"id": "partition1", "minInclusive": "99", "maxExclusive": "FF"}
+        ]
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c", plan, pkranges)
+
+        def page(*sorts):
+            return json.dumps({"Documents": [
+                {"orderByItems": [{"item": sort}], "payload": sort} for sort in sorts
+            ]}).encode()
+
+        pipeline.provide_data("partition0", 0, page(1, 3), "p0-page1")
+        pipeline.provide_data("partition1", 0, page(2, 4), "p1-page1")
+        result = pipeline.next_batch()
+        self.assertEqual([b"1", b"2", b"3"], result.items)
+        self.assertFalse(result.terminated)
+
+        pipeline.cancel()
+        result = pipeline.next_batch()
+        self.assertEqual([], result.items)
+        self.assertEqual([], result.requests)
+        self.assertTrue(result.terminated)
+
+        # A call that's interrupted by cancel(), from another thread, raises CancelledError instead.
+        self.assertTrue(issubclass(azure_cosmoscx.CancelledError, azure_cosmoscx.QueryEngineError))
+
+    def test_id(self):
+        plan = {
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryInfo": {},
+            "queryRanges": []
+        }
+        pkranges = {"PartitionKeyRanges": [
+            {"id": "partition0", "minInclusive": "", "maxExclusive": "FF"}
+        ]}
+        plan, pkranges = json.dumps(plan), json.dumps(pkranges)
+        engine = azure_cosmoscx.QueryEngine()
+        pipeline = engine.create_pipeline("SELECT * FROM c", plan, pkranges)
+
+        query_id = pipeline.id()
+        self.assertTrue(query_id)
+        pipeline.next_batch()
+        pipeline.next_batch()
+        self.assertEqual(query_id, pipeline.id())
+        self.assertNotEqual(
+            query_id, engine.create_pipeline("SELECT * FROM c", plan, pkranges).id())
+
+    def test_passthrough(self):
+        pkranges = {"PartitionKeyRanges": [
+            {"id": "partition0", "minInclusive": "", "maxExclusive": "80"},
+            {"id": "partition1", "minInclusive": "80", "maxExclusive": "FF"}
+        ]}
+        parameters = [{"name": "@id", "value": "item1"}]
+        query = {"query": "SELECT * FROM c WHERE c.id = @id", "parameters": parameters}
+
+        # The partition key ranges can be provided as Python objects or as the raw gateway response.
+        for ranges in [pkranges["PartitionKeyRanges"], json.dumps(pkranges)]:
+            pipeline = azure_cosmoscx.QueryEngine().create_passthrough_pipeline(query, ranges)
+            result = pipeline.next_batch()
+            self.assertEqual(["partition0"], [r.pkrange_id for r in result.requests])
+            self.assertEqual(parameters, json.loads(result.requests[0].encoded_parameters))
+
+        with self.assertRaises(azure_cosmoscx.UnsupportedQueryError):
+            azure_cosmoscx.QueryEngine().create_passthrough_pipeline(
+                "SELECT * FROM c ORDER BY c.id", json.dumps(pkranges))
+
+    def test_pkrange_gone(self):
+        plan = {"partitionedQueryExecutionInfoVersion": 1, "queryInfo": {}, "queryRanges": []}
+        pkranges = {"PartitionKeyRanges": [
+            {"id": "partition0", "minInclusive": "", "maxExclusive": "80"},
+            {"id": "partition1", "minInclusive": "80", "maxExclusive": "FF"}
+        ]}
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            "SELECT * FROM c", json.dumps(plan), json.dumps(pkranges))
+        pipeline.next_batch()
+        pipeline.provide_data("partition0", 0,
+                              json.dumps({"Documents": [1]}).encode(), "token1")
+
+        # The partition splits before its second page is requested.
+        pipeline.handle_pkrange_gone("partition0", [
+            {"id": "partition2", "minInclusive": "", "maxExclusive": "40"},
+            {"id": "partition3", "minInclusive": "40", "maxExclusive": "80"}
+        ])
+        result = pipeline.next_batch()
+        self.assertEqual(1, len(result.items))
+        self.assertEqual([("partition2", "token1")], [(r.pkrange_id, r.continuation) for r in result.requests])
+
+    def test_supported_features(self):
+        names, header = azure_cosmoscx.supported_features()
+        self.assertIn("HybridSearch", names)
+        self.assertEqual("".join(name + "," for name in names), header)
+
+        names, header = azure_cosmoscx.supported_features(["HybridSearch", "NotAFeature"])
+        self.assertNotIn("HybridSearch", names)
+        self.assertEqual("".join(name + "," for name in names), header)
+        self.assertNotIn(",,", header)
+
+    def test_panic_raises_internal_error(self):
+        with self.assertRaises(azure_cosmoscx.InternalError) as raised:
+            azure_cosmoscx._azure_cosmoscx._panic()
+        self.assertIn("_panic was called", str(raised.exception))
+
+    def test_parameters(self):
+        plan = {
+            "partitionedQueryExecutionInfoVersion": 1,
+            "queryRanges": [],
+            "hybridSearchQueryInfo": {
+                "globalStatisticsQuery": "SELECT COUNT(1) AS documentCount, [] AS fullTextStatistics FROM c",
+                "componentQueryInfos": [
+                    {"rewrittenQuery": "SELECT * FROM c WHERE c.score > @score ORDER BY c.score0 DESC", "orderBy": ["Descending"]},
+                    {"rewrittenQuery": "SELECT * FROM c ORDER BY c.score1 DESC", "orderBy": ["Descending"]}
+                ],
+                "componentWeights": [1.0, 1.0],
+                "skip": 0,
+                "take": 2,
+                "requiresGlobalStatistics": True
+            }
+        }
+        pkranges = {"PartitionKeyRanges": [
+            {"id": "partition0", "minInclusive": "", "maxExclusive": "FF"}
+        ]}
+        parameters = [{"name": "@score", "value": 0.5}]
+        pipeline = azure_cosmoscx.QueryEngine().create_pipeline(
+            {"query": "SELECT * FROM c", "parameters": parameters}, json.dumps(plan), json.dumps(pkranges))
+
+        # The global statistics query is sent without the parameters.
+        result = pipeline.next_batch()
+        self.assertEqual(1, len(result.requests))
+        request = result.requests[0]
+        self.assertEqual(plan["hybridSearchQueryInfo"]["globalStatisticsQuery"], request.query)
+        self.assertFalse(request.include_parameters)
+        self.assertIsNone(request.encoded_parameters)
+        pipeline.provide_data("partition0", 0, json.dumps(
+            {"Documents": [{"documentCount": 42, "fullTextStatistics": []}]}).encode(), None)
+
+        # The component queries are sent with them.
+        result = pipeline.next_batch()
+        self.assertEqual(2, len(result.requests))
+        for request in result.requests:
+            self.assertTrue(request.include_parameters)
+            self.assertEqual(parameters, json.loads(request.encoded_parameters))